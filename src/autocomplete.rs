@@ -1,17 +1,24 @@
 use std::ffi::OsString;
 use std::path::PathBuf;
-use std::{iter, ops::Range, path, path::Path, vec};
+use std::{ops::Range, path, path::Path};
 
 pub trait Completer: std::fmt::Debug {
     type Options: Default;
 
     fn complete(&self, input: &str, opts: Self::Options) -> Vec<String>;
+
+    /// Position in the input at which completions are inserted. Anything between
+    /// this position and the end of the input is replaced by the completion.
+    /// By default, completions are appended to the input.
+    fn start(&self, input: &str) -> usize {
+        input.len()
+    }
 }
 
 #[derive(Debug)]
 pub struct Autocomplete<T> {
-    /// Available command completions.
-    completions: Option<iter::Peekable<iter::Cycle<vec::IntoIter<String>>>>,
+    /// Available completions, and the index of the current one.
+    completions: Option<(Vec<String>, usize)>,
     /// Range within the input that is being completed.
     range: Range<usize>,
     /// The completer we are using to find candidates.
@@ -27,48 +34,89 @@ impl<T: Completer> Autocomplete<T> {
         }
     }
 
+    pub fn completer_mut(&mut self) -> &mut T {
+        &mut self.completer
+    }
+
     pub fn invalidate(&mut self) {
         self.completions = None;
         self.range = 0..0;
     }
 
+    /// The completions being cycled through, and the index of the current one.
+    pub fn completions(&self) -> Option<(&[String], usize)> {
+        self.completions
+            .as_ref()
+            .map(|(completions, i)| (completions.as_slice(), *i))
+    }
+
     pub fn next(&mut self, input: &str, cursor: usize) -> Option<(String, Range<usize>)> {
         match &mut self.completions {
-            Some(iter) => {
-                iter.next().map(|completion| {
-                    let range = self.range.clone();
-                    // New completion range starts where current one did, but ends
-                    // based on new completion length.
-                    self.range = self.range.start..self.range.start + completion.len();
-
-                    (completion, range)
-                })
+            Some((completions, i)) => {
+                *i = (*i + 1) % completions.len();
+
+                let completion = completions[*i].clone();
+                let range = self.range.clone();
+                // New completion range starts where current one did, but ends
+                // based on new completion length.
+                self.range = self.range.start..self.range.start + completion.len();
+
+                Some((completion, range))
             }
             None => {
-                let candidates = self
-                    .completer
-                    .complete(&input[..cursor], Default::default());
-                let mut iter = candidates.into_iter().cycle().peekable();
-
-                iter.next().map(|completion| {
-                    if iter.peek() == Some(&completion) {
-                        // If there's only one match, we can go ahead and invalidate the rest
-                        // of the completions so that next time this function is called, it
-                        // loads new matches based on this one match.
-                        self.invalidate();
-                    } else {
-                        // Otherwise, base the range on the position returned from the
-                        // completer.
-                        self.range = cursor..cursor + completion.len();
-                        self.completions = Some(iter);
-                    }
-                    (completion, cursor..cursor)
-                })
+                let input = &input[..cursor];
+                let start = self.completer.start(input);
+                let candidates = self.completer.complete(input, Default::default());
+                let completion = candidates.first()?.clone();
+
+                if candidates.iter().all(|c| c == &completion) {
+                    // If there's only one match, we can go ahead and invalidate the rest
+                    // of the completions so that next time this function is called, it
+                    // loads new matches based on this one match.
+                    self.invalidate();
+                } else {
+                    // Otherwise, base the range on the position returned from the
+                    // completer.
+                    self.range = start..start + completion.len();
+                    self.completions = Some((candidates, 0));
+                }
+                Some((completion, start..cursor))
             }
         }
     }
 }
 
+/// Match the pattern against the candidate as a subsequence. Returns a score
+/// if it matches, where lower is better: prefix matches score zero, and other
+/// matches score higher the more spread out the matched characters are.
+pub fn fuzzy_match(pattern: &str, candidate: &str) -> Option<usize> {
+    if candidate.starts_with(pattern) {
+        return Some(0);
+    }
+    let mut chars = candidate.char_indices();
+    let mut score = 1;
+    let mut last = None;
+
+    for p in pattern.chars() {
+        let (i, _) = chars.find(|(_, c)| *c == p)?;
+
+        score += last.map_or(i, |last| i - last - 1);
+        last = Some(i);
+    }
+    Some(score)
+}
+
+/// Filter and sort candidates by how well they fuzzily match the pattern.
+pub fn fuzzy_filter<'a>(pattern: &str, candidates: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut matches: Vec<_> = candidates
+        .filter_map(|c| fuzzy_match(pattern, c).map(|score| (score, c)))
+        .collect();
+
+    matches.sort_by(|(a, x), (b, y)| a.cmp(b).then(x.len().cmp(&y.len())).then(x.cmp(y)));
+    matches.dedup_by(|(_, x), (_, y)| x == y);
+    matches.into_iter().map(|(_, c)| c.to_owned()).collect()
+}
+
 #[derive(Debug)]
 pub struct FileCompleter {
     cwd: path::PathBuf,
@@ -205,6 +253,28 @@ mod test {
         assert_eq!(Some(("two.png".to_owned(), 0..7)), auto.next("", 0),);
     }
 
+    #[test]
+    fn test_fuzzy_filter() {
+        let candidates = [
+            "v/split",
+            "v/split/copy",
+            "selection/paste",
+            "slice",
+            "split",
+        ];
+
+        assert_eq!(
+            fuzzy_filter("spl", candidates.iter().cloned()),
+            vec!["split", "v/split", "v/split/copy"]
+        );
+        assert_eq!(fuzzy_filter("sl", candidates.iter().cloned())[0], "slice");
+        assert_eq!(
+            fuzzy_filter("sps", candidates.iter().cloned()),
+            vec!["selection/paste"]
+        );
+        assert!(fuzzy_filter("xyz", candidates.iter().cloned()).is_empty());
+    }
+
     #[test]
     fn test_autocomplete_file() {
         let tmp = tempfile::tempdir().unwrap();
@@ -1,3 +1,5 @@
+use crate::color;
+use crate::pattern::Dither;
 use crate::pixels::PixelsMut;
 use crate::view::{ViewCoords, ViewExtent};
 
@@ -7,7 +9,7 @@ use crate::gfx::shape2d::{Fill, Rotation, Shape, Stroke};
 use crate::gfx::{Rgba8, ZDepth};
 
 use crate::util::vector_angle;
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, VecDeque};
 use std::f32::consts::PI;
 use std::fmt;
 
@@ -44,6 +46,10 @@ pub enum BrushMode {
         /// snap angle (degrees)
         Option<u32>,
     ),
+    /// Mask strokes with a dithering pattern.
+    Dither(Dither),
+    /// Only change the color of non-transparent pixels, preserving their alpha.
+    Recolor,
 }
 
 impl fmt::Display for BrushMode {
@@ -57,6 +63,8 @@ impl fmt::Display for BrushMode {
             Self::XRay => "xray".fmt(f),
             Self::Line(Some(snap)) => write!(f, "{} degree snap line", snap),
             Self::Line(None) => write!(f, "line"),
+            Self::Dither(d) => write!(f, "dither {}", d),
+            Self::Recolor => "recolor".fmt(f),
         }
     }
 }
@@ -78,6 +86,18 @@ pub struct Brush {
     pub stroke: Vec<Point2<i32>>,
     /// Current stroke color.
     pub color: Rgba8,
+    /// Color of the pixels masked out by dithering. Transparent pixels
+    /// are left unchanged.
+    pub bg: Rgba8,
+    /// Number of recent positions averaged to smooth strokes, or zero if
+    /// strokes aren't stabilized.
+    pub stabilize: usize,
+    /// Grid cell size, if strokes paint whole grid cells.
+    pub grid: Option<(u32, u32)>,
+
+    /// Frame pairs of the view being drawn on. Strokes on one frame of a pair
+    /// are mirrored onto the other.
+    pub pairs: BTreeSet<(usize, usize)>,
 
     /// Currently active brush modes.
     modes: BTreeSet<BrushMode>,
@@ -85,6 +105,8 @@ pub struct Brush {
     curr: Point2<i32>,
     /// Previous brush position.
     prev: Point2<i32>,
+    /// Recent input positions, most recent last, for stabilizing strokes.
+    recent: VecDeque<Point2<i32>>,
 }
 
 impl Default for Brush {
@@ -94,9 +116,14 @@ impl Default for Brush {
             state: BrushState::NotDrawing,
             stroke: Vec::with_capacity(32),
             color: Rgba8::TRANSPARENT,
+            bg: Rgba8::TRANSPARENT,
+            stabilize: 0,
+            grid: None,
+            pairs: BTreeSet::new(),
             modes: BTreeSet::new(),
             curr: Point2::new(0, 0),
             prev: Point2::new(0, 0),
+            recent: VecDeque::new(),
         }
     }
 }
@@ -115,13 +142,24 @@ impl Brush {
                 self.unset(line_mode);
             }
         }
+        if let BrushMode::Dither(_) = m {
+            // Only one dithering pattern may be active at a time.
+            if let Some(d) = self.dither() {
+                self.modes.remove(&BrushMode::Dither(d));
+            }
+        }
         self.modes.insert(m)
     }
 
     /// De-activate the given brush mode.
     pub fn unset(&mut self, m: BrushMode) -> bool {
-        match self.line_mode() {
-            Some(line_mode) if matches!(m, BrushMode::Line(_)) => self.modes.remove(&line_mode),
+        match (self.line_mode(), self.dither()) {
+            (Some(line_mode), _) if matches!(m, BrushMode::Line(_)) => {
+                self.modes.remove(&line_mode)
+            }
+            (_, Some(d)) if matches!(m, BrushMode::Dither(_)) => {
+                self.modes.remove(&BrushMode::Dither(d))
+            }
             _ => self.modes.remove(&m),
         }
     }
@@ -154,10 +192,18 @@ impl Brush {
     }
 
     /// Start drawing. Called when input is first pressed.
-    pub fn start_drawing(&mut self, p: ViewCoords<i32>, color: Rgba8, extent: ViewExtent) {
+    pub fn start_drawing(
+        &mut self,
+        p: ViewCoords<i32>,
+        color: Rgba8,
+        bg: Rgba8,
+        extent: ViewExtent,
+    ) {
         self.state = BrushState::DrawStarted(extent);
         self.color = color;
+        self.bg = bg;
         self.stroke = Vec::with_capacity(32);
+        self.recent.clear();
         self.draw(p);
     }
 
@@ -170,8 +216,38 @@ impl Brush {
             .next()
     }
 
+    /// If dithering is active, return its pattern.
+    pub fn dither(&self) -> Option<Dither> {
+        self.modes.iter().find_map(|m| match m {
+            BrushMode::Dither(d) => Some(*d),
+            _ => None,
+        })
+    }
+
+    /// Smooth the given input position, by averaging it with the most recent
+    /// input positions.
+    fn stabilized(&mut self, p: ViewCoords<i32>) -> ViewCoords<i32> {
+        if self.stabilize <= 1 {
+            return p;
+        }
+        self.recent.push_back(*p);
+
+        while self.recent.len() > self.stabilize {
+            self.recent.pop_front();
+        }
+        let n = self.recent.len() as f32;
+        let (x, y) = self
+            .recent
+            .iter()
+            .fold((0., 0.), |(x, y), q| (x + q.x as f32, y + q.y as f32));
+
+        ViewCoords::new((x / n).round() as i32, (y / n).round() as i32)
+    }
+
     /// Draw. Called while input is pressed.
     pub fn draw(&mut self, p: ViewCoords<i32>) {
+        let p = self.stabilized(p);
+
         self.prev = if let BrushState::DrawStarted(_) = self.state {
             *p
         } else {
@@ -246,6 +322,36 @@ impl Brush {
                 pixels.push(Point2::new(p.x, fh as i32 - p.y - 1));
             }
         }
+        if !self.pairs.is_empty() {
+            for p in pixels.clone() {
+                let frame_index = (p.x / fw as i32) as usize;
+
+                for &(a, b) in &self.pairs {
+                    let other = if frame_index == a {
+                        b
+                    } else if frame_index == b {
+                        a
+                    } else {
+                        continue;
+                    };
+                    if other >= nframes {
+                        continue;
+                    }
+                    let (fw, size) = (fw as i32, self.size as i32);
+                    let x = p.x - frame_index as i32 * fw;
+                    let left = other as i32 * fw;
+
+                    // Heads are centered on the pixel left of the middle for even
+                    // sizes, so the mirrored head is shifted by one to cover the
+                    // mirror image of the same pixels. The head is then kept within
+                    // the frame, so that wide brushes don't bleed onto its neighbors.
+                    let x = left + fw - x - size % 2;
+                    let x = x.max(left + size / 2).min(left + fw - size + size / 2);
+
+                    pixels.push(Point2::new(x, p.y));
+                }
+            }
+        }
         if self.is_set(BrushMode::Multi) {
             for p in pixels.clone() {
                 let frame_index = p.x / fw as i32;
@@ -258,8 +364,109 @@ impl Brush {
         pixels.iter().map(|p| ViewCoords::new(p.x, p.y)).collect()
     }
 
+    /// Check whether the current stroke depends on the pixels it's painted
+    /// over, and has to be blended onto the view with [`Brush::blend`].
+    pub fn is_blended(&self) -> bool {
+        (self.color.a != 0xff || self.is_set(BrushMode::Recolor)) && !self.is_set(BrushMode::Erase)
+    }
+
+    /// Return the pixels covered by the current stroke, each only once, so
+    /// that translucent strokes don't blend more than once onto a pixel. When
+    /// snapping to the grid, every grid cell touched by the stroke is covered.
+    pub fn coverage(&self) -> Vec<ViewCoords<i32>> {
+        let extent = match self.state {
+            BrushState::DrawStarted(extent)
+            | BrushState::Drawing(extent)
+            | BrushState::DrawEnded(extent) => extent,
+            BrushState::NotDrawing => return Vec::new(),
+        };
+        let size = self.size as i32;
+        let mut pixels = BTreeSet::new();
+
+        for p in &self.stroke {
+            for head in self.expand(ViewCoords::new(p.x, p.y), extent) {
+                let (x, y) = (head.x - size / 2, head.y - size / 2);
+
+                for dy in 0..size {
+                    for dx in 0..size {
+                        pixels.insert((x + dx, y + dy));
+                    }
+                }
+            }
+        }
+        if let Some((gw, gh)) = self.grid {
+            let (gw, gh) = (gw.max(1) as i32, gh.max(1) as i32);
+            let cells: BTreeSet<_> = pixels
+                .iter()
+                .map(|(x, y)| (x.div_euclid(gw) * gw, y.div_euclid(gh) * gh))
+                .collect();
+
+            pixels = cells
+                .into_iter()
+                .flat_map(|(x, y)| {
+                    (0..gh).flat_map(move |dy| (0..gw).map(move |dx| (x + dx, y + dy)))
+                })
+                .collect();
+        }
+        pixels
+            .into_iter()
+            .map(|(x, y)| ViewCoords::new(x, y))
+            .collect()
+    }
+
+    /// Return the color painted by the current stroke at the given pixel, if
+    /// any. When dithering, pixels outside of the pattern are painted with the
+    /// background color, unless it's transparent or the brush is erasing.
+    pub fn color_at(&self, p: ViewCoords<i32>) -> Option<Rgba8> {
+        match self.dither() {
+            Some(d) if !d.covers(p.x, p.y) => {
+                if self.bg.a == 0 || self.is_set(BrushMode::Erase) {
+                    None
+                } else {
+                    Some(self.bg)
+                }
+            }
+            _ => Some(self.color),
+        }
+    }
+
+    /// Blend the current stroke at the given pixel onto the pixel's color.
+    /// Returns `None` if the pixel isn't painted.
+    pub fn blend(&self, p: ViewCoords<i32>, dst: Rgba8) -> Option<Rgba8> {
+        let src = self.color_at(p)?;
+
+        if self.is_set(BrushMode::Recolor) {
+            if dst.a == 0 {
+                return None;
+            }
+            return Some(color::over(dst.alpha(0xff), src).alpha(dst.a));
+        }
+        Some(color::over(dst, src))
+    }
+
     /// Return the brush's output strokes as shapes.
     pub fn output(&self, stroke: Stroke, fill: Fill, scale: f32, align: Align) -> Vec<Shape> {
+        // Dithered strokes and strokes snapped to the grid are output one pixel
+        // at a time, so that each pixel can be masked. Pixels are already
+        // aligned to their bottom-left.
+        if self.dither().is_some() || self.grid.is_some() {
+            return self
+                .coverage()
+                .into_iter()
+                .filter_map(|p| {
+                    let color = self.color_at(p)?;
+                    let (x, y) = (p.x as f32, p.y as f32);
+
+                    Some(Shape::Rectangle(
+                        Rect::new(x, y, x + scale, y + scale),
+                        ZDepth::ZERO,
+                        Rotation::ZERO,
+                        stroke,
+                        Fill::Solid(color.into()),
+                    ))
+                })
+                .collect();
+        }
         match self.state {
             BrushState::DrawStarted(extent)
             | BrushState::Drawing(extent)
@@ -522,4 +729,190 @@ mod test {
             assert_eq!(canvas, brush15);
         }
     }
+
+    #[test]
+    fn test_coverage() {
+        let mut brush = Brush::default();
+        let extent = ViewExtent::new(8, 8, 1);
+
+        brush.size = 2;
+        brush.start_drawing(
+            ViewCoords::new(2, 2),
+            Rgba8::RED.alpha(0x80),
+            Rgba8::TRANSPARENT,
+            extent,
+        );
+        brush.draw(ViewCoords::new(3, 2));
+
+        assert!(brush.is_blended());
+        // Two overlapping 2x2 heads cover a 3x2 area, with no duplicates.
+        assert_eq!(
+            brush.coverage(),
+            vec![
+                ViewCoords::new(1, 1),
+                ViewCoords::new(1, 2),
+                ViewCoords::new(2, 1),
+                ViewCoords::new(2, 2),
+                ViewCoords::new(3, 1),
+                ViewCoords::new(3, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dither() {
+        let mut brush = Brush::default();
+        let extent = ViewExtent::new(8, 8, 1);
+        let (fg, bg) = (Rgba8::RED, Rgba8::BLUE);
+
+        assert!(brush.set(BrushMode::Dither(Dither::Bayer(4))));
+        assert!(brush.set(BrushMode::Dither(Dither::Checker(1))));
+        assert_eq!(brush.dither(), Some(Dither::Checker(1)));
+
+        brush.size = 2;
+        brush.start_drawing(ViewCoords::new(1, 1), fg, Rgba8::TRANSPARENT, extent);
+        assert_eq!(
+            brush
+                .output(Stroke::NONE, Fill::Empty, 1., Align::BottomLeft)
+                .len(),
+            2
+        );
+        assert_eq!(brush.color_at(ViewCoords::new(0, 0)), Some(fg));
+        assert_eq!(brush.color_at(ViewCoords::new(1, 0)), None);
+
+        brush.bg = bg;
+        assert_eq!(
+            brush
+                .output(Stroke::NONE, Fill::Empty, 1., Align::BottomLeft)
+                .len(),
+            4
+        );
+        assert_eq!(brush.color_at(ViewCoords::new(1, 0)), Some(bg));
+
+        assert!(brush.unset(BrushMode::Dither(Dither::default())));
+        assert_eq!(brush.dither(), None);
+        assert_eq!(brush.color_at(ViewCoords::new(1, 0)), Some(fg));
+    }
+
+    #[test]
+    fn test_recolor() {
+        let mut brush = Brush::default();
+        let p = ViewCoords::new(0, 0);
+
+        brush.color = Rgba8::RED;
+        assert!(!brush.is_blended());
+        assert_eq!(brush.blend(p, Rgba8::TRANSPARENT), Some(Rgba8::RED));
+
+        brush.set(BrushMode::Recolor);
+        assert!(brush.is_blended());
+        assert_eq!(brush.blend(p, Rgba8::TRANSPARENT), None);
+        assert_eq!(
+            brush.blend(p, Rgba8::BLUE.alpha(0x80)),
+            Some(Rgba8::RED.alpha(0x80))
+        );
+    }
+
+    #[test]
+    fn test_stabilize() {
+        let mut brush = Brush::default();
+        let extent = ViewExtent::new(16, 16, 1);
+
+        brush.stabilize = 4;
+        brush.start_drawing(
+            ViewCoords::new(0, 0),
+            Rgba8::WHITE,
+            Rgba8::TRANSPARENT,
+            extent,
+        );
+        // A jittery horizontal stroke straightens out, and lags behind the input.
+        for (x, y) in [(1, 1), (2, -1), (3, 1), (4, -1), (5, 1), (6, -1)] {
+            brush.draw(ViewCoords::new(x, y));
+        }
+        assert!(brush.stroke[2..].iter().all(|p| p.y == 0));
+        assert_eq!(brush.stroke.last(), Some(&Point2::new(5, 0)));
+    }
+
+    #[test]
+    fn test_grid() {
+        let mut brush = Brush::default();
+        let extent = ViewExtent::new(16, 16, 1);
+
+        brush.grid = Some((4, 2));
+        brush.start_drawing(
+            ViewCoords::new(5, 1),
+            Rgba8::WHITE,
+            Rgba8::TRANSPARENT,
+            extent,
+        );
+        assert_eq!(
+            brush.coverage(),
+            (4..8)
+                .flat_map(|x| (0..2).map(move |y| ViewCoords::new(x, y)))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            brush
+                .output(Stroke::NONE, Fill::Empty, 1., Align::BottomLeft)
+                .len(),
+            8
+        );
+
+        // Strokes crossing into another cell paint it whole.
+        brush.draw(ViewCoords::new(8, 1));
+        assert_eq!(brush.coverage().len(), 16);
+    }
+
+    #[test]
+    fn test_expand_pair() {
+        let mut brush = Brush::default();
+        let extent = ViewExtent::new(8, 8, 4);
+
+        brush.pairs.insert((1, 3));
+
+        assert_eq!(
+            brush.expand(ViewCoords::new(9, 2), extent),
+            vec![ViewCoords::new(9, 2), ViewCoords::new(30, 2)]
+        );
+        assert_eq!(
+            brush.expand(ViewCoords::new(30, 2), extent),
+            vec![ViewCoords::new(30, 2), ViewCoords::new(9, 2)]
+        );
+        assert_eq!(
+            brush.expand(ViewCoords::new(2, 2), extent),
+            vec![ViewCoords::new(2, 2)]
+        );
+
+        brush.pairs.clear();
+
+        assert_eq!(
+            brush.expand(ViewCoords::new(9, 2), extent),
+            vec![ViewCoords::new(9, 2)]
+        );
+    }
+
+    #[test]
+    fn test_expand_pair_sized() {
+        let mut brush = Brush::default();
+        let extent = ViewExtent::new(8, 8, 2);
+
+        brush.pairs.insert((0, 1));
+
+        // Even sizes mirror the covered pixels exactly.
+        brush.size = 2;
+        assert_eq!(
+            brush.expand(ViewCoords::new(2, 2), extent),
+            vec![ViewCoords::new(2, 2), ViewCoords::new(14, 2)]
+        );
+
+        // Mirrored heads stay within their frame.
+        brush.size = 3;
+        assert_eq!(
+            brush.expand(ViewCoords::new(8, 2), extent),
+            vec![ViewCoords::new(8, 2), ViewCoords::new(6, 2)]
+        );
+        assert_eq!(
+            brush.expand(ViewCoords::new(7, 2), extent),
+            vec![ViewCoords::new(7, 2), ViewCoords::new(9, 2)]
+        );
+    }
 }
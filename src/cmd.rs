@@ -1,18 +1,26 @@
-use crate::autocomplete::{self, Autocomplete, FileCompleter, FileCompleterOpts};
+use crate::autocomplete::{self, fuzzy_filter, Autocomplete, FileCompleter, FileCompleterOpts};
 use crate::brush::BrushMode;
+use crate::color::Shift;
 use crate::history::History;
+use crate::io::RawFormat;
 use crate::parser::*;
+use crate::pattern::{Pattern, MAX_BAYER_SIZE};
+use crate::pixels::ScaleFilter;
 use crate::platform;
-use crate::session::{Direction, Input, Mode, PanState, Tool, VisualState};
+use crate::preview::{Colorblindness, HardwarePalette, MAX_VALUE_BANDS};
+use crate::session::{Direction, Input, Mode, PanState, Settings, Tool, VisualState};
+use crate::theme::ThemeChoice;
+use crate::view::ViewId;
 
 use memoir::traits::Parse;
 use memoir::*;
 
 use crate::gfx::Rect;
-use crate::gfx::Rgba8;
+use crate::gfx::{Rgb8, Rgba8};
 
 use std::fmt;
 use std::path::Path;
+use std::time;
 
 pub const COMMENT: char = '-';
 
@@ -23,12 +31,34 @@ pub enum Op {
     Set(f32),
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Axis {
     Horizontal,
     Vertical,
 }
 
+/// Where to move the content of a frame to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Align {
+    Center,
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl fmt::Display for Align {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Center => write!(f, "center"),
+            Self::Top => write!(f, "top"),
+            Self::Bottom => write!(f, "bottom"),
+            Self::Left => write!(f, "left"),
+            Self::Right => write!(f, "right"),
+        }
+    }
+}
+
 /// User command. Most of the interactions available to
 /// the user are modeled as commands that are processed
 /// by the session.
@@ -41,15 +71,25 @@ pub enum Command {
     BrushSize(Op),
     BrushUnset(BrushMode),
 
+    Align(Align),
+    Back(time::Duration),
+    CheckpointSave(String),
+    CheckpointRestore(String),
     #[allow(dead_code)]
     Crop(Rect<u32>),
     ChangeDir(Option<String>),
     Echo(Value),
 
+    // Color math. The last field is whether to set the result as the foreground color.
+    ColorMix(Rgba8, Rgba8, f32, bool),
+    ColorShift(Rgba8, Vec<Shift>, bool),
+
     // Files
     Edit(Vec<String>),
+    EditRaw(String, u32, u32),
     EditFrames(Vec<String>),
-    Export(Option<u32>, String),
+    Export(Option<u32>, Option<ScaleFilter>, String),
+    ExportRaw(String, RawFormat, Option<RawFormat>),
     Write(Option<String>),
     WriteFrames(Option<String>),
     WriteQuit,
@@ -66,18 +106,60 @@ pub enum Command {
     FramePrev,
     FrameNext,
     FrameResize(u32, u32),
+    FramePair(usize, usize),
+    FrameUnpair,
+
+    // Guides
+    Guide(Axis, u32),
+    GuideClear,
 
     // Palette
     PaletteAdd(Rgba8),
     PaletteClear,
     PaletteGradient(Rgba8, Rgba8, usize),
     PaletteSample,
+    PaletteSampleFile(String, Option<usize>),
     PaletteSort,
-    PaletteWrite(String),
+    PaletteWrite(Option<String>),
+    PaletteMove(usize, usize),
+    PaletteNew(String),
+    PaletteSwitch(String),
+    PaletteNext,
+    PalettePrev,
+
+    Recolor(String),
+    ColorKey(Rgba8),
+    Shift(i32, i32),
+    LutSet,
+    LutClear,
+    LutIr,
+    LutApply,
+
+    // Display
+    Filter(Option<String>),
+    PreviewPalette(Option<HardwarePalette>),
+    PreviewColorblind(Option<Colorblindness>),
+    PreviewValues(Option<u32>),
+
+    // Plugins
+    Plugin(String, Vec<String>),
+    PluginList,
+
+    // Timelapse
+    RecordStart(Option<String>),
+    RecordStop,
+
+    // Collaboration
+    CollabHost(Option<u16>),
+    CollabJoin(String, String),
+    CollabLeave,
 
     // Navigation
     Pan(i32, i32),
+    CursorMove(i32, i32),
+    Goto(i32, i32),
     Zoom(Op),
+    ZoomFit,
 
     PaintColor(Rgba8, i32, i32),
     PaintForeground(i32, i32),
@@ -97,30 +179,57 @@ pub enum Command {
     SelectionErase,
     SelectionJump(Direction),
     SelectionFlip(Axis),
+    SelectionRotate(f32),
+    SelectionGrow(u32),
+    SelectionShrink(u32),
+    SelectionInvert,
+    SelectColor(Option<Rgba8>),
+    PreviewRotate(f32),
+    Generate(Pattern),
 
     // Settings
     Set(String, Value),
+    SettingsSave,
+    RxrcTrust,
     Toggle(String),
     Reset,
     Map(Box<KeyMapping>),
     MapClear,
 
     Slice(Option<usize>),
+    Trim(u32),
+    Stats,
     Fill(Option<Rgba8>),
 
     SwapColors,
+    ColorPrev,
+    ColorNext,
 
     Mode(Mode),
+    History,
     Tool(Tool),
     ToolPrev,
+    Theme(ThemeChoice),
 
     Undo,
     Redo,
+    HistoryClear,
+    Unerase,
 
     // View
     ViewCenter,
     ViewNext,
     ViewPrev,
+    ViewSplit(usize, usize, bool),
+    ViewAppend(ViewId),
+    ViewMirror,
+    WorkspaceNew(String),
+    WorkspaceSwitch(String),
+    WorkspaceMove(String),
+    WorkspaceNext,
+    WorkspacePrev,
+    ReferenceAnim(Option<ViewId>),
+    Compare(Option<ViewId>),
 
     Noop,
 }
@@ -132,6 +241,7 @@ impl Command {
             Self::Zoom(_)
                 | Self::BrushSize(_)
                 | Self::Pan(_, _)
+                | Self::CursorMove(_, _)
                 | Self::Undo
                 | Self::Redo
                 | Self::ViewNext
@@ -154,10 +264,19 @@ impl fmt::Display for Command {
             Self::BrushSize(Op::Decr) => write!(f, "Decrease brush size"),
             Self::BrushSize(Op::Set(s)) => write!(f, "Set brush size to {}", s),
             Self::BrushUnset(m) => write!(f, "Unset brush `{}` mode", m),
+            Self::Align(a) => write!(f, "Align the current frame's content to the {}", a),
+            Self::Back(d) => write!(f, "Restore view to its state {}s ago", d.as_secs()),
+            Self::CheckpointSave(name) => write!(f, "Save view checkpoint '{}'", name),
+            Self::CheckpointRestore(name) => write!(f, "Restore view to checkpoint '{}'", name),
             Self::Crop(_) => write!(f, "Crop view"),
             Self::ChangeDir(_) => write!(f, "Change the current working directory"),
             Self::Echo(_) => write!(f, "Echo a value"),
+            Self::ColorMix(a, b, t, _) => write!(f, "Mix {} and {} by {}", a, b, t),
+            Self::ColorShift(c, _, _) => {
+                write!(f, "Shift the hue, saturation or lightness of {}", c)
+            }
             Self::Edit(_) => write!(f, "Edit path(s)"),
+            Self::EditRaw(_, w, h) => write!(f, "Edit raw RGBA image of size {}x{}", w, h),
             Self::EditFrames(_) => write!(f, "Edit path(s) as animation frames"),
             Self::Fill(Some(c)) => write!(f, "Fill view with {color}", color = c),
             Self::Fill(None) => write!(f, "Fill view with background color"),
@@ -167,9 +286,16 @@ impl fmt::Display for Command {
             Self::MapClear => write!(f, "Clear all key mappings"),
             Self::Mode(Mode::Help) => write!(f, "Toggle help"),
             Self::Mode(m) => write!(f, "Switch to {} mode", m),
+            Self::History => write!(f, "Browse command history"),
+            Self::HistoryClear => write!(f, "Clear the undo history of the active view"),
             Self::FrameAdd => write!(f, "Add a blank frame to the view"),
             Self::FrameClone(i) => write!(f, "Clone frame {} and add it to the view", i),
             Self::FrameRemove => write!(f, "Remove the last frame of the view"),
+            Self::FramePair(a, b) => write!(f, "Mirror strokes between frames {} and {}", a, b),
+            Self::FrameUnpair => write!(f, "Remove all frame pairs"),
+            Self::Guide(Axis::Horizontal, y) => write!(f, "Add a horizontal guide at y = {}", y),
+            Self::Guide(Axis::Vertical, x) => write!(f, "Add a vertical guide at x = {}", x),
+            Self::GuideClear => write!(f, "Remove all guides"),
             Self::FramePrev => write!(f, "Navigate to previous frame"),
             Self::FrameNext => write!(f, "Navigate to next frame"),
             Self::Noop => write!(f, "No-op"),
@@ -179,37 +305,93 @@ impl fmt::Display for Command {
                 write!(f, "Create {n} colors gradient from {cs} to {ce}")
             }
             Self::PaletteSample => write!(f, "Sample palette from view"),
+            Self::PaletteSampleFile(path, _) => write!(f, "Sample palette from {}", path),
             Self::PaletteSort => write!(f, "Sort palette colors"),
+            Self::PaletteMove(from, to) => {
+                write!(f, "Move palette color from index {} to {}", from, to)
+            }
+            Self::PaletteNew(name) => write!(f, "Create palette '{}'", name),
+            Self::PaletteSwitch(name) => write!(f, "Switch to palette '{}'", name),
+            Self::PaletteNext => write!(f, "Switch to the next palette"),
+            Self::PalettePrev => write!(f, "Switch to the previous palette"),
             Self::Pan(x, 0) if *x > 0 => write!(f, "Pan workspace right"),
             Self::Pan(x, 0) if *x < 0 => write!(f, "Pan workspace left"),
             Self::Pan(0, y) if *y > 0 => write!(f, "Pan workspace up"),
             Self::Pan(0, y) if *y < 0 => write!(f, "Pan workspace down"),
             Self::Pan(x, y) => write!(f, "Pan workspace by {},{}", x, y),
+            Self::CursorMove(x, y) => write!(f, "Move cursor by {},{} pixels", x, y),
+            Self::Goto(x, y) => write!(f, "Center workspace on pixel {},{}", x, y),
             Self::Quit => write!(f, "Quit active view"),
             Self::QuitAll => write!(f, "Quit all views"),
+            Self::Recolor(_) => write!(f, "Recolor view using a color mapping file"),
+            Self::Shift(x, y) => write!(f, "Shift view by {},{} with wraparound", x, y),
+            Self::LutSet => write!(f, "Set view as lookup texture"),
+            Self::LutClear => write!(f, "Clear lookup texture"),
+            Self::LutIr => write!(f, "Generate lookup texture IR of view"),
+            Self::LutApply => write!(f, "Apply lookup texture to view"),
+            Self::ColorKey(c) => write!(f, "Make all {} pixels of the view transparent", c),
             Self::Redo => write!(f, "Redo view edit"),
+            Self::RecordStart(_) => write!(f, "Start recording a timelapse of the workspace"),
+            Self::RecordStop => write!(f, "Stop recording the timelapse"),
+            Self::CollabHost(_) => write!(f, "Host a collaborative editing session"),
+            Self::CollabJoin(addr, _) => {
+                write!(f, "Join the collaborative session at {}", addr)
+            }
+            Self::CollabLeave => write!(f, "Leave the collaborative session"),
             Self::FrameResize(_, _) => write!(f, "Resize active view frame"),
             Self::Tool(Tool::Pan(_)) => write!(f, "Pan tool"),
             Self::Tool(Tool::Brush) => write!(f, "Brush tool"),
             Self::Tool(Tool::Sampler) => write!(f, "Color sampler tool"),
             Self::Tool(Tool::FloodFill) => write!(f, "Flood fill tool"),
+            Self::Tool(Tool::Measure(_)) => write!(f, "Measure tool"),
             Self::ToolPrev => write!(f, "Switch to previous tool"),
+            Self::Theme(t) => write!(f, "Switch to {} theme", t),
             Self::Set(s, v) => write!(f, "Set {setting} to {val}", setting = s, val = v),
+            Self::SettingsSave => write!(f, "Save settings and key mappings"),
+            Self::RxrcTrust => write!(f, "Trust and source the directory's `.rxrc` script"),
             Self::Slice(Some(n)) => write!(f, "Slice view into {} frame(s)", n),
             Self::Slice(None) => write!(f, "Reset view slices"),
+            Self::Trim(0) => write!(f, "Crop view frames to their content"),
+            Self::Trim(n) => write!(
+                f,
+                "Crop view frames to their content, with {} pixel(s) of padding",
+                n
+            ),
             Self::Source(_) => write!(f, "Source an rx script (eg. a palette)"),
+            Self::Stats => write!(f, "Show color usage statistics"),
             Self::SwapColors => write!(f, "Swap foreground & background colors"),
+            Self::ColorPrev => write!(f, "Use the previous foreground color"),
+            Self::ColorNext => write!(f, "Use the next foreground color"),
             Self::Toggle(s) => write!(f, "Toggle {setting} on/off", setting = s),
             Self::Undo => write!(f, "Undo view edit"),
+            Self::Unerase => write!(f, "Restore pixels removed by the last erase stroke"),
             Self::ViewCenter => write!(f, "Center active view"),
             Self::ViewNext => write!(f, "Go to next view"),
             Self::ViewPrev => write!(f, "Go to previous view"),
+            Self::ViewAppend(id) => write!(f, "Append frames of view #{} to active view", id),
+            Self::ViewMirror => write!(f, "Mirror active view in a new view"),
+            Self::WorkspaceNew(name) => write!(f, "Create workspace '{}'", name),
+            Self::WorkspaceSwitch(name) => write!(f, "Switch to workspace '{}'", name),
+            Self::WorkspaceMove(name) => write!(f, "Move active view to workspace '{}'", name),
+            Self::WorkspaceNext => write!(f, "Switch to the next workspace"),
+            Self::WorkspacePrev => write!(f, "Switch to the previous workspace"),
+            Self::ReferenceAnim(Some(id)) => write!(f, "Overlay animation of view #{}", id),
+            Self::ReferenceAnim(None) => write!(f, "Clear reference animation"),
+            Self::Compare(Some(id)) => write!(f, "Compare the active view with view #{}", id),
+            Self::Compare(None) => write!(f, "Toggle the view comparison"),
+            Self::ViewSplit(from, to, false) => {
+                write!(f, "Move frames {}-{} into a new view", from, to)
+            }
+            Self::ViewSplit(from, to, true) => {
+                write!(f, "Copy frames {}-{} into a new view", from, to)
+            }
             Self::Write(None) => write!(f, "Write view to disk"),
             Self::Write(Some(_)) => write!(f, "Write view to disk as..."),
             Self::WriteQuit => write!(f, "Write file to disk and quit"),
             Self::Zoom(Op::Incr) => write!(f, "Zoom in view"),
             Self::Zoom(Op::Decr) => write!(f, "Zoom out view"),
             Self::Zoom(Op::Set(z)) => write!(f, "Set view zoom to {:.1}", z),
+            Self::ZoomFit => write!(f, "Zoom view to fit the window"),
             Self::Reset => write!(f, "Reset all settings to default"),
             Self::SelectionFill(None) => write!(f, "Fill selection with foreground color"),
             Self::SelectionYank => write!(f, "Yank (copy) selection"),
@@ -232,6 +414,14 @@ impl fmt::Display for Command {
             Self::SelectionErase => write!(f, "Erase selection contents"),
             Self::SelectionFlip(Axis::Horizontal) => write!(f, "Flip selection horizontally"),
             Self::SelectionFlip(Axis::Vertical) => write!(f, "Flip selection vertically"),
+            Self::SelectionRotate(deg) => write!(f, "Rotate selection by {} degrees", deg),
+            Self::SelectionGrow(n) => write!(f, "Grow selection by {} pixel(s)", n),
+            Self::SelectionShrink(n) => write!(f, "Shrink selection by {} pixel(s)", n),
+            Self::SelectionInvert => write!(f, "Invert selection"),
+            Self::SelectColor(None) => write!(f, "Select pixels of the foreground color"),
+            Self::SelectColor(Some(c)) => write!(f, "Select pixels of color {}", c),
+            Self::Generate(pattern) => write!(f, "Fill selection with {} pattern", pattern),
+            Self::PreviewRotate(deg) => write!(f, "Preview selection rotated by {} degrees", deg),
             Self::PaintColor(_, x, y) => write!(f, "Paint {:2},{:2}", x, y),
             _ => write!(f, "..."),
         }
@@ -241,6 +431,10 @@ impl fmt::Display for Command {
 impl From<Command> for String {
     fn from(cmd: Command) -> Self {
         match cmd {
+            Command::Align(a) => format!("align {}", a),
+            Command::Back(d) => format!("back {}s", d.as_secs()),
+            Command::CheckpointSave(name) => format!("checkpoint save {}", name),
+            Command::CheckpointRestore(name) => format!("checkpoint restore {}", name),
             Command::Brush => format!("brush"),
             Command::BrushSet(m) => format!("brush/set {}", m),
             Command::BrushSize(Op::Incr) => format!("brush/size +"),
@@ -248,44 +442,141 @@ impl From<Command> for String {
             Command::BrushSize(Op::Set(s)) => format!("brush/size {}", s),
             Command::BrushUnset(m) => format!("brush/unset {}", m),
             Command::Echo(_) => unimplemented!(),
+            Command::ColorMix(a, b, t, fg) => {
+                format!("color/mix {} {} {}{}", a, b, t, if fg { " fg" } else { "" })
+            }
+            Command::ColorShift(c, shifts, fg) => format!(
+                "color/shift {}{}{}",
+                c,
+                shifts.iter().map(|s| format!(" {}", s)).collect::<String>(),
+                if fg { " fg" } else { "" }
+            ),
             Command::Edit(_) => unimplemented!(),
+            Command::EditRaw(path, w, h) => format!("e {} --size {}x{}", path, w, h),
             Command::Fill(Some(c)) => format!("v/fill {}", c),
             Command::Fill(None) => format!("v/fill"),
+            Command::Filter(None) => format!("filter"),
+            Command::Filter(Some(path)) => format!("filter {}", path),
             Command::ForceQuit => format!("q!"),
             Command::ForceQuitAll => format!("qa!"),
             Command::Map(_) => format!("map <key> <command> {{<command>}}"),
             Command::Mode(m) => format!("mode {}", m),
+            Command::History => format!("history"),
+            Command::HistoryClear => format!("history/clear"),
             Command::FrameAdd => format!("f/add"),
             Command::FrameClone(i) => format!("f/clone {}", i),
             Command::FrameRemove => format!("f/remove"),
-            Command::Export(None, path) => format!("export {}", path),
-            Command::Export(Some(s), path) => format!("export @{}x {}", s, path),
+            Command::FramePair(a, b) => format!("pair {} {}", a, b),
+            Command::FrameUnpair => format!("pair/clear"),
+            Command::Guide(Axis::Horizontal, y) => format!("guide h {}", y),
+            Command::Guide(Axis::Vertical, x) => format!("guide v {}", x),
+            Command::GuideClear => format!("guide/clear"),
+            Command::Export(scale, filter, path) => {
+                let mut s = String::from("export ");
+                if let Some(scale) = scale {
+                    s.push_str(&format!("@{}x ", scale));
+                }
+                if let Some(filter) = filter {
+                    s.push_str(&format!("--filter {} ", filter));
+                }
+                s.push_str(&path);
+                s
+            }
+            Command::ExportRaw(path, format, None) => {
+                format!("export raw {} --format {}", path, format)
+            }
+            Command::ExportRaw(path, format, Some(palette)) => format!(
+                "export raw {} --format {} --palette {}",
+                path, format, palette
+            ),
             Command::Noop => format!(""),
             Command::PaletteAdd(c) => format!("p/add {}", c),
             Command::PaletteClear => format!("p/clear"),
-            Command::PaletteWrite(_) => format!("p/write"),
+            Command::PaletteWrite(None) => format!("p/write"),
+            Command::PaletteWrite(Some(path)) => format!("p/write {}", path),
+            Command::PaletteMove(from, to) => format!("p/move {} {}", from, to),
+            Command::PaletteNew(name) => format!("palette/new {}", name),
+            Command::PaletteSwitch(name) => format!("palette/switch {}", name),
+            Command::PaletteNext => format!("palette/next"),
+            Command::PalettePrev => format!("palette/prev"),
             Command::PaletteSample => format!("p/sample"),
+            Command::PaletteSampleFile(path, None) => format!("p/sample {}", path),
+            Command::PaletteSampleFile(path, Some(n)) => format!("p/sample {} {}", path, n),
             Command::PaletteGradient(cs, ce, n) => format!("p/gradient {} {} {}", cs, ce, n),
             Command::Pan(x, y) => format!("pan {} {}", x, y),
+            Command::CursorMove(x, y) => format!("cursor/move {} {}", x, y),
+            Command::Goto(x, y) => format!("goto {} {}", x, y),
+            Command::PaintColor(c, x, y) => format!("paint/color {} {} {}", c, x, y),
+            Command::Plugin(name, args) => format!(
+                "plugin {}{}",
+                name,
+                args.iter().map(|a| format!(" {}", a)).collect::<String>()
+            ),
+            Command::PluginList => format!("plugin/list"),
             Command::Quit => format!("q"),
+            Command::Recolor(path) => format!("recolor {}", path),
+            Command::ColorKey(c) => format!("colorkey {}", c),
+            Command::Shift(x, y) => format!("shift {} {}", x, y),
+            Command::LutSet => format!("lut/set"),
+            Command::LutClear => format!("lut/clear"),
+            Command::LutIr => format!("lut/ir"),
+            Command::LutApply => format!("lut/apply"),
             Command::Redo => format!("redo"),
+            Command::RecordStart(None) => format!("record/start"),
+            Command::RecordStart(Some(path)) => format!("record/start {}", path),
+            Command::RecordStop => format!("record/stop"),
+            Command::CollabHost(None) => format!("collab/host"),
+            Command::CollabHost(Some(port)) => format!("collab/host {}", port),
+            Command::CollabJoin(addr, token) => format!("collab/join {} {}", addr, token),
+            Command::CollabLeave => format!("collab/leave"),
             Command::FrameResize(w, h) => format!("f/resize {} {}", w, h),
             Command::Set(s, v) => format!("set {} = {}", s, v),
+            Command::SettingsSave => format!("set/save"),
+            Command::RxrcTrust => format!("rxrc/trust"),
             Command::Slice(Some(n)) => format!("slice {}", n),
             Command::Slice(None) => format!("slice"),
+            Command::Trim(n) => format!("trim {}", n),
             Command::Source(Some(path)) => format!("source {}", path),
+            Command::Stats => format!("stats"),
             Command::SwapColors => format!("swap"),
+            Command::ColorPrev => format!("color/prev"),
+            Command::ColorNext => format!("color/next"),
+            Command::PreviewColorblind(None) => format!("preview/colorblind off"),
+            Command::PreviewColorblind(Some(c)) => format!("preview/colorblind {}", c),
+            Command::PreviewValues(None) => format!("preview/values off"),
+            Command::PreviewValues(Some(n)) => format!("preview/values {}", n),
+            Command::PreviewPalette(None) => format!("preview/palette off"),
+            Command::PreviewPalette(Some(p)) => format!("preview/palette {}", p),
+            Command::PreviewRotate(deg) => format!("preview/rotate {}", deg),
+            Command::SelectionRotate(deg) => format!("selection/rotate {}", deg),
+            Command::Generate(pattern) => format!("gen/{}", pattern),
+            Command::Theme(t) => format!("theme {}", t),
             Command::Toggle(s) => format!("toggle {}", s),
             Command::Undo => format!("undo"),
+            Command::Unerase => format!("unerase"),
             Command::ViewCenter => format!("v/center"),
             Command::ViewNext => format!("v/next"),
             Command::ViewPrev => format!("v/prev"),
+            Command::ViewAppend(id) => format!("v/append {}", id),
+            Command::ViewMirror => format!("v/mirror"),
+            Command::WorkspaceNew(name) => format!("ws/new {}", name),
+            Command::WorkspaceSwitch(name) => format!("ws/switch {}", name),
+            Command::WorkspaceMove(name) => format!("ws/move {}", name),
+            Command::WorkspaceNext => format!("ws/next"),
+            Command::WorkspacePrev => format!("ws/prev"),
+            Command::ReferenceAnim(Some(id)) => format!("reference/anim {}", id),
+            Command::ReferenceAnim(None) => format!("reference/anim"),
+            Command::Compare(Some(id)) => format!("compare {}", id),
+            Command::Compare(None) => format!("compare"),
+            Command::ViewSplit(from, to, false) => format!("v/split {} {}", from, to),
+            Command::ViewSplit(from, to, true) => format!("v/split/copy {} {}", from, to),
             Command::Write(None) => format!("w"),
             Command::Write(Some(path)) => format!("w {}", path),
             Command::WriteQuit => format!("wq"),
             Command::Zoom(Op::Incr) => format!("v/zoom +"),
             Command::Zoom(Op::Decr) => format!("v/zoom -"),
             Command::Zoom(Op::Set(z)) => format!("v/zoom {}", z),
+            Command::ZoomFit => format!("zoom fit"),
             _ => unimplemented!(),
         }
     }
@@ -296,6 +587,8 @@ impl From<Command> for String {
 #[derive(PartialEq, Debug, Clone)]
 pub struct KeyMapping {
     pub input: Input,
+    /// Keys to press, in order, before `input`. Empty unless this maps a key sequence.
+    pub prefix: Vec<platform::Key>,
     pub press: Command,
     pub release: Option<Command>,
     pub modes: Vec<Mode>,
@@ -330,21 +623,32 @@ impl KeyMapping {
         );
 
         let character = between('\'', '\'', character())
-            .map(Input::Character)
+            .map(|c| (Input::Character(c), Vec::new()))
             .skip(whitespace())
             .then(press.clone())
             .map(|(input, press)| ((input, press), None));
-        let key = param::<platform::Key>()
-            .map(Input::Key)
+        let mouse = mouse()
+            .map(|input| (input, Vec::new()))
             .skip(whitespace())
+            .then(press.clone())
+            .skip(optional(whitespace()))
+            .then(optional(between('{', '}', release.clone())));
+        // One or more keys. All keys but the last form the prefix of a key sequence.
+        let key = many::<_, Vec<platform::Key>>(param::<platform::Key>().skip(whitespace()))
+            .map(|mut keys| {
+                let last = keys.pop().expect("there is at least one key");
+                (Input::Key(last), keys)
+            })
             .then(press)
             .skip(optional(whitespace()))
             .then(optional(between('{', '}', release)));
 
         character
+            .or(mouse)
             .or(key)
-            .map(move |((input, press), release)| KeyMapping {
+            .map(move |(((input, prefix), press), release)| KeyMapping {
                 input,
+                prefix,
                 press,
                 release,
                 modes: modes.clone(),
@@ -520,7 +824,16 @@ impl CommandLine {
 
     pub fn set_cwd(&mut self, path: &Path) {
         let exts: Vec<_> = self.extensions.iter().map(|s| s.as_str()).collect();
-        self.autocomplete = Autocomplete::new(CommandCompleter::new(path, exts.as_slice()));
+        let mut completer = CommandCompleter::new(path, exts.as_slice());
+
+        completer.colors = std::mem::take(&mut self.autocomplete.completer_mut().colors);
+        self.autocomplete = Autocomplete::new(completer);
+    }
+
+    /// Set the palette colors offered as completions.
+    pub fn set_colors(&mut self, colors: &[Rgba8]) {
+        self.autocomplete.completer_mut().colors =
+            colors.iter().map(|c| Rgb8::from(*c).to_string()).collect();
     }
 
     pub fn parse(&self, input: &str) -> Result<Command, Error> {
@@ -736,8 +1049,47 @@ impl Default for Commands {
                 p.value(Command::ForceQuitAll)
             })
             .command("export", "Export view", |p| {
-                p.then(optional(scale().skip(whitespace())).then(path()))
-                    .map(|(_, (scale, path))| Command::Export(scale, path))
+                let raw_format = |label| {
+                    string("rgb565")
+                        .value(RawFormat::Rgb565)
+                        .or(string("rgba8").value(RawFormat::Rgba8))
+                        .or(string("indexed").value(RawFormat::Indexed))
+                        .label(label)
+                };
+                let format = whitespace()
+                    .then(string("--format"))
+                    .then(whitespace())
+                    .then(raw_format("rgb565/rgba8/indexed"))
+                    .map(|(_, f)| f);
+                let palette = whitespace()
+                    .then(string("--palette"))
+                    .then(whitespace())
+                    .then(raw_format("rgb565/rgba8"))
+                    .try_map(|(_, f)| match f {
+                        RawFormat::Indexed => Err("palette can't be exported as indexed colors"),
+                        f => Ok(f),
+                    });
+                let raw = peek(string("raw").then(whitespace()))
+                    .then(path())
+                    .then(optional(format))
+                    .then(optional(palette))
+                    .map(|(((_, path), format), palette)| {
+                        Command::ExportRaw(path, format.unwrap_or(RawFormat::Rgba8), palette)
+                    });
+
+                let filter = string("--filter")
+                    .then(whitespace())
+                    .then(token().label("scale2x/scale3x"))
+                    .try_map(|(_, f)| f.parse::<ScaleFilter>())
+                    .skip(whitespace());
+
+                p.then(
+                    raw.or(optional(scale().skip(whitespace()))
+                        .then(optional(filter))
+                        .then(path())
+                        .map(|((scale, filter), path)| Command::Export(scale, filter, path))),
+                )
+                .map(|(_, cmd)| cmd)
             })
             .command("wq", "Write & quit view", |p| p.value(Command::WriteQuit))
             .command("x", "Write & quit view", |p| p.value(Command::WriteQuit))
@@ -745,12 +1097,44 @@ impl Default for Commands {
                 p.then(optional(path()))
                     .map(|(_, path)| Command::Write(path))
             })
+            .command("record/start", "Start recording a timelapse", |p| {
+                p.then(optional(path()))
+                    .map(|(_, path)| Command::RecordStart(path))
+            })
+            .command("record/stop", "Stop recording the timelapse", |p| {
+                p.value(Command::RecordStop)
+            })
+            .command("collab/host", "Host a collaborative editing session", |p| {
+                p.then(optional(natural::<u16>().label("<port>")))
+                    .map(|(_, port)| Command::CollabHost(port))
+            })
+            .command("collab/join", "Join a collaborative editing session", |p| {
+                p.then(token().label("<address>"))
+                    .skip(whitespace())
+                    .then(token().label("<token>"))
+                    .map(|((_, addr), token)| Command::CollabJoin(addr, token))
+            })
+            .command("collab/leave", "Leave the collaborative session", |p| {
+                p.value(Command::CollabLeave)
+            })
             .command("w/frames", "Write view as individual frames", |p| {
                 p.then(optional(path()))
                     .map(|(_, dir)| Command::WriteFrames(dir))
             })
             .command("e", "Edit path(s)", |p| {
-                p.then(paths()).map(|(_, paths)| Command::Edit(paths))
+                // Raw images need a size, eg. `:e data.rgba --size 64x64`.
+                let raw = peek(path().skip(whitespace()).skip(string("--size")))
+                    .skip(whitespace())
+                    .then(
+                        natural::<u32>()
+                            .skip(symbol('x'))
+                            .then(natural::<u32>())
+                            .label("<width>x<height>"),
+                    )
+                    .map(|(path, (w, h))| Command::EditRaw(path, w, h));
+
+                p.then(raw.or(paths().map(Command::Edit)))
+                    .map(|(_, cmd)| cmd)
             })
             .command("e/frames", "Edit frames as view", |p| {
                 p.then(paths()).map(|(_, paths)| Command::EditFrames(paths))
@@ -758,6 +1142,14 @@ impl Default for Commands {
             .command("help", "Display help", |p| {
                 p.value(Command::Mode(Mode::Help))
             })
+            .command("search", "Search commands and key bindings", |p| {
+                p.value(Command::Mode(Mode::Search))
+            })
+            .command(
+                "history",
+                "Search command history, and re-run a command",
+                |p| p.value(Command::History),
+            )
             .command("set", "Set setting to value", |p| {
                 p.then(setting())
                     .skip(optional(whitespace()))
@@ -769,6 +1161,16 @@ impl Default for Commands {
                     ))
                     .map(|((_, k), v)| Command::Set(k, v.unwrap_or(Value::Bool(true))))
             })
+            .command(
+                "set/save",
+                "Save changed settings and key mappings, to be loaded on startup",
+                |p| p.value(Command::SettingsSave),
+            )
+            .command(
+                "rxrc/trust",
+                "Trust the `.rxrc` script that wasn't sourced, and source it",
+                |p| p.value(Command::RxrcTrust),
+            )
             .command("unset", "Set setting to `off`", |p| {
                 p.then(setting())
                     .map(|(_, k)| Command::Set(k, Value::Bool(false)))
@@ -783,6 +1185,14 @@ impl Default for Commands {
                 p.then(optional(natural::<usize>().label("<n>")))
                     .map(|(_, n)| Command::Slice(n))
             })
+            .command(
+                "trim",
+                "Crop view frames to their non-transparent pixels, with optional <padding>",
+                |p| {
+                    p.then(optional(natural::<u32>().label("<padding>")))
+                        .map(|(_, n)| Command::Trim(n.unwrap_or(0)))
+                },
+            )
             .command(
                 "source",
                 "Source an rx script (eg. palette or config)",
@@ -804,8 +1214,9 @@ impl Default for Commands {
                         .or(symbol('+')
                             .value(Command::Zoom(Op::Incr))
                             .or(symbol('-').value(Command::Zoom(Op::Decr)))
+                            .or(string("fit").value(Command::ZoomFit))
                             .or(fail("couldn't parse zoom parameter")))
-                        .label("+/-"),
+                        .label("+/-/fit"),
                 )
                 .map(|(_, cmd)| cmd)
             })
@@ -844,6 +1255,9 @@ impl Default for Commands {
             .command("flood", "Switch to flood fill tool", |p| {
                 p.value(Command::Tool(Tool::FloodFill))
             })
+            .command("measure", "Switch to measure tool", |p| {
+                p.value(Command::Tool(Tool::Measure(None)))
+            })
             .command("mode", "Set session mode, eg. `visual` or `normal`", |p| {
                 p.then(param::<Mode>()).map(|(_, m)| Command::Mode(m))
             })
@@ -865,6 +1279,75 @@ impl Default for Commands {
             .command("v/center", "Center the active view", |p| {
                 p.value(Command::ViewCenter)
             })
+            .command("v/split", "Move a range of frames into a new view", |p| {
+                p.then(tuple::<usize>(
+                    natural().label("<from>"),
+                    natural().label("<to>"),
+                ))
+                .map(|(_, (from, to))| Command::ViewSplit(from, to, false))
+            })
+            .command(
+                "v/split/copy",
+                "Copy a range of frames into a new view",
+                |p| {
+                    p.then(tuple::<usize>(
+                        natural().label("<from>"),
+                        natural().label("<to>"),
+                    ))
+                    .map(|(_, (from, to))| Command::ViewSplit(from, to, true))
+                },
+            )
+            .command(
+                "reference/anim",
+                "Overlay the animation of a view on the active view, or clear it",
+                |p| {
+                    p.then(optional(natural::<u16>().label("<view>")))
+                        .map(|(_, id)| Command::ReferenceAnim(id.map(ViewId::from)))
+                },
+            )
+            .command(
+                "compare",
+                "Overlay a view on the active view to compare them, or toggle the overlay",
+                |p| {
+                    p.then(optional(natural::<u16>().label("<view>")))
+                        .map(|(_, id)| Command::Compare(id.map(ViewId::from)))
+                },
+            )
+            .command(
+                "v/append",
+                "Append the frames of a view to the active view",
+                |p| {
+                    p.then(natural::<u16>().label("<view>"))
+                        .map(|(_, id)| Command::ViewAppend(ViewId::from(id)))
+                },
+            )
+            .command(
+                "v/mirror",
+                "Mirror the active view in a new view, to edit it at another zoom level",
+                |p| p.value(Command::ViewMirror),
+            )
+            .command(
+                "ws/new",
+                "Create a workspace with a blank view, and switch to it",
+                |p| {
+                    p.then(workspace_name())
+                        .map(|(_, name)| Command::WorkspaceNew(name))
+                },
+            )
+            .command("ws/switch", "Switch to a workspace", |p| {
+                p.then(workspace_name())
+                    .map(|(_, name)| Command::WorkspaceSwitch(name))
+            })
+            .command("ws/move", "Move the active view to a workspace", |p| {
+                p.then(workspace_name())
+                    .map(|(_, name)| Command::WorkspaceMove(name))
+            })
+            .command("ws/next", "Switch to the next workspace", |p| {
+                p.value(Command::WorkspaceNext)
+            })
+            .command("ws/prev", "Switch to the previous workspace", |p| {
+                p.value(Command::WorkspacePrev)
+            })
             .command("v/clear", "Clear the active view", |p| {
                 p.value(Command::Fill(Some(Rgba8::TRANSPARENT)))
             })
@@ -875,6 +1358,22 @@ impl Default for Commands {
                 p.then(tuple::<i32>(integer().label("<x>"), integer().label("<y>")))
                     .map(|(_, (x, y))| Command::Pan(x, y))
             })
+            .command(
+                "cursor/move",
+                "Move the cursor by a number of pixels of the active view",
+                |p| {
+                    p.then(tuple::<i32>(integer().label("<x>"), integer().label("<y>")))
+                        .map(|(_, (x, y))| Command::CursorMove(x, y))
+                },
+            )
+            .command(
+                "goto",
+                "Center the workspace on a pixel of the active view, and move the cursor to it",
+                |p| {
+                    p.then(tuple::<i32>(integer().label("<x>"), integer().label("<y>")))
+                        .map(|(_, (x, y))| Command::Goto(x, y))
+                },
+            )
             .command("map", "Map keys to a command in all modes", |p| {
                 p.then(KeyMapping::parser(&[
                     Mode::Normal,
@@ -901,6 +1400,28 @@ impl Default for Commands {
             .command("map/clear!", "Clear all key mappings", |p| {
                 p.value(Command::MapClear)
             })
+            .command("color/mix", "Mix two colors and echo the result", |p| {
+                p.then(tuple::<Rgba8>(
+                    color().label("<from>"),
+                    color().label("<to>"),
+                ))
+                .skip(whitespace())
+                .then(rational::<f32>().label("<amount>"))
+                .then(optional(whitespace().then(string("fg"))))
+                .map(|(((_, (a, b)), t), fg)| Command::ColorMix(a, b, t, fg.is_some()))
+            })
+            .command(
+                "color/shift",
+                "Shift a color's hue, saturation or lightness and echo the result",
+                |p| {
+                    p.then(color())
+                        .then(many::<_, Vec<Shift>>(
+                            whitespace().then(Shift::parser()).map(|(_, s)| s),
+                        ))
+                        .then(optional(whitespace().then(string("fg"))))
+                        .map(|(((_, c), shifts), fg)| Command::ColorShift(c, shifts, fg.is_some()))
+                },
+            )
             .command("p/add", "Add a color to the palette", |p| {
                 p.then(color()).map(|(_, rgba)| Command::PaletteAdd(rgba))
             })
@@ -918,17 +1439,208 @@ impl Default for Commands {
             })
             .command(
                 "p/sample",
-                "Sample palette colors from the active view",
-                |p| p.value(Command::PaletteSample),
+                "Sample palette colors from the active view, or from an image file",
+                |p| {
+                    let count = whitespace()
+                        .then(natural::<usize>().label("<count>"))
+                        .map(|(_, n)| n);
+
+                    p.then(optional(path().then(optional(count))))
+                        .map(|(_, file)| match file {
+                            Some((path, n)) => Command::PaletteSampleFile(path, n),
+                            None => Command::PaletteSample,
+                        })
+                },
             )
             .command("p/sort", "Sort the palette colors", |p| {
                 p.value(Command::PaletteSort)
             })
+            .command("p/move", "Move a palette color to another index", |p| {
+                p.then(tuple::<usize>(
+                    natural().label("<from>"),
+                    natural().label("<to>"),
+                ))
+                .map(|(_, (from, to))| Command::PaletteMove(from, to))
+            })
             .command("p/write", "Write the color palette to a file", |p| {
-                p.then(path()).map(|(_, path)| Command::PaletteWrite(path))
+                p.then(optional(path()))
+                    .map(|(_, path)| Command::PaletteWrite(path))
+            })
+            .command("palette/new", "Create a palette and switch to it", |p| {
+                p.then(palette_name())
+                    .map(|(_, name)| Command::PaletteNew(name))
+            })
+            .command("palette/switch", "Switch to a named palette", |p| {
+                p.then(palette_name())
+                    .map(|(_, name)| Command::PaletteSwitch(name))
+            })
+            .command("palette/next", "Switch to the next palette", |p| {
+                p.value(Command::PaletteNext)
+            })
+            .command("palette/prev", "Switch to the previous palette", |p| {
+                p.value(Command::PalettePrev)
+            })
+            .command("recolor", "Recolor the active view using a mapping file", |p| {
+                p.then(path()).map(|(_, path)| Command::Recolor(path))
+            })
+            .command(
+                "shift",
+                "Offset the view frames by <dx> <dy>, wrapping pixels around the edges",
+                |p| {
+                    p.then(tuple::<i32>(
+                        integer().label("<dx>"),
+                        integer().label("<dy>"),
+                    ))
+                    .map(|(_, (x, y))| Command::Shift(x, y))
+                },
+            )
+            .command(
+                "align",
+                "Move the content of the current frame to its center or to an edge, eg. `bottom`",
+                |p| {
+                    let align = string("center")
+                        .value(Align::Center)
+                        .or(string("top").value(Align::Top))
+                        .or(string("bottom").value(Align::Bottom))
+                        .or(string("left").value(Align::Left))
+                        .or(string("right").value(Align::Right))
+                        .label("center/top/bottom/left/right");
+
+                    p.then(align).map(|(_, a)| Command::Align(a))
+                },
+            )
+            .command(
+                "lut/set",
+                "Set the active view as the lookup texture",
+                |p| p.value(Command::LutSet),
+            )
+            .command("lut/clear", "Clear the lookup texture", |p| {
+                p.value(Command::LutClear)
+            })
+            .command(
+                "lut/ir",
+                "Generate the IR of the active view, using the lookup texture",
+                |p| p.value(Command::LutIr),
+            )
+            .command(
+                "lut/apply",
+                "Resolve the active view's IR through the lookup texture",
+                |p| p.value(Command::LutApply),
+            )
+            .command(
+                "colorkey",
+                "Make all pixels of the given color transparent, eg. a solid background",
+                |p| p.then(color()).map(|(_, c)| Command::ColorKey(c)),
+            )
+            .command(
+                "filter",
+                "Toggle a fragment shader to display views through, eg. `crt.frag`",
+                |p| p.then(optional(path())).map(|(_, p)| Command::Filter(p)),
+            )
+            .command(
+                "preview/palette",
+                "Display colors as they would look on a hardware palette, eg. `nes`",
+                |p| {
+                    p.then(token().label("gb/nes/c64/off"))
+                        .try_map(|(_, p)| match p.as_str() {
+                            "off" => Ok(Command::PreviewPalette(None)),
+                            other => other.parse().map(|p| Command::PreviewPalette(Some(p))),
+                        })
+                },
+            )
+            .command(
+                "preview/colorblind",
+                "Simulate a color vision deficiency, eg. `deuteranopia`",
+                |p| {
+                    p.then(word().label("deuteranopia/protanopia/tritanopia/off"))
+                        .try_map(|(_, c)| match c.as_str() {
+                            "off" => Ok(Command::PreviewColorblind(None)),
+                            other => other.parse().map(|c| Command::PreviewColorblind(Some(c))),
+                        })
+                },
+            )
+            .command(
+                "preview/values",
+                "Display values only, posterized into <n> bands",
+                |p| {
+                    p.then(token().label("<n>/off"))
+                        .try_map(|(_, n)| match n.as_str() {
+                            "off" => Ok(Command::PreviewValues(None)),
+                            other => match other.parse::<u32>() {
+                                Ok(n) if (2..=MAX_VALUE_BANDS).contains(&n) => {
+                                    Ok(Command::PreviewValues(Some(n)))
+                                }
+                                _ => Err(format!(
+                                    "invalid band count {:?}, must be between 2 and {}, or 'off'",
+                                    other, MAX_VALUE_BANDS
+                                )),
+                            },
+                        })
+                },
+            )
+            .command(
+                "plugin",
+                "Run a plugin on the selection or view, eg. `blur 2`",
+                |p| {
+                    p.then(token().label("<plugin>"))
+                        .skip(optional(whitespace()))
+                        .then(any::<_, Vec<String>>(token().skip(optional(whitespace()))))
+                        .map(|((_, name), args)| Command::Plugin(name, args))
+                },
+            )
+            .command("plugin/list", "List available plugins", |p| {
+                p.value(Command::PluginList)
             })
             .command("undo", "Undo the last edit", |p| p.value(Command::Undo))
+            .command(
+                "back",
+                "Restore the active view to its state some time ago, eg. `5m`",
+                |p| {
+                    let unit = character()
+                        .try_map(|c| match c {
+                            's' => Ok(1),
+                            'm' => Ok(60),
+                            'h' => Ok(60 * 60),
+                            _ => Err("unit must be one of `s`, `m` or `h`"),
+                        })
+                        .label("s/m/h");
+
+                    p.then(natural::<u64>().label("<duration>"))
+                        .then(unit)
+                        .map(|((_, n), unit)| Command::Back(time::Duration::from_secs(n * unit)))
+                },
+            )
+            .command(
+                "checkpoint",
+                "Save or restore a named state of the active view, eg. `save before-shading`",
+                |p| {
+                    let save = string("save")
+                        .value(true)
+                        .or(string("restore").value(false))
+                        .label("save/restore");
+
+                    p.then(save).skip(whitespace()).then(checkpoint_name()).map(
+                        |((_, save), name)| {
+                            if save {
+                                Command::CheckpointSave(name)
+                            } else {
+                                Command::CheckpointRestore(name)
+                            }
+                        },
+                    )
+                },
+            )
             .command("redo", "Redo the last edit", |p| p.value(Command::Redo))
+            .command(
+                "history/clear",
+                "Clear the undo history of the active view, to free memory",
+                |p| p.value(Command::HistoryClear),
+            )
+            .command(
+                "unerase",
+                "Restore pixels removed by the last erase stroke",
+                |p| p.value(Command::Unerase),
+            )
             .command("f/add", "Add a blank frame to the active view", |p| {
                 p.value(Command::FrameAdd)
             })
@@ -954,21 +1666,69 @@ impl Default for Commands {
                 ))
                 .map(|(_, (w, h))| Command::FrameResize(w, h))
             })
+            .command("pair", "Mirror strokes between two frames", |p| {
+                p.then(tuple::<usize>(
+                    natural().label("<frame>"),
+                    natural().label("<frame>"),
+                ))
+                .map(|(_, (a, b))| Command::FramePair(a, b))
+            })
+            .command("pair/clear", "Remove all frame pairs", |p| {
+                p.value(Command::FrameUnpair)
+            })
+            .command("guide", "Add a horizontal or vertical guide", |p| {
+                p.then(word().label("h/v"))
+                    .skip(whitespace())
+                    .then(natural::<u32>().label("<offset>"))
+                    .try_map(|((_, axis), n)| match axis.as_str() {
+                        "h" => Ok(Command::Guide(Axis::Horizontal, n)),
+                        "v" => Ok(Command::Guide(Axis::Vertical, n)),
+                        _ => Err(format!("unknown axis {:?}, must be 'h' or 'v'", axis)),
+                    })
+            })
+            .command("guide/clear", "Remove all guides", |p| {
+                p.value(Command::GuideClear)
+            })
             .command("tool", "Switch tool", |p| {
                 p.then(word().label("pan/brush/sampler/.."))
                     .try_map(|(_, t)| match t.as_str() {
                         "pan" => Ok(Command::Tool(Tool::Pan(PanState::default()))),
                         "brush" => Ok(Command::Tool(Tool::Brush)),
                         "sampler" => Ok(Command::Tool(Tool::Sampler)),
+                        "measure" => Ok(Command::Tool(Tool::Measure(None))),
                         _ => Err(format!("unknown tool {:?}", t)),
                     })
             })
             .command("tool/prev", "Switch to previous tool", |p| {
                 p.value(Command::ToolPrev)
             })
+            .command("theme", "Switch UI theme", |p| {
+                p.then(word().label("dark/light/auto"))
+                    .try_map(|(_, t)| match t.as_str() {
+                        "dark" => Ok(Command::Theme(ThemeChoice::Dark)),
+                        "light" => Ok(Command::Theme(ThemeChoice::Light)),
+                        "auto" => Ok(Command::Theme(ThemeChoice::Auto)),
+                        _ => Err(format!("unknown theme {:?}", t)),
+                    })
+            })
+            .command(
+                "stats",
+                "Show color usage of the active view or selection",
+                |p| p.value(Command::Stats),
+            )
             .command("swap", "Swap foreground and background colors", |p| {
                 p.value(Command::SwapColors)
             })
+            .command(
+                "color/prev",
+                "Cycle back through recent foreground colors",
+                |p| p.value(Command::ColorPrev),
+            )
+            .command(
+                "color/next",
+                "Cycle forward through recent foreground colors",
+                |p| p.value(Command::ColorNext),
+            )
             .command("reset!", "Reset all settings to defaults", |p| {
                 p.value(Command::Reset)
             })
@@ -1015,6 +1775,69 @@ impl Default for Commands {
                         _ => Err(format!("unknown axis {:?}, must be 'x' or 'y'", t)),
                     })
             })
+            .command("selection/rotate", "Rotate selection", |p| {
+                p.then(rational::<f32>().label("<degrees>"))
+                    .map(|(_, deg)| Command::SelectionRotate(deg))
+            })
+            .command("selection/grow", "Grow selection by <n> pixels", |p| {
+                p.then(natural::<u32>().label("<n>"))
+                    .map(|(_, n)| Command::SelectionGrow(n))
+            })
+            .command("selection/shrink", "Shrink selection by <n> pixels", |p| {
+                p.then(natural::<u32>().label("<n>"))
+                    .map(|(_, n)| Command::SelectionShrink(n))
+            })
+            .command("selection/invert", "Invert selection", |p| {
+                p.value(Command::SelectionInvert)
+            })
+            .command("select/color", "Select all pixels of a color", |p| {
+                p.then(optional(color()))
+                    .map(|(_, rgba)| Command::SelectColor(rgba))
+            })
+            .command("gen/noise", "Fill selection with fg/bg noise", |p| {
+                p.then(rational::<f32>().label("<density>"))
+                    .then(optional(
+                        whitespace()
+                            .then(natural::<u64>().label("<seed>"))
+                            .map(|(_, seed)| seed),
+                    ))
+                    .try_map(|((_, density), seed)| {
+                        if (0. ..=1.).contains(&density) {
+                            Ok(Command::Generate(Pattern::Noise(density, seed)))
+                        } else {
+                            Err(format!("density must be between 0 and 1, got {}", density))
+                        }
+                    })
+            })
+            .command(
+                "gen/checker",
+                "Fill selection with a fg/bg checkerboard",
+                |p| {
+                    p.then(natural::<u32>().label("<size>")).try_map(|(_, n)| {
+                        if n > 0 {
+                            Ok(Command::Generate(Pattern::Checker(n)))
+                        } else {
+                            Err(String::from("size must be greater than zero"))
+                        }
+                    })
+                },
+            )
+            .command("gen/bayer", "Dither selection from fg to bg", |p| {
+                p.then(natural::<u32>().label("<size>")).try_map(|(_, n)| {
+                    if n.is_power_of_two() && (2..=MAX_BAYER_SIZE).contains(&n) {
+                        Ok(Command::Generate(Pattern::Bayer(n)))
+                    } else {
+                        Err(format!(
+                            "size must be a power of two between 2 and {}, got {}",
+                            MAX_BAYER_SIZE, n
+                        ))
+                    }
+                })
+            })
+            .command("preview/rotate", "Preview rotated selection", |p| {
+                p.then(rational::<f32>().label("<degrees>"))
+                    .map(|(_, deg)| Command::PreviewRotate(deg))
+            })
             .command("paint/color", "Paint color", |p| {
                 p.then(color())
                     .skip(whitespace())
@@ -1059,20 +1882,80 @@ impl Default for Commands {
 #[derive(Debug)]
 pub struct CommandCompleter {
     file_completer: FileCompleter,
+    /// Command names.
+    commands: Vec<&'static str>,
+    /// Setting names.
+    settings: Vec<String>,
+    /// Palette colors, eg. `#ff0000`.
+    colors: Vec<String>,
 }
 
 impl CommandCompleter {
+    /// Commands whose first argument is a setting name.
+    const SETTING_COMMANDS: &'static [&'static str] = &["set", "unset", "toggle"];
+
     fn new<P: AsRef<Path>>(cwd: P, exts: &[&str]) -> Self {
+        let mut settings: Vec<_> = Settings::default().names().map(str::to_owned).collect();
+        settings.sort();
+
         Self {
             file_completer: FileCompleter::new(cwd, exts),
+            commands: Commands::default()
+                .iter()
+                .map(|(name, _, _)| *name)
+                .filter(|name| *name != "#")
+                .collect(),
+            settings,
+            colors: Vec::new(),
+        }
+    }
+
+    /// Fuzzy completion of command names, setting names and palette colors.
+    /// Returns the position of the word being completed, and the candidates,
+    /// which replace the word.
+    fn complete_fuzzy(&self, input: &str) -> Option<(usize, Vec<String>)> {
+        let start = input.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word = &input[start..];
+        let line = input.strip_prefix(':')?;
+
+        if word.starts_with('#') {
+            let start = start.max(1);
+            let word = &input[start..];
+
+            return Some((
+                start,
+                fuzzy_filter(word, self.colors.iter().map(|s| s.as_str())),
+            ));
+        }
+        if !line.contains(char::is_whitespace) {
+            return Some((1, fuzzy_filter(line, self.commands.iter().cloned())));
         }
+        let cmd = line.split_whitespace().next()?;
+
+        if Self::SETTING_COMMANDS.contains(&cmd)
+            && input[..start].trim_end() == &input[..cmd.len() + 1]
+        {
+            return Some((
+                start,
+                fuzzy_filter(word, self.settings.iter().map(|s| s.as_str())),
+            ));
+        }
+        None
     }
 }
 
 impl autocomplete::Completer for CommandCompleter {
     type Options = ();
 
+    fn start(&self, input: &str) -> usize {
+        self.complete_fuzzy(input)
+            .map_or(input.len(), |(start, _)| start)
+    }
+
     fn complete(&self, input: &str, _opts: ()) -> Vec<String> {
+        if let Some((_, candidates)) = self.complete_fuzzy(input) {
+            return candidates;
+        }
         let p = Commands::default().parser();
 
         match p.parse(input) {
@@ -1085,9 +1968,18 @@ impl autocomplete::Completer for CommandCompleter {
                 Command::Source(path) | Command::Write(path) => {
                     self.complete_path(path.as_ref(), input, Default::default())
                 }
+                Command::Recolor(path) => {
+                    self.complete_path(Some(&path), input, Default::default())
+                }
+                Command::Filter(path) => {
+                    self.complete_path(path.as_ref(), input, Default::default())
+                }
                 Command::Edit(paths) | Command::EditFrames(paths) => {
                     self.complete_path(paths.last(), input, Default::default())
                 }
+                Command::EditRaw(path, _, _) => {
+                    self.complete_path(Some(&path), input, Default::default())
+                }
                 _ => vec![],
             },
             Err(_) => vec![],
@@ -1119,6 +2011,7 @@ impl CommandCompleter {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::pattern::Dither;
     use std::{fs, fs::File};
 
     #[test]
@@ -1167,6 +2060,30 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_command_completer_fuzzy() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut cc = CommandCompleter::new(tmp.path(), &["png"]);
+        cc.colors = vec!["#ff0000".to_owned(), "#00ff00".to_owned()];
+
+        let mut auto = Autocomplete::new(cc);
+
+        assert_eq!(
+            auto.next(":v/spc", 6),
+            Some(("v/split/copy".to_owned(), 1..6))
+        );
+        auto.invalidate();
+        assert_eq!(
+            auto.next(":set grid/spc", 13),
+            Some(("grid/spacing".to_owned(), 5..13))
+        );
+        auto.invalidate();
+        assert_eq!(
+            auto.next(":p/add #0f", 10),
+            Some(("#00ff00".to_owned(), 7..10))
+        );
+    }
+
     #[test]
     fn test_command_line() {
         let tmp = tempfile::tempdir().unwrap();
@@ -1374,6 +2291,8 @@ mod test {
             p.parse(":set foo"),
             Ok((Command::Set("foo".to_owned(), Value::Bool(true)), ""))
         );
+        assert_eq!(p.parse(":set/save"), Ok((Command::SettingsSave, "")));
+        assert_eq!(p.parse(":rxrc/trust"), Ok((Command::RxrcTrust, "")));
 
         assert_eq!(
             param::<platform::Key>()
@@ -1403,6 +2322,306 @@ mod test {
         assert!(p.parse(":zoom -").is_ok());
         assert!(p.parse(":zoom 3.0").is_ok());
         assert!(p.parse(":zoom -1.0").is_err());
+        assert_eq!(p.parse(":zoom fit"), Ok((Command::ZoomFit, "")));
+        assert!(p.parse(":zoom fat").is_err());
+    }
+
+    #[test]
+    fn test_brush_commands() {
+        let p = Commands::default().line_parser();
+
+        assert_eq!(
+            p.parse(":brush/set dither bayer 8"),
+            Ok((Command::BrushSet(BrushMode::Dither(Dither::Bayer(8))), ""))
+        );
+        assert_eq!(
+            p.parse(":brush/unset dither"),
+            Ok((
+                Command::BrushUnset(BrushMode::Dither(Dither::Checker(1))),
+                ""
+            ))
+        );
+        assert_eq!(
+            String::from(Command::BrushSet(BrushMode::Dither(Dither::Checker(2)))),
+            "brush/set dither checker 2"
+        );
+        assert!(p.parse(":brush/set dither bayer 16").is_err());
+        assert!(p.parse(":brush/set dither checker 0").is_err());
+    }
+
+    #[test]
+    fn test_cursor_commands() {
+        let p = Commands::default().line_parser();
+
+        assert_eq!(p.parse(":goto 12 34"), Ok((Command::Goto(12, 34), "")));
+        assert_eq!(
+            p.parse(":cursor/move -1 0"),
+            Ok((Command::CursorMove(-1, 0), ""))
+        );
+        assert_eq!(String::from(Command::Goto(12, 34)), "goto 12 34");
+        assert!(p.parse(":goto 12").is_err());
+    }
+
+    #[test]
+    fn test_colorkey_command() {
+        let p = Commands::default().line_parser();
+
+        assert_eq!(
+            p.parse(":colorkey #ff00ff"),
+            Ok((Command::ColorKey(Rgba8::new(0xff, 0, 0xff, 0xff)), ""))
+        );
+        assert!(p.parse(":colorkey").is_err());
+    }
+
+    #[test]
+    fn test_filter_command() {
+        let p = Commands::default().line_parser();
+
+        assert_eq!(
+            p.parse(":filter crt.frag"),
+            Ok((Command::Filter(Some(String::from("crt.frag"))), ""))
+        );
+        assert_eq!(p.parse(":filter"), Ok((Command::Filter(None), "")));
+    }
+
+    #[test]
+    fn test_preview_palette_command() {
+        let p = Commands::default().line_parser();
+
+        assert_eq!(
+            p.parse(":preview/palette nes"),
+            Ok((Command::PreviewPalette(Some(HardwarePalette::Nes)), ""))
+        );
+        assert_eq!(
+            p.parse(":preview/palette c64"),
+            Ok((Command::PreviewPalette(Some(HardwarePalette::C64)), ""))
+        );
+        assert_eq!(
+            p.parse(":preview/palette off"),
+            Ok((Command::PreviewPalette(None), ""))
+        );
+        assert!(p.parse(":preview/palette snes").is_err());
+    }
+
+    #[test]
+    fn test_preview_colorblind_command() {
+        let p = Commands::default().line_parser();
+
+        assert_eq!(
+            p.parse(":preview/colorblind deuteranopia"),
+            Ok((
+                Command::PreviewColorblind(Some(Colorblindness::Deuteranopia)),
+                ""
+            ))
+        );
+        assert_eq!(
+            p.parse(":preview/colorblind off"),
+            Ok((Command::PreviewColorblind(None), ""))
+        );
+        assert!(p.parse(":preview/colorblind").is_err());
+    }
+
+    #[test]
+    fn test_preview_values_command() {
+        let p = Commands::default().line_parser();
+
+        assert_eq!(
+            p.parse(":preview/values 4"),
+            Ok((Command::PreviewValues(Some(4)), ""))
+        );
+        assert_eq!(
+            p.parse(":preview/values off"),
+            Ok((Command::PreviewValues(None), ""))
+        );
+        assert!(p.parse(":preview/values 1").is_err());
+        assert!(p.parse(":preview/values 17").is_err());
+    }
+
+    #[test]
+    fn test_plugin_command() {
+        let p = Commands::default().line_parser();
+
+        assert_eq!(
+            p.parse(":plugin blur"),
+            Ok((Command::Plugin(String::from("blur"), vec![]), ""))
+        );
+        assert_eq!(
+            p.parse(":plugin noise 0.5 #ff0000"),
+            Ok((
+                Command::Plugin(
+                    String::from("noise"),
+                    vec![String::from("0.5"), String::from("#ff0000")]
+                ),
+                ""
+            ))
+        );
+        assert_eq!(p.parse(":plugin/list"), Ok((Command::PluginList, "")));
+        assert!(p.parse(":plugin").is_err());
+    }
+
+    #[test]
+    fn test_palette_commands() {
+        let p = Commands::default().line_parser();
+
+        assert_eq!(
+            p.parse(":palette/new skin"),
+            Ok((Command::PaletteNew(String::from("skin")), ""))
+        );
+        assert_eq!(
+            p.parse(":palette/switch db16"),
+            Ok((Command::PaletteSwitch(String::from("db16")), ""))
+        );
+        assert_eq!(p.parse(":p/move 3 0"), Ok((Command::PaletteMove(3, 0), "")));
+        assert!(p.parse(":p/move 3").is_err());
+        assert_eq!(p.parse(":palette/next"), Ok((Command::PaletteNext, "")));
+        assert_eq!(p.parse(":palette/prev"), Ok((Command::PalettePrev, "")));
+        assert_eq!(p.parse(":p/write"), Ok((Command::PaletteWrite(None), "")));
+        assert_eq!(
+            p.parse(":p/write skin.rx"),
+            Ok((Command::PaletteWrite(Some(String::from("skin.rx"))), ""))
+        );
+        assert!(p.parse(":palette/new").is_err());
+        assert!(p.parse(":palette/new ../skin").is_err());
+        assert!(p.parse(":palette/switch .hidden").is_err());
+    }
+
+    #[test]
+    fn test_workspace_commands() {
+        let p = Commands::default().line_parser();
+
+        assert_eq!(
+            p.parse(":ws/new ui-icons"),
+            Ok((Command::WorkspaceNew(String::from("ui-icons")), ""))
+        );
+        assert_eq!(
+            p.parse(":ws/switch main"),
+            Ok((Command::WorkspaceSwitch(String::from("main")), ""))
+        );
+        assert_eq!(
+            p.parse(":ws/move ui-icons"),
+            Ok((Command::WorkspaceMove(String::from("ui-icons")), ""))
+        );
+        assert_eq!(p.parse(":ws/next"), Ok((Command::WorkspaceNext, "")));
+        assert_eq!(p.parse(":ws/prev"), Ok((Command::WorkspacePrev, "")));
+        assert!(p.parse(":ws/new").is_err());
+    }
+
+    #[test]
+    fn test_palette_sample_command() {
+        let p = Commands::default().line_parser();
+
+        assert_eq!(p.parse(":p/sample"), Ok((Command::PaletteSample, "")));
+        assert_eq!(
+            p.parse(":p/sample tiles.png"),
+            Ok((
+                Command::PaletteSampleFile(String::from("tiles.png"), None),
+                ""
+            ))
+        );
+        assert_eq!(
+            p.parse(":p/sample tiles.png 16"),
+            Ok((
+                Command::PaletteSampleFile(String::from("tiles.png"), Some(16)),
+                ""
+            ))
+        );
+        assert!(p.parse(":p/sample tiles.png sixteen").is_err());
+    }
+
+    #[test]
+    fn test_collab_commands() {
+        let p = Commands::default().line_parser();
+
+        assert_eq!(p.parse(":collab/host"), Ok((Command::CollabHost(None), "")));
+        assert_eq!(
+            p.parse(":collab/host 9000"),
+            Ok((Command::CollabHost(Some(9000)), ""))
+        );
+        assert_eq!(
+            p.parse(":collab/join 10.0.0.2:9000 8f3a1c"),
+            Ok((
+                Command::CollabJoin(String::from("10.0.0.2:9000"), String::from("8f3a1c")),
+                ""
+            ))
+        );
+        assert_eq!(p.parse(":collab/leave"), Ok((Command::CollabLeave, "")));
+        assert!(p.parse(":collab/join").is_err());
+        assert!(p.parse(":collab/join 10.0.0.2:9000").is_err());
+        assert!(p.parse(":collab/host 99999").is_err());
+
+        // Edits are exchanged as `paint/color` commands.
+        let cmd = Command::PaintColor(Rgba8::new(0xff, 0, 0, 0x80), 3, -1);
+        assert_eq!(
+            p.parse(&format!(":{}", String::from(cmd.clone()))),
+            Ok((cmd, ""))
+        );
+    }
+
+    #[test]
+    fn test_export_command() {
+        let p = Commands::default().line_parser();
+
+        assert_eq!(
+            p.parse(":export @2x out.png"),
+            Ok((Command::Export(Some(2), None, String::from("out.png")), ""))
+        );
+        assert_eq!(
+            p.parse(":export raw.png"),
+            Ok((Command::Export(None, None, String::from("raw.png")), ""))
+        );
+        assert_eq!(
+            p.parse(":export @3x --filter scale3x out.png"),
+            Ok((
+                Command::Export(Some(3), Some(ScaleFilter::Scale3x), String::from("out.png")),
+                ""
+            ))
+        );
+        assert_eq!(
+            p.parse(":export --filter scale2x out.png"),
+            Ok((
+                Command::Export(None, Some(ScaleFilter::Scale2x), String::from("out.png")),
+                ""
+            ))
+        );
+        assert!(p.parse(":export --filter hq4x out.png").is_err());
+        assert_eq!(
+            p.parse(":export raw out.bin"),
+            Ok((
+                Command::ExportRaw(String::from("out.bin"), RawFormat::Rgba8, None),
+                ""
+            ))
+        );
+        assert_eq!(
+            p.parse(":export raw out.bin --format indexed --palette rgb565"),
+            Ok((
+                Command::ExportRaw(
+                    String::from("out.bin"),
+                    RawFormat::Indexed,
+                    Some(RawFormat::Rgb565)
+                ),
+                ""
+            ))
+        );
+        assert!(p.parse(":export raw out.bin --format rgb888").is_err());
+        assert!(p.parse(":export raw out.bin --palette indexed").is_err());
+    }
+
+    #[test]
+    fn test_edit_command() {
+        let p = Commands::default().line_parser();
+
+        assert_eq!(
+            p.parse(":e a.png b.png"),
+            Ok((
+                Command::Edit(vec![String::from("a.png"), String::from("b.png")]),
+                ""
+            ))
+        );
+        assert_eq!(
+            p.parse(":e data.rgba --size 64x32"),
+            Ok((Command::EditRaw(String::from("data.rgba"), 64, 32), ""))
+        );
+        assert!(p.parse(":e data.rgba --size 64").is_err());
     }
 
     #[test]
@@ -1413,6 +2632,153 @@ mod test {
         p.parse(":v/fill #ff00ff").unwrap();
     }
 
+    #[test]
+    fn test_back_command() {
+        let p = Commands::default().line_parser();
+        let secs = |n| Command::Back(time::Duration::from_secs(n));
+
+        assert_eq!(p.parse(":back 5m"), Ok((secs(5 * 60), "")));
+        assert!(p.parse(":back 5").is_err());
+        assert_eq!(p.parse(":back 30s"), Ok((secs(30), "")));
+        assert_eq!(p.parse(":back 1h"), Ok((secs(60 * 60), "")));
+        assert!(p.parse(":back 5x").is_err());
+    }
+
+    #[test]
+    fn test_checkpoint_command() {
+        let p = Commands::default().line_parser();
+
+        assert_eq!(
+            p.parse(":checkpoint save before-shading"),
+            Ok((Command::CheckpointSave(String::from("before-shading")), ""))
+        );
+        assert_eq!(
+            p.parse(":checkpoint restore before-shading"),
+            Ok((
+                Command::CheckpointRestore(String::from("before-shading")),
+                ""
+            ))
+        );
+        assert!(p.parse(":checkpoint save").is_err());
+        assert!(p.parse(":checkpoint load before-shading").is_err());
+    }
+
+    #[test]
+    fn test_align_command() {
+        let p = Commands::default().line_parser();
+
+        assert_eq!(
+            p.parse(":align center"),
+            Ok((Command::Align(Align::Center), ""))
+        );
+        assert_eq!(
+            p.parse(":align bottom"),
+            Ok((Command::Align(Align::Bottom), ""))
+        );
+        assert!(p.parse(":align").is_err());
+        assert!(p.parse(":align middle").is_err());
+    }
+
+    #[test]
+    fn test_trim_command() {
+        let p = Commands::default().line_parser();
+
+        assert_eq!(p.parse(":trim"), Ok((Command::Trim(0), "")));
+        assert_eq!(p.parse(":trim 2"), Ok((Command::Trim(2), "")));
+        assert!(p.parse(":trim -1").is_err());
+    }
+
+    #[test]
+    fn test_compare_command() {
+        let p = Commands::default().line_parser();
+
+        assert_eq!(p.parse(":compare"), Ok((Command::Compare(None), "")));
+        assert_eq!(
+            p.parse(":compare 2"),
+            Ok((Command::Compare(Some(ViewId::from(2))), ""))
+        );
+        assert!(p.parse(":compare two").is_err());
+    }
+
+    #[test]
+    fn test_color_commands() {
+        let p = Commands::default().line_parser();
+        let (red, blue) = (Rgba8::new(0xff, 0, 0, 0xff), Rgba8::new(0, 0, 0xff, 0xff));
+
+        assert_eq!(
+            p.parse(":color/mix #ff0000 #0000ff 0.25"),
+            Ok((Command::ColorMix(red, blue, 0.25, false), ""))
+        );
+        assert_eq!(
+            p.parse(":color/shift #ff0000 +h10 -s5 fg"),
+            Ok((
+                Command::ColorShift(red, vec![Shift::Hue(10.), Shift::Saturation(-5.)], true),
+                ""
+            ))
+        );
+        assert!(p.parse(":color/shift #ff0000 h10").is_err());
+    }
+
+    #[test]
+    fn test_select_color_command() {
+        let p = Commands::default().line_parser();
+
+        assert_eq!(
+            p.parse(":select/color #aabbcc"),
+            Ok((
+                Command::SelectColor(Some(Rgba8::new(0xaa, 0xbb, 0xcc, 0xff))),
+                ""
+            ))
+        );
+        assert_eq!(
+            p.parse(":select/color"),
+            Ok((Command::SelectColor(None), ""))
+        );
+        assert!(p.parse(":select/color red").is_err());
+        assert_eq!(
+            p.parse(":selection/grow 2"),
+            Ok((Command::SelectionGrow(2), ""))
+        );
+        assert_eq!(
+            p.parse(":selection/invert"),
+            Ok((Command::SelectionInvert, ""))
+        );
+        assert!(p.parse(":selection/shrink -1").is_err());
+    }
+
+    #[test]
+    fn test_gen_commands() {
+        let p = Commands::default().line_parser();
+
+        assert_eq!(
+            p.parse(":gen/noise 0.25"),
+            Ok((Command::Generate(Pattern::Noise(0.25, None)), ""))
+        );
+        assert_eq!(
+            p.parse(":gen/noise 0.25 42"),
+            Ok((Command::Generate(Pattern::Noise(0.25, Some(42))), ""))
+        );
+        assert_eq!(
+            String::from(Command::Generate(Pattern::Noise(0.25, Some(42)))),
+            "gen/noise 0.25 42"
+        );
+        assert_eq!(
+            p.parse(":gen/checker 2"),
+            Ok((Command::Generate(Pattern::Checker(2)), ""))
+        );
+        assert_eq!(
+            p.parse(":gen/bayer 4"),
+            Ok((Command::Generate(Pattern::Bayer(4)), ""))
+        );
+        assert_eq!(
+            String::from(Command::Generate(Pattern::Bayer(4))),
+            "gen/bayer 4"
+        );
+        assert!(p.parse(":gen/noise 1.5").is_err());
+        assert!(p.parse(":gen/checker 0").is_err());
+        assert!(p.parse(":gen/bayer 3").is_err());
+    }
+
     #[test]
     fn test_unknown_command() {
         let p = Commands::default().line_parser();
@@ -1442,6 +2808,27 @@ mod test {
 
         let (_, rest) = p.parse("map <ctrl> :tool sampler {:tool/prev}").unwrap();
         assert_eq!(rest, "");
+
+        let ((_, km), rest) = p.parse("map <space> f r :f/resize 16 16").unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(km.input, Input::Key(platform::Key::R));
+        assert_eq!(km.prefix, vec![platform::Key::Space, platform::Key::F]);
+        assert_eq!(km.press, Command::FrameResize(16, 16));
+
+        let ((_, km), rest) = p
+            .parse("map <mouse-middle> :tool pan {:tool/prev}")
+            .unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(km.input, Input::Mouse(platform::MouseButton::Middle));
+        assert_eq!(km.release, Some(Command::ToolPrev));
+
+        let ((_, km), _) = p.parse("map <wheel-up> :brush/size +").unwrap();
+        assert_eq!(km.input, Input::Wheel(crate::session::Wheel::Up));
+        assert!(p.parse("map <mouse-left> :undo").is_err());
+
+        let ((_, km), _) = p.parse("map : :mode command").unwrap();
+        assert_eq!(km.input, Input::Key(platform::Key::Colon));
+        assert!(km.prefix.is_empty());
     }
 
     #[test]
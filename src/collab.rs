@@ -0,0 +1,587 @@
+//! Collaborative editing over the network.
+//!
+//! One session hosts, and other sessions join it over TCP. Edits to the active
+//! view are exchanged as `paint/color` commands, one per line, so the wire
+//! format is the same as the command line's. The host relays the edits it
+//! receives to its other peers. When two peers paint the same pixel, the last
+//! edit received wins.
+//!
+//! Guests must first send the token the host was started with, as a `token <token>`
+//! line, or they are disconnected. The host then sends its active view as a
+//! single `snapshot <width> <height> <frames> <pixels>` line, with the pixels
+//! hex-encoded, and sends it again whenever its active view changes size.
+use crate::gfx::Rgba8;
+use crate::view::{ViewExtent, ViewId};
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time;
+
+/// Port hosts listen on by default.
+pub const DEFAULT_PORT: u16 = 7767;
+
+/// How often the session checks for remote edits while collaborating.
+pub const POLL_INTERVAL: time::Duration = time::Duration::from_millis(16);
+
+/// Time a guest has to send the token after connecting.
+pub const HANDSHAKE_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
+/// Largest view width or height, in pixels, accepted in a snapshot.
+pub const MAX_SIZE: u32 = 4096;
+
+/// Longest line accepted from a peer, in bytes. Fits a snapshot of the largest view.
+pub const MAX_LINE_LENGTH: usize = MAX_SIZE as usize * MAX_SIZE as usize * 8 + 64;
+
+/// Identifies a connected peer.
+pub type PeerId = usize;
+
+/// A message from the network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// A peer connected.
+    Joined(PeerId),
+    /// A peer disconnected.
+    Left(PeerId),
+    /// A peer sent a line.
+    Line(PeerId, String),
+    /// A peer sent a snapshot of its active view, top row first.
+    Snapshot(PeerId, ViewExtent, Vec<Rgba8>),
+}
+
+/// Our role in the collaboration.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Role {
+    Host,
+    Guest,
+}
+
+/// A connected peer.
+#[derive(Debug)]
+struct Peer {
+    id: PeerId,
+    stream: TcpStream,
+    /// Buffers written to the peer by its writer thread.
+    writer: mpsc::Sender<Arc<[u8]>>,
+}
+
+/// A collaboration session, either hosted or joined.
+#[derive(Debug)]
+pub struct Collab {
+    pub role: Role,
+    /// Address we're listening on, or connected to.
+    pub addr: SocketAddr,
+
+    peers: Arc<Mutex<Vec<Peer>>>,
+    messages: mpsc::Receiver<Message>,
+    closed: Arc<AtomicBool>,
+
+    /// View the baseline pixels belong to.
+    view: Option<ViewId>,
+    /// Size of the baseline.
+    size: (u32, u32),
+    /// View pixels as last sent or received, top row first.
+    baseline: Vec<Rgba8>,
+}
+
+impl Collab {
+    /// Host a collaboration on the given address and port. Guests must send the
+    /// given token to join.
+    pub fn host(bind: &str, port: u16, token: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind((bind, port))?;
+        let addr = listener.local_addr()?;
+        let (tx, messages) = mpsc::channel();
+        let peers = Arc::new(Mutex::new(Vec::new()));
+        let closed = Arc::new(AtomicBool::new(false));
+
+        {
+            let peers = peers.clone();
+            let closed = closed.clone();
+            let token = token.to_owned();
+
+            thread::spawn(move || {
+                for (id, stream) in listener.incoming().enumerate() {
+                    if closed.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(_) => continue,
+                    };
+                    let (peers, tx, token) = (peers.clone(), tx.clone(), token.clone());
+
+                    // Wait for the token on a separate thread, so that a slow guest
+                    // doesn't hold up the others.
+                    thread::spawn(move || {
+                        if Self::handshake(&stream, &token).is_err() {
+                            stream.shutdown(Shutdown::Both).ok();
+                            return;
+                        }
+                        if Self::connect(id, stream, &peers, &tx).is_ok() {
+                            tx.send(Message::Joined(id)).ok();
+                        }
+                    });
+                }
+            });
+        }
+
+        Ok(Self::new(Role::Host, addr, peers, messages, closed))
+    }
+
+    /// Join a collaboration hosted at the given address, with the host's token.
+    pub fn join(addr: &str, token: &str) -> io::Result<Self> {
+        let mut stream = TcpStream::connect(addr)?;
+        let addr = stream.peer_addr()?;
+        let (tx, messages) = mpsc::channel();
+        let peers = Arc::new(Mutex::new(Vec::new()));
+        let closed = Arc::new(AtomicBool::new(false));
+
+        writeln!(stream, "token {}", token)?;
+
+        Self::connect(0, stream, &peers, &tx)?;
+
+        Ok(Self::new(Role::Guest, addr, peers, messages, closed))
+    }
+
+    /// Number of connected peers.
+    pub fn peers(&self) -> usize {
+        self.peers.lock().map_or(0, |p| p.len())
+    }
+
+    /// Get the messages received since the last call, without blocking.
+    pub fn receive(&self) -> Vec<Message> {
+        self.messages.try_iter().collect()
+    }
+
+    /// Send lines to all peers, except the given one. Peers which can't be
+    /// written to are disconnected.
+    pub fn send(&self, lines: &[String], except: Option<PeerId>) {
+        self.write(lines, |id| Some(id) != except);
+    }
+
+    /// Send a snapshot of a view, top row first, to a single peer, or to all
+    /// peers if none is given.
+    pub fn send_snapshot(&self, extent: ViewExtent, pixels: &[Rgba8], peer: Option<PeerId>) {
+        let line = self::encode_snapshot(extent, pixels);
+
+        self.write(&[line], |id| peer.map_or(true, |p| p == id));
+    }
+
+    /// Record a pixel, in view coordinates, as painted by a peer, so that it
+    /// isn't sent back as a local edit.
+    pub fn received(&mut self, color: Rgba8, x: i32, y: i32) {
+        let (w, h) = self.size;
+
+        if x >= 0 && y >= 0 && (x as u32) < w && (y as u32) < h {
+            let row = (h - y as u32 - 1) as usize;
+            self.baseline[row * w as usize + x as usize] = color;
+        }
+    }
+
+    /// Set the baseline to the given view pixels, eg. after a snapshot was received.
+    pub fn reset(&mut self, view: ViewId, width: u32, height: u32, pixels: &[Rgba8]) {
+        self.view = Some(view);
+        self.size = (width, height);
+        self.baseline = pixels.to_vec();
+    }
+
+    /// Compare the view pixels with the baseline, and return the pixels that
+    /// changed, in view coordinates. The baseline is then updated. If the view
+    /// or its size changed, the baseline is reset, and `None` is returned.
+    pub fn diff(
+        &mut self,
+        view: ViewId,
+        width: u32,
+        height: u32,
+        pixels: &[Rgba8],
+    ) -> Option<Vec<(Rgba8, i32, i32)>> {
+        if self.view != Some(view) || self.size != (width, height) {
+            self.reset(view, width, height, pixels);
+
+            return None;
+        }
+        let changed = self
+            .baseline
+            .iter()
+            .zip(pixels)
+            .enumerate()
+            .filter(|(_, (a, b))| a != b)
+            .map(|(i, (_, b))| {
+                let (x, row) = (i as u32 % width, i as u32 / width);
+                (*b, x as i32, (height - row - 1) as i32)
+            })
+            .collect();
+
+        self.baseline.copy_from_slice(pixels);
+
+        Some(changed)
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+
+    fn new(
+        role: Role,
+        addr: SocketAddr,
+        peers: Arc<Mutex<Vec<Peer>>>,
+        messages: mpsc::Receiver<Message>,
+        closed: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            role,
+            addr,
+            peers,
+            messages,
+            closed,
+            view: None,
+            size: (0, 0),
+            baseline: Vec::new(),
+        }
+    }
+
+    /// Read the token line sent by a guest, and check it against ours.
+    fn handshake(stream: &TcpStream, token: &str) -> io::Result<()> {
+        let mut line = String::new();
+
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        // Read byte by byte, so that nothing past the token line is consumed.
+        for byte in io::Read::bytes(stream) {
+            match byte? {
+                b'\n' => break,
+                b => line.push(b as char),
+            }
+            if line.len() > token.len() + 16 {
+                break;
+            }
+        }
+        stream.set_read_timeout(None)?;
+
+        if line.trim_end().strip_prefix("token ") == Some(token) {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "invalid token",
+            ))
+        }
+    }
+
+    /// Register a peer, and read its lines and write to it on separate threads.
+    fn connect(
+        id: PeerId,
+        stream: TcpStream,
+        peers: &Arc<Mutex<Vec<Peer>>>,
+        tx: &mpsc::Sender<Message>,
+    ) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut output = stream.try_clone()?;
+        let (writer, buffers) = mpsc::channel::<Arc<[u8]>>();
+        let peers = {
+            let mut guard = peers
+                .lock()
+                .map_err(|_| io::Error::new(io::ErrorKind::Other, "peer list is poisoned"))?;
+            guard.push(Peer { id, stream, writer });
+            peers.clone()
+        };
+        let tx = tx.clone();
+
+        // Writes block on slow peers, so they're done on a separate thread.
+        // Peers which can't be written to are disconnected.
+        thread::spawn(move || {
+            for buf in buffers {
+                if output.write_all(&buf).is_err() {
+                    output.shutdown(Shutdown::Both).ok();
+                    break;
+                }
+            }
+        });
+
+        thread::spawn(move || {
+            loop {
+                let line = match self::read_line(&mut reader, MAX_LINE_LENGTH) {
+                    Ok(Some(line)) => line,
+                    // Lines which are too long or aren't valid UTF-8 are skipped.
+                    Err(err) if err.kind() == io::ErrorKind::InvalidData => continue,
+                    Ok(None) | Err(_) => break,
+                };
+                let msg = match line.strip_prefix("snapshot ") {
+                    Some(snapshot) => match self::decode_snapshot(snapshot) {
+                        Some((extent, pixels)) => Message::Snapshot(id, extent, pixels),
+                        None => continue,
+                    },
+                    None => Message::Line(id, line),
+                };
+                if tx.send(msg).is_err() {
+                    return;
+                }
+            }
+            if let Ok(mut peers) = peers.lock() {
+                peers.retain(|p| p.id != id);
+            }
+            tx.send(Message::Left(id)).ok();
+        });
+
+        Ok(())
+    }
+
+    fn write(&self, lines: &[String], to: impl Fn(PeerId) -> bool) {
+        if lines.is_empty() {
+            return;
+        }
+        let mut buf = lines.join("\n");
+        buf.push('\n');
+
+        let buf: Arc<[u8]> = buf.into_bytes().into();
+
+        if let Ok(mut peers) = self.peers.lock() {
+            peers.retain(|p| !to(p.id) || p.writer.send(buf.clone()).is_ok());
+        }
+    }
+}
+
+impl Drop for Collab {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::SeqCst);
+
+        if let Ok(peers) = self.peers.lock() {
+            for p in peers.iter() {
+                p.stream.shutdown(Shutdown::Both).ok();
+            }
+        }
+        // Wake up the listener, so that it notices we're closed.
+        if self.role == Role::Host {
+            let ip = if self.addr.ip().is_unspecified() {
+                [127, 0, 0, 1].into()
+            } else {
+                self.addr.ip()
+            };
+            TcpStream::connect((ip, self.addr.port())).ok();
+        }
+    }
+}
+
+/// Generate a random token for guests to join with.
+pub fn token() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+
+    hasher.write_u128(
+        time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    format!("{:016x}", hasher.finish())
+}
+
+/// Read a line, without its line ending. Returns `None` at the end of the stream.
+/// Lines longer than `max` bytes are consumed without being buffered, and result
+/// in an error.
+fn read_line(reader: &mut impl BufRead, max: usize) -> io::Result<Option<String>> {
+    let mut buf = Vec::new();
+    let mut overlong = false;
+    let mut eof = true;
+
+    loop {
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            break;
+        }
+        eof = false;
+
+        let (chunk, done) = match available.iter().position(|b| *b == b'\n') {
+            Some(i) => (&available[..i], true),
+            None => (available, false),
+        };
+        let consumed = chunk.len() + done as usize;
+
+        if buf.len() + chunk.len() > max {
+            overlong = true;
+            buf = Vec::new();
+        } else if !overlong {
+            buf.extend_from_slice(chunk);
+        }
+        reader.consume(consumed);
+
+        if done {
+            break;
+        }
+    }
+    if eof {
+        return Ok(None);
+    }
+    if overlong {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "line is too long",
+        ));
+    }
+    if buf.last() == Some(&b'\r') {
+        buf.pop();
+    }
+    String::from_utf8(buf)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Encode a snapshot line, without its trailing newline.
+fn encode_snapshot(extent: ViewExtent, pixels: &[Rgba8]) -> String {
+    let mut line = format!("snapshot {} {} {} ", extent.fw, extent.fh, extent.nframes);
+
+    line.reserve(pixels.len() * 8);
+    for c in pixels {
+        line.push_str(&format!("{:02x}{:02x}{:02x}{:02x}", c.r, c.g, c.b, c.a));
+    }
+    line
+}
+
+/// Decode the arguments of a snapshot line. Returns `None` if it's malformed, or
+/// if the view is larger than [`MAX_SIZE`] in either dimension.
+fn decode_snapshot(s: &str) -> Option<(ViewExtent, Vec<Rgba8>)> {
+    let mut parts = s.split(' ');
+    let fw: u32 = parts.next()?.parse().ok()?;
+    let fh: u32 = parts.next()?.parse().ok()?;
+    let nframes: usize = parts.next()?.parse().ok()?;
+    let hex = parts.next()?;
+
+    let width = fw.checked_mul(u32::try_from(nframes).ok()?)?;
+    if width == 0 || width > MAX_SIZE || fh == 0 || fh > MAX_SIZE {
+        return None;
+    }
+    let len = (width as usize).checked_mul(fh as usize)?.checked_mul(8)?;
+
+    if parts.next().is_some() || hex.len() != len {
+        return None;
+    }
+    let extent = ViewExtent::new(fw, fh, nframes);
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    let pixels = bytes
+        .chunks(4)
+        .map(|c| Rgba8::new(c[0], c[1], c[2], c[3]))
+        .collect();
+
+    Some((extent, pixels))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TOKEN: &str = "0123456789abcdef";
+
+    fn wait(collab: &Collab, n: usize) -> Vec<Message> {
+        let start = time::Instant::now();
+        let mut msgs = Vec::new();
+
+        while msgs.len() < n && start.elapsed() < time::Duration::from_secs(5) {
+            msgs.extend(collab.receive());
+            thread::sleep(time::Duration::from_millis(1));
+        }
+        msgs
+    }
+
+    #[test]
+    fn test_collab_exchange() {
+        let host = Collab::host("127.0.0.1", 0, TOKEN).unwrap();
+        let guest = Collab::join(&format!("127.0.0.1:{}", host.addr.port()), TOKEN).unwrap();
+
+        guest.send(&[String::from("paint/color #ff0000 1 1")], None);
+
+        assert_eq!(
+            wait(&host, 2),
+            vec![
+                Message::Joined(0),
+                Message::Line(0, String::from("paint/color #ff0000 1 1"))
+            ]
+        );
+
+        let extent = ViewExtent::new(2, 1, 1);
+        let pixels = vec![Rgba8::RED, Rgba8::new(0x1, 0x2, 0x3, 0x4)];
+
+        host.send_snapshot(extent, &pixels, Some(0));
+
+        assert_eq!(wait(&guest, 1), vec![Message::Snapshot(0, extent, pixels)]);
+
+        drop(guest);
+
+        assert_eq!(wait(&host, 1), vec![Message::Left(0)]);
+        assert_eq!(host.peers(), 0);
+    }
+
+    #[test]
+    fn test_collab_token() {
+        let host = Collab::host("127.0.0.1", 0, TOKEN).unwrap();
+        let guest = Collab::join(&format!("127.0.0.1:{}", host.addr.port()), "wrong").unwrap();
+
+        // The guest is disconnected, and the host never hears of it.
+        assert_eq!(wait(&guest, 1), vec![Message::Left(0)]);
+        assert!(host.receive().is_empty());
+        assert_eq!(host.peers(), 0);
+    }
+
+    #[test]
+    fn test_collab_snapshot() {
+        let extent = ViewExtent::new(1, 1, 2);
+        let pixels = vec![Rgba8::BLUE, Rgba8::TRANSPARENT];
+        let line = encode_snapshot(extent, &pixels);
+
+        assert_eq!(line, "snapshot 1 1 2 0000ffff00000000");
+        assert_eq!(
+            decode_snapshot(line.strip_prefix("snapshot ").unwrap()),
+            Some((extent, pixels))
+        );
+        assert_eq!(decode_snapshot("1 1 2 0000ffff"), None);
+        assert_eq!(decode_snapshot("1 1 1 0000ffzz"), None);
+
+        // Dimensions are checked before the pixels.
+        assert_eq!(decode_snapshot("0 1 1 "), None);
+        assert_eq!(decode_snapshot("4097 1 1 0000ffff"), None);
+        assert_eq!(decode_snapshot("4096 1 2 0000ffff"), None);
+        assert_eq!(decode_snapshot("4294967295 4294967295 2 0000ffff"), None);
+        assert_eq!(decode_snapshot("1 1 18446744073709551615 0000ffff"), None);
+    }
+
+    #[test]
+    fn test_collab_read_line() {
+        let input = [&b"a\r\n"[..], b"xxxxx\nb\n", b"\xff\nc"].concat();
+        let mut reader = BufReader::with_capacity(2, input.as_slice());
+        let mut read = || read_line(&mut reader, 4).map_err(|e| e.kind());
+
+        assert_eq!(read(), Ok(Some(String::from("a"))));
+        // The line is too long.
+        assert_eq!(read(), Err(io::ErrorKind::InvalidData));
+        assert_eq!(read(), Ok(Some(String::from("b"))));
+        // The line isn't valid UTF-8.
+        assert_eq!(read(), Err(io::ErrorKind::InvalidData));
+        assert_eq!(read(), Ok(Some(String::from("c"))));
+        assert_eq!(read(), Ok(None));
+    }
+
+    #[test]
+    fn test_collab_diff() {
+        let (r, g, b) = (Rgba8::RED, Rgba8::GREEN, Rgba8::BLUE);
+        let mut collab = Collab::host("127.0.0.1", 0, TOKEN).unwrap();
+        let id = ViewId::default();
+
+        // The first diff only sets the baseline.
+        assert_eq!(collab.diff(id, 2, 2, &[r, r, r, r]), None);
+        // The top-left pixel is at the top of the view.
+        assert_eq!(collab.diff(id, 2, 2, &[g, r, r, r]), Some(vec![(g, 0, 1)]));
+        assert_eq!(collab.diff(id, 2, 2, &[g, r, r, r]), Some(vec![]));
+
+        // Received pixels aren't reported.
+        collab.received(b, 1, 0);
+        assert_eq!(collab.diff(id, 2, 2, &[g, r, r, b]), Some(vec![]));
+
+        // Resizing resets the baseline.
+        assert_eq!(collab.diff(id, 1, 1, &[b]), None);
+    }
+}
@@ -11,3 +11,192 @@ pub const YELLOW: Rgba8 = Rgba8::new(0xff, 0xff, 0x66, 0xff);
 pub const LIGHT_GREEN: Rgba8 = Rgba8::new(0xbb, 0xff, 0xee, 0xff);
 pub const GREEN: Rgba8 = Rgba8::new(0x38, 0xb7, 0x55, 0xff);
 pub const BLUE: Rgba8 = Rgba8::new(0x29, 0x36, 0x6f, 0xff);
+
+/// Count the number of pixels of each color, ignoring fully transparent
+/// pixels. Returns the colors sorted by usage, most used first.
+pub fn usage(pixels: &[Rgba8]) -> Vec<(Rgba8, usize)> {
+    let mut counts = std::collections::BTreeMap::new();
+
+    for p in pixels.iter().filter(|p| p.a != 0) {
+        *counts.entry(*p).or_insert(0) += 1;
+    }
+    let mut usage: Vec<_> = counts.into_iter().collect();
+    usage.sort_by(|(_, a), (_, b)| b.cmp(a));
+    usage
+}
+
+/// Mix two colors. An `amount` of `0.0` yields `a`, and `1.0` yields `b`.
+pub fn mix(a: Rgba8, b: Rgba8, amount: f32) -> Rgba8 {
+    let t = amount.clamp(0., 1.);
+    let lerp = |x: u8, y: u8| (x as f32 * (1. - t) + y as f32 * t).round() as u8;
+
+    Rgba8::new(
+        lerp(a.r, b.r),
+        lerp(a.g, b.g),
+        lerp(a.b, b.b),
+        lerp(a.a, b.a),
+    )
+}
+
+/// Composite `fg` over `bg`, using the alpha of both colors.
+pub fn over(bg: Rgba8, fg: Rgba8) -> Rgba8 {
+    let (fa, ba) = (fg.a as f32 / 255., bg.a as f32 / 255.);
+    let a = fa + ba * (1. - fa);
+
+    if a == 0. {
+        return TRANSPARENT;
+    }
+    let blend = |f: u8, b: u8| {
+        ((f as f32 * fa + b as f32 * ba * (1. - fa)) / a)
+            .round()
+            .clamp(0., 255.) as u8
+    };
+    Rgba8::new(
+        blend(fg.r, bg.r),
+        blend(fg.g, bg.g),
+        blend(fg.b, bg.b),
+        (a * 255.).round() as u8,
+    )
+}
+
+/// An adjustment of one of a color's HSL components, eg. `+h10` or `-s5`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Shift {
+    /// Hue shift, in degrees.
+    Hue(f32),
+    /// Saturation shift, in percentage points.
+    Saturation(f32),
+    /// Lightness shift, in percentage points.
+    Lightness(f32),
+}
+
+impl std::fmt::Display for Shift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (c, n) = match self {
+            Self::Hue(n) => ('h', n),
+            Self::Saturation(n) => ('s', n),
+            Self::Lightness(n) => ('l', n),
+        };
+        write!(f, "{}{}{}", if *n < 0. { '-' } else { '+' }, c, n.abs())
+    }
+}
+
+/// Apply HSL shifts to a color. Alpha is preserved.
+pub fn shift(color: Rgba8, shifts: &[Shift]) -> Rgba8 {
+    let (mut h, mut s, mut l) = self::to_hsl(color);
+
+    for shift in shifts {
+        match shift {
+            Shift::Hue(n) => h = (h + n).rem_euclid(360.),
+            Shift::Saturation(n) => s = (s + n / 100.).clamp(0., 1.),
+            Shift::Lightness(n) => l = (l + n / 100.).clamp(0., 1.),
+        }
+    }
+    self::from_hsl(h, s, l).alpha(color.a)
+}
+
+/// Convert a color to hue (degrees), saturation and lightness (`0.0` to `1.0`).
+pub fn to_hsl(color: Rgba8) -> (f32, f32, f32) {
+    let (r, g, b) = (
+        color.r as f32 / 255.,
+        color.g as f32 / 255.,
+        color.b as f32 / 255.,
+    );
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.;
+    let d = max - min;
+
+    if d == 0. {
+        return (0., 0., l);
+    }
+    let s = d / (1. - (2. * l - 1.).abs());
+    let h = if max == r {
+        60. * ((g - b) / d).rem_euclid(6.)
+    } else if max == g {
+        60. * ((b - r) / d + 2.)
+    } else {
+        60. * ((r - g) / d + 4.)
+    };
+    (h, s, l)
+}
+
+/// Convert hue (degrees), saturation and lightness (`0.0` to `1.0`) to an opaque color.
+pub fn from_hsl(h: f32, s: f32, l: f32) -> Rgba8 {
+    let c = (1. - (2. * l - 1.).abs()) * s;
+    let x = c * (1. - ((h / 60.).rem_euclid(2.) - 1.).abs());
+    let m = l - c / 2.;
+
+    let (r, g, b) = match (h.rem_euclid(360.) / 60.) as u32 {
+        0 => (c, x, 0.),
+        1 => (x, c, 0.),
+        2 => (0., c, x),
+        3 => (0., x, c),
+        4 => (x, 0., c),
+        _ => (c, 0., x),
+    };
+    let channel = |n: f32| ((n + m) * 255.).round().clamp(0., 255.) as u8;
+
+    Rgba8::new(channel(r), channel(g), channel(b), 0xff)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mix() {
+        let (red, blue) = (Rgba8::new(0xff, 0, 0, 0xff), Rgba8::new(0, 0, 0xff, 0xff));
+
+        assert_eq!(mix(red, blue, 0.), red);
+        assert_eq!(mix(red, blue, 1.), blue);
+        assert_eq!(mix(red, blue, 0.25), Rgba8::new(0xbf, 0, 0x40, 0xff));
+    }
+
+    #[test]
+    fn test_over() {
+        let (red, blue) = (Rgba8::new(0xff, 0, 0, 0xff), Rgba8::new(0, 0, 0xff, 0xff));
+
+        assert_eq!(over(red, blue), blue);
+        assert_eq!(over(red, TRANSPARENT), red);
+        assert_eq!(over(red, blue.alpha(0x80)), Rgba8::new(0x7f, 0, 0x80, 0xff));
+        assert_eq!(over(TRANSPARENT, blue.alpha(0x80)), blue.alpha(0x80));
+        assert_eq!(over(TRANSPARENT, TRANSPARENT), TRANSPARENT);
+    }
+
+    #[test]
+    fn test_hsl_roundtrip() {
+        for color in [
+            Rgba8::new(0x88, 0x44, 0x22, 0xff),
+            Rgba8::new(0x12, 0xab, 0xef, 0xff),
+            GREY,
+            WHITE,
+            BLACK,
+        ] {
+            let (h, s, l) = to_hsl(color);
+            assert_eq!(from_hsl(h, s, l), color);
+        }
+    }
+
+    #[test]
+    fn test_shift() {
+        let red = Rgba8::new(0xff, 0, 0, 0x80);
+
+        assert_eq!(
+            shift(red, &[Shift::Hue(120.)]),
+            Rgba8::new(0, 0xff, 0, 0x80)
+        );
+        assert_eq!(
+            shift(red, &[Shift::Hue(-120.)]),
+            Rgba8::new(0, 0, 0xff, 0x80)
+        );
+        assert_eq!(
+            shift(red, &[Shift::Saturation(-100.)]),
+            Rgba8::new(0x80, 0x80, 0x80, 0x80)
+        );
+        assert_eq!(
+            shift(red, &[Shift::Lightness(50.)]),
+            Rgba8::new(0xff, 0xff, 0xff, 0x80)
+        );
+    }
+}
@@ -0,0 +1,369 @@
+//! Configuration scripts.
+//!
+//! Scripts are sourced on startup in order of precedence, each overriding the
+//! ones before it:
+//!
+//! 1. The builtin configuration.
+//! 2. The user's `init.rx`, in the config directory.
+//! 3. The generated [`GENERATED`] script, in the config directory.
+//! 4. The project's `.rxrc`, in the working directory, or in a directory
+//!    opened later on.
+//!
+//! Settings and key mappings changed while rx is running can be saved with
+//! `:set/save`, to the generated script. That script is divided into sections,
+//! each starting with a `-- [<name>]` comment line. Saving rewrites the sections
+//! generated by rx, and keeps the others as they are, so that the script can be
+//! edited by hand.
+//!
+//! Since project scripts come with the files being edited, they are only
+//! sourced once the user trusts them with `:rxrc/trust`, and again when they
+//! change.
+use crate::cmd::{Value, COMMENT};
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the generated script, in the config directory.
+pub const GENERATED: &str = "settings.rx";
+/// Name of the list of trusted project scripts, in the config directory.
+pub const TRUSTED: &str = "trusted";
+/// Name of project scripts.
+pub const PROJECT: &str = ".rxrc";
+
+/// A SHA-256 digest.
+pub type Digest = [u8; 32];
+
+/// Project scripts the user trusts, along with a SHA-256 digest of their
+/// contents at the time they were trusted.
+#[derive(Debug, Default)]
+pub struct Trusted {
+    scripts: BTreeMap<PathBuf, Digest>,
+}
+
+impl Trusted {
+    /// Load the list from the given file, which has one `<digest> <path>` line per
+    /// script, with the digest in hexadecimal. A missing file is an empty list.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let list = match std::fs::read_to_string(path) {
+            Ok(list) => list,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let scripts = list
+            .lines()
+            .filter_map(|l| l.split_once(' '))
+            .filter_map(|(digest, path)| self::decode(digest).map(|d| (PathBuf::from(path), d)))
+            .collect();
+
+        Ok(Self { scripts })
+    }
+
+    /// Write the list to the given file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let list: String = self
+            .scripts
+            .iter()
+            .map(|(path, digest)| format!("{} {}\n", self::encode(digest), path.display()))
+            .collect();
+
+        std::fs::write(path, list)
+    }
+
+    /// Check whether the script at the given path, with the given contents, is trusted.
+    pub fn contains(&self, script: &Path, contents: &[u8]) -> bool {
+        self.scripts.get(script) == Some(&self::sha256(contents))
+    }
+
+    /// Trust the script at the given path, as long as it has the given contents.
+    pub fn insert(&mut self, script: PathBuf, contents: &[u8]) {
+        self.scripts.insert(script, self::sha256(contents));
+    }
+}
+
+/// Render a value so that it's parsed back as the same value by `:set`.
+pub fn value(v: &Value) -> String {
+    match v {
+        Value::Bool(true) => String::from("on"),
+        Value::Bool(false) => String::from("off"),
+        Value::U32(u) => u.to_string(),
+        // Floats always have a decimal point, so they aren't parsed as integers.
+        Value::F64(x) => format!("{:?}", x),
+        Value::U32Tuple(x, y) => format!("{} {}", x, y),
+        Value::F32Tuple(x, y) => format!("{:?} {:?}", x, y),
+        Value::Str(s) => format!("\"{}\"", s),
+        Value::Rgba8(c) => c.to_string(),
+        Value::Ident(i) => i.clone(),
+    }
+}
+
+/// Replace the sections of a script with the given ones, by name. Sections
+/// that aren't found are added at the end, and lines before the first
+/// section are kept.
+pub fn update(script: &str, sections: &[(&str, Vec<String>)]) -> String {
+    let mut parsed: Vec<(Option<String>, Vec<String>)> = vec![(None, Vec::new())];
+
+    for line in script.lines() {
+        if let Some(name) = self::section(line) {
+            parsed.push((Some(name.to_owned()), Vec::new()));
+        } else if let Some((_, lines)) = parsed.last_mut() {
+            lines.push(line.to_owned());
+        }
+    }
+    for (name, lines) in sections {
+        match parsed.iter_mut().find(|(n, _)| n.as_deref() == Some(*name)) {
+            Some((_, existing)) => *existing = lines.clone(),
+            None => parsed.push((Some((*name).to_owned()), lines.clone())),
+        }
+    }
+
+    let mut output = String::new();
+    for (name, mut lines) in parsed {
+        // Sections are separated by a single blank line.
+        while lines.last().map_or(false, |l| l.trim().is_empty()) {
+            lines.pop();
+        }
+        if let Some(name) = name {
+            if !output.is_empty() {
+                output.push('\n');
+            }
+            output.push_str(&format!("{}{} [{}]\n", COMMENT, COMMENT, name));
+        }
+        for line in lines {
+            output.push_str(&line);
+            output.push('\n');
+        }
+    }
+    output
+}
+
+/// SHA-256 digest of the given data.
+fn sha256(data: &[u8]) -> Digest {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    // The message is padded with a one bit, zeros, and its length in bits, to a
+    // multiple of the block size.
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 64];
+
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = h;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = hh
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+
+            hh = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (x, y) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+            *x = x.wrapping_add(y);
+        }
+    }
+
+    let mut digest = [0; 32];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(h) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+/// Encode a digest in hexadecimal.
+fn encode(digest: &Digest) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decode a digest from hexadecimal.
+fn decode(hex: &str) -> Option<Digest> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut digest = [0; 32];
+    for (i, b) in digest.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(digest)
+}
+
+/// Get the name of the section started by the given line, if any.
+fn section(line: &str) -> Option<&str> {
+    line.strip_prefix(COMMENT)?
+        .strip_prefix(COMMENT)?
+        .trim()
+        .strip_prefix('[')?
+        .strip_suffix(']')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gfx::Rgba8;
+
+    #[test]
+    fn test_value() {
+        assert_eq!(value(&Value::Bool(false)), "off");
+        assert_eq!(value(&Value::F64(2.)), "2.0");
+        assert_eq!(value(&Value::U32Tuple(8, 16)), "8 16");
+        assert_eq!(value(&Value::Str(String::from("make"))), "\"make\"");
+        assert_eq!(value(&Value::Rgba8(Rgba8::RED)), "#ff0000");
+    }
+
+    #[test]
+    fn test_update() {
+        let script = "\
+set debug = on
+
+-- [settings]
+set grid = on
+
+-- [mine]
+map <f1> :help
+";
+        let updated = update(
+            script,
+            &[
+                ("settings", vec![String::from("set checker = on")]),
+                ("mappings", vec![String::from("map <f2> :brush")]),
+            ],
+        );
+        assert_eq!(
+            updated,
+            "\
+set debug = on
+
+-- [settings]
+set checker = on
+
+-- [mine]
+map <f1> :help
+
+-- [mappings]
+map <f2> :brush
+"
+        );
+        assert_eq!(
+            update(
+                &updated,
+                &[("settings", vec![String::from("set checker = on")])]
+            ),
+            updated
+        );
+        assert_eq!(update("", &[("settings", vec![])]), "-- [settings]\n");
+    }
+
+    #[test]
+    fn test_trusted() {
+        let file = std::env::temp_dir().join(format!("rx-trusted-{}", std::process::id()));
+        let script = PathBuf::from("/projects/sprites/.rxrc");
+        let mut trusted = Trusted::load(&file).unwrap();
+
+        assert!(!trusted.contains(&script, b"set grid = on"));
+        trusted.insert(script.clone(), b"set grid = on");
+        trusted.save(&file).unwrap();
+
+        let trusted = Trusted::load(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+
+        assert!(trusted.contains(&script, b"set grid = on"));
+        assert!(!trusted.contains(&script, b"set grid = off"));
+        assert!(!trusted.contains(Path::new("/projects/.rxrc"), b"set grid = on"));
+    }
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            encode(&sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            encode(&sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            encode(&sha256(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        assert_eq!(
+            encode(&sha256(
+                b"abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmn\
+                  hijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu"
+            )),
+            "cf5b16a778af8380036ce59e7b0492370b249b11e8f07a51afac45037afee9d1"
+        );
+        // Messages of 56 bytes or more don't leave room for the length in their
+        // last block, and need an extra block of padding.
+        let a = |len| encode(&sha256(&vec![b'a'; len]));
+
+        assert_eq!(
+            a(55),
+            "9f4390f8d30c2dd92ec9f095b65e2b9ae9b0a925a5258e241c9f1e910f734318"
+        );
+        assert_eq!(
+            a(56),
+            "b35439a4ac6f0948b6d6f9e3c6af0f5f590ce20f1bde7090ef7970686ec6738a"
+        );
+        assert_eq!(
+            a(63),
+            "7d3e74a05d7db15bce4ad9ec0658ea98e3f06eeecf16b4c6fff2da457ddc2f34"
+        );
+        assert_eq!(
+            a(64),
+            "ffe054fe7ae0cb6dc65c3af9b61d5209f439851db43d0ba5997337df154668eb"
+        );
+        assert_eq!(
+            encode(&sha256(&vec![b'a'; 1_000_000])),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+        assert_eq!(decode(&encode(&sha256(b"abc"))), Some(sha256(b"abc")));
+        assert_eq!(decode("e3b0c442"), None);
+    }
+}
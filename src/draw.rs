@@ -1,8 +1,13 @@
 use crate::brush::{Align, Brush, BrushMode};
+use crate::cmd::Axis;
 use crate::color;
 use crate::execution::Execution;
-use crate::font::{TextAlign, TextBatch};
+use crate::font::{Font, TextAlign, TextBatch};
+use crate::palette::Palettes;
+use crate::panel::Field;
 use crate::platform;
+use crate::preview;
+use crate::search::Search;
 use crate::session;
 use crate::session::{Mode, Session, Tool, VisualState};
 use crate::sprite;
@@ -30,9 +35,6 @@ pub const CURSOR_LAYER: ZDepth = ZDepth(0.0);
 pub const XRAY_RADIUS: f32 = 3.0;
 pub const XRAY_MIN_ZOOM: f32 = 16.0;
 
-pub const GLYPH_WIDTH: f32 = 8.;
-pub const GLYPH_HEIGHT: f32 = 14.;
-
 #[rustfmt::skip]
 pub const CHECKER: [u8; 16] = [
     0x55, 0x55, 0x55, 0xff,
@@ -41,8 +43,11 @@ pub const CHECKER: [u8; 16] = [
     0x55, 0x55, 0x55, 0xff,
 ];
 const CHECKER_REPEAT: f32 = 4.;
-const LINE_HEIGHT: f32 = GLYPH_HEIGHT + 4.;
+const LINE_SPACING: f32 = 4.;
 const MARGIN: f32 = 10.;
+const HISTOGRAM_BINS: usize = 32;
+const HISTOGRAM_BIN_WIDTH: f32 = 4.;
+const HISTOGRAM_HEIGHT: f32 = 48.;
 
 pub mod cursors {
     use super::*;
@@ -82,6 +87,7 @@ pub mod cursors {
             Tool::Sampler => self::SAMPLER,
             Tool::Pan(_) => self::PAN,
             Tool::FloodFill => self::FLOOD,
+            Tool::Measure(_) => self::CROSSHAIR,
 
             Tool::Brush => match m {
                 Mode::Visual(_) if in_selection && in_view => self::OMNI,
@@ -126,12 +132,30 @@ impl Context {
         avg_frametime: &time::Duration,
         execution: &Execution,
     ) {
+        let text_scale = session.settings["ui/text-scale"].to_u64() as f32;
+
+        self.text_batch.set_scale(text_scale);
+        self.overlay_batch.set_scale(text_scale);
+
         self::draw_brush(session, &session.brush, &mut self.ui_batch);
+        self::draw_brush_outline(session, &session.brush, &mut self.ui_batch);
+        self::draw_brush_hud(
+            session,
+            &session.brush,
+            &mut self.ui_batch,
+            &mut self.overlay_batch,
+        );
+        self::draw_crosshair(session, &mut self.ui_batch);
         self::draw_paste(session, &mut self.paste_batch);
         self::draw_grid(session, &mut self.ui_batch);
+        self::draw_guides(session, &mut self.ui_batch);
+        self::draw_ruler(session, &mut self.ui_batch, &mut self.overlay_batch);
+        self::draw_tooltip(session, &mut self.overlay_batch);
         self::draw_ui(session, &mut self.ui_batch, &mut self.text_batch);
         self::draw_overlay(session, avg_frametime, &mut self.overlay_batch, execution);
-        self::draw_palette(session, &mut self.ui_batch);
+        self::draw_palette(session, &mut self.ui_batch, &mut self.text_batch);
+        self::draw_panel(session, &mut self.ui_batch, &mut self.text_batch);
+        self::draw_histogram(session, &mut self.ui_batch, &mut self.text_batch);
         self::draw_cursor(session, &mut self.cursor_sprite, &mut self.tool_batch);
         self::draw_checker(session, &mut self.checker_batch);
     }
@@ -147,8 +171,16 @@ impl Context {
     }
 }
 
+/// Height of a line of text, including spacing.
+fn line_height(text: &TextBatch) -> f32 {
+    text.glyph_height() + self::LINE_SPACING
+}
+
 fn draw_ui(session: &Session, canvas: &mut shape2d::Batch, text: &mut TextBatch) {
+    let glyph_width = text.glyph_width();
+    let line_height = self::line_height(text);
     let view = session.active_view();
+    let theme = &session.theme;
 
     if let Some(selection) = session.selection {
         let fill = match session.mode {
@@ -176,7 +208,7 @@ fn draw_ui(session: &Session, canvas: &mut shape2d::Batch, text: &mut TextBatch)
             let y = if s.y2 >= s.y1 {
                 (s.y2 + 1) as f32 * z + 1.
             } else {
-                (s.y2) as f32 * z - self::LINE_HEIGHT + 1.
+                (s.y2) as f32 * z - line_height + 1.
             };
             text.add(
                 &t,
@@ -198,8 +230,35 @@ fn draw_ui(session: &Session, canvas: &mut shape2d::Batch, text: &mut TextBatch)
             Stroke::new(1., stroke.into()),
             Fill::Empty,
         ));
+        // Corner handles, when the floating selection can be scaled.
+        if session.is_scaling_paste() {
+            for (x, y) in [
+                (r.x1, r.y1),
+                (r.x2 - 1, r.y1),
+                (r.x1, r.y2 - 1),
+                (r.x2 - 1, r.y2 - 1),
+            ] {
+                canvas.add(Shape::Rectangle(
+                    Rect::new(x, y, x + 1, y + 1).map(|n| n as f32).transform(t),
+                    self::UI_LAYER,
+                    Rotation::ZERO,
+                    Stroke::new(1., color::WHITE.into()),
+                    Fill::Solid(stroke.into()),
+                ));
+            }
+        }
         // Selection fill.
-        if r.intersects(view.layer_bounds()) {
+        if let Some(mask) = session.selection_mask() {
+            for span in mask.spans() {
+                canvas.add(Shape::Rectangle(
+                    span.map(|n| n as f32).transform(t),
+                    self::UI_LAYER,
+                    Rotation::ZERO,
+                    Stroke::NONE,
+                    Fill::Solid(fill.into()),
+                ));
+            }
+        } else if r.intersects(view.layer_bounds()) {
             canvas.add(Shape::Rectangle(
                 r.intersection(view.layer_bounds())
                     .map(|n| n as f32)
@@ -212,7 +271,25 @@ fn draw_ui(session: &Session, canvas: &mut shape2d::Batch, text: &mut TextBatch)
         }
     }
 
-    for v in session.views.iter() {
+    // Outline of the clipping mask, which painting is constrained to.
+    if let (Mode::Normal, Some(mask)) = (session.mode, session.clip_mask()) {
+        let offset = session.offset + view.offset;
+        let t = Matrix4::from_translation(offset.extend(0.)) * Matrix4::from_scale(view.zoom);
+
+        canvas.add(Shape::Rectangle(
+            mask.selection.bounds().map(|n| n as f32).transform(t),
+            self::UI_LAYER,
+            Rotation::ZERO,
+            Stroke::new(1., color::RED.alpha(0x88).into()),
+            Fill::Empty,
+        ));
+    }
+
+    for v in session
+        .views
+        .iter()
+        .filter(|v| session.workspaces.contains(v.id))
+    {
         let offset = v.offset + session.offset;
 
         // Frame lines
@@ -226,7 +303,7 @@ fn draw_ui(session: &Session, canvas: &mut shape2d::Batch, text: &mut TextBatch)
                 ),
                 self::UI_LAYER,
                 Rotation::ZERO,
-                Stroke::new(1.0, Rgba::new(1., 1., 1., 0.6)),
+                Stroke::new(1.0, theme.foreground.alpha(0x99).into()),
             ));
         }
         // View border
@@ -237,12 +314,12 @@ fn draw_ui(session: &Session, canvas: &mut shape2d::Batch, text: &mut TextBatch)
                 Mode::Visual(_) => {
                     Rgba8::new(color::RED.r, color::RED.g, color::RED.b, 0xdd).into()
                 }
-                _ => color::WHITE.into(),
+                _ => theme.foreground.into(),
             }
         } else if session.hover_view == Some(v.id) {
-            Rgba::new(0.7, 0.7, 0.7, 1.0)
+            theme.hover
         } else {
-            Rgba::new(0.5, 0.5, 0.5, 1.0)
+            theme.border
         };
         canvas.add(Shape::Rectangle(
             Rect::new(r.x1 - 1., r.y1 - 1., r.x2 + 1., r.y2 + 1.) + session.offset,
@@ -252,14 +329,29 @@ fn draw_ui(session: &Session, canvas: &mut shape2d::Batch, text: &mut TextBatch)
             Fill::Empty,
         ));
 
+        if session.lut == Some(v.id) {
+            // Lookup texture indicator
+            text.add(
+                "lut",
+                r.x2 + session.offset.x,
+                offset.y - line_height,
+                self::TEXT_LAYER,
+                color::YELLOW,
+                TextAlign::Right,
+            );
+        }
         if session.settings["ui/view-info"].is_set() {
             // View info
+            let info = match v.fps {
+                Some(fps) => format!("{}x{}x{} @ {}fps", v.fw, v.fh, v.animation.len(), fps),
+                None => format!("{}x{}x{}", v.fw, v.fh, v.animation.len()),
+            };
             text.add(
-                &format!("{}x{}x{}", v.fw, v.fh, v.animation.len()),
+                &info,
                 offset.x,
-                offset.y - self::LINE_HEIGHT,
+                offset.y - line_height,
                 self::TEXT_LAYER,
-                color::GREY,
+                theme.secondary,
                 TextAlign::Left,
             );
         }
@@ -269,9 +361,9 @@ fn draw_ui(session: &Session, canvas: &mut shape2d::Batch, text: &mut TextBatch)
         text.add(
             &view.status(),
             MARGIN,
-            MARGIN + self::LINE_HEIGHT,
+            MARGIN + line_height,
             self::TEXT_LAYER,
-            Rgba8::WHITE,
+            theme.foreground,
             TextAlign::Left,
         );
 
@@ -279,12 +371,24 @@ fn draw_ui(session: &Session, canvas: &mut shape2d::Batch, text: &mut TextBatch)
         text.add(
             &format!("{:>5}%", (view.zoom * 100.) as u32),
             session.width - MARGIN,
-            MARGIN + self::LINE_HEIGHT,
+            MARGIN + line_height,
             self::TEXT_LAYER,
-            Rgba8::WHITE,
+            theme.foreground,
             TextAlign::Right,
         );
 
+        if session.workspaces.names().len() > 1 {
+            // Active workspace, left of the zoom level.
+            text.add(
+                &format!("[{}]", session.workspaces.active),
+                session.width - MARGIN - glyph_width * 7.,
+                MARGIN + line_height,
+                self::TEXT_LAYER,
+                theme.secondary,
+                TextAlign::Right,
+            );
+        }
+
         if session.width >= 600. {
             let cursor = session.view_coords(view.id, session.cursor);
             let hover_color = session
@@ -293,41 +397,60 @@ fn draw_ui(session: &Session, canvas: &mut shape2d::Batch, text: &mut TextBatch)
             text.add(
                 &format!("{:>4},{:<4} {}", cursor.x, cursor.y, hover_color),
                 (session.width * 0.5).floor(),
-                MARGIN + self::LINE_HEIGHT,
+                MARGIN + line_height,
                 self::TEXT_LAYER,
-                Rgba8::WHITE,
+                theme.foreground,
                 TextAlign::Left,
             );
         }
     }
 
+    if let Some(r) = self::preview_rect(session, view) {
+        // Animation preview backdrop
+        let background = match session.settings["background"].to_rgba8() {
+            c if c.a == 0 => theme.background,
+            c => c,
+        };
+        canvas.add(Shape::Rectangle(
+            Rect::new(r.x1 - 1., r.y1 - 1., r.x2 + 1., r.y2 + 1.),
+            self::UI_LAYER,
+            Rotation::ZERO,
+            Stroke::new(1.0, color::GREY.into()),
+            Fill::Solid(background.into()),
+        ));
+    }
+
     if session.settings["ui/switcher"].is_set() {
         if session.width >= 400. {
             // Fg color
             canvas.add(Shape::Rectangle(
                 Rect::origin(11., 11.).with_origin(
                     (session.width * 0.4).floor(),
-                    self::LINE_HEIGHT + self::MARGIN + 2.,
+                    line_height + self::MARGIN + 2.,
                 ),
                 self::UI_LAYER,
                 Rotation::ZERO,
-                Stroke::new(1.0, Rgba::WHITE),
+                Stroke::new(1.0, theme.foreground.into()),
                 Fill::Solid(session.fg.into()),
             ));
             // Bg color
             canvas.add(Shape::Rectangle(
                 Rect::origin(11., 11.).with_origin(
                     (session.width * 0.4).floor() + 25.,
-                    self::LINE_HEIGHT + self::MARGIN + 2.,
+                    line_height + self::MARGIN + 2.,
                 ),
                 self::UI_LAYER,
                 Rotation::ZERO,
-                Stroke::new(1.0, Rgba::WHITE),
+                Stroke::new(1.0, theme.foreground.into()),
                 Fill::Solid(session.bg.into()),
             ));
         }
     }
 
+    if session.mode == Mode::Search {
+        self::draw_search(session, canvas, text);
+    }
+
     // Command-line & message
     if session.mode == Mode::Command {
         let s = format!("{}", &session.cmdline.input());
@@ -336,18 +459,19 @@ fn draw_ui(session: &Session, canvas: &mut shape2d::Batch, text: &mut TextBatch)
             MARGIN,
             MARGIN,
             self::TEXT_LAYER,
-            Rgba8::WHITE,
+            theme.foreground,
             TextAlign::Left,
         );
         if session.settings["ui/cursor"].is_set() {
             text.glyph(
-                96,
-                MARGIN + session.cmdline.cursor as f32 * self::GLYPH_WIDTH,
+                Font::CURSOR,
+                MARGIN + session.cmdline.cursor as f32 * glyph_width,
                 MARGIN,
                 self::TEXT_LAYER,
                 Rgba8::RED,
             );
         }
+        self::draw_completions(session, canvas, text);
     } else if !session.message.is_execution()
         && !session.message.is_debug()
         && session.settings["ui/message"].is_set()
@@ -385,12 +509,139 @@ fn draw_ui(session: &Session, canvas: &mut shape2d::Batch, text: &mut TextBatch)
     }
 }
 
+/// Draw the command-line completions being cycled through, above the command line.
+fn draw_completions(session: &Session, canvas: &mut shape2d::Batch, text: &mut TextBatch) {
+    let glyph_width = text.glyph_width();
+    let line_height = self::line_height(text);
+    const MAX_ROWS: usize = 8;
+
+    let (completions, current) = match session.cmdline.autocomplete.completions() {
+        Some(c) => c,
+        None => return,
+    };
+    let theme = &session.theme;
+    let skip = (current + 1).saturating_sub(MAX_ROWS);
+    let rows = completions.len().min(MAX_ROWS);
+    let width = completions
+        .iter()
+        .skip(skip)
+        .take(rows)
+        .map(|c| c.len())
+        .max()
+        .unwrap_or(0) as f32
+        * glyph_width;
+    let bottom = MARGIN + line_height * 2.;
+
+    canvas.add(Shape::Rectangle(
+        Rect::new(
+            MARGIN - 2.,
+            bottom - 2.,
+            MARGIN + width + 2.,
+            bottom + rows as f32 * line_height,
+        ),
+        self::UI_LAYER,
+        Rotation::ZERO,
+        Stroke::new(1., theme.secondary.into()),
+        Fill::Solid(theme.background.into()),
+    ));
+
+    for (row, (i, completion)) in completions
+        .iter()
+        .enumerate()
+        .skip(skip)
+        .take(rows)
+        .enumerate()
+    {
+        let y = bottom + (rows - row - 1) as f32 * line_height;
+        let color = if i == current {
+            theme.foreground
+        } else {
+            theme.secondary
+        };
+        text.add(
+            completion,
+            MARGIN,
+            y,
+            self::TEXT_LAYER,
+            color,
+            TextAlign::Left,
+        );
+    }
+}
+
+fn draw_search(session: &Session, canvas: &mut shape2d::Batch, text: &mut TextBatch) {
+    let glyph_width = text.glyph_width();
+    let line_height = self::line_height(text);
+    // Width of the key/command column, and of the whole list, in glyphs.
+    const NAME_COLUMNS: usize = 20;
+    const COLUMNS: usize = 72;
+
+    let search = &session.search;
+    let theme = &session.theme;
+    let skip = (search.selected + 1).saturating_sub(Search::MAX_ROWS);
+    let rows = search.matches().count().min(Search::MAX_ROWS);
+    let width = COLUMNS as f32 * glyph_width;
+    let x = ((session.width - width) / 2.).floor().max(MARGIN);
+    let top = session.height - line_height * 4.;
+    let bottom = top - (rows + 1) as f32 * line_height;
+
+    canvas.add(Shape::Rectangle(
+        Rect::new(x - 4., bottom - 4., x + width + 4., top + 4.),
+        self::HELP_LAYER,
+        Rotation::ZERO,
+        Stroke::new(1., theme.secondary.into()),
+        Fill::Solid(theme.background.into()),
+    ));
+    text.add(
+        &format!("{}_", search.input),
+        x,
+        top - line_height,
+        self::HELP_LAYER,
+        theme.foreground,
+        TextAlign::Left,
+    );
+
+    for (row, (i, entry)) in search
+        .matches()
+        .enumerate()
+        .skip(skip)
+        .take(rows)
+        .enumerate()
+    {
+        let y = top - (row + 2) as f32 * line_height;
+        let (name, help) = if i == search.selected {
+            (color::RED, theme.foreground)
+        } else {
+            (theme.secondary, theme.secondary)
+        };
+        // Names longer than their column, eg. command lines from the history,
+        // push the description to the right.
+        let offset = NAME_COLUMNS.max(entry.name.chars().count() + 1);
+        let description: String = entry
+            .help
+            .chars()
+            .take(COLUMNS.saturating_sub(offset))
+            .collect();
+
+        text.add(&entry.name, x, y, self::HELP_LAYER, name, TextAlign::Left);
+        text.add(
+            &description,
+            x + offset as f32 * glyph_width,
+            y,
+            self::HELP_LAYER,
+            help,
+            TextAlign::Left,
+        );
+    }
+}
+
 fn draw_overlay(
     session: &Session,
     avg_frametime: &time::Duration,
     text: &mut TextBatch,
     exec: &Execution,
 ) {
+    let line_height = self::line_height(text);
     let debug = session.settings["debug"].is_set();
 
     match exec {
@@ -398,11 +649,29 @@ fn draw_overlay(
             text.add(
                 &format!("* recording: {} (<End> to stop)", path.display()),
                 MARGIN * 2.,
-                session.height - self::LINE_HEIGHT - MARGIN,
+                session.height - line_height - MARGIN,
                 ZDepth::ZERO,
                 color::RED,
                 TextAlign::Left,
             );
+
+            // Input activity indicator.
+            let mut inputs: Vec<String> = session
+                .keys_pressed()
+                .iter()
+                .map(|k| k.to_string())
+                .collect();
+            if session.is_mouse_pressed() {
+                inputs.push("<mouse>".to_owned());
+            }
+            text.add(
+                &inputs.join(" "),
+                MARGIN * 2.,
+                session.height - line_height * 2. - MARGIN,
+                ZDepth::ZERO,
+                color::YELLOW,
+                TextAlign::Left,
+            );
         }
         Execution::Replaying { events, path, .. } => {
             if let Some(event) = events.front() {
@@ -413,13 +682,26 @@ fn draw_overlay(
                         String::from(event.clone()),
                     ),
                     MARGIN * 2.,
-                    session.height - self::LINE_HEIGHT - MARGIN,
+                    session.height - line_height - MARGIN,
                     ZDepth::ZERO,
                     color::LIGHT_GREEN,
                     TextAlign::Left,
                 );
             }
         }
+        Execution::Fuzzing { fuzzer } => {
+            text.add(
+                &format!(
+                    "~ fuzzing: seed {} ({} frame(s) left)",
+                    fuzzer.seed, fuzzer.frames
+                ),
+                MARGIN * 2.,
+                session.height - line_height - MARGIN,
+                ZDepth::ZERO,
+                color::YELLOW,
+                TextAlign::Left,
+            );
+        }
         Execution::Normal => {}
     }
 
@@ -438,7 +720,7 @@ fn draw_overlay(
         text.add(
             txt,
             session.width - MARGIN,
-            session.height - MARGIN - self::LINE_HEIGHT,
+            session.height - MARGIN - line_height,
             ZDepth::ZERO,
             Rgba8::WHITE,
             TextAlign::Right,
@@ -457,13 +739,27 @@ fn draw_overlay(
     }
 }
 
-fn draw_palette(session: &Session, batch: &mut shape2d::Batch) {
+fn draw_palette(session: &Session, batch: &mut shape2d::Batch, text: &mut TextBatch) {
     if !session.settings["ui/palette"].is_set() {
         return;
     }
 
     let p = &session.palette;
     let height = p.height;
+
+    // Named palettes are labeled above the first column.
+    if session.palettes.active != Palettes::DEFAULT {
+        let rows = usize::min(p.size(), height) as f32;
+
+        text.add(
+            &session.palettes.active,
+            p.x + 1.,
+            p.y + rows * p.cellsize + LINE_SPACING,
+            self::TEXT_LAYER,
+            session.theme.secondary,
+            TextAlign::Left,
+        );
+    }
     for (i, color) in p.colors.iter().rev().cloned().enumerate() {
         let x = if i >= height {
             (i / height) as f32 * p.cellsize
@@ -478,6 +774,10 @@ fn draw_palette(session: &Session, batch: &mut shape2d::Batch) {
                 stroke = shape2d::Stroke::new(1., Rgba::WHITE);
             }
         }
+        // The colors are displayed in reverse.
+        if p.drag == Some(p.size() - i - 1) {
+            stroke = shape2d::Stroke::new(1., Rgba::WHITE);
+        }
 
         batch.add(Shape::Rectangle(
             Rect::new(p.x + x, p.y + y, p.x + x + p.cellsize, p.y + y + p.cellsize),
@@ -487,11 +787,138 @@ fn draw_palette(session: &Session, batch: &mut shape2d::Batch) {
             shape2d::Fill::Solid(color.into()),
         ));
     }
+
+    // Recently used foreground colors, in a strip to the right of the palette,
+    // starting at the top.
+    if session.color_history.len() > 1 {
+        let columns = (p.size() as f32 / height as f32).ceil().max(1.);
+        let rows = usize::min(p.size(), height) as f32;
+        let cellsize = (p.cellsize / 2.).floor();
+        let x = p.x + columns * p.cellsize + 2.;
+        let top = p.y + rows * p.cellsize;
+
+        for (i, color) in session.color_history.iter().enumerate() {
+            let y = top - (i + 1) as f32 * cellsize;
+
+            batch.add(Shape::Rectangle(
+                Rect::new(x, y, x + cellsize, y + cellsize),
+                self::PALETTE_LAYER,
+                Rotation::ZERO,
+                Stroke::NONE,
+                shape2d::Fill::Solid((*color).into()),
+            ));
+        }
+    }
+}
+
+fn draw_panel(session: &Session, canvas: &mut shape2d::Batch, text: &mut TextBatch) {
+    if !session.settings["ui/settings"].is_set() {
+        return;
+    }
+    let panel = &session.panel;
+    let active = panel.drag.map(|d| d.field).or(panel.hover);
+
+    for (i, field) in Field::ALL.iter().enumerate() {
+        let r = panel.rect(i);
+        let value = field.value(&session.settings, session.brush.size);
+        let stroke = if active == Some(*field) {
+            Stroke::new(1., Rgba::WHITE)
+        } else {
+            Stroke::NONE
+        };
+
+        canvas.add(Shape::Rectangle(
+            r,
+            self::UI_LAYER,
+            Rotation::ZERO,
+            stroke,
+            Fill::Solid(Rgba8::new(0, 0, 0, 0xaa).into()),
+        ));
+        text.add(
+            field.label(),
+            r.x1 + 4.,
+            r.y1 + 2.,
+            self::TEXT_LAYER,
+            color::GREY,
+            TextAlign::Left,
+        );
+        text.add(
+            &format!("< {} >", value),
+            r.x2 - 4.,
+            r.y1 + 2.,
+            self::TEXT_LAYER,
+            Rgba8::WHITE,
+            TextAlign::Right,
+        );
+    }
+}
+
+fn draw_histogram(session: &Session, canvas: &mut shape2d::Batch, text: &mut TextBatch) {
+    if !session.settings["ui/histogram"].is_set() {
+        return;
+    }
+    let bins = session
+        .active_view()
+        .with_pixels(|_, pixels| preview::histogram(pixels, HISTOGRAM_BINS));
+    let max = bins.iter().copied().max().unwrap_or_default().max(1);
+
+    // The histogram sits above a ramp of the values it counts, which are
+    // posterized along with the display.
+    let ramp = HISTOGRAM_BIN_WIDTH;
+    let width = HISTOGRAM_BINS as f32 * HISTOGRAM_BIN_WIDTH;
+    let x1 = session.width - MARGIN - width;
+    let y1 = MARGIN + self::line_height(text) * 2.;
+
+    canvas.add(Shape::Rectangle(
+        Rect::new(
+            x1 - 1.,
+            y1 - 1.,
+            x1 + width + 1.,
+            y1 + ramp + HISTOGRAM_HEIGHT + 1.,
+        ),
+        self::UI_LAYER,
+        Rotation::ZERO,
+        Stroke::new(1., color::GREY.into()),
+        Fill::Solid(Rgba8::new(0, 0, 0, 0xaa).into()),
+    ));
+
+    for (i, n) in bins.iter().enumerate() {
+        let x = x1 + i as f32 * HISTOGRAM_BIN_WIDTH;
+        let value = (i as f32 + 0.5) / HISTOGRAM_BINS as f32;
+        let value = session
+            .preview_values
+            .map_or(value, |bands| preview::posterize(value, bands));
+        // Bins are of linear luminance, while shape colors are in sRGB.
+        let value = preview::encode(value);
+
+        canvas.add(Shape::Rectangle(
+            Rect::new(x, y1, x + HISTOGRAM_BIN_WIDTH, y1 + ramp),
+            self::TEXT_LAYER,
+            Rotation::ZERO,
+            Stroke::NONE,
+            Fill::Solid(Rgba::new(value, value, value, 1.)),
+        ));
+        if *n > 0 {
+            let height = (*n as f32 / max as f32 * HISTOGRAM_HEIGHT).max(1.);
+
+            canvas.add(Shape::Rectangle(
+                Rect::new(x, y1 + ramp, x + HISTOGRAM_BIN_WIDTH, y1 + ramp + height),
+                self::TEXT_LAYER,
+                Rotation::ZERO,
+                Stroke::NONE,
+                Fill::Solid(session.theme.foreground.into()),
+            ));
+        }
+    }
 }
 
 fn draw_checker(session: &Session, batch: &mut sprite2d::Batch) {
     if session.settings["checker"].is_set() {
-        for v in session.views.iter() {
+        for v in session
+            .views
+            .iter()
+            .filter(|v| session.workspaces.contains(v.id))
+        {
             let ratio = v.width() as f32 / v.height() as f32;
             let rx = CHECKER_REPEAT * v.zoom * ratio;
             let ry = CHECKER_REPEAT * v.zoom;
@@ -509,38 +936,70 @@ fn draw_checker(session: &Session, batch: &mut sprite2d::Batch) {
 }
 
 fn draw_grid(session: &Session, batch: &mut shape2d::Batch) {
+    let v = session.active_view();
+    let t = session.offset + v.offset;
+    let w = v.width();
+    let h = v.height();
+    let m = Matrix4::from_translation(t.extend(0.)) * Matrix4::from_scale(v.zoom);
+
+    if session.settings["grid/pixels"].is_set()
+        && v.zoom >= session.settings["grid/pixels-zoom"].to_u64() as f32
+    {
+        let color = session.settings["grid/pixels-color"].to_rgba8().alpha(0x88);
+        let stroke = Stroke::new(1., color.into());
+
+        for x in 1..w {
+            let x = x as f32;
+
+            batch.add(Shape::Line(
+                Line::new([x, 0.], [x, h as f32]).transform(m),
+                self::GRID_LAYER,
+                Rotation::ZERO,
+                stroke,
+            ));
+        }
+        for y in 1..h {
+            let y = y as f32;
+
+            batch.add(Shape::Line(
+                Line::new([0., y], [w as f32, y]).transform(m),
+                self::GRID_LAYER,
+                Rotation::ZERO,
+                stroke,
+            ));
+        }
+    }
+
     if session.settings["grid"].is_set() {
         let color = session.settings["grid/color"].to_rgba8().alpha(0xcc);
         let (gx, gy) = session.settings["grid/spacing"].clone().into();
+        let major = session.settings["grid/major"].to_u64() as u32;
 
-        let v = session.active_view();
-        let t = session.offset + v.offset;
-        let w = v.width();
-        let h = v.height();
-        let m = Matrix4::from_translation(t.extend(0.)) * Matrix4::from_scale(v.zoom);
+        // Major grid lines are drawn every `major` cells, with a thicker stroke.
+        let stroke = |n: u32, spacing: u32| {
+            if major > 0 && (n / spacing) % major == 0 {
+                Stroke::new(2., color.alpha(0xff).into())
+            } else {
+                Stroke::new(1., color.into())
+            }
+        };
 
         // Grid columns.
         for x in (0..).step_by(gx as usize).skip(1).take_while(|x| *x < w) {
-            let h = h as f32;
-            let x = x as f32;
-
             batch.add(Shape::Line(
-                Line::new([x, 0.], [x, h]).transform(m),
+                Line::new([x as f32, 0.], [x as f32, h as f32]).transform(m),
                 self::GRID_LAYER,
                 Rotation::ZERO,
-                Stroke::new(1., color.into()),
+                stroke(x, gx),
             ));
         }
         // Grid rows.
         for y in (0..).step_by(gy as usize).skip(1).take_while(|y| *y < h) {
-            let w = w as f32;
-            let y = y as f32;
-
             batch.add(Shape::Line(
-                Line::new([0., y], [w, y]).transform(m),
+                Line::new([0., y as f32], [w as f32, y as f32]).transform(m),
                 self::GRID_LAYER,
                 Rotation::ZERO,
-                Stroke::new(1., color.into()),
+                stroke(y, gy),
             ));
         }
 
@@ -565,6 +1024,84 @@ fn draw_grid(session: &Session, batch: &mut shape2d::Batch) {
     }
 }
 
+fn draw_guides(session: &Session, batch: &mut shape2d::Batch) {
+    let v = session.active_view();
+    let t = session.offset + v.offset;
+    let (w, h) = (v.width(), v.height());
+    let m = Matrix4::from_translation(t.extend(0.)) * Matrix4::from_scale(v.zoom);
+    let stroke = Stroke::new(1., session.settings["guide/color"].to_rgba8().into());
+
+    for (axis, offset) in &session.guides {
+        let line = match axis {
+            Axis::Horizontal if *offset <= h => {
+                Line::new([0., *offset as f32], [w as f32, *offset as f32])
+            }
+            Axis::Vertical if *offset <= w => {
+                Line::new([*offset as f32, 0.], [*offset as f32, h as f32])
+            }
+            _ => continue,
+        };
+        batch.add(Shape::Line(
+            line.transform(m),
+            self::UI_LAYER,
+            Rotation::ZERO,
+            stroke,
+        ));
+    }
+}
+
+fn draw_ruler(session: &Session, canvas: &mut shape2d::Batch, text: &mut TextBatch) {
+    let ruler = if let Tool::Measure(Some(ruler)) = &session.tool {
+        ruler
+    } else {
+        return;
+    };
+    let v = session.active_view();
+    let t = session.offset + v.offset;
+    let m = Matrix4::from_translation(t.extend(0.)) * Matrix4::from_scale(v.zoom);
+
+    // Connect the centers of the two pixels.
+    let (from, to) = (ruler.from, ruler.to);
+    let line = Line::new(
+        [from.x as f32 + 0.5, from.y as f32 + 0.5],
+        [to.x as f32 + 0.5, to.y as f32 + 0.5],
+    );
+    canvas.add(Shape::Line(
+        line.transform(m),
+        self::UI_LAYER,
+        Rotation::ZERO,
+        Stroke::new(1., color::YELLOW.into()),
+    ));
+
+    for p in &[from, to] {
+        let r = Rect::new(p.x as f32, p.y as f32, p.x as f32 + 1., p.y as f32 + 1.);
+
+        canvas.add(Shape::Rectangle(
+            r.transform(m),
+            self::UI_LAYER,
+            Rotation::ZERO,
+            Stroke::new(1., color::YELLOW.into()),
+            Fill::Empty,
+        ));
+    }
+
+    // Readout, next to the end point.
+    let p = t + Vector2::new(to.x as f32 + 1., to.y as f32 + 1.) * v.zoom;
+    text.add(
+        &format!(
+            "dx={} dy={} d={:.2}",
+            ruler.dx(),
+            ruler.dy(),
+            ruler.distance()
+        ),
+        p.x + MARGIN,
+        p.y + MARGIN,
+        ZDepth::ZERO,
+        color::YELLOW,
+        TextAlign::Left,
+    );
+}
+
 fn draw_cursor(session: &Session, inverted: &mut sprite::Sprite, batch: &mut sprite2d::Batch) {
     if !session.settings["ui/cursor"].is_set() {
         return;
@@ -635,8 +1172,7 @@ fn draw_brush(session: &Session, brush: &Brush, shapes: &mut shape2d::Batch) {
                         // while erasing over previously erased pixels in the same stroke.
                         // To make this 100% correct, we have to read the underlying color
                         // from the view's staging buffer.
-                        if let Some(color) = v.color_at(view_coords.into()).cloned().map(Rgba::from)
-                        {
+                        if let Some(color) = v.color_at(view_coords.into()).map(Rgba::from) {
                             (
                                 Stroke::new(
                                     1.0,
@@ -669,7 +1205,7 @@ fn draw_brush(session: &Session, brush: &Brush, shapes: &mut shape2d::Batch) {
                     {
                         let p: ViewCoords<u32> = view_coords.into();
 
-                        if let Some(xray) = v.color_at(p).cloned() {
+                        if let Some(xray) = v.color_at(p) {
                             if xray != session.fg {
                                 let center = *session.session_coords(v.id, view_coords)
                                     + Vector2::new(z / 2., z / 2.);
@@ -704,6 +1240,131 @@ fn draw_brush(session: &Session, brush: &Brush, shapes: &mut shape2d::Batch) {
     }
 }
 
+/// Draw the outline of the brush footprint, over the view.
+fn draw_brush_outline(session: &Session, brush: &Brush, shapes: &mut shape2d::Batch) {
+    if !session.settings["ui/brush-outline"].is_set() || session.palette.hover.is_some() {
+        return;
+    }
+    if session.mode != Mode::Normal || session.tool != Tool::Brush {
+        return;
+    }
+    let v = session.active_view();
+    let c = session.cursor;
+
+    if !v.contains(c - session.offset) {
+        return;
+    }
+    let view_coords = session.active_view_coords(c);
+
+    for p in brush.expand(view_coords.into(), v.extent()) {
+        shapes.add(brush.shape(
+            *session.session_coords(v.id, p.into()),
+            self::CURSOR_LAYER,
+            Stroke::new(1.0, Rgba::WHITE),
+            Fill::Empty,
+            v.zoom,
+            Align::BottomLeft,
+        ));
+    }
+}
+
+/// Draw the brush size and footprint at the cursor, shortly after the size changed.
+fn draw_brush_hud(
+    session: &Session,
+    brush: &Brush,
+    shapes: &mut shape2d::Batch,
+    text: &mut TextBatch,
+) {
+    if session.brush_hud.is_zero() || session.mode != Mode::Normal {
+        return;
+    }
+    let c = session.cursor;
+    let z = session.active_view().zoom;
+    let fg = session.theme.foreground;
+
+    shapes.add(brush.shape(
+        *c,
+        self::UI_LAYER,
+        Stroke::new(1.0, fg.into()),
+        Fill::Solid(fg.alpha(0x33).into()),
+        z,
+        Align::Center,
+    ));
+    text.add(
+        &format!("{}px", brush.size),
+        c.x + brush.size as f32 * z / 2. + MARGIN,
+        c.y + brush.size as f32 * z / 2. + MARGIN,
+        ZDepth::ZERO,
+        fg,
+        TextAlign::Left,
+    );
+}
+
+/// Draw a crosshair spanning the whole workspace, at the cursor position.
+fn draw_crosshair(session: &Session, shapes: &mut shape2d::Batch) {
+    if !session.settings["ui/crosshair"].is_set() || !session.settings["input/mouse"].is_set() {
+        return;
+    }
+    let v = session.active_view();
+    let mut c = session.cursor;
+
+    // Go through the center of the pixel under the cursor, if any.
+    if v.contains(c - session.offset) {
+        let p = session.active_view_coords(c);
+        let z = v.zoom;
+
+        c = session.session_coords(v.id, p) + Vector2::new(z / 2., z / 2.);
+    }
+    let stroke = Stroke::new(1.0, color::LIGHT_GREY.alpha(0x88).into());
+
+    shapes.add(Shape::Line(
+        Line::new([c.x, 0.], [c.x, session.height]),
+        self::CURSOR_LAYER,
+        Rotation::ZERO,
+        stroke,
+    ));
+    shapes.add(Shape::Line(
+        Line::new([0., c.y], [session.width, c.y]),
+        self::CURSOR_LAYER,
+        Rotation::ZERO,
+        stroke,
+    ));
+}
+
+/// Draw information about the pixel under the cursor, next to the cursor: its
+/// coordinates, and with `ui/tooltip`, its color and palette index.
+fn draw_tooltip(session: &Session, text: &mut TextBatch) {
+    let tooltip = session.settings["ui/tooltip"].is_set();
+
+    if !(tooltip || session.settings["ui/coords"].is_set())
+        || !session.settings["input/mouse"].is_set()
+    {
+        return;
+    }
+    let (v, c) = match session.hover_view {
+        Some(v) => (v, session.cursor),
+        None => return,
+    };
+    let p = session.view_coords(v, c);
+    let mut info = format!("{},{}", p.x, p.y);
+
+    if let (true, Some(color)) = (tooltip, session.hover_color) {
+        info.push_str(&format!(" {} a={}", Rgb8::from(color), color.a));
+
+        if let Some(i) = session.palette.colors.iter().position(|c| *c == color) {
+            info.push_str(&format!(" [{}]", i));
+        }
+    }
+    text.add(
+        &info,
+        c.x + MARGIN * 2.,
+        c.y + MARGIN * 2.,
+        ZDepth::ZERO,
+        session.theme.foreground,
+        TextAlign::Left,
+    );
+}
+
 fn draw_paste(session: &Session, batch: &mut sprite2d::Batch) {
     if let (Mode::Visual(VisualState::Pasting), Some(s)) = (session.mode, session.selection) {
         batch.add(
@@ -730,6 +1391,80 @@ pub fn draw_view_animation<R>(session: &Session, v: &View<R>) -> sprite2d::Batch
     )
 }
 
+/// Screen area of the animation preview of the given view, if it's enabled.
+pub fn preview_rect<R>(session: &Session, v: &View<R>) -> Option<Rect<f32>> {
+    if !session.settings["ui/preview"].is_set() {
+        return None;
+    }
+    let scale = session.settings["ui/preview-scale"].to_u64().clamp(1, 16) as f32;
+    let (x2, y2) = (session.width - MARGIN, session.height - MARGIN);
+
+    Some(Rect::new(
+        x2 - v.fw as f32 * scale,
+        y2 - v.fh as f32 * scale,
+        x2,
+        y2,
+    ))
+}
+
+/// Draw the current animation frame of the given view in the preview area.
+pub fn draw_preview<R>(session: &Session, v: &View<R>) -> Option<sprite2d::Batch> {
+    self::preview_rect(session, v).map(|r| {
+        sprite2d::Batch::singleton(
+            v.width(),
+            v.fh,
+            *v.animation.val(),
+            r,
+            self::PALETTE_LAYER,
+            Rgba::TRANSPARENT,
+            1.,
+            Repeat::default(),
+        )
+    })
+}
+
+/// Draw the current animation frame of the reference view over each frame of the
+/// given view, semi-transparently.
+pub fn draw_reference<R>(session: &Session, v: &View<R>, reference: &View<R>) -> sprite2d::Batch {
+    let opacity = session.settings["reference/opacity"].to_f64().clamp(0., 1.) as f32;
+    let mut batch = sprite2d::Batch::new(reference.width(), reference.fh);
+
+    for frame in v.animation.frames.iter() {
+        batch.add(
+            *reference.animation.val(),
+            Rect::new(
+                frame.x1,
+                0.,
+                frame.x1 + reference.fw as f32,
+                reference.fh as f32,
+            ) * v.zoom
+                + (session.offset + v.offset),
+            self::VIEW_LAYER,
+            Rgba::TRANSPARENT,
+            opacity,
+            Repeat::default(),
+        );
+    }
+    batch
+}
+
+/// Draw the compared view over the given view, at half opacity, aligned to its
+/// bottom-left corner.
+pub fn draw_compare<R>(session: &Session, v: &View<R>, other: &View<R>) -> sprite2d::Batch {
+    let mut batch = sprite2d::Batch::new(other.width(), other.fh);
+    let r = Rect::new(0., 0., other.width() as f32, other.fh as f32);
+
+    batch.add(
+        r,
+        r * v.zoom + (session.offset + v.offset),
+        self::VIEW_LAYER,
+        Rgba::TRANSPARENT,
+        0.5,
+        Repeat::default(),
+    );
+    batch
+}
+
 pub fn draw_view_composites<R>(session: &Session, v: &View<R>) -> sprite2d::Batch {
     let mut batch = sprite2d::Batch::new(v.width(), v.fh);
 
@@ -748,6 +1483,10 @@ pub fn draw_view_composites<R>(session: &Session, v: &View<R>) -> sprite2d::Batc
 }
 
 pub fn draw_help(session: &Session, text: &mut TextBatch, shape: &mut shape2d::Batch) {
+    text.set_scale(session.settings["ui/text-scale"].to_u64() as f32);
+
+    let glyph_width = text.glyph_width();
+    let line_height = self::line_height(text);
     shape.add(Shape::Rectangle(
         Rect::origin(session.width, session.height),
         ZDepth(0.0),
@@ -763,7 +1502,7 @@ pub fn draw_help(session: &Session, text: &mut TextBatch, shape: &mut shape2d::B
         Fill::Solid(Rgba::BLACK),
     ));
 
-    let column_offset = self::GLYPH_WIDTH * 24.;
+    let column_offset = glyph_width * 24.;
     let left_margin = self::MARGIN * 2.;
 
     text.add(
@@ -773,7 +1512,7 @@ pub fn draw_help(session: &Session, text: &mut TextBatch, shape: &mut shape2d::B
             platform::Key::Escape,
         ),
         left_margin,
-        session.height - self::MARGIN - self::LINE_HEIGHT,
+        session.height - self::MARGIN - line_height,
         self::HELP_LAYER,
         color::LIGHT_GREY,
         TextAlign::Left,
@@ -788,9 +1527,9 @@ pub fn draw_help(session: &Session, text: &mut TextBatch, shape: &mut shape2d::B
         .filter_map(|kb| kb.display.as_ref().map(|d| (d, kb)))
         .partition(|(_, kb)| kb.modes.contains(&Mode::Normal));
 
-    let mut line = (0..(session.height as usize - self::LINE_HEIGHT as usize * 4))
+    let mut line = (0..(session.height as usize - line_height as usize * 4))
         .rev()
-        .step_by(self::LINE_HEIGHT as usize);
+        .step_by(line_height as usize);
 
     for (display, kb) in normal_kbs.iter() {
         if let Some(y) = line.next() {
@@ -859,7 +1598,7 @@ pub fn draw_help(session: &Session, text: &mut TextBatch, shape: &mut shape2d::B
     }
 
     for (i, l) in session.help().iter().enumerate() {
-        let y = session.height - (i + 4) as f32 * self::LINE_HEIGHT;
+        let y = session.height - (i + 4) as f32 * line_height;
 
         text.add(
             l,
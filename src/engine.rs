@@ -0,0 +1,207 @@
+//! Headless editing engine, for embedding rx in other programs.
+//!
+//! The engine drives a [`Session`] without a window. Views are edited with the
+//! same commands available on the rx command line, and rendered on the CPU with
+//! the software renderer, so that their pixels can be read back at any time.
+//!
+//! ```no_run
+//! use rx::Engine;
+//!
+//! let mut engine = Engine::new(16, 16)?;
+//!
+//! engine.execute("paint/color #ff0000 0 0")?;
+//! engine.execute("undo")?;
+//!
+//! assert_eq!(engine.pixels().len(), 16 * 16);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+use crate::data::{self, Assets};
+use crate::execution::Execution;
+use crate::gfx::Rgba8;
+use crate::platform::LogicalSize;
+use crate::renderer::Renderer as _;
+use crate::session::{ExitReason, Session, State};
+use crate::soft;
+use crate::view::FileStatus;
+
+use directories as dirs;
+
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// A headless rx session.
+pub struct Engine {
+    session: Session,
+    renderer: soft::Renderer,
+    execution: Execution,
+}
+
+impl Engine {
+    /// Size of the virtual window the session is laid out in.
+    const WINDOW_SIZE: LogicalSize = LogicalSize::new(1280., 720.);
+
+    /// Create an engine with a blank view of the given size. The user's
+    /// `init.rx` is not sourced.
+    pub fn new(width: u32, height: u32) -> io::Result<Self> {
+        let proj_dirs = dirs::ProjectDirs::from("io", "cloudhead", "rx")
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "config directory not found"))?;
+        let base_dirs = dirs::BaseDirs::new()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "home directory not found"))?;
+        let cwd = std::env::current_dir()?;
+        let size = Self::WINDOW_SIZE;
+
+        let session = Session::new(
+            size.width as u32,
+            size.height as u32,
+            cwd,
+            proj_dirs,
+            base_dirs,
+        )
+        .with_blank(FileStatus::NoFile, width, height)
+        .init(Some("-".into()))?;
+        let renderer = soft::Renderer::headless(size, 1., Assets::new(data::GLYPHS))?;
+
+        let mut engine = Self {
+            session,
+            renderer,
+            execution: Execution::Normal,
+        };
+        let effects = engine.session.update(
+            &mut vec![],
+            &mut engine.execution,
+            Duration::default(),
+            Duration::default(),
+        );
+        engine.renderer.init(effects, &engine.session);
+
+        Ok(engine)
+    }
+
+    /// Open the given paths in new views. The last view opened becomes active.
+    pub fn open<P: AsRef<Path>>(&mut self, paths: &[P]) -> io::Result<()> {
+        self.session.edit(paths)?;
+        self.step()
+    }
+
+    /// Execute a command, eg. `"paint/color #ff0000 1 1"` or `"undo"`. The
+    /// leading `:` is optional. Returns an error if the command can't be parsed,
+    /// or if it fails. Files written by the command are written by the time
+    /// this returns.
+    pub fn execute(&mut self, input: &str) -> io::Result<()> {
+        let result = self.session.remote(input);
+
+        self.step()?;
+
+        result
+            .map(|_| ())
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+    }
+
+    /// Pixels of the active view, row by row, starting from the top.
+    pub fn pixels(&self) -> Vec<Rgba8> {
+        let (_, pixels) = self.session.active_view().pixels();
+
+        pixels
+    }
+
+    /// Size of the active view, including all of its frames.
+    pub fn size(&self) -> (u32, u32) {
+        let extent = self.session.active_view().extent();
+
+        (extent.width(), extent.height())
+    }
+
+    /// Colors of the palette.
+    pub fn palette(&self) -> &[Rgba8] {
+        &self.session.palette.colors
+    }
+
+    /// Whether the session is still running, ie. it hasn't been quit.
+    pub fn is_running(&self) -> bool {
+        self.session.is_running()
+    }
+
+    /// The underlying session.
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+
+    /// Run a single session update and render, applying pending edits.
+    fn step(&mut self) -> io::Result<()> {
+        let effects = self.session.update(
+            &mut vec![],
+            &mut self.execution,
+            Duration::default(),
+            Duration::default(),
+        );
+        self.renderer.frame(
+            &mut self.session,
+            &mut self.execution,
+            effects,
+            &Duration::default(),
+        )?;
+        self.session.cleanup();
+
+        if let Some(err) = self.session.wait_for_saves() {
+            return Err(io::Error::new(io::ErrorKind::Other, err));
+        }
+        match &self.session.state {
+            State::Closing(ExitReason::Error(e)) => {
+                Err(io::Error::new(io::ErrorKind::Other, e.clone()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_engine() {
+        let mut engine = Engine::new(8, 4).unwrap();
+
+        assert_eq!(engine.size(), (8, 4));
+        assert!(engine.pixels().iter().all(|p| *p == Rgba8::TRANSPARENT));
+
+        engine.execute("paint/color #ff0000 1 2").unwrap();
+        let painted: Vec<_> = engine
+            .pixels()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, p)| *p != Rgba8::TRANSPARENT)
+            .collect();
+        assert_eq!(painted, vec![(2 * 8 + 1, Rgba8::RED)]);
+
+        engine.execute(":undo").unwrap();
+        assert!(engine.pixels().iter().all(|p| *p == Rgba8::TRANSPARENT));
+
+        assert!(engine.execute("paint/color red").is_err());
+        assert!(engine.is_running());
+
+        // Commands that fail when run are errors too.
+        assert_eq!(
+            engine.execute("f/resize 0 0").unwrap_err().to_string(),
+            "cannot set frame dimension to `0`"
+        );
+        assert_eq!(
+            engine.execute("w").unwrap_err().to_string(),
+            "no file name given"
+        );
+
+        // Files are written by the time the command returns.
+        let dir = std::env::temp_dir().join(format!("rx-engine-{}", std::process::id()));
+        let path = dir.join("engine.png");
+
+        engine.execute(&format!("w {}", path.display())).unwrap();
+        assert_eq!(crate::image::load(&path).unwrap().1, 8);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        engine.execute("q!").unwrap();
+        assert!(!engine.is_running());
+    }
+}
@@ -1,5 +1,8 @@
 use crate::event::TimedEvent;
+use crate::fuzz::Fuzzer;
 use crate::gfx::Rgba8;
+use crate::image;
+use crate::renderer::Backend;
 use crate::util;
 
 use std::collections::VecDeque;
@@ -92,6 +95,8 @@ pub enum ExecutionMode {
     Normal,
     Record(PathBuf, DigestMode, GifMode),
     Replay(PathBuf, DigestMode),
+    /// Fuzz the session for a number of frames, with the given seed.
+    Fuzz(u64, u64),
 }
 
 /// Execution mode. Controls whether the session is playing or recording
@@ -130,6 +135,11 @@ pub enum Execution {
         /// Frame recorder.
         recorder: FrameRecorder,
     },
+    /// Feeding randomized inputs to the session.
+    Fuzzing {
+        /// Input generator.
+        fuzzer: Fuzzer,
+    },
 }
 
 impl Execution {
@@ -138,6 +148,13 @@ impl Execution {
         Ok(Self::Normal)
     }
 
+    /// Create a fuzzing execution.
+    pub fn fuzzing(seed: u64, frames: u64) -> io::Result<Self> {
+        Ok(Self::Fuzzing {
+            fuzzer: Fuzzer::new(seed, frames),
+        })
+    }
+
     /// Create a recording.
     pub fn recording<P: AsRef<Path>>(
         path: P,
@@ -145,6 +162,7 @@ impl Execution {
         w: u16,
         h: u16,
         gif_mode: GifMode,
+        backend: Backend,
     ) -> io::Result<Self> {
         use io::{Error, ErrorKind};
 
@@ -159,7 +177,7 @@ impl Execution {
 
         std::fs::create_dir_all(path)?;
 
-        let digest = DigestState::from(digest_mode, path.join(file_name).with_extension("digest"))?;
+        let digest = DigestState::from(digest_mode, Self::digest_path(path, file_name, backend))?;
         let gif_recorder = if gif_mode == GifMode::Record {
             GifRecorder::new(path.join(file_name).with_extension("gif"), w, h)?
         } else {
@@ -177,7 +195,11 @@ impl Execution {
     }
 
     /// Create a replay.
-    pub fn replaying<P: AsRef<Path>>(path: P, mode: DigestMode) -> io::Result<Self> {
+    pub fn replaying<P: AsRef<Path>>(
+        path: P,
+        mode: DigestMode,
+        backend: Backend,
+    ) -> io::Result<Self> {
         use io::{Error, ErrorKind};
 
         let mut events = VecDeque::new();
@@ -191,7 +213,7 @@ impl Execution {
             ))?
             .as_ref();
 
-        let digest = DigestState::from(mode, path.join(file_name).with_extension("digest"))?;
+        let digest = DigestState::from(mode, Self::digest_path(path, file_name, backend))?;
 
         let recorder = match &digest {
             DigestState {
@@ -217,7 +239,7 @@ impl Execution {
                         ));
                     }
                 }
-                FrameRecorder::from(frames, mode)
+                FrameRecorder::from(frames, mode, path.with_extension(""))
             }
             _ => FrameRecorder::new(GifRecorder::dummy(), GifMode::Ignore, mode),
         };
@@ -260,7 +282,12 @@ impl Execution {
         matches!(self, Execution::Recording { .. })
     }
 
-    pub fn record(&mut self, data: &[Rgba8]) -> Result<(), VerifyResult> {
+    pub fn is_fuzzing(&self) -> bool {
+        matches!(self, Execution::Fuzzing { .. })
+    }
+
+    /// Record a frame of the given size.
+    pub fn record(&mut self, data: &[Rgba8], w: u32, h: u32) -> Result<(), VerifyResult> {
         match self {
             // Replaying and verifying digests.
             Self::Replaying {
@@ -273,9 +300,16 @@ impl Execution {
                 recorder,
                 ..
             } => {
-                let vr = recorder.verify_frame(data);
+                let vr = recorder.verify_frame(data, w, h);
                 result.record(&vr);
 
+                if vr.is_err() && result.failures <= FrameRecorder::MAX_FAILURE_ARTIFACTS {
+                    match recorder.save_artifacts(data, w, h, result.failures) {
+                        Ok(path) => error!("verify: frame saved to `{}`", path.display()),
+                        Err(e) => error!("verify: error saving frame: {}", e),
+                    }
+                }
+
                 if vr.is_err() {
                     return Err(vr);
                 }
@@ -359,6 +393,18 @@ impl Execution {
 
     ////////////////////////////////////////////////////////////////////////////
 
+    /// Path of the digest file of a recording. Renderers don't rasterize
+    /// identically, so backends other than GL keep their own digests, eg.
+    /// `simple.soft.digest`.
+    fn digest_path(path: &Path, file_name: &Path, backend: Backend) -> PathBuf {
+        match backend {
+            Backend::Gl => path.join(file_name).with_extension("digest"),
+            other => path
+                .join(file_name)
+                .with_extension(format!("{}.digest", other)),
+        }
+    }
+
     fn write_digest<P: AsRef<Path>>(recorder: &FrameRecorder, path: P) -> io::Result<()> {
         let path = path.as_ref();
 
@@ -451,26 +497,38 @@ impl GifRecorder {
 pub struct FrameRecorder {
     frames: VecDeque<Hash>,
     last_verified: Option<Hash>,
+    /// The last frame that passed verification, and its size.
+    last_okay: Option<(Vec<Rgba8>, u32, u32)>,
+    /// Path prefix of the images saved when verification fails.
+    artifacts: PathBuf,
     gif_recorder: GifRecorder,
     gif_mode: GifMode,
     digest_mode: DigestMode,
 }
 
 impl FrameRecorder {
+    /// Number of mismatching frames for which images are saved, after which
+    /// the replay is aborted.
+    pub const MAX_FAILURE_ARTIFACTS: u32 = 3;
+
     fn new(gif_recorder: GifRecorder, gif_mode: GifMode, digest_mode: DigestMode) -> Self {
         Self {
             frames: VecDeque::new(),
             last_verified: None,
+            last_okay: None,
+            artifacts: PathBuf::new(),
             gif_recorder,
             gif_mode,
             digest_mode,
         }
     }
 
-    fn from(frames: Vec<Hash>, digest_mode: DigestMode) -> Self {
+    fn from(frames: Vec<Hash>, digest_mode: DigestMode, artifacts: PathBuf) -> Self {
         Self {
             frames: frames.into(),
             last_verified: None,
+            last_okay: None,
+            artifacts,
             gif_recorder: GifRecorder::dummy(),
             gif_mode: GifMode::Ignore,
             digest_mode,
@@ -491,7 +549,7 @@ impl FrameRecorder {
         }
     }
 
-    fn verify_frame(&mut self, data: &[Rgba8]) -> VerifyResult {
+    fn verify_frame(&mut self, data: &[Rgba8], w: u32, h: u32) -> VerifyResult {
         let actual = Self::hash(data);
 
         if self.frames.is_empty() {
@@ -504,6 +562,8 @@ impl FrameRecorder {
 
         if let Some(expected) = self.frames.pop_front() {
             if actual == expected {
+                self.last_okay = Some((data.to_vec(), w, h));
+
                 VerifyResult::Okay(actual)
             } else {
                 VerifyResult::Failed(actual, expected)
@@ -517,6 +577,42 @@ impl FrameRecorder {
         self.gif_recorder.finish()
     }
 
+    /// Save the images of a mismatching frame, numbered `n`. Since digests
+    /// don't hold the expected pixels, the frame is saved alongside the last
+    /// frame that passed verification, and a diff of the two, with changed
+    /// pixels highlighted. Returns the path of the actual frame.
+    fn save_artifacts(&self, data: &[Rgba8], w: u32, h: u32, n: u32) -> io::Result<PathBuf> {
+        let prefix = format!("{}.failed-{}", self.artifacts.display(), n);
+        let actual = PathBuf::from(format!("{}.actual.png", prefix));
+
+        image::save_as(&actual, w, h, 1, data)?;
+
+        if let Some((prev, pw, ph)) = &self.last_okay {
+            image::save_as(format!("{}.previous.png", prefix), *pw, *ph, 1, prev)?;
+
+            if (*pw, *ph) == (w, h) {
+                let diff = Self::diff(prev, data);
+                image::save_as(format!("{}.diff.png", prefix), w, h, 1, &diff)?;
+            }
+        }
+        Ok(actual)
+    }
+
+    /// Highlight the pixels that differ between two frames of the same size.
+    /// Unchanged pixels are dimmed.
+    fn diff(a: &[Rgba8], b: &[Rgba8]) -> Vec<Rgba8> {
+        a.iter()
+            .zip(b)
+            .map(|(a, b)| {
+                if a == b {
+                    Rgba8::new(b.r / 4, b.g / 4, b.b / 4, 0xff)
+                } else {
+                    Rgba8::new(0xff, 0x0, 0xff, 0xff)
+                }
+            })
+            .collect()
+    }
+
     fn hash(data: &[Rgba8]) -> Hash {
         use std::hash::Hasher;
 
@@ -531,14 +627,16 @@ impl FrameRecorder {
 #[derive(Debug, Clone)]
 pub struct ReplayResult {
     eof: bool,
-    failed: bool,
+    failures: u32,
+    /// Number of frames verified before the first failure.
+    failed_after: u32,
     okay_count: u32,
     stale_count: u32,
 }
 
 impl ReplayResult {
     pub fn is_ok(&self) -> bool {
-        !self.failed
+        self.failures == 0
     }
 
     pub fn is_err(&self) -> bool {
@@ -549,9 +647,17 @@ impl ReplayResult {
         self.eof
     }
 
+    /// Whether enough frames failed verification that the replay should stop.
+    pub fn is_aborted(&self) -> bool {
+        self.failures >= FrameRecorder::MAX_FAILURE_ARTIFACTS
+    }
+
     pub fn summary(&self) -> String {
         if self.is_err() {
-            format!("replay failed after {} frames", self.okay_count)
+            format!(
+                "replay failed after {} frames ({} mismatching)",
+                self.failed_after, self.failures
+            )
         } else {
             format!("ok ({} frames)", self.okay_count)
         }
@@ -563,7 +669,8 @@ impl ReplayResult {
         ReplayResult {
             okay_count: 0,
             stale_count: 0,
-            failed: false,
+            failures: 0,
+            failed_after: 0,
             eof: false,
         }
     }
@@ -576,7 +683,11 @@ impl ReplayResult {
             }
             VerifyResult::Failed(actual, expected) => {
                 error!("verify: {} != {}", actual, expected);
-                self.failed = true;
+
+                if self.failures == 0 {
+                    self.failed_after = self.okay_count;
+                }
+                self.failures += 1;
             }
             VerifyResult::Eof => {
                 self.eof = true;
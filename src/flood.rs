@@ -3,7 +3,7 @@ use crate::gfx::math::Point2;
 use crate::gfx::rect::Rect;
 use crate::gfx::shape2d::{Fill, Rotation, Shape, Stroke};
 use crate::gfx::ZDepth;
-use crate::view::{View, ViewCoords, ViewResource};
+use crate::view::{Resource, View, ViewCoords};
 
 struct Grid {
     pixels: Vec<Rgba8>,
@@ -47,17 +47,13 @@ pub struct FloodFiller {
 
 impl FloodFiller {
     pub fn new(
-        view: &View<ViewResource>,
+        view: &View<Resource>,
         starting_point: ViewCoords<f32>,
         replacement_color: Rgba8,
     ) -> Option<FloodFiller> {
-        let (snapshot, pixels) = view.layer.current_snapshot();
-        let bounds = snapshot.extent.rect();
-        let grid = Grid::new(
-            pixels.to_vec(),
-            bounds.width() as usize,
-            bounds.height() as usize,
-        );
+        let (extent, pixels) = view.pixels();
+        let bounds = extent.rect();
+        let grid = Grid::new(pixels, bounds.width() as usize, bounds.height() as usize);
 
         let starting_point = Point2::new(
             starting_point.x as usize,
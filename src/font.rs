@@ -1,5 +1,131 @@
 use crate::gfx::sprite2d;
 use crate::gfx::{Rect, Repeat, Rgba8, ZDepth};
+use crate::image;
+
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
+
+/// A bitmap font. Glyphs are laid out in a grid of [`Font::COLUMNS`] by
+/// [`Font::ROWS`], starting with the space character, and all have the same size.
+#[derive(Clone)]
+pub struct Font {
+    /// Atlas width.
+    pub width: u32,
+    /// Atlas height.
+    pub height: u32,
+    /// Atlas pixels, top row first.
+    pub pixels: Vec<Rgba8>,
+}
+
+impl Font {
+    /// Number of glyphs per atlas row.
+    pub const COLUMNS: u32 = 16;
+    /// Number of glyph rows in the atlas.
+    pub const ROWS: u32 = 8;
+    /// Glyph used for the text cursor.
+    pub const CURSOR: usize = 96;
+
+    /// Size of a uf2 file: a table of glyph widths, followed by the glyphs.
+    const UF2_SIZE: usize = 256 + 256 * 32;
+
+    /// Decode a font atlas from PNG data.
+    pub fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let (pixels, width, height) = image::read(bytes)?;
+
+        if width % Self::COLUMNS != 0 || height % Self::ROWS != 0 || width == 0 || height == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "font atlas must be a grid of {}x{} glyphs",
+                    Self::COLUMNS,
+                    Self::ROWS
+                ),
+            ));
+        }
+        Ok(Self {
+            width,
+            height,
+            pixels: Rgba8::align(&pixels).to_vec(),
+        })
+    }
+
+    /// Decode a font in the uf2 format, used by Uxn programs. Glyphs are up to
+    /// 16x16 pixels, and are made monospace by using the widest advance of the
+    /// printable characters. Rows below the lowest pixel are trimmed.
+    pub fn from_uf2(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() != Self::UF2_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "uf2 fonts must be {} bytes long, got {}",
+                    Self::UF2_SIZE,
+                    bytes.len()
+                ),
+            ));
+        }
+        let (widths, glyphs) = bytes.split_at(256);
+        let printable = 32..128;
+
+        // Glyphs are stored as four 8x8 tiles, one bit per pixel, in the order:
+        // top-left, bottom-left, top-right, bottom-right.
+        let pixel = |c: usize, x: usize, y: usize| -> bool {
+            let tile = (x / 8) * 2 + y / 8;
+            let row = glyphs[c * 32 + tile * 8 + y % 8];
+
+            row & (0x80 >> (x % 8)) != 0
+        };
+
+        let gw = printable
+            .clone()
+            .map(|c| widths[c] as u32)
+            .max()
+            .unwrap_or(0)
+            .clamp(1, 16);
+        let gh = printable
+            .flat_map(|c| (0..16).filter(move |y| (0..16).any(|x| pixel(c, x, *y))))
+            .max()
+            .map_or(16, |y| y as u32 + 1);
+
+        let width = gw * Self::COLUMNS;
+        let height = gh * Self::ROWS;
+        let mut pixels = vec![Rgba8::TRANSPARENT; (width * height) as usize];
+
+        for i in 0..(Self::COLUMNS * Self::ROWS) as usize {
+            let (gx, gy) = (
+                (i as u32 % Self::COLUMNS) * gw,
+                (i as u32 / Self::COLUMNS) * gh,
+            );
+            for y in 0..gh {
+                for x in 0..gw {
+                    let on = if i == Self::CURSOR {
+                        true
+                    } else {
+                        pixel(i + 32, x as usize, y as usize)
+                    };
+                    if on {
+                        pixels[((gy + y) * width + gx + x) as usize] = Rgba8::WHITE;
+                    }
+                }
+            }
+        }
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+}
+
+impl fmt::Debug for Font {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Font")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
 
 pub enum TextAlign {
     Left,
@@ -10,13 +136,47 @@ pub struct TextBatch {
     raw: sprite2d::Batch,
     gw: f32,
     gh: f32,
+    /// Scale at which glyphs are drawn.
+    scale: f32,
+    /// Hash of everything added to the batch.
+    hasher: DefaultHasher,
 }
 
 impl TextBatch {
     pub fn new(w: u32, h: u32, gw: f32, gh: f32) -> Self {
         let raw = sprite2d::Batch::new(w, h);
+        let mut batch = Self {
+            raw,
+            gw,
+            gh,
+            scale: 1.,
+            hasher: DefaultHasher::new(),
+        };
+        batch.clear();
+        batch
+    }
+
+    /// Create an empty text batch for a font atlas of the given size.
+    pub fn atlas(w: u32, h: u32) -> Self {
+        Self::new(w, h, (w / Font::COLUMNS) as f32, (h / Font::ROWS) as f32)
+    }
+
+    /// Set the scale at which glyphs are drawn. Should be a whole number, for
+    /// glyphs to stay crisp.
+    pub fn set_scale(&mut self, scale: f32) {
+        scale.to_bits().hash(&mut self.hasher);
+
+        self.scale = scale;
+    }
 
-        Self { raw, gw, gh }
+    /// Width of a single glyph, as drawn.
+    pub fn glyph_width(&self) -> f32 {
+        self.gw * self.scale
+    }
+
+    /// Height of a single glyph, as drawn.
+    pub fn glyph_height(&self) -> f32 {
+        self.gh * self.scale
     }
 
     pub fn add(
@@ -30,14 +190,19 @@ impl TextBatch {
     ) {
         let offset: usize = 32;
 
+        text.hash(&mut self.hasher);
+        self.hash_glyph(sx, sy, z, color);
+        matches!(align, TextAlign::Right).hash(&mut self.hasher);
+
         let gw = self.gw;
         let gh = self.gh;
+        let (dw, dh) = (self.glyph_width(), self.glyph_height());
         let rgba = color.into();
 
         match align {
             TextAlign::Left => {}
             TextAlign::Right => {
-                sx -= gw * text.chars().count() as f32;
+                sx -= dw * text.chars().count() as f32;
             }
         }
 
@@ -48,23 +213,30 @@ impl TextBatch {
 
             self.raw.add(
                 Rect::new(x, y, x + gw, y + gh),
-                Rect::new(sx, sy, sx + gw, sy + gh),
+                Rect::new(sx, sy, sx + dw, sy + dh),
                 z,
                 rgba,
                 1.0,
                 Repeat::default(),
             );
-            sx += gw;
+            sx += dw;
         }
     }
 
     pub fn offset(&mut self, x: f32, y: f32) {
+        x.to_bits().hash(&mut self.hasher);
+        y.to_bits().hash(&mut self.hasher);
+
         self.raw.offset(x, y);
     }
 
     pub fn glyph(&mut self, glyph: usize, sx: f32, sy: f32, z: ZDepth, color: Rgba8) {
+        glyph.hash(&mut self.hasher);
+        self.hash_glyph(sx, sy, z, color);
+
         let gw = self.gw;
         let gh = self.gh;
+        let (dw, dh) = (self.glyph_width(), self.glyph_height());
         let rgba = color.into();
 
         let i: usize = glyph;
@@ -73,7 +245,7 @@ impl TextBatch {
 
         self.raw.add(
             Rect::new(x, y, x + gw, y + gh),
-            Rect::new(sx, sy, sx + gw, sy + gh),
+            Rect::new(sx, sy, sx + dw, sy + dh),
             z,
             rgba,
             1.0,
@@ -85,7 +257,55 @@ impl TextBatch {
         self.raw.vertices()
     }
 
+    /// Hash of the batch contents. Batches with the same hash have the same vertices.
+    pub fn hash(&self) -> u64 {
+        self.hasher.finish()
+    }
+
     pub fn clear(&mut self) {
-        self.raw.clear()
+        self.hasher = DefaultHasher::new();
+        self.raw.clear();
+
+        // Batches built with different fonts don't share vertices.
+        self.gw.to_bits().hash(&mut self.hasher);
+        self.gh.to_bits().hash(&mut self.hasher);
+    }
+
+    fn hash_glyph(&mut self, sx: f32, sy: f32, z: ZDepth, color: Rgba8) {
+        let Rgba8 { r, g, b, a } = color;
+
+        sx.to_bits().hash(&mut self.hasher);
+        sy.to_bits().hash(&mut self.hasher);
+        z.0.to_bits().hash(&mut self.hasher);
+        [r, g, b, a].hash(&mut self.hasher);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_uf2() {
+        let mut bytes = vec![0; Font::UF2_SIZE];
+        // An 'A' that is 6 pixels wide, with a pixel in each corner of a 6x10 box.
+        let a = b'A' as usize;
+        bytes[a] = 6;
+        bytes[256 + a * 32] = 0b1000_0100;
+        bytes[256 + a * 32 + 8 + 1] = 0b1000_0100;
+
+        let font = Font::from_uf2(&bytes).unwrap();
+        assert_eq!(font.width, 6 * Font::COLUMNS);
+        assert_eq!(font.height, 10 * Font::ROWS);
+
+        let (gx, gy) = ((a as u32 - 32) % 16 * 6, (a as u32 - 32) / 16 * 10);
+        let at = |x: u32, y: u32| font.pixels[((gy + y) * font.width + gx + x) as usize];
+        assert_eq!(at(0, 0), Rgba8::WHITE);
+        assert_eq!(at(5, 0), Rgba8::WHITE);
+        assert_eq!(at(0, 9), Rgba8::WHITE);
+        assert_eq!(at(5, 9), Rgba8::WHITE);
+        assert_eq!(at(1, 1), Rgba8::TRANSPARENT);
+
+        assert!(Font::from_uf2(&bytes[1..]).is_err());
     }
 }
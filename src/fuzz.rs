@@ -0,0 +1,360 @@
+//! Randomized session inputs, for catching panics and invariant violations.
+//!
+//! Inputs are generated from a seed, so that a failing run can be reproduced
+//! by fuzzing again with the same seed.
+use crate::event::Event;
+use crate::platform::{
+    InputState, Key, KeyboardInput, LogicalDelta, LogicalPosition, ModifiersState, MouseButton,
+};
+
+/// An input generated by the fuzzer.
+#[derive(Debug, Clone)]
+pub enum Input {
+    /// A user input event.
+    Event(Event),
+    /// A command line, without the leading `:`.
+    Command(String),
+}
+
+/// Generates randomized streams of events and commands.
+#[derive(Debug)]
+pub struct Fuzzer {
+    /// The seed the fuzzer was created with.
+    pub seed: u64,
+    /// Number of frames left to fuzz.
+    pub frames: u64,
+    /// Random number generator state.
+    state: u64,
+}
+
+impl Fuzzer {
+    /// Maximum number of inputs generated per frame.
+    const MAX_INPUTS: u64 = 4;
+    /// Commands which may be generated. Placeholders are replaced with random
+    /// values: `{n}` with a small natural number, `{s}` with a size, `{i}`
+    /// with an integer, `{c}` with a color, `{d}` with an angle and `{z}` with
+    /// a zoom level. Commands which touch the file system or quit are left out.
+    const COMMANDS: &'static [&'static str] = &[
+        "brush",
+        "brush/size {s}",
+        "brush/size +",
+        "brush/size -",
+        "brush/toggle xsym",
+        "brush/toggle ysym",
+        "brush/toggle erase",
+        "brush/toggle multi",
+        "brush/toggle line",
+        "brush/toggle perfect",
+        "brush/toggle recolor",
+        "brush/set dither checker {z}",
+        "brush/set dither bayer 8",
+        "brush/unset dither",
+        "flood",
+        "sampler",
+        "sampler/off",
+        "measure",
+        "tool/prev",
+        "visual",
+        "mode normal",
+        "undo",
+        "redo",
+        "history/clear",
+        "checkpoint save {s}",
+        "checkpoint restore {s}",
+        "unerase",
+        "f/add",
+        "f/clone {i}",
+        "f/remove",
+        "f/prev",
+        "f/next",
+        "f/resize {s} {s}",
+        "slice {n}",
+        "slice",
+        "pair {n} {n}",
+        "pair/clear",
+        "v/next",
+        "v/prev",
+        "v/center",
+        "v/split {n} {n}",
+        "v/split/copy {n} {n}",
+        "v/append {n}",
+        "v/clear",
+        "v/fill {c}",
+        "zoom {z}",
+        "zoom +",
+        "zoom -",
+        "zoom fit",
+        "pan {i} {i}",
+        "cursor/move {i} {i}",
+        "goto {n} {n}",
+        "p/add {c}",
+        "p/gradient {c} {c} {n}",
+        "p/sample",
+        "p/sort",
+        "p/clear",
+        "colorkey {c}",
+        "shift {i} {i}",
+        "align center",
+        "align bottom",
+        "trim",
+        "trim {n}",
+        "v/mirror",
+        "ws/new {s}",
+        "ws/next",
+        "ws/prev",
+        "lut/set",
+        "lut/ir",
+        "lut/apply",
+        "reference/anim {n}",
+        "reference/anim",
+        "compare {n}",
+        "compare",
+        "swap",
+        "guide h {n}",
+        "guide v {n}",
+        "guide/clear",
+        "selection/move {i} {i}",
+        "selection/resize {i} {i}",
+        "selection/offset {i} {i}",
+        "selection/expand",
+        "selection/yank",
+        "selection/cut",
+        "selection/paste",
+        "selection/erase",
+        "selection/fill {c}",
+        "selection/flip x",
+        "selection/flip y",
+        "selection/rotate {d}",
+        "preview/rotate {d}",
+        "selection/jump +",
+        "selection/jump -",
+        "selection/grow {n}",
+        "selection/shrink {n}",
+        "selection/invert",
+        "select/color {c}",
+        "gen/noise 0.5",
+        "gen/noise 0.5 {n}",
+        "gen/checker {z}",
+        "gen/bayer 4",
+        "paint/color {c} {i} {i}",
+        "paint/line {c} {i} {i} {i} {i}",
+        "paint/fg {i} {i}",
+        "paint/bg {i} {i}",
+        "paint/p {n} {i} {i}",
+        "toggle checker",
+        "toggle grid",
+        "toggle animation",
+        "toggle snap",
+        "toggle grid/pixels",
+        "set layout = grid",
+        "set layout = vertical",
+        "set brush/opacity = 128",
+        "set brush/opacity = 255",
+        "set brush/stabilize = {n}",
+        "toggle brush/grid",
+    ];
+    /// Keys which may be pressed.
+    const KEYS: &'static [Key] = &[
+        Key::Num1,
+        Key::Num2,
+        Key::A,
+        Key::B,
+        Key::E,
+        Key::F,
+        Key::H,
+        Key::J,
+        Key::K,
+        Key::L,
+        Key::P,
+        Key::R,
+        Key::S,
+        Key::U,
+        Key::V,
+        Key::X,
+        Key::Y,
+        Key::Z,
+        Key::Left,
+        Key::Up,
+        Key::Right,
+        Key::Down,
+        Key::Backspace,
+        Key::Return,
+        Key::Space,
+        Key::Tab,
+        Key::Escape,
+        Key::Comma,
+        Key::Period,
+        Key::Slash,
+        Key::LBracket,
+        Key::RBracket,
+        Key::Equal,
+        Key::Minus,
+        Key::Alt,
+        Key::Control,
+        Key::Shift,
+        Key::Super,
+    ];
+    /// Characters which may be typed. Letters are left out, so that commands
+    /// such as `:w` or `:q` can't be typed by accident.
+    const CHARS: &'static [char] = &['0', '1', '2', '3', '8', ' ', '+', '-', '.', '#', '/', ':'];
+
+    /// Create a new fuzzer which runs for the given number of frames.
+    pub fn new(seed: u64, frames: u64) -> Self {
+        Self {
+            seed,
+            frames,
+            // The generator state must never be zero.
+            state: seed ^ 0x9e37_79b9_7f4a_7c15,
+        }
+    }
+
+    /// Generate the inputs for the next frame, for a window of the given size.
+    pub fn step(&mut self, width: f32, height: f32) -> Vec<Input> {
+        let n = self.below(Self::MAX_INPUTS + 1);
+
+        self.frames = self.frames.saturating_sub(1);
+        (0..n).map(|_| self.input(width, height)).collect()
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+
+    fn input(&mut self, width: f32, height: f32) -> Input {
+        let state = if self.chance(2) {
+            InputState::Pressed
+        } else {
+            InputState::Released
+        };
+
+        match self.below(16) {
+            0..=5 => {
+                // Favor the center of the window, which is where views are.
+                let (x, y) = if self.chance(2) {
+                    (
+                        width as f64 / 2. + self.between(-128, 128) as f64,
+                        height as f64 / 2. + self.between(-128, 128) as f64,
+                    )
+                } else {
+                    (
+                        self.below(width as u64 + 1) as f64,
+                        self.below(height as u64 + 1) as f64,
+                    )
+                };
+                Input::Event(Event::CursorMoved(LogicalPosition::new(x, y)))
+            }
+            6..=7 => Input::Event(Event::MouseInput(MouseButton::Left, state)),
+            8 => Input::Event(Event::MouseWheel(self.delta())),
+            9 if self.chance(2) => Input::Event(Event::TouchpadScroll(self.delta())),
+            9 => Input::Event(Event::TouchpadMagnify(self.between(-4, 4) as f64 / 8.)),
+            10..=12 => {
+                let key = *self.pick(Self::KEYS);
+                let modifiers = ModifiersState {
+                    shift: self.chance(4),
+                    ctrl: self.chance(8),
+                    alt: self.chance(8),
+                    meta: false,
+                };
+                Input::Event(Event::KeyboardInput(KeyboardInput {
+                    state,
+                    key: Some(key),
+                    modifiers,
+                }))
+            }
+            13 => Input::Event(Event::ReceivedCharacter(
+                *self.pick(Self::CHARS),
+                ModifiersState::default(),
+            )),
+            _ => Input::Command(self.command()),
+        }
+    }
+
+    fn command(&mut self) -> String {
+        let template = *self.pick(Self::COMMANDS);
+        let mut cmd = String::with_capacity(template.len());
+        let mut parts = template.split('{');
+
+        cmd.push_str(parts.next().unwrap_or_default());
+
+        for part in parts {
+            let (placeholder, rest) = part.split_at(1);
+            let value = match placeholder {
+                "n" => self.below(9).to_string(),
+                "s" => self.between(1, 64).to_string(),
+                "i" => self.between(-32, 32).to_string(),
+                "c" => format!("#{:06x}", self.below(0x100_0000)),
+                "d" => self.pick(&["90", "180", "270", "45"]).to_string(),
+                "z" => self.between(1, 16).to_string(),
+                _ => unreachable!(),
+            };
+            cmd.push_str(&value);
+            cmd.push_str(rest.trim_start_matches('}'));
+        }
+        cmd
+    }
+
+    fn delta(&mut self) -> LogicalDelta {
+        LogicalDelta {
+            x: self.between(-4, 4) as f64,
+            y: self.between(-4, 4) as f64,
+        }
+    }
+
+    /// Return the next random number, using *xorshift64\**.
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Return a random number in the range `0..n`.
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n.max(1)
+    }
+
+    /// Return a random number in the range `min..=max`.
+    fn between(&mut self, min: i64, max: i64) -> i64 {
+        min + self.below((max - min + 1) as u64) as i64
+    }
+
+    /// Return `true` with a probability of one in `n`.
+    fn chance(&mut self, n: u64) -> bool {
+        self.below(n) == 0
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u64) as usize]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cmd::CommandLine;
+
+    #[test]
+    fn test_fuzzer_deterministic() {
+        let mut a = Fuzzer::new(42, 64);
+        let mut b = Fuzzer::new(42, 64);
+
+        for _ in 0..64 {
+            let (a, b) = (a.step(640., 480.), b.step(640., 480.));
+            assert_eq!(format!("{:?}", a), format!("{:?}", b));
+        }
+        assert_eq!(a.frames, 0);
+    }
+
+    #[test]
+    fn test_fuzzer_commands() {
+        let cmdline = CommandLine::new("/dev/null", "/dev/null", &[]);
+        let mut fuzzer = Fuzzer::new(0, 0);
+
+        for _ in 0..1024 {
+            let cmd = fuzzer.command();
+            assert!(
+                cmdline.parse(&format!(":{}", cmd)).is_ok(),
+                "`{}` should parse",
+                cmd
+            );
+        }
+    }
+}
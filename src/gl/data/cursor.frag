@@ -7,13 +7,16 @@ out vec4 fragColor;
 in  float f_scale;
 
 void main() {
-	vec2 fb_size = vec2(textureSize(framebuffer, 0));
-	vec2 fb_coord = gl_FragCoord.xy / fb_size / f_scale;
-	vec4 fb_texel = texture(
+	ivec2 fb_size = textureSize(framebuffer, 0);
+	ivec2 fb_coord = ivec2(gl_FragCoord.xy / f_scale);
+	// Fetch the texel directly, since the framebuffer is sampled with
+	// filtering, which would blend neighbouring texels at fractional scales.
+	vec4 fb_texel = texelFetch(
 		framebuffer,
 		// NOTE: This is inverted because the position in the
 		// vertex shader is inverted.
-		vec2(fb_coord.x, fb_size.y - fb_coord.y)
+		clamp(ivec2(fb_coord.x, fb_size.y - fb_coord.y - 1), ivec2(0), fb_size - 1),
+		0
 	);
 
 	vec4 texel = texture(cursor, f_uv);
@@ -1,11 +1,63 @@
 uniform sampler2D framebuffer;
+// Hardware palette to remap colors to, if `palette_size` isn't zero.
+uniform sampler2D palette;
+uniform int       palette_size;
+// Color vision deficiency simulation. Rows of the matrix are uploaded as
+// columns, so it's applied by multiplying from the left.
+uniform mat3      colorblind;
+// Number of value bands to posterize the display into, if it isn't zero.
+uniform int       value_bands;
 
 in  vec2 f_uv;
 out vec4 fragColor;
 
+// Sample a texture so that texels stay sharp, and only the pixels straddling
+// two texels are blended. With fractional scales, this avoids texels being
+// drawn with uneven sizes. Requires linear filtering.
+vec4 sharp(sampler2D tex, vec2 uv) {
+	vec2 size = vec2(textureSize(tex, 0));
+	vec2 p    = uv * size;
+	vec2 seam = floor(p + 0.5);
+
+	p = seam + clamp((p - seam) / fwidth(p), -0.5, 0.5);
+
+	return texture(tex, p / size);
+}
+
+// Find the palette color nearest to the given color.
+vec3 nearest(vec3 color) {
+	vec3  result = color;
+	float best = 4.0;
+
+	for (int i = 0; i < palette_size; i++) {
+		vec3  c = texelFetch(palette, ivec2(i, 0), 0).rgb;
+		vec3  d = c - color;
+		float dist = dot(d, d);
+
+		if (dist < best) {
+			best = dist;
+			result = c;
+		}
+	}
+	return result;
+}
+
 void main() {
-	fragColor = texture(
+	vec4 color = sharp(
 		framebuffer,
 		vec2(f_uv.s, 1 - f_uv.t)
 	);
+	if (palette_size > 0) {
+		color.rgb = nearest(color.rgb);
+	}
+	color.rgb = clamp(color.rgb * colorblind, 0.0, 1.0);
+
+	if (value_bands > 1) {
+		float bands = float(value_bands);
+		float luma  = dot(color.rgb, vec3(0.2126, 0.7152, 0.0722));
+		float band  = min(floor(luma * bands), bands - 1.0);
+
+		color.rgb = vec3(band / (bands - 1.0));
+	}
+	fragColor = color;
 }
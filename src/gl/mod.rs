@@ -1,14 +1,15 @@
 use crate::cmd::Axis;
 use crate::draw;
 use crate::execution::Execution;
-use crate::font::TextBatch;
+use crate::font::{Font, TextBatch};
+use crate::pixels;
 use crate::platform::{self, LogicalSize};
+use crate::preview::HardwarePalette;
 use crate::renderer;
 use crate::session::{self, Blending, Effect, Session};
 use crate::sprite;
 use crate::util;
-use crate::view::resource::ViewResource;
-use crate::view::{View, ViewId, ViewOp, ViewState};
+use crate::view::{Resource, View, ViewId, ViewOp, ViewState};
 use crate::{data, data::Assets, image};
 
 use crate::gfx::{shape2d, sprite2d, Origin, Rgba, Rgba8, ZDepth};
@@ -34,8 +35,10 @@ use luminance_gl::gl33;
 use std::collections::BTreeMap;
 use std::error::Error;
 use std::fmt;
+use std::fs;
 use std::io;
 use std::mem;
+use std::path::PathBuf;
 use std::time;
 
 type Backend = gl33::GL33;
@@ -50,6 +53,17 @@ const SAMPLER: Sampler = Sampler {
     depth_comparison: None,
 };
 
+/// Sampler for the screen framebuffer. It's sampled with filtering, so that
+/// fractional UI scales don't produce uneven pixels. See `screen.frag`.
+const SCREEN_SAMPLER: Sampler = Sampler {
+    wrap_r: Wrap::ClampToEdge,
+    wrap_s: Wrap::ClampToEdge,
+    wrap_t: Wrap::ClampToEdge,
+    min_filter: MinFilter::Linear,
+    mag_filter: MagFilter::Linear,
+    depth_comparison: None,
+};
+
 #[derive(UniformInterface)]
 struct Sprite2dInterface {
     tex: Uniform<TextureBinding<Dim2, pixel::NormUnsigned>>,
@@ -125,6 +139,36 @@ struct Cursor2dVertex {
 #[derive(UniformInterface)]
 struct Screen2dInterface {
     framebuffer: Uniform<TextureBinding<Dim2, pixel::NormUnsigned>>,
+    palette: Uniform<TextureBinding<Dim2, pixel::NormUnsigned>>,
+    palette_size: Uniform<i32>,
+    colorblind: Uniform<[[f32; 3]; 3]>,
+    value_bands: Uniform<i32>,
+}
+
+/// Uniforms available to view filters. Besides the sprite uniforms, filters
+/// may declare the view `size` in pixels and the `time` in seconds since the
+/// filter was loaded.
+#[derive(UniformInterface)]
+struct Filter2dInterface {
+    tex: Uniform<TextureBinding<Dim2, pixel::NormUnsigned>>,
+    ortho: Uniform<M44>,
+    transform: Uniform<M44>,
+    #[uniform(unbound)]
+    size: Uniform<[f32; 2]>,
+    #[uniform(unbound)]
+    time: Uniform<f32>,
+}
+
+/// A user fragment shader which views are displayed through.
+struct ViewFilter {
+    /// Path to the shader source.
+    path: PathBuf,
+    /// Modification time of the source, used to reload it when it changes.
+    modified: Option<time::SystemTime>,
+    /// When the filter was loaded.
+    loaded: time::Instant,
+    /// Shader program, if the source compiled.
+    program: Option<Program<Backend, VertexSemantics, (), Filter2dInterface>>,
 }
 
 pub struct Renderer {
@@ -144,9 +188,12 @@ pub struct Renderer {
     final_batch: shape2d::Batch,
 
     font: Texture<Backend, Dim2, pixel::SRGBA8UI>,
+    /// The built-in font, used when no other font is set.
+    glyphs: Font,
     cursors: Texture<Backend, Dim2, pixel::SRGBA8UI>,
     checker: Texture<Backend, Dim2, pixel::SRGBA8UI>,
     paste: Texture<Backend, Dim2, pixel::SRGBA8UI>,
+    palette: Texture<Backend, Dim2, pixel::SRGBA8UI>,
     paste_outputs: Vec<Tess<Backend, Sprite2dVertex>>,
 
     sprite2d: Program<Backend, VertexSemantics, (), Sprite2dInterface>,
@@ -155,6 +202,41 @@ pub struct Renderer {
     screen2d: Program<Backend, VertexSemantics, (), Screen2dInterface>,
 
     view_data: BTreeMap<ViewId, ViewData>,
+    filter: Option<ViewFilter>,
+    preview_palette: Option<HardwarePalette>,
+
+    text_cache: Option<TextTess>,
+    overlay_cache: Option<TextTess>,
+    help_cache: Option<TextTess>,
+}
+
+/// Tessellation built from a text batch, along with the batch hash.
+struct TextTess {
+    hash: u64,
+    tess: Tess<Backend, Sprite2dVertex>,
+}
+
+impl TextTess {
+    /// Get the tessellation of the given batch. The cached tessellation is
+    /// re-used if the batch hasn't changed since it was built.
+    fn get<'a>(
+        cache: &'a mut Option<Self>,
+        batch: &TextBatch,
+        ctx: &mut Context,
+    ) -> &'a Tess<Backend, Sprite2dVertex> {
+        let hash = batch.hash();
+
+        match cache {
+            Some(cached) if cached.hash == hash => {}
+            _ => {
+                *cache = Some(Self {
+                    hash,
+                    tess: ctx.tessellation::<_, Sprite2dVertex>(&batch.vertices()),
+                });
+            }
+        }
+        &cache.as_ref().expect("the cache was just filled").tess
+    }
 }
 
 struct LayerData {
@@ -239,9 +321,35 @@ struct ViewData {
     layer: LayerData,
     staging_fb: Framebuffer<Backend, Dim2, pixel::SRGBA8UI, pixel::Depth32F>,
     anim_tess: Option<Tess<Backend, Sprite2dVertex>>,
+    /// State `anim_tess` was built from.
+    anim_state: Option<AnimationState>,
     layer_tess: Option<Tess<Backend, Sprite2dVertex>>,
 }
 
+/// View state the animation tessellation depends on.
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct AnimationState {
+    frame: Rect<f32>,
+    fw: u32,
+    fh: u32,
+    width: u32,
+    zoom: f32,
+    offset: Vector2<f32>,
+}
+
+impl AnimationState {
+    fn new<R>(s: &Session, v: &View<R>) -> Self {
+        Self {
+            frame: *v.animation.val(),
+            fw: v.fw,
+            fh: v.fh,
+            width: v.width(),
+            zoom: v.zoom,
+            offset: s.offset + v.offset,
+        }
+    }
+}
+
 impl ViewData {
     fn new(w: u32, h: u32, pixels: Option<&[Rgba8]>, ctx: &mut Context) -> Self {
         let mut staging_fb: Framebuffer<Backend, Dim2, pixel::SRGBA8UI, pixel::Depth32F> =
@@ -256,6 +364,7 @@ impl ViewData {
             layer: LayerData::new(w, h, pixels, ctx),
             staging_fb,
             anim_tess: None,
+            anim_state: None,
             layer_tess: None,
         }
     }
@@ -360,21 +469,28 @@ impl<'a> renderer::Renderer<'a> for Renderer {
         let ctx = Backend::new().map_err(Error::State)?;
         let mut ctx = Context { ctx };
 
-        let (font_img, font_w, font_h) = image::read(assets.glyphs)?;
+        let glyphs = Font::decode(assets.glyphs)?;
         let (cursors_img, cursors_w, cursors_h) = image::read(data::CURSORS)?;
         let (checker_w, checker_h) = (2, 2);
         let (paste_w, paste_h) = (8, 8);
 
-        let mut font =
-            Texture::new(&mut ctx, [font_w, font_h], 0, self::SAMPLER).map_err(Error::Texture)?;
+        let mut font = Texture::new(&mut ctx, [glyphs.width, glyphs.height], 0, self::SAMPLER)
+            .map_err(Error::Texture)?;
         let mut cursors = Texture::new(&mut ctx, [cursors_w, cursors_h], 0, self::SAMPLER)
             .map_err(Error::Texture)?;
         let paste =
             Texture::new(&mut ctx, [paste_w, paste_h], 0, self::SAMPLER).map_err(Error::Texture)?;
         let mut checker = Texture::new(&mut ctx, [checker_w, checker_h], 0, self::SAMPLER)
             .map_err(Error::Texture)?;
+        let palette = Texture::new(
+            &mut ctx,
+            [HardwarePalette::MAX_COLORS as u32, 1],
+            0,
+            self::SAMPLER,
+        )
+        .map_err(Error::Texture)?;
 
-        font.upload_raw(GenMipmaps::No, &font_img)
+        font.upload_raw(GenMipmaps::No, util::align_u8(&glyphs.pixels))
             .map_err(Error::Texture)?;
         cursors
             .upload_raw(GenMipmaps::No, &cursors_img)
@@ -408,7 +524,7 @@ impl<'a> renderer::Renderer<'a> for Renderer {
             &mut ctx,
             [win_size.width as u32, win_size.height as u32],
             0,
-            self::SAMPLER,
+            self::SCREEN_SAMPLER,
         )
         .map_err(Error::Framebuffer)?;
 
@@ -451,13 +567,20 @@ impl<'a> renderer::Renderer<'a> for Renderer {
             cursor2d,
             screen2d,
             font,
+            glyphs,
             cursors,
             checker,
             paste,
+            palette,
             paste_outputs: Vec::new(),
             staging_batch: shape2d::Batch::new(),
             final_batch: shape2d::Batch::new(),
             view_data: BTreeMap::new(),
+            filter: None,
+            preview_palette: None,
+            text_cache: None,
+            overlay_cache: None,
+            help_cache: None,
         })
     }
 
@@ -479,6 +602,8 @@ impl<'a> renderer::Renderer<'a> for Renderer {
         self.final_batch.clear();
 
         self.handle_effects(effects, session).unwrap();
+        self.update_filter(session);
+        self.update_preview_palette(session);
         self.update_view_animations(session);
         self.update_view_composites(session);
 
@@ -502,20 +627,21 @@ impl<'a> renderer::Renderer<'a> for Renderer {
             render_st,
             pipeline_st,
             paste,
+            palette,
             paste_outputs,
             view_data,
+            filter,
+            text_cache,
+            overlay_cache,
+            help_cache,
             ..
         } = self;
 
         draw_ctx.clear();
         draw_ctx.draw(session, avg_frametime, execution);
 
-        let text_tess = self
-            .ctx
-            .tessellation::<_, Sprite2dVertex>(&draw_ctx.text_batch.vertices());
-        let overlay_tess = self
-            .ctx
-            .tessellation::<_, Sprite2dVertex>(&draw_ctx.overlay_batch.vertices());
+        let text_tess = TextTess::get(text_cache, &draw_ctx.text_batch, &mut self.ctx);
+        let overlay_tess = TextTess::get(overlay_cache, &draw_ctx.overlay_batch, &mut self.ctx);
         let ui_tess = self
             .ctx
             .tessellation::<_, Shape2dVertex>(&draw_ctx.ui_batch.vertices());
@@ -542,6 +668,29 @@ impl<'a> renderer::Renderer<'a> for Renderer {
                     .tessellation::<_, Sprite2dVertex>(&draw_ctx.paste_batch.vertices()),
             )
         };
+        let reference_tess = session.reference_view().map(|r| {
+            let v = session.active_view();
+            let batch = draw::draw_reference(session, v, r);
+            let tess = self
+                .ctx
+                .tessellation::<_, Sprite2dVertex>(batch.vertices().as_slice());
+
+            (r.id, v.zoom, tess)
+        });
+        let compare_tess = session.compare_view().map(|c| {
+            let v = session.active_view();
+            let batch = draw::draw_compare(session, v, c);
+            let tess = self
+                .ctx
+                .tessellation::<_, Sprite2dVertex>(batch.vertices().as_slice());
+
+            (c.id, v.zoom, tess)
+        });
+        let preview_tess = draw::draw_preview(session, session.active_view()).map(|batch| {
+            self.ctx
+                .tessellation::<_, Sprite2dVertex>(batch.vertices().as_slice())
+        });
+        let active_id = session.views.active_id;
         let staging_tess = if self.staging_batch.is_empty() {
             None
         } else {
@@ -567,9 +716,8 @@ impl<'a> renderer::Renderer<'a> for Renderer {
             let win_tess = self
                 .ctx
                 .tessellation::<_, Shape2dVertex>(win.vertices().as_slice());
-            let text_tess = self
-                .ctx
-                .tessellation::<_, Sprite2dVertex>(text.vertices().as_slice());
+            let text_tess = TextTess::get(help_cache, &text, &mut self.ctx);
+
             Some((win_tess, text_tess))
         } else {
             None
@@ -590,13 +738,20 @@ impl<'a> renderer::Renderer<'a> for Renderer {
             &v_data.staging_fb,
             pipeline_st,
             |pipeline, mut shd_gate| {
-                // Render staged brush strokes.
+                // Render staged brush strokes. Pixels are replaced rather than blended,
+                // so that translucent strokes don't accumulate where they overlap.
                 if let Some(tess) = staging_tess {
                     shd_gate.shade(shape2d, |mut iface, uni, mut rdr_gate| {
                         iface.set(&uni.ortho, view_ortho.into());
                         iface.set(&uni.transform, identity);
 
-                        rdr_gate.render(render_st, |mut tess_gate| tess_gate.render(&tess))
+                        let render_st = render_st.clone().set_blending(blending::Blending {
+                            equation: Equation::Additive,
+                            src: Factor::One,
+                            dst: Factor::Zero,
+                        });
+
+                        rdr_gate.render(&render_st, |mut tess_gate| tess_gate.render(&tess))
                     })?;
                 }
                 // Render staging paste buffer.
@@ -661,7 +816,10 @@ impl<'a> renderer::Renderer<'a> for Renderer {
         );
 
         // Render to screen framebuffer.
-        let bg = Rgba::from(session.settings["background"].to_rgba8());
+        let bg = match session.settings["background"].to_rgba8() {
+            bg if bg.a == 0 => Rgba::from(session.theme.workspace),
+            bg => Rgba::from(bg),
+        };
         let screen_st = &pipeline_st
             .clone()
             .set_clear_color([bg.r, bg.g, bg.b, bg.a]);
@@ -685,25 +843,58 @@ impl<'a> renderer::Renderer<'a> for Renderer {
                 }
 
                 for (id, v) in view_data.iter_mut() {
-                    if let Some(view) = session.views.get(*id) {
+                    if let Some(view) = session
+                        .views
+                        .get(*id)
+                        .filter(|_| session.workspaces.contains(*id))
+                    {
                         let transform =
                             Matrix4::from_translation(
                                 (session.offset + view.offset).extend(*draw::VIEW_LAYER),
                             ) * Matrix4::from_nonuniform_scale(view.zoom, view.zoom, 1.0);
 
-                        // Render views.
-                        shd_gate.shade(sprite2d, |mut iface, uni, mut rdr_gate| {
-                            let bound_view = pipeline
-                                .bind_texture(v.layer.fb.color_slot())
-                                .expect("binding textures never fails");
+                        // Render views, through the view filter if there is one.
+                        if let Some(ViewFilter {
+                            program: Some(program),
+                            loaded,
+                            ..
+                        }) = filter
+                        {
+                            shd_gate.shade(program, |mut iface, uni, mut rdr_gate| {
+                                let [w, h] = v.layer.fb.size();
+                                let bound_view = pipeline
+                                    .bind_texture(v.layer.fb.color_slot())
+                                    .expect("binding textures never fails");
+
+                                iface.set(&uni.ortho, ortho);
+                                iface.set(&uni.transform, transform.into());
+                                iface.set(&uni.tex, bound_view.binding());
+                                iface.set(&uni.size, [w as f32, h as f32]);
+                                iface.set(&uni.time, loaded.elapsed().as_secs_f32());
+
+                                rdr_gate.render(render_st, |mut tess_gate| {
+                                    tess_gate.render(&v.layer.tess)
+                                })
+                            })?;
+                        } else {
+                            shd_gate.shade(sprite2d, |mut iface, uni, mut rdr_gate| {
+                                let bound_view = pipeline
+                                    .bind_texture(v.layer.fb.color_slot())
+                                    .expect("binding textures never fails");
+
+                                iface.set(&uni.ortho, ortho);
+                                iface.set(&uni.transform, transform.into());
+                                iface.set(&uni.tex, bound_view.binding());
+
+                                rdr_gate.render(render_st, |mut tess_gate| {
+                                    tess_gate.render(&v.layer.tess)
+                                })
+                            })?;
+                        }
 
+                        shd_gate.shade(sprite2d, |mut iface, uni, mut rdr_gate| {
                             iface.set(&uni.ortho, ortho);
                             iface.set(&uni.transform, transform.into());
-                            iface.set(&uni.tex, bound_view.binding());
-
-                            rdr_gate.render(render_st, |mut tess_gate| {
-                                tess_gate.render(&v.layer.tess)
-                            })?;
 
                             // TODO: We only need to render this on the active view.
                             let staging_texture = v.staging_fb.color_slot();
@@ -721,6 +912,42 @@ impl<'a> renderer::Renderer<'a> for Renderer {
                     }
                 }
 
+                // Render reference animation over the active view.
+                if let Some((id, zoom, tess)) = &reference_tess {
+                    if let Some(r) = view_data.get_mut(id) {
+                        shd_gate.shade(sprite2d, |mut iface, uni, mut rdr_gate| {
+                            let bound_reference = pipeline
+                                .bind_texture(r.layer.fb.color_slot())
+                                .expect("binding textures never fails");
+                            let t = Matrix4::from_translation(Vector2::new(0., *zoom).extend(0.));
+
+                            iface.set(&uni.ortho, ortho);
+                            iface.set(&uni.transform, t.into());
+                            iface.set(&uni.tex, bound_reference.binding());
+
+                            rdr_gate.render(render_st, |mut tess_gate| tess_gate.render(tess))
+                        })?;
+                    }
+                }
+
+                // Render compared view over the active view.
+                if let Some((id, zoom, tess)) = &compare_tess {
+                    if let Some(c) = view_data.get_mut(id) {
+                        shd_gate.shade(sprite2d, |mut iface, uni, mut rdr_gate| {
+                            let bound_compare = pipeline
+                                .bind_texture(c.layer.fb.color_slot())
+                                .expect("binding textures never fails");
+                            let t = Matrix4::from_translation(Vector2::new(0., *zoom).extend(0.));
+
+                            iface.set(&uni.ortho, ortho);
+                            iface.set(&uni.transform, t.into());
+                            iface.set(&uni.tex, bound_compare.binding());
+
+                            rdr_gate.render(render_st, |mut tess_gate| tess_gate.render(tess))
+                        })?;
+                    }
+                }
+
                 // Render UI.
                 shd_gate.shade(shape2d, |mut iface, uni, mut rdr_gate| {
                     iface.set(&uni.ortho, ortho);
@@ -729,6 +956,21 @@ impl<'a> renderer::Renderer<'a> for Renderer {
                     rdr_gate.render(render_st, |mut tess_gate| tess_gate.render(&ui_tess))
                 })?;
 
+                // Render animation preview of the active view.
+                if let (Some(tess), Some(v)) = (&preview_tess, view_data.get_mut(&active_id)) {
+                    shd_gate.shade(sprite2d, |mut iface, uni, mut rdr_gate| {
+                        let bound_layer = pipeline
+                            .bind_texture(v.layer.fb.color_slot())
+                            .expect("binding textures never fails");
+
+                        iface.set(&uni.ortho, ortho);
+                        iface.set(&uni.transform, identity);
+                        iface.set(&uni.tex, bound_layer.binding());
+
+                        rdr_gate.render(render_st, |mut tess_gate| tess_gate.render(tess))
+                    })?;
+                }
+
                 // Render text, tool & view animations.
                 shd_gate.shade(sprite2d, |mut iface, uni, mut rdr_gate| {
                     iface.set(&uni.ortho, ortho);
@@ -738,7 +980,10 @@ impl<'a> renderer::Renderer<'a> for Renderer {
                     if session.settings["animation"].is_set() {
                         for (id, v) in view_data.iter_mut() {
                             match (&v.anim_tess, session.views.get(*id)) {
-                                (Some(tess), Some(view)) if view.animation.len() > 1 => {
+                                (Some(tess), Some(view))
+                                    if view.animation.len() > 1
+                                        && session.workspaces.contains(*id) =>
+                                {
                                     let bound_layer = pipeline
                                         .bind_texture(v.layer.fb.color_slot())
                                         .expect("binding textures never fails");
@@ -766,7 +1011,7 @@ impl<'a> renderer::Renderer<'a> for Renderer {
                         iface.set(&uni.transform, identity);
 
                         // Render text.
-                        rdr_gate.render(render_st, |mut tess_gate| tess_gate.render(&text_tess))?;
+                        rdr_gate.render(render_st, |mut tess_gate| tess_gate.render(text_tess))?;
                     }
                     {
                         let bound_tool = pipeline
@@ -794,7 +1039,7 @@ impl<'a> renderer::Renderer<'a> for Renderer {
                         iface.set(&uni.ortho, ortho);
                         iface.set(&uni.transform, identity);
 
-                        rdr_gate.render(render_st, |mut tess_gate| tess_gate.render(&text_tess))
+                        rdr_gate.render(render_st, |mut tess_gate| tess_gate.render(text_tess))
                     })?;
                 }
                 Ok(())
@@ -810,8 +1055,27 @@ impl<'a> renderer::Renderer<'a> for Renderer {
                 let bound_screen = pipeline
                     .bind_texture(screen_fb.color_slot())
                     .expect("binding textures never fails");
+                let bound_palette = pipeline
+                    .bind_texture(palette)
+                    .expect("binding textures never fails");
                 shd_gate.shade(screen2d, |mut iface, uni, mut rdr_gate| {
+                    let palette_size = session
+                        .preview_palette
+                        .map_or(0, |p| p.colors().len() as i32);
+
                     iface.set(&uni.framebuffer, bound_screen.binding());
+                    iface.set(&uni.palette, bound_palette.binding());
+                    iface.set(&uni.palette_size, palette_size);
+                    iface.set(
+                        &uni.colorblind,
+                        session
+                            .preview_colorblind
+                            .map_or([[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]], |c| c.matrix()),
+                    );
+                    iface.set(
+                        &uni.value_bands,
+                        session.preview_values.map_or(0, |n| n as i32),
+                    );
 
                     rdr_gate.render(render_st, |mut tess_gate| tess_gate.render(&screen_tess))
                 })?;
@@ -828,7 +1092,7 @@ impl<'a> renderer::Renderer<'a> for Renderer {
                             Matrix4::ortho(screen_w, screen_h, Origin::BottomLeft).into(),
                         );
 
-                        rdr_gate.render(render_st, |mut tess_gate| tess_gate.render(&overlay_tess))
+                        rdr_gate.render(render_st, |mut tess_gate| tess_gate.render(overlay_tess))
                     })?;
                 }
 
@@ -863,24 +1127,35 @@ impl<'a> renderer::Renderer<'a> for Renderer {
 
                 match state {
                     ViewState::Dirty(_) if is_resized => {
-                        vr.record_view_resized(v_data.layer.pixels(), extent);
+                        vr.resource
+                            .borrow_mut()
+                            .record_view_resized(v_data.layer.pixels(), extent);
                     }
                     ViewState::Dirty(_) => {
-                        vr.record_view_painted(v_data.layer.pixels());
+                        vr.resource
+                            .borrow_mut()
+                            .record_view_painted(v_data.layer.pixels());
                     }
                     ViewState::Okay | ViewState::Damaged(_) => {}
                 }
             }
         }
 
-        if !execution.is_normal() {
+        let timelapse = session.timelapse_due();
+
+        if !execution.is_normal() || timelapse {
             let texels = screen_fb
                 .color_slot()
                 .get_raw_texels()
                 .expect("binding textures never fails");
             let texels = Rgba8::align(&texels);
 
-            execution.record(texels).ok();
+            if timelapse {
+                session.record_timelapse(texels, screen_w, screen_h);
+            }
+            if !execution.is_normal() {
+                execution.record(texels, screen_w, screen_h).ok();
+            }
         }
 
         Ok(())
@@ -915,11 +1190,85 @@ impl Renderer {
                 (self.win_size.height / scale) as u32,
             ],
             0,
-            self::SAMPLER,
+            self::SCREEN_SAMPLER,
         )
         .unwrap();
     }
 
+    fn handle_font_changed(&mut self, font: &Font) -> Result<(), RendererError> {
+        let mut texture = Texture::new(&mut self.ctx, [font.width, font.height], 0, self::SAMPLER)
+            .map_err(RendererError::Texture)?;
+        texture
+            .upload_raw(GenMipmaps::No, util::align_u8(&font.pixels))
+            .map_err(RendererError::Texture)?;
+
+        self.font = texture;
+        self.draw_ctx.text_batch = TextBatch::atlas(font.width, font.height);
+        self.draw_ctx.overlay_batch = TextBatch::atlas(font.width, font.height);
+
+        Ok(())
+    }
+
+    /// Upload the colors of the session's preview palette, if it changed.
+    fn update_preview_palette(&mut self, session: &Session) {
+        if self.preview_palette == session.preview_palette {
+            return;
+        }
+        if let Some(p) = session.preview_palette {
+            let mut texels = vec![Rgba8::TRANSPARENT; HardwarePalette::MAX_COLORS];
+            texels[..p.colors().len()].copy_from_slice(p.colors());
+
+            self.palette
+                .upload_raw(GenMipmaps::No, util::align_u8(&texels))
+                .expect("uploading texels never fails");
+        }
+        self.preview_palette = session.preview_palette;
+    }
+
+    /// Load the session's view filter, or reload it if its source changed.
+    fn update_filter(&mut self, session: &mut Session) {
+        let path = match &session.filter {
+            Some(path) => path.clone(),
+            None => {
+                self.filter = None;
+                return;
+            }
+        };
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+
+        if let Some(f) = &self.filter {
+            if f.path == path && f.modified == modified {
+                return;
+            }
+        }
+        let program = fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| {
+                self.ctx
+                    .new_shader_program::<VertexSemantics, (), Filter2dInterface>()
+                    .from_strings(include_str!("data/sprite.vert"), None, None, &source)
+                    .map(|p| p.ignore_warnings())
+                    .map_err(|e| e.to_string())
+            });
+        let program = match program {
+            Ok(program) => Some(program),
+            Err(e) => {
+                session.message(
+                    format!("Error: {}: {}", path.display(), e),
+                    session::MessageType::Error,
+                );
+                None
+            }
+        };
+
+        self.filter = Some(ViewFilter {
+            path,
+            modified,
+            loaded: time::Instant::now(),
+            program,
+        });
+    }
+
     fn handle_effects(
         &mut self,
         mut effects: Vec<Effect>,
@@ -933,14 +1282,23 @@ impl Renderer {
                 Effect::SessionScaled(scale) => {
                     self.handle_session_scale_changed(scale);
                 }
+                Effect::FontChanged(font) => {
+                    let font = font.unwrap_or_else(|| self.glyphs.clone());
+                    self.handle_font_changed(&font)?;
+                }
                 Effect::ViewActivated(_) => {}
                 Effect::ViewAdded(id) => {
                     // FIXME: This should be done when the view is added in the ViewManager.
-                    if let Some((s, pixels)) = session.views.get_snapshot_safe(id) {
-                        let (w, h) = (s.width(), s.height());
-
-                        self.view_data
-                            .insert(id, ViewData::new(w, h, Some(pixels), &mut self.ctx));
+                    if let Some(v) = session.views.get(id) {
+                        let data = v.with_pixels(|extent, pixels| {
+                            ViewData::new(
+                                extent.width(),
+                                extent.height(),
+                                Some(pixels),
+                                &mut self.ctx,
+                            )
+                        });
+                        self.view_data.insert(id, data);
                     }
                 }
                 Effect::ViewRemoved(id) => {
@@ -970,11 +1328,7 @@ impl Renderer {
         Ok(())
     }
 
-    fn handle_view_ops(
-        &mut self,
-        v: &View<ViewResource>,
-        ops: &[ViewOp],
-    ) -> Result<(), RendererError> {
+    fn handle_view_ops(&mut self, v: &View<Resource>, ops: &[ViewOp]) -> Result<(), RendererError> {
         use RendererError as Error;
 
         for op in ops {
@@ -1000,7 +1354,7 @@ impl Renderer {
                         .get_mut(&v.id)
                         .expect("views must have associated view data");
 
-                    let (_, texels) = v.layer.get_snapshot_rect(&src.map(|n| n as i32)).unwrap(); // TODO: Handle this nicely?
+                    let texels = v.pixels_rect(&src.map(|n| n as i32)).unwrap(); // TODO: Handle this nicely?
                     let texels = util::align_u8(&texels);
 
                     view.layer
@@ -1015,7 +1369,7 @@ impl Renderer {
                         .map_err(Error::Texture)?;
                 }
                 ViewOp::Yank(src) => {
-                    let (_, pixels) = v.layer.get_snapshot_rect(&src.map(|n| n)).unwrap();
+                    let pixels = v.pixels_rect(&src.map(|n| n)).unwrap();
                     let (w, h) = (src.width() as u32, src.height() as u32);
                     let [paste_w, paste_h] = self.paste.size();
 
@@ -1030,7 +1384,7 @@ impl Renderer {
                         .map_err(Error::Texture)?;
                 }
                 ViewOp::Flip(src, dir) => {
-                    let (_, mut pixels) = v.layer.get_snapshot_rect(&src.map(|n| n)).unwrap();
+                    let mut pixels = v.pixels_rect(&src.map(|n| n)).unwrap();
                     let (w, h) = (src.width() as u32, src.height() as u32);
                     let [paste_w, paste_h] = self.paste.size();
 
@@ -1064,7 +1418,50 @@ impl Renderer {
                         .upload_raw(GenMipmaps::No, body)
                         .map_err(Error::Texture)?;
                 }
+                ViewOp::Rotate(src, degrees) => {
+                    let pixels = v.pixels_rect(src).unwrap();
+                    let (w, h) = (src.width() as u32, src.height() as u32);
+                    let [paste_w, paste_h] = self.paste.size();
+
+                    if paste_w != w || paste_h != h {
+                        self.paste = Texture::new(&mut self.ctx, [w, h], 0, self::SAMPLER)
+                            .map_err(Error::Texture)?;
+                    }
+
+                    // When the area spans whole frames, rotate each frame separately.
+                    let fw = v.fw;
+                    let pixels = if w > fw && w % fw == 0 && src.x1 as u32 % fw == 0 {
+                        let nframes = (w / fw) as usize;
+                        let frames = util::split_frames(&pixels, fw as usize, h as usize, nframes)
+                            .into_iter()
+                            .map(|f| pixels::rotsprite(&f, fw, h, *degrees))
+                            .collect();
+
+                        util::stitch_frames(frames, fw as usize, h as usize, Rgba8::TRANSPARENT)
+                    } else {
+                        pixels::rotsprite(&pixels, w, h, *degrees)
+                    };
+                    let body = util::align_u8(&pixels);
+
+                    self.paste
+                        .upload_raw(GenMipmaps::No, body)
+                        .map_err(Error::Texture)?;
+                }
                 ViewOp::Paste(dst) => {
+                    let (w, h) = (dst.width() as u32, dst.height() as u32);
+                    let [paste_w, paste_h] = self.paste.size();
+
+                    // When the paste buffer was scaled, resample it to the destination size.
+                    if paste_w != w || paste_h != h {
+                        let texels = self.paste.get_raw_texels().map_err(Error::Texture)?;
+                        let pixels = pixels::resize(Rgba8::align(&texels), paste_w, paste_h, w, h);
+
+                        self.paste = Texture::new(&mut self.ctx, [w, h], 0, self::SAMPLER)
+                            .map_err(Error::Texture)?;
+                        self.paste
+                            .upload_raw(GenMipmaps::No, util::align_u8(&pixels))
+                            .map_err(Error::Texture)?;
+                    }
                     let [paste_w, paste_h] = self.paste.size();
                     let batch = sprite2d::Batch::singleton(
                         paste_w,
@@ -1100,24 +1497,22 @@ impl Renderer {
         Ok(())
     }
 
-    fn handle_view_damaged(&mut self, view: &View<ViewResource>) -> Result<(), RendererError> {
+    fn handle_view_damaged(&mut self, view: &View<Resource>) -> Result<(), RendererError> {
         let layer = &mut self
             .view_data
             .get_mut(&view.id)
             .expect("views must have associated view data")
             .layer;
 
-        let (_, pixels) = view.layer.current_snapshot();
-
         layer.clear()?;
-        layer.upload(util::align_u8(pixels))?;
+        view.with_pixels(|_, pixels| layer.upload(util::align_u8(pixels)))?;
 
         Ok(())
     }
 
     fn handle_view_resized(
         &mut self,
-        view: &View<ViewResource>,
+        view: &View<Resource>,
         vw: u32,
         vh: u32,
     ) -> Result<(), RendererError> {
@@ -1126,13 +1521,13 @@ impl Renderer {
 
     fn resize_view(
         &mut self,
-        view: &View<ViewResource>,
+        view: &View<Resource>,
         vw: u32,
         vh: u32,
     ) -> Result<(), RendererError> {
         // View size changed. Re-create view resources.
         let (ew, eh) = {
-            let extent = view.resource.extent;
+            let extent = view.resource.borrow().extent;
             (extent.width(), extent.height())
         };
 
@@ -1149,7 +1544,7 @@ impl Renderer {
         //     :f/remove
         //     :undo
         //
-        if let Some((_, texels)) = view.layer.get_snapshot_rect(&trect) {
+        if let Some(texels) = view.pixels_rect(&trect) {
             let texels = util::align_u8(&texels);
             let l = &mut view_data.layer;
 
@@ -1167,15 +1562,20 @@ impl Renderer {
         }
         // TODO: Does this need to run if the view has only one frame?
         for v in s.views.iter() {
-            // FIXME: When `v.animation.val()` doesn't change, we don't need
-            // to re-create the buffer.
-            let batch = draw::draw_view_animation(s, v);
-
             if let Some(vd) = self.view_data.get_mut(&v.id) {
+                let state = AnimationState::new(s, v);
+
+                // Only re-create the buffer when the displayed frame or its placement changed.
+                if vd.anim_tess.is_some() && vd.anim_state == Some(state) {
+                    continue;
+                }
+                let batch = draw::draw_view_animation(s, v);
+
                 vd.anim_tess = Some(
                     self.ctx
                         .tessellation::<_, Sprite2dVertex>(batch.vertices().as_slice()),
                 );
+                vd.anim_state = Some(state);
             }
         }
     }
@@ -1195,5 +1595,5 @@ impl Renderer {
 }
 
 fn text_batch([w, h]: [u32; 2]) -> TextBatch {
-    TextBatch::new(w, h, draw::GLYPH_WIDTH, draw::GLYPH_HEIGHT)
+    TextBatch::atlas(w, h)
 }
@@ -1,13 +1,38 @@
 use std::collections::VecDeque;
 use std::io;
 use std::path::Path;
+use std::time;
+
+/// A command entered, along with when it was entered, in seconds since the
+/// UNIX epoch. Entries loaded from older history files have no time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub line: String,
+    pub time: Option<u64>,
+}
+
+impl Entry {
+    /// Parse an entry from a line of the history file, eg. `1700000000\t:w`.
+    fn parse(s: &str) -> Self {
+        match s.split_once('\t').map(|(t, line)| (t.parse::<u64>(), line)) {
+            Some((Ok(time), line)) => Self {
+                line: line.to_owned(),
+                time: Some(time),
+            },
+            _ => Self {
+                line: s.to_owned(),
+                time: None,
+            },
+        }
+    }
+}
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct History {
     /// History path.
     pub path: std::path::PathBuf,
-    /// The history of commands entered.
-    entries: VecDeque<String>,
+    /// The history of commands entered, most recent first.
+    entries: VecDeque<Entry>,
     /// The current cursor into the history.
     cursor: Option<usize>,
     /// Maximum number of entries.
@@ -32,7 +57,7 @@ impl History {
             let r = BufReader::new(f);
 
             for line in r.lines() {
-                self.add(line?);
+                self.push(Entry::parse(&line?));
             }
         }
         Ok(())
@@ -56,20 +81,45 @@ impl History {
         let mut w = BufWriter::new(f);
 
         for entry in self.entries.iter().rev() {
-            w.write_all(entry.as_bytes())?;
+            if let Some(time) = entry.time {
+                write!(w, "{}\t", time)?;
+            }
+            w.write_all(entry.line.as_bytes())?;
             w.write_all(b"\n")?;
         }
         w.flush()
     }
 
     pub fn add<S: Into<String>>(&mut self, s: S) {
-        let entry = s.into();
-        if self.entries.front() != Some(&entry) {
-            self.entries.push_front(entry);
-            self.entries.truncate(self.capacity);
+        let time = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .ok();
+
+        self.push(Entry {
+            line: s.into(),
+            time,
+        });
+    }
+
+    fn push(&mut self, entry: Entry) {
+        match self.entries.front_mut() {
+            // Entering the same command again only updates its time.
+            Some(front) if front.line == entry.line => {
+                front.time = entry.time;
+            }
+            _ => {
+                self.entries.push_front(entry);
+                self.entries.truncate(self.capacity);
+            }
         }
     }
 
+    /// Iterate over the entries, most recent first.
+    pub fn iter(&self) -> impl Iterator<Item = &Entry> + '_ {
+        self.entries.iter()
+    }
+
     pub fn reset(&mut self) {
         self.cursor = None;
     }
@@ -94,7 +144,7 @@ impl History {
             .iter()
             .rev()
             .skip(start)
-            .position(|e| e.starts_with(prefix) && e != prefix)
+            .position(|e| e.line.starts_with(prefix) && e.line != prefix)
         {
             Some(index) => {
                 let cursor = self.len() - 1 - start - index;
@@ -115,7 +165,7 @@ impl History {
         self.entries
             .iter()
             .skip(start)
-            .position(|e| e.starts_with(prefix) && e != prefix)
+            .position(|e| e.line.starts_with(prefix) && e.line != prefix)
             .and_then(move |index| {
                 let cursor = start + index;
 
@@ -125,7 +175,18 @@ impl History {
     }
 
     pub fn get(&self, index: usize) -> Option<&str> {
-        self.entries.get(index).map(|s| s.as_str())
+        self.entries.get(index).map(|e| e.line.as_str())
+    }
+}
+
+/// Describe how long ago the given time was, relative to `now`, eg. `5m ago`.
+/// Times are in seconds since the UNIX epoch.
+pub fn age(time: u64, now: u64) -> String {
+    match now.saturating_sub(time) {
+        s if s < 60 => String::from("just now"),
+        s if s < 60 * 60 => format!("{}m ago", s / 60),
+        s if s < 60 * 60 * 24 => format!("{}h ago", s / (60 * 60)),
+        s => format!("{}d ago", s / (60 * 60 * 24)),
     }
 }
 
@@ -133,7 +194,7 @@ impl History {
 
 #[cfg(test)]
 mod test {
-    use super::History;
+    use super::*;
 
     #[test]
     fn test_history() {
@@ -215,6 +276,41 @@ mod test {
         assert_eq!(h1, h2);
     }
 
+    #[test]
+    fn test_history_times() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join(".history");
+
+        // Entries without a time are from history files predating times.
+        std::fs::write(&path, ":w\n1700000000\t:q\n").unwrap();
+
+        let mut h = History::new(&path, 16);
+        h.load().unwrap();
+
+        assert_eq!(
+            h.iter().cloned().collect::<Vec<_>>(),
+            vec![
+                Entry {
+                    line: String::from(":q"),
+                    time: Some(1700000000)
+                },
+                Entry {
+                    line: String::from(":w"),
+                    time: None
+                },
+            ]
+        );
+        h.save().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            ":w\n1700000000\t:q\n"
+        );
+
+        assert_eq!(age(1700000000, 1700000030), "just now");
+        assert_eq!(age(1700000000, 1700000000 + 60 * 5), "5m ago");
+        assert_eq!(age(1700000000, 1700000000 + 60 * 60 * 50), "2d ago");
+    }
+
     #[test]
     fn test_history_capacity() {
         let mut h = History::new("/dev/null", 3);
@@ -226,8 +322,11 @@ mod test {
         h.add("fifth");
 
         assert_eq!(
-            h.entries.iter().collect::<Vec<_>>().as_slice(),
-            &["fifth", "fourth", "third"]
+            h.entries
+                .iter()
+                .map(|e| e.line.as_str())
+                .collect::<Vec<_>>(),
+            vec!["fifth", "fourth", "third"]
         );
     }
 }
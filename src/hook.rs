@@ -0,0 +1,176 @@
+//! Shell hooks, run after certain session events.
+//!
+//! A hook is a shell command set with eg. `:set hook/write = "make assets"`.
+//! Occurrences of `{}` in the command are replaced with the quoted path of the
+//! file concerned, which is also available in the `RX_PATH` environment
+//! variable. Hooks run in the background, in the session's working directory,
+//! with no standard input. Hooks that run for longer than [`TIMEOUT`] are
+//! killed.
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time;
+
+/// Maximum time a hook is allowed to run for.
+pub const TIMEOUT: time::Duration = time::Duration::from_secs(60);
+
+/// Outcome of a hook.
+#[derive(Debug)]
+pub struct HookResult {
+    /// The setting the hook was configured with, eg. `hook/write`.
+    pub name: String,
+    pub result: io::Result<()>,
+}
+
+/// Runs hooks, each on its own thread.
+#[derive(Debug)]
+pub struct Hooks {
+    tx: mpsc::Sender<HookResult>,
+    results: mpsc::Receiver<HookResult>,
+}
+
+impl Hooks {
+    pub fn new() -> Self {
+        let (tx, results) = mpsc::channel();
+
+        Self { tx, results }
+    }
+
+    /// Run the hook command for the given path, in the given directory.
+    pub fn run(&self, name: &str, command: &str, path: &Path, cwd: &Path) {
+        let tx = self.tx.clone();
+        let name = name.to_owned();
+        let command = self::expand(command, path);
+        let path = path.to_path_buf();
+        let cwd = cwd.to_path_buf();
+
+        thread::spawn(move || {
+            let result = self::execute(&command, &path, &cwd);
+            tx.send(HookResult { name, result }).ok();
+        });
+    }
+
+    /// Get the results of the hooks completed since the last call, without blocking.
+    pub fn results(&self) -> Vec<HookResult> {
+        self.results.try_iter().collect()
+    }
+}
+
+impl Default for Hooks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Replace occurrences of `{}` in the command with the quoted path.
+pub fn expand(command: &str, path: &Path) -> String {
+    command.replace("{}", &self::quote(&path.to_string_lossy()))
+}
+
+/// Quote a string for the shell.
+fn quote(s: &str) -> String {
+    if cfg!(windows) {
+        format!("\"{}\"", s.replace('"', "\\\""))
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+/// Run a command through the shell and wait for it to exit.
+fn execute(command: &str, path: &Path, cwd: &Path) -> io::Result<()> {
+    let mut shell = if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C");
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c");
+        cmd
+    };
+    let mut child = shell
+        .arg(command)
+        .current_dir(cwd)
+        .env("RX_PATH", path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Read the error output as it's written, so that the hook never blocks on it.
+    let stderr = child.stderr.take().map(|mut err| {
+        thread::spawn(move || {
+            let mut s = String::new();
+            err.read_to_string(&mut s).ok();
+            s
+        })
+    });
+    let start = time::Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start.elapsed() >= TIMEOUT {
+            child.kill().ok();
+            child.wait().ok();
+
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("`{}` timed out after {}s", command, TIMEOUT.as_secs()),
+            ));
+        }
+        thread::sleep(time::Duration::from_millis(10));
+    };
+
+    if status.success() {
+        return Ok(());
+    }
+    let stderr = stderr.and_then(|t| t.join().ok()).unwrap_or_default();
+    let reason = stderr
+        .lines()
+        .rev()
+        .find(|l| !l.trim().is_empty())
+        .map(|l| l.trim().to_owned())
+        .unwrap_or_else(|| status.to_string());
+
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        format!("`{}` failed: {}", command, reason),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(
+            expand("cp {} assets/", Path::new("rx.png")),
+            if cfg!(windows) {
+                "cp \"rx.png\" assets/"
+            } else {
+                "cp 'rx.png' assets/"
+            }
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            expand("echo {} {}", Path::new("it's.png")),
+            "echo 'it'\\''s.png' 'it'\\''s.png'"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_execute() {
+        let cwd = std::env::temp_dir();
+        let path = Path::new("out.png");
+
+        execute("test \"$RX_PATH\" = out.png", path, &cwd).unwrap();
+
+        let err = execute("echo oops >&2; exit 3", path, &cwd).unwrap_err();
+        assert_eq!(err.to_string(), "`echo oops >&2; exit 3` failed: oops");
+    }
+}
@@ -1,14 +1,16 @@
 use crate::gfx::color::Rgba8;
 
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
 use std::ffi::OsStr;
 use std::fmt;
 use std::fs::File;
-use std::io;
+use std::io::{self, Write};
 use std::path::{self, PathBuf};
 
 use crate::pixels;
 use crate::util;
+use crate::zip;
 
 enum Encoding {
     Png,
@@ -30,6 +32,13 @@ impl Encoding {
     }
 }
 
+/// Sizes of the images written to `.ico` files, in pixels.
+pub const ICON_SIZES: &[u32] = &[16, 32, 48];
+/// Maximum width and height of the thumbnails written to `.ora` files.
+pub const THUMBNAIL_SIZE: u32 = 256;
+/// Maximum width and height of a Photoshop document.
+pub const PSD_MAX_SIZE: u32 = 30000;
+
 pub struct Path {
     parent: PathBuf,
     name: String,
@@ -89,8 +98,13 @@ pub fn load<P: AsRef<path::Path>>(path: P) -> io::Result<(Vec<u8>, u32, u32)> {
             format!("error opening {}: {}", path.as_ref().display(), e),
         )
     })?;
+    let result = match path.as_ref().extension().and_then(OsStr::to_str) {
+        Some("ora") => self::read_ora(f),
+        Some("psd") => self::read_psd(f),
+        _ => self::read(f),
+    };
 
-    self::read(f).map_err(|e| {
+    result.map_err(|e| {
         io::Error::new(
             e.kind(),
             format!("error loading {}: {}", path.as_ref().display(), e),
@@ -98,17 +112,20 @@ pub fn load<P: AsRef<path::Path>>(path: P) -> io::Result<(Vec<u8>, u32, u32)> {
     })
 }
 
+/// Read a PNG image into an 8-bit RGBA buffer. Indexed, grayscale and RGB images
+/// are converted to RGBA.
 pub fn read<R: io::Read>(reader: R) -> io::Result<(Vec<u8>, u32, u32)> {
-    let decoder = png::Decoder::new(reader);
+    let mut decoder = png::Decoder::new(reader);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
 
     let (info, mut reader) = decoder
         .read_info()
         .map_err(|_e| io::Error::new(io::ErrorKind::InvalidData, "decoding failed"))?;
 
-    if info.color_type != png::ColorType::RGBA {
+    if info.bit_depth != png::BitDepth::Eight {
         return Err(io::Error::new(
             io::ErrorKind::InvalidData,
-            "only 8-bit RGBA images are supported",
+            "only 8-bit images are supported",
         ));
     }
 
@@ -119,9 +136,204 @@ pub fn read<R: io::Read>(reader: R) -> io::Result<(Vec<u8>, u32, u32)> {
         .next_frame(&mut buffer)
         .map_err(|_e| io::Error::new(io::ErrorKind::InvalidData, "decoding failed"))?;
 
+    let buffer = match info.color_type {
+        png::ColorType::RGBA => buffer,
+        png::ColorType::RGB => buffer
+            .chunks_exact(3)
+            .flat_map(|c| [c[0], c[1], c[2], 0xff])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buffer
+            .chunks_exact(2)
+            .flat_map(|c| [c[0], c[0], c[0], c[1]])
+            .collect(),
+        png::ColorType::Grayscale => buffer.iter().flat_map(|c| [*c, *c, *c, 0xff]).collect(),
+        png::ColorType::Indexed => {
+            // Palette images are always expanded by the decoder.
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "indexed images could not be expanded",
+            ));
+        }
+    };
+
     Ok((buffer, width, height))
 }
 
+/// Read an OpenRaster image into an 8-bit RGBA buffer. The layers are read
+/// flattened, from the image's merged copy.
+pub fn read_ora<R: io::Read>(mut reader: R) -> io::Result<(Vec<u8>, u32, u32)> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    let archive = zip::Reader::new(&bytes)?;
+    if archive.read("mimetype")? != b"image/openraster" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not an OpenRaster image",
+        ));
+    }
+    self::read(archive.read("mergedimage.png")?.as_slice())
+}
+
+/// Read a Photoshop document into an 8-bit RGBA buffer. The layers are read
+/// flattened, from the document's merged image. Only 8-bit RGB and grayscale
+/// documents are supported.
+pub fn read_psd<R: io::Read>(mut reader: R) -> io::Result<(Vec<u8>, u32, u32)> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_owned());
+    let truncated = || invalid("document is truncated");
+    let u16_at = |i: usize| {
+        bytes
+            .get(i..i + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or_else(truncated)
+    };
+    let u32_at = |i: usize| {
+        bytes
+            .get(i..i + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(truncated)
+    };
+
+    if bytes.get(..4) != Some(&b"8BPS"[..]) || u16_at(4)? != 1 {
+        return Err(invalid("not a Photoshop document"));
+    }
+    let channels = u16_at(12)? as usize;
+    let (height, width) = (u32_at(14)?, u32_at(18)?);
+    let (w, h) = (width as usize, height as usize);
+
+    if !(1..=PSD_MAX_SIZE).contains(&width) || !(1..=PSD_MAX_SIZE).contains(&height) {
+        return Err(invalid("document dimensions are out of range"));
+    }
+
+    if u16_at(22)? != 8 {
+        return Err(invalid("only 8-bit documents are supported"));
+    }
+    let colors = match u16_at(24)? {
+        1 => 1,
+        3 => 3,
+        _ => return Err(invalid("only RGB and grayscale documents are supported")),
+    };
+    if channels < colors {
+        return Err(invalid("document is missing color channels"));
+    }
+    // The color channels can be followed by an alpha channel, which is the
+    // only other channel needed.
+    let planes = channels.min(colors + 1);
+    let size = planes
+        .checked_mul(w)
+        .and_then(|n| n.checked_mul(h))
+        .ok_or_else(truncated)?;
+
+    // Skip the color mode data, image resources and layers, which are each
+    // prefixed with their length.
+    let mut offset: usize = 26;
+    for _ in 0..3 {
+        offset = offset
+            .checked_add(4 + u32_at(offset)? as usize)
+            .ok_or_else(truncated)?;
+    }
+    let compression = u16_at(offset)?;
+    let offset = offset + 2;
+    let remaining = bytes.len() - offset;
+
+    let data = match compression {
+        0 if size > remaining => return Err(truncated()),
+        0 => bytes[offset..offset + size].to_vec(),
+        1 => {
+            // The compressed size of every row of every channel comes first.
+            let sizes = channels
+                .checked_mul(h)
+                .and_then(|n| n.checked_mul(2))
+                .filter(|n| *n <= remaining)
+                .ok_or_else(truncated)?;
+            // Every two bytes of *PackBits* data unpack to at most 128 bytes.
+            if size / 64 > remaining - sizes {
+                return Err(truncated());
+            }
+            let mut start = offset + sizes;
+            let mut data = Vec::with_capacity(size);
+
+            for row in 0..planes * h {
+                let n = u16_at(offset + row * 2)? as usize;
+                let packed = bytes.get(start..start + n).ok_or_else(truncated)?;
+                let unpacked = self::unpack_bits(packed, w)
+                    .ok_or_else(|| invalid("document has invalid compressed data"))?;
+
+                data.extend_from_slice(&unpacked);
+                start += n;
+            }
+            data
+        }
+        _ => return Err(invalid("unsupported compression method")),
+    };
+
+    let plane = |i: usize| &data[i * w * h..(i + 1) * w * h];
+    let (r, g, b) = if colors == 1 {
+        (plane(0), plane(0), plane(0))
+    } else {
+        (plane(0), plane(1), plane(2))
+    };
+    let buffer = (0..w * h)
+        .flat_map(|i| {
+            if planes == colors {
+                return [r[i], g[i], b[i], 0xff];
+            }
+            let a = plane(colors)[i];
+
+            // Translucent pixels of the merged image are composited over white.
+            [
+                self::unmatte(r[i], a),
+                self::unmatte(g[i], a),
+                self::unmatte(b[i], a),
+                a,
+            ]
+        })
+        .collect();
+
+    Ok((buffer, width, height))
+}
+
+/// Decode a row of the given width compressed with *PackBits*.
+fn unpack_bits(input: &[u8], width: usize) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(width);
+    let mut i = 0;
+
+    while i < input.len() {
+        let n = input[i] as i8;
+        i += 1;
+
+        if n >= 0 {
+            // Copy the next `n + 1` bytes.
+            let bytes = input.get(i..i + n as usize + 1)?;
+            output.extend_from_slice(bytes);
+            i += bytes.len();
+        } else if n != -128 {
+            // Repeat the next byte `1 - n` times.
+            let byte = *input.get(i)?;
+            output.extend(std::iter::repeat(byte).take(1 + n.unsigned_abs() as usize));
+            i += 1;
+        }
+    }
+    if output.len() == width {
+        Some(output)
+    } else {
+        None
+    }
+}
+
+/// Undo the compositing of a color component over white, given the alpha.
+fn unmatte(c: u8, a: u8) -> u8 {
+    if a == 0 {
+        return 0;
+    }
+    let (c, a) = (c as i32, a as i32);
+
+    ((c - (0xff - a)).max(0) * 0xff / a).min(0xff) as u8
+}
+
 pub fn save_as<P: AsRef<path::Path>>(
     path: P,
     w: u32,
@@ -129,10 +341,18 @@ pub fn save_as<P: AsRef<path::Path>>(
     scale: u32,
     pixels: &[Rgba8],
 ) -> io::Result<()> {
-    let f = File::create(path.as_ref())?;
+    let path = path.as_ref();
+    let f = File::create(path)?;
     let out = &mut io::BufWriter::new(f);
 
-    self::write(out, w, h, scale, pixels)
+    match path.extension().and_then(OsStr::to_str) {
+        Some("bmp") => self::write_bmp(out, w, h, scale, pixels),
+        Some("ico") => self::write_ico(out, w, h, pixels, ICON_SIZES),
+        Some("ora") => self::write_ora(out, w, h, scale, pixels),
+        Some("rgba") if scale == 1 => out.write_all(util::align_u8(pixels)),
+        Some("rgba") => out.write_all(util::align_u8(&pixels::scale(pixels, w, h, scale))),
+        _ => self::write(out, w, h, scale, pixels),
+    }
 }
 
 pub fn write<W: io::Write>(out: W, w: u32, h: u32, scale: u32, pixels: &[Rgba8]) -> io::Result<()> {
@@ -160,12 +380,530 @@ pub fn write<W: io::Write>(out: W, w: u32, h: u32, scale: u32, pixels: &[Rgba8])
         .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
 }
 
+/// Write pixels as a 32-bit BMP, with an alpha channel.
+pub fn write_bmp<W: io::Write>(
+    mut out: W,
+    w: u32,
+    h: u32,
+    scale: u32,
+    pixels: &[Rgba8],
+) -> io::Result<()> {
+    /// Size of the `BITMAPFILEHEADER` and `BITMAPV4HEADER` structures.
+    const HEADER_SIZE: u32 = 14 + 108;
+
+    let scaled;
+    let (w, h, pixels) = if scale == 1 {
+        (w, h, pixels)
+    } else {
+        scaled = pixels::scale(pixels, w, h, scale);
+        (w * scale, h * scale, scaled.as_slice())
+    };
+    let data = self::dib_rows(w, pixels);
+    let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+
+    // File header.
+    header.extend_from_slice(b"BM");
+    header.extend_from_slice(&(HEADER_SIZE + data.len() as u32).to_le_bytes());
+    header.extend_from_slice(&[0; 4]);
+    header.extend_from_slice(&HEADER_SIZE.to_le_bytes());
+
+    // Bitmap header. Color masks are used so that the alpha channel is read.
+    header.extend_from_slice(&108u32.to_le_bytes());
+    header.extend_from_slice(&(w as i32).to_le_bytes());
+    header.extend_from_slice(&(h as i32).to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes()); // Planes.
+    header.extend_from_slice(&32u16.to_le_bytes()); // Bits per pixel.
+    header.extend_from_slice(&3u32.to_le_bytes()); // `BI_BITFIELDS` compression.
+    header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    header.extend_from_slice(&2835i32.to_le_bytes()); // 72 DPI.
+    header.extend_from_slice(&2835i32.to_le_bytes());
+    header.extend_from_slice(&[0; 8]); // Colors used and important.
+    for mask in [0x00ff0000u32, 0x0000ff00, 0x000000ff, 0xff000000] {
+        header.extend_from_slice(&mask.to_le_bytes());
+    }
+    header.extend_from_slice(b"BGRs"); // sRGB color space, little-endian.
+    header.extend_from_slice(&[0; 48]); // Endpoints and gamma, unused.
+
+    out.write_all(&header)?;
+    out.write_all(&data)
+}
+
+/// Write pixels as an OpenRaster image, with a single layer.
+pub fn write_ora<W: io::Write>(
+    out: W,
+    w: u32,
+    h: u32,
+    scale: u32,
+    pixels: &[Rgba8],
+) -> io::Result<()> {
+    let scaled;
+    let (w, h, pixels) = if scale == 1 {
+        (w, h, pixels)
+    } else {
+        scaled = pixels::scale(pixels, w, h, scale);
+        (w * scale, h * scale, scaled.as_slice())
+    };
+    let mut png = Vec::new();
+    self::write(&mut png, w, h, 1, pixels)?;
+
+    // Larger images are scaled down to fit the thumbnail size.
+    let longest = w.max(h);
+    let thumbnail = if longest > THUMBNAIL_SIZE {
+        let tw = (w * THUMBNAIL_SIZE / longest).max(1);
+        let th = (h * THUMBNAIL_SIZE / longest).max(1);
+        let mut thumbnail = Vec::new();
+
+        self::write(
+            &mut thumbnail,
+            tw,
+            th,
+            1,
+            &pixels::resize(pixels, w, h, tw, th),
+        )?;
+        Some(thumbnail)
+    } else {
+        None
+    };
+    let stack = format!(
+        "<?xml version='1.0' encoding='UTF-8'?>\n\
+         <image version=\"0.0.5\" w=\"{}\" h=\"{}\">\n  \
+         <stack>\n    \
+         <layer name=\"Layer\" src=\"data/layer0.png\" x=\"0\" y=\"0\" \
+         opacity=\"1.0\" visibility=\"visible\"/>\n  \
+         </stack>\n\
+         </image>\n",
+        w, h
+    );
+
+    // The mimetype comes first, so that the format can be recognized
+    // without reading the whole archive.
+    let mut archive = zip::Writer::new(out);
+    archive.add("mimetype", b"image/openraster")?;
+    archive.add("stack.xml", stack.as_bytes())?;
+    archive.add("data/layer0.png", &png)?;
+    archive.add(
+        "Thumbnails/thumbnail.png",
+        thumbnail.as_deref().unwrap_or(&png),
+    )?;
+    archive.add("mergedimage.png", &png)?;
+    archive.finish()
+}
+
+/// Write pixels as an icon, containing one image per size. Each image is
+/// the pixels scaled to fit a square of that size.
+pub fn write_ico<W: io::Write>(
+    mut out: W,
+    w: u32,
+    h: u32,
+    pixels: &[Rgba8],
+    sizes: &[u32],
+) -> io::Result<()> {
+    let images: Vec<(u32, Vec<u8>)> = sizes
+        .iter()
+        .map(|size| {
+            let pixels = pixels::fit(pixels, w, h, *size);
+            let data = self::dib_rows(*size, &pixels);
+            // The transparency mask has one bit per pixel, with rows aligned to four bytes.
+            // Since transparency is read from the alpha channel, it is left empty.
+            let mask = vec![0; ((*size as usize + 31) / 32) * 4 * *size as usize];
+            let mut dib = Vec::with_capacity(40 + data.len() + mask.len());
+
+            // Bitmap header. The height counts both the color and mask rows.
+            dib.extend_from_slice(&40u32.to_le_bytes());
+            dib.extend_from_slice(&(*size as i32).to_le_bytes());
+            dib.extend_from_slice(&(*size as i32 * 2).to_le_bytes());
+            dib.extend_from_slice(&1u16.to_le_bytes()); // Planes.
+            dib.extend_from_slice(&32u16.to_le_bytes()); // Bits per pixel.
+            dib.extend_from_slice(&[0; 24]); // Uncompressed, with no palette.
+            dib.extend_from_slice(&data);
+            dib.extend_from_slice(&mask);
+
+            (*size, dib)
+        })
+        .collect();
+
+    let mut header = Vec::with_capacity(6 + 16 * images.len());
+    let mut offset = header.capacity() as u32;
+
+    header.extend_from_slice(&0u16.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes()); // Icon type.
+    header.extend_from_slice(&(images.len() as u16).to_le_bytes());
+
+    for (size, dib) in &images {
+        // A size of zero means 256 pixels.
+        let dim = if *size >= 256 { 0 } else { *size as u8 };
+
+        header.extend_from_slice(&[dim, dim, 0, 0]);
+        header.extend_from_slice(&1u16.to_le_bytes()); // Planes.
+        header.extend_from_slice(&32u16.to_le_bytes()); // Bits per pixel.
+        header.extend_from_slice(&(dib.len() as u32).to_le_bytes());
+        header.extend_from_slice(&offset.to_le_bytes());
+
+        offset += dib.len() as u32;
+    }
+    out.write_all(&header)?;
+
+    for (_, dib) in &images {
+        out.write_all(dib)?;
+    }
+    Ok(())
+}
+
+/// Convert pixels into BGRA rows, bottom row first, as used by BMP and ICO.
+fn dib_rows(w: u32, pixels: &[Rgba8]) -> Vec<u8> {
+    pixels
+        .chunks(w as usize)
+        .rev()
+        .flat_map(|row| row.iter().flat_map(|c| [c.b, c.g, c.r, c.a]))
+        .collect()
+}
+
+/// Convert pixels into indices of the given palette, which can hold at most 256
+/// colors. Returns the palette with duplicates removed, and the indices. Transparent
+/// pixels are mapped to a transparent entry, which is added to the palette if
+/// necessary. Fails if a color isn't in the palette.
+pub fn index(pixels: &[Rgba8], palette: &[Rgba8]) -> io::Result<(Vec<Rgba8>, Vec<u8>)> {
+    let mut palette = palette.iter().fold(Vec::new(), |mut acc, c| {
+        if !acc.contains(c) {
+            acc.push(*c);
+        }
+        acc
+    });
+    if pixels.iter().any(|p| p.a == 0) && !palette.contains(&Rgba8::TRANSPARENT) {
+        palette.push(Rgba8::TRANSPARENT);
+    }
+    if palette.len() > 256 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "palette has more than 256 colors",
+        ));
+    }
+
+    let indices: BTreeMap<Rgba8, u8> = palette
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (*c, i as u8))
+        .collect();
+    let image = pixels
+        .iter()
+        .map(|p| {
+            let p = if p.a == 0 { &Rgba8::TRANSPARENT } else { p };
+
+            indices.get(p).copied().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("color {} is not in the palette", p),
+                )
+            })
+        })
+        .collect::<io::Result<Vec<u8>>>()?;
+
+    Ok((palette, image))
+}
+
+/// Save indexed pixels as a PNG, with the given palette and bit depth (1, 2, 4 or 8).
+pub fn save_indexed_as<P: AsRef<path::Path>>(
+    path: P,
+    w: u32,
+    h: u32,
+    scale: u32,
+    pixels: &[u8],
+    palette: &[Rgba8],
+    bits: u8,
+) -> io::Result<()> {
+    let f = File::create(path.as_ref())?;
+    let out = &mut io::BufWriter::new(f);
+
+    self::write_indexed(out, w, h, scale, pixels, palette, bits)
+}
+
+/// Write indexed pixels as a PNG. Each pixel is an index into the palette, and
+/// the bit depth must be large enough to hold all indices. Palette transparency is
+/// written as a `tRNS` chunk.
+pub fn write_indexed<W: io::Write>(
+    out: W,
+    w: u32,
+    h: u32,
+    scale: u32,
+    pixels: &[u8],
+    palette: &[Rgba8],
+    bits: u8,
+) -> io::Result<()> {
+    let depth = match png::BitDepth::from_u8(bits) {
+        Some(depth) if bits <= 8 => depth,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid bit depth {}, must be 1, 2, 4 or 8", bits),
+            ))
+        }
+    };
+    assert!(!palette.is_empty() && palette.len() <= 1 << bits);
+
+    let width = w * scale;
+    let height = h * scale;
+    let mut encoder = png::Encoder::new(out, width, height);
+
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(depth);
+    encoder.set_palette(palette.iter().flat_map(|c| [c.r, c.g, c.b]).collect());
+
+    let mut writer = encoder.write_header()?;
+
+    // Only the alpha values up to the last non-opaque color need to be written.
+    if let Some(n) = palette.iter().rposition(|c| c.a != 0xff) {
+        let alphas: Vec<u8> = palette[..=n].iter().map(|c| c.a).collect();
+
+        writer
+            .write_chunk(png::chunk::tRNS, &alphas)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    }
+
+    let scaled;
+    let pixels = if scale == 1 {
+        pixels
+    } else {
+        scaled = pixels::scale(pixels, w, h, scale);
+        scaled.as_slice()
+    };
+    let data = self::pack(pixels, width as usize, bits as usize);
+
+    writer
+        .write_image_data(&data)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+}
+
+/// Pack indices of the given bit depth into bytes, most significant bits first.
+/// Each row starts on a new byte.
+fn pack(pixels: &[u8], width: usize, bits: usize) -> Vec<u8> {
+    if bits == 8 {
+        return pixels.to_vec();
+    }
+    let per_byte = 8 / bits;
+    let mut data = Vec::with_capacity(pixels.len() / per_byte + 1);
+
+    for row in pixels.chunks(width) {
+        for chunk in row.chunks(per_byte) {
+            let byte = chunk
+                .iter()
+                .enumerate()
+                .fold(0u8, |byte, (i, ix)| byte | ix << (8 - bits * (i + 1)));
+            data.push(byte);
+        }
+    }
+    data
+}
+
 #[cfg(test)]
 mod test {
-    use super::Path;
+    use super::*;
     use std::convert::TryFrom;
     use std::path;
 
+    #[test]
+    fn test_pack() {
+        assert_eq!(
+            pack(&[1, 0, 1, 1, 0, 0, 0, 1, 1], 9, 1),
+            vec![0b1011_0001, 0b1000_0000]
+        );
+        assert_eq!(
+            pack(&[3, 2, 1, 0, 1, 2], 3, 2),
+            vec![0b1110_0100, 0b0001_1000]
+        );
+        assert_eq!(pack(&[0xa, 0xb, 0xc], 3, 4), vec![0xab, 0xc0]);
+        assert_eq!(pack(&[7, 8, 9], 3, 8), vec![7, 8, 9]);
+    }
+
+    #[test]
+    fn test_write_indexed() {
+        let palette = [
+            Rgba8::TRANSPARENT,
+            Rgba8::new(0xff, 0, 0, 0xff),
+            Rgba8::new(0, 0, 0xff, 0x80),
+        ];
+        let indices = [0, 1, 2, 1, 0, 2];
+        let mut out = Vec::new();
+
+        write_indexed(&mut out, 3, 2, 2, &indices, &palette, 2).unwrap();
+
+        let (rgba, w, h) = read(out.as_slice()).unwrap();
+        let pixels: Vec<Rgba8> = rgba
+            .chunks_exact(4)
+            .map(|c| Rgba8::new(c[0], c[1], c[2], c[3]))
+            .collect();
+
+        assert_eq!((w, h), (6, 4));
+        assert_eq!(pixels.len(), 24);
+        assert_eq!(
+            &pixels[..6],
+            &[palette[0], palette[0], palette[1], palette[1], palette[2], palette[2]]
+        );
+        assert_eq!(
+            &pixels[18..],
+            &[palette[1], palette[1], palette[0], palette[0], palette[2], palette[2]]
+        );
+    }
+
+    #[test]
+    fn test_write_bmp() {
+        let red = Rgba8::new(0xff, 0, 0, 0xff);
+        let mut out = Vec::new();
+
+        write_bmp(&mut out, 2, 1, 1, &[red, Rgba8::TRANSPARENT]).unwrap();
+
+        assert_eq!(&out[..2], b"BM");
+        assert_eq!(out.len(), 14 + 108 + 8);
+        assert_eq!(&out[2..6], &(out.len() as u32).to_le_bytes());
+        assert_eq!(&out[122..], &[0, 0, 0xff, 0xff, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_write_ico() {
+        let pixels = vec![Rgba8::WHITE; 8 * 4];
+        let mut out = Vec::new();
+
+        write_ico(&mut out, 8, 4, &pixels, &[16, 32]).unwrap();
+
+        assert_eq!(&out[..6], &[0, 0, 1, 0, 2, 0]);
+        assert_eq!(out[6], 16);
+        assert_eq!(out[22], 32);
+
+        // Each image is a header, followed by the pixels and the mask.
+        let size16 = 40 + 16 * 16 * 4 + 4 * 16;
+        let size32 = 40 + 32 * 32 * 4 + 4 * 32;
+
+        assert_eq!(&out[14..18], &(size16 as u32).to_le_bytes());
+        assert_eq!(&out[18..22], &38u32.to_le_bytes());
+        assert_eq!(&out[34..38], &((38 + size16) as u32).to_le_bytes());
+        assert_eq!(out.len(), 38 + size16 + size32);
+    }
+
+    #[test]
+    fn test_ora() {
+        let pixels = [Rgba8::RED, Rgba8::TRANSPARENT, Rgba8::BLUE];
+        let mut out = Vec::new();
+
+        write_ora(&mut out, 3, 1, 2, &pixels).unwrap();
+        assert_eq!(&out[30..38], b"mimetype");
+
+        let archive = zip::Reader::new(&out).unwrap();
+        let stack = String::from_utf8(archive.read("stack.xml").unwrap()).unwrap();
+        assert!(stack.contains("<image version=\"0.0.5\" w=\"6\" h=\"2\">"));
+        assert!(stack.contains("src=\"data/layer0.png\""));
+        assert_eq!(
+            archive.read("data/layer0.png").unwrap(),
+            archive.read("Thumbnails/thumbnail.png").unwrap()
+        );
+
+        let (rgba, w, h) = read_ora(out.as_slice()).unwrap();
+        assert_eq!((w, h), (6, 2));
+        assert_eq!(&rgba[..8], &[0xff, 0, 0, 0xff, 0xff, 0, 0, 0xff]);
+
+        // Large images get a smaller thumbnail.
+        let mut out = Vec::new();
+        write_ora(&mut out, 512, 128, 1, &vec![Rgba8::RED; 512 * 128]).unwrap();
+
+        let archive = zip::Reader::new(&out).unwrap();
+        let thumbnail = archive.read("Thumbnails/thumbnail.png").unwrap();
+        let (_, w, h) = read(thumbnail.as_slice()).unwrap();
+        assert_eq!((w, h), (256, 64));
+    }
+
+    #[test]
+    fn test_read_psd() {
+        let psd = |channels: u16, mode: u16, compression: u16, data: &[u8]| {
+            let mut psd = b"8BPS".to_vec();
+
+            psd.extend_from_slice(&1u16.to_be_bytes());
+            psd.extend_from_slice(&[0; 6]);
+            psd.extend_from_slice(&channels.to_be_bytes());
+            psd.extend_from_slice(&1u32.to_be_bytes()); // Height.
+            psd.extend_from_slice(&2u32.to_be_bytes()); // Width.
+            psd.extend_from_slice(&8u16.to_be_bytes());
+            psd.extend_from_slice(&mode.to_be_bytes());
+            psd.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 2, 0xa, 0xb, 0, 0, 0, 0]);
+            psd.extend_from_slice(&compression.to_be_bytes());
+            psd.extend_from_slice(data);
+            psd
+        };
+
+        // Red and half-transparent blue, composited over white.
+        let rgba = [0xff, 0x7f, 0, 0x7f, 0, 0xff, 0xff, 0x80];
+        let (pixels, w, h) = read_psd(psd(4, 3, 0, &rgba).as_slice()).unwrap();
+        assert_eq!((w, h), (2, 1));
+        assert_eq!(pixels, vec![0xff, 0, 0, 0xff, 0, 0, 0xff, 0x80]);
+
+        // The same, with *PackBits* compression, and an extra channel which is ignored.
+        let rle = [
+            0, 3, 0, 3, 0, 3, 0, 3, 0, 2, // Row sizes.
+            1, 0xff, 0x7f, // Copy the next two bytes.
+            1, 0, 0x7f, //
+            1, 0, 0xff, //
+            1, 0xff, 0x80, //
+            0xff, 0x42, // Repeat the next byte twice.
+        ];
+        let (pixels, _, _) = read_psd(psd(5, 3, 1, &rle).as_slice()).unwrap();
+        assert_eq!(pixels, vec![0xff, 0, 0, 0xff, 0, 0, 0xff, 0x80]);
+
+        let (pixels, _, _) = read_psd(psd(1, 1, 0, &[0x10, 0x20]).as_slice()).unwrap();
+        assert_eq!(pixels, vec![0x10, 0x10, 0x10, 0xff, 0x20, 0x20, 0x20, 0xff]);
+
+        assert!(read_psd(psd(4, 4, 0, &rgba).as_slice()).is_err());
+        assert!(read_psd(psd(4, 3, 0, &rgba[..6]).as_slice()).is_err());
+        assert!(read_psd(psd(4, 3, 1, &rle).as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_read_psd_header() {
+        let header = |width: u32, height: u32, compression: u16| {
+            let mut psd = b"8BPS".to_vec();
+
+            psd.extend_from_slice(&1u16.to_be_bytes());
+            psd.extend_from_slice(&[0; 6]);
+            psd.extend_from_slice(&4u16.to_be_bytes());
+            psd.extend_from_slice(&height.to_be_bytes());
+            psd.extend_from_slice(&width.to_be_bytes());
+            psd.extend_from_slice(&8u16.to_be_bytes());
+            psd.extend_from_slice(&3u16.to_be_bytes());
+            psd.extend_from_slice(&[0; 12]);
+            psd.extend_from_slice(&compression.to_be_bytes());
+            psd
+        };
+        let error = |psd: Vec<u8>| read_psd(psd.as_slice()).unwrap_err().to_string();
+
+        // Dimensions past the limit are rejected outright.
+        assert_eq!(
+            error(header(PSD_MAX_SIZE + 1, 1, 0)),
+            "document dimensions are out of range"
+        );
+        assert_eq!(
+            error(header(u32::MAX, u32::MAX, 0)),
+            "document dimensions are out of range"
+        );
+        assert_eq!(
+            error(header(0, 1, 0)),
+            "document dimensions are out of range"
+        );
+
+        // Large documents without the data to match are rejected before
+        // anything is allocated.
+        for compression in [0, 1] {
+            assert_eq!(
+                error(header(PSD_MAX_SIZE, PSD_MAX_SIZE, compression)),
+                "document is truncated"
+            );
+        }
+        // A header cut short.
+        let mut psd = header(2, 1, 0);
+        psd.truncate(20);
+        assert_eq!(error(psd), "document is truncated");
+
+        // Sections claiming to be larger than the document.
+        let mut psd = header(2, 1, 0);
+        psd[26..30].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(error(psd), "document is truncated");
+    }
+
     #[test]
     fn test_image_path() {
         assert!(Path::try_from(path::Path::new("/")).is_err());
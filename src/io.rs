@@ -1,24 +1,272 @@
+use crate::cmd::Value;
 use crate::image;
-use crate::view::ViewExtent;
+use crate::parser;
+use crate::pixels;
+use crate::util;
+use crate::view::{path::RAW_FORMAT, ViewExtent};
 
 use crate::gfx::color::Rgba8;
 
-use std::io;
+use memoir::traits::Parse;
+use memoir::*;
+
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
 use std::path::Path;
 
-#[allow(dead_code)]
-#[derive(Debug)]
+/// Archive contents, other than the pixels.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Manifest {
     pub extent: ViewExtent,
+    /// Palette colors, if the palette was saved with the archive.
+    pub palette: Option<Vec<Rgba8>>,
+    /// Per-frame animation delays, in milliseconds.
+    pub delays: Option<Vec<u64>>,
+    /// View settings, eg. `animation/delay`.
+    pub settings: Vec<(String, Value)>,
 }
 
-#[allow(dead_code)]
+impl Manifest {
+    pub fn new(extent: ViewExtent) -> Self {
+        Self {
+            extent,
+            palette: None,
+            delays: None,
+            settings: Vec::new(),
+        }
+    }
+
+    /// Write the manifest. Each line is a directive, eg. `extent 16 16 4`.
+    pub fn write<W: io::Write>(&self, mut w: W) -> io::Result<()> {
+        let ViewExtent { fw, fh, nframes } = self.extent;
+
+        writeln!(w, "extent {} {} {}", fw, fh, nframes)?;
+
+        if let Some(palette) = &self.palette {
+            for color in palette {
+                writeln!(w, "color {}", color)?;
+            }
+        }
+        if let Some(delays) = &self.delays {
+            for delay in delays {
+                writeln!(w, "delay {}", delay)?;
+            }
+        }
+        for (k, v) in &self.settings {
+            match v {
+                Value::Str(s) => writeln!(w, "set {} = {}", k, self::escape(s))?,
+                Value::U32Tuple(x, y) => writeln!(w, "set {} = {} {}", k, x, y)?,
+                Value::F32Tuple(x, y) => writeln!(w, "set {} = {} {}", k, x, y)?,
+                v => writeln!(w, "set {} = {}", k, v)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a manifest. Unknown directives are ignored, so that manifests
+    /// written by newer versions can still be read. Only the extent is
+    /// required.
+    pub fn read<R: io::BufRead>(r: R) -> io::Result<Self> {
+        let mut extent = None;
+        let mut palette: Option<Vec<Rgba8>> = None;
+        let mut delays: Option<Vec<u64>> = None;
+        let mut settings = Vec::new();
+
+        for (i, line) in r.lines().enumerate() {
+            let line = line?;
+            let (directive, args) = line.split_once(' ').unwrap_or((line.as_str(), ""));
+            let invalid = |e: memoir::result::Error| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("manifest: {} on line {}", e, i + 1),
+                )
+            };
+
+            match directive {
+                "extent" => {
+                    let ((fw, fh), nframes) = natural::<u32>()
+                        .skip(whitespace())
+                        .then(natural::<u32>())
+                        .skip(whitespace())
+                        .then(natural::<usize>())
+                        .end()
+                        .parse(args)
+                        .map(|(out, _)| out)
+                        .map_err(|(e, _)| invalid(e))?;
+
+                    extent = Some(ViewExtent::new(fw, fh, nframes));
+                }
+                "color" => {
+                    let (color, _) = parser::color()
+                        .end()
+                        .parse(args)
+                        .map_err(|(e, _)| invalid(e))?;
+
+                    palette.get_or_insert_with(Vec::new).push(color);
+                }
+                "delay" => {
+                    let (delay, _) = natural::<u64>()
+                        .end()
+                        .parse(args)
+                        .map_err(|(e, _)| invalid(e))?;
+
+                    delays.get_or_insert_with(Vec::new).push(delay);
+                }
+                "set" => {
+                    let (name, value) = parser::setting()
+                        .skip(optional(whitespace()))
+                        .skip(symbol('='))
+                        .skip(optional(whitespace()))
+                        .parse(args)
+                        .map_err(|(e, _)| invalid(e))?;
+
+                    let value = if value.starts_with('"') {
+                        self::unescape(value).map(Value::Str).ok_or_else(|| {
+                            invalid(memoir::result::Error::new("invalid string".to_owned()))
+                        })?
+                    } else {
+                        Value::parser()
+                            .end()
+                            .parse(value)
+                            .map(|(v, _)| v)
+                            .map_err(|(e, _)| invalid(e))?
+                    };
+                    settings.push((name, value));
+                }
+                _ => {
+                    debug!("manifest: skipping unknown directive {:?}", directive);
+                }
+            }
+        }
+
+        let extent = extent.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "manifest: missing extent")
+        })?;
+
+        Ok(Self {
+            extent,
+            palette,
+            delays,
+            settings,
+        })
+    }
+}
+
+/// An rx archive, which holds view pixels along with a manifest.
 #[derive(Debug)]
 pub struct Archive {
-    pub layers: Vec<Vec<Vec<Rgba8>>>,
+    /// Pixels of each layer, laid out like the view.
+    pub layers: Vec<Vec<Rgba8>>,
     pub manifest: Manifest,
 }
 
+impl Archive {
+    /// Write the archive, compressed. The manifest comes first, followed by a
+    /// blank line and the pixels of each layer.
+    pub fn write<W: io::Write>(&self, w: W) -> io::Result<()> {
+        let mut w = snap::Writer::new(w);
+
+        self.manifest.write(&mut w)?;
+        writeln!(w)?;
+
+        for layer in &self.layers {
+            w.write_all(util::align_u8(layer))?;
+        }
+        w.flush()
+    }
+
+    /// Read an archive written with [`Archive::write`].
+    pub fn read<R: io::Read>(r: R) -> io::Result<Self> {
+        let mut r = io::BufReader::new(snap::Reader::new(r));
+        let mut manifest = String::new();
+
+        loop {
+            let mut line = String::new();
+
+            if r.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            manifest.push_str(&line);
+        }
+        let manifest = Manifest::read(manifest.as_bytes())?;
+
+        let mut pixels = Vec::new();
+        r.read_to_end(&mut pixels)?;
+
+        let ViewExtent { fw, fh, nframes } = manifest.extent;
+        let size = fw as usize * fh as usize * nframes * 4;
+
+        if size == 0 || pixels.is_empty() || pixels.len() % size != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "archive: expected {} bytes per layer, got {} bytes",
+                    size,
+                    pixels.len()
+                ),
+            ));
+        }
+        let layers = pixels
+            .chunks(size)
+            .map(|layer| Rgba8::align(layer).to_vec())
+            .collect();
+
+        Ok(Self { layers, manifest })
+    }
+
+    /// Write the archive to the given path.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let f = fs::File::create(path)?;
+
+        self.write(io::BufWriter::new(f))
+    }
+
+    /// Read the archive at the given path.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let f = fs::File::open(path)?;
+
+        Self::read(io::BufReader::new(f))
+    }
+}
+
+/// Quote a string, escaping quotes, backslashes and line breaks.
+fn escape(s: &str) -> String {
+    let mut escaped = String::from('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Parse a string quoted with [`escape`]. Nothing may follow the closing quote.
+fn unescape(s: &str) -> Option<String> {
+    let mut chars = s.strip_prefix('"')?.chars();
+    let mut unescaped = String::new();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return chars.as_str().trim().is_empty().then_some(unescaped),
+            '\\' => unescaped.push(match chars.next()? {
+                'n' => '\n',
+                'r' => '\r',
+                c @ ('"' | '\\') => c,
+                _ => return None,
+            }),
+            c => unescaped.push(c),
+        }
+    }
+    None
+}
+
 pub fn load_image<P: AsRef<Path>>(path: P) -> io::Result<(u32, u32, Vec<Rgba8>)> {
     let (buffer, width, height) = image::load(path)?;
     let pixels = Rgba8::align(&buffer);
@@ -27,3 +275,402 @@ pub fn load_image<P: AsRef<Path>>(path: P) -> io::Result<(u32, u32, Vec<Rgba8>)>
 
     Ok((width, height, pixels.into()))
 }
+
+/// Pixel format of raw exports.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RawFormat {
+    /// 16-bit color, with 5 bits of red, 6 of green and 5 of blue, little-endian.
+    Rgb565,
+    /// 32-bit color, one byte per channel, in RGBA order.
+    Rgba8,
+    /// One byte per pixel, indexing into the palette.
+    Indexed,
+}
+
+impl fmt::Display for RawFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rgb565 => "rgb565".fmt(f),
+            Self::Rgba8 => "rgba8".fmt(f),
+            Self::Indexed => "indexed".fmt(f),
+        }
+    }
+}
+
+/// Export view pixels as raw bytes, in the given format. Frames are written one
+/// after the other, each one row by row, top row first. If a palette format is
+/// given, the palette is also written, next to the pixels, with a `.pal` extension.
+/// Returns the number of pixels written.
+pub fn export_raw(
+    path: &Path,
+    extent: ViewExtent,
+    pixels: &[Rgba8],
+    format: RawFormat,
+    palette: &[Rgba8],
+    palette_format: Option<RawFormat>,
+) -> io::Result<usize> {
+    if palette_format == Some(RawFormat::Indexed) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "palette can't be written as indexed colors",
+        ));
+    }
+    let pixels = self::frames(extent, pixels);
+
+    // When indexing, a transparent color may be added to the palette.
+    // Otherwise, only duplicates are removed.
+    let (palette, data) = match format {
+        RawFormat::Indexed => image::index(&pixels, palette)?,
+        format => (image::index(&[], palette)?.0, self::encode(&pixels, format)),
+    };
+    fs::write(path, data)?;
+
+    if let Some(f) = palette_format {
+        fs::write(path.with_extension("pal"), self::encode(&palette, f))?;
+    }
+    Ok(pixels.len())
+}
+
+/// Programming language of source code exports.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Language {
+    C,
+    Rust,
+}
+
+/// Export view pixels as source code, defining a byte array of RGBA pixels,
+/// with frames one after the other, as well as the frame size and count.
+/// Definitions are named after the file. Returns the number of pixels written.
+pub fn export_source(
+    path: &Path,
+    language: Language,
+    extent: ViewExtent,
+    pixels: &[Rgba8],
+    scale: u32,
+) -> io::Result<usize> {
+    let name = path
+        .file_stem()
+        .map(|s| self::identifier(&s.to_string_lossy()))
+        .unwrap_or_else(|| String::from("SPRITE"));
+    let scaled = pixels::scale(pixels, extent.width(), extent.height(), scale);
+    let extent = ViewExtent::new(extent.fw * scale, extent.fh * scale, extent.nframes);
+    let pixels = self::frames(extent, &scaled);
+
+    let f = fs::File::create(path)?;
+    let out = &mut io::BufWriter::new(f);
+
+    self::write_source(out, &name, language, extent, &pixels)?;
+
+    Ok(pixels.len())
+}
+
+/// Write frame pixels as source code. See [`export_source`].
+fn write_source<W: io::Write>(
+    mut out: W,
+    name: &str,
+    language: Language,
+    extent: ViewExtent,
+    pixels: &[Rgba8],
+) -> io::Result<()> {
+    let bytes = util::align_u8(pixels);
+    let constants = [
+        ("WIDTH", extent.fw as usize),
+        ("HEIGHT", extent.fh as usize),
+        ("FRAMES", extent.nframes),
+    ];
+
+    match language {
+        Language::C => {
+            for (suffix, n) in constants {
+                writeln!(out, "#define {}_{} {}", name, suffix, n)?;
+            }
+            writeln!(out)?;
+            writeln!(
+                out,
+                "static const unsigned char {}[{}] = {{",
+                name,
+                bytes.len()
+            )?;
+        }
+        Language::Rust => {
+            for (suffix, n) in constants {
+                writeln!(out, "pub const {}_{}: usize = {};", name, suffix, n)?;
+            }
+            writeln!(out)?;
+            writeln!(out, "pub static {}: [u8; {}] = [", name, bytes.len())?;
+        }
+    }
+    for line in bytes.chunks(16) {
+        let line: Vec<_> = line.iter().map(|b| format!("0x{:02x},", b)).collect();
+        writeln!(out, "    {}", line.join(" "))?;
+    }
+    match language {
+        Language::C => writeln!(out, "}};"),
+        Language::Rust => writeln!(out, "];"),
+    }
+}
+
+/// Turn a file name into an upper-case identifier, valid in both C and Rust.
+fn identifier(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    match name.chars().next() {
+        Some(c) if c.is_ascii_digit() => format!("_{}", name),
+        Some(_) => name,
+        None => String::from("SPRITE"),
+    }
+}
+
+/// Encode colors in the given format, which can't be indexed.
+fn encode(colors: &[Rgba8], format: RawFormat) -> Vec<u8> {
+    match format {
+        RawFormat::Rgb565 => colors
+            .iter()
+            .flat_map(|c| {
+                let c = (c.r as u16 >> 3) << 11 | (c.g as u16 >> 2) << 5 | c.b as u16 >> 3;
+                c.to_le_bytes()
+            })
+            .collect(),
+        RawFormat::Rgba8 => colors.iter().flat_map(|c| [c.r, c.g, c.b, c.a]).collect(),
+        RawFormat::Indexed => unreachable!("indexed colors require a palette"),
+    }
+}
+
+/// Re-order view pixels so that each frame is contiguous.
+fn frames(extent: ViewExtent, pixels: &[Rgba8]) -> Vec<Rgba8> {
+    let (fw, fh, width) = (
+        extent.fw as usize,
+        extent.fh as usize,
+        extent.width() as usize,
+    );
+    let mut result = Vec::with_capacity(pixels.len());
+
+    for i in 0..extent.nframes {
+        for y in 0..fh {
+            let start = y * width + i * fw;
+            result.extend_from_slice(&pixels[start..start + fw]);
+        }
+    }
+    result
+}
+
+/// Load raw RGBA pixels of the given size, without a header. The path must
+/// have the raw image extension, so that the view is saved back as raw pixels.
+pub fn load_raw<P: AsRef<Path>>(path: P, width: u32, height: u32) -> io::Result<Vec<Rgba8>> {
+    if path.as_ref().extension() != Some(RAW_FORMAT.as_ref()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{}: only raw images can be given a size, and they must have a `.{}` extension",
+                path.as_ref().display(),
+                RAW_FORMAT
+            ),
+        ));
+    }
+    let bytes = fs::read(&path)?;
+    let size = width as usize * height as usize * 4;
+
+    if bytes.len() != size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{}: expected {} bytes for a {}x{} image, got {}",
+                path.as_ref().display(),
+                size,
+                width,
+                height,
+                bytes.len()
+            ),
+        ));
+    }
+    Ok(Rgba8::align(&bytes).to_vec())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_manifest_roundtrip() {
+        let manifest = Manifest {
+            extent: ViewExtent::new(16, 24, 4),
+            palette: Some(vec![Rgba8::RED, Rgba8::new(0x11, 0x22, 0x33, 0xff)]),
+            delays: Some(vec![160, 160, 320, 80]),
+            settings: vec![
+                ("animation/delay".to_owned(), Value::U32(160)),
+                ("grid".to_owned(), Value::Bool(true)),
+                ("grid/spacing".to_owned(), Value::U32Tuple(8, 8)),
+            ],
+        };
+
+        let mut buf = Vec::new();
+        manifest.write(&mut buf).unwrap();
+
+        assert_eq!(Manifest::read(buf.as_slice()).unwrap(), manifest);
+    }
+
+    #[test]
+    fn test_manifest_escape() {
+        let manifest = Manifest {
+            settings: vec![
+                (
+                    "a".to_owned(),
+                    Value::Str("say \"hi\"\nset b = 1".to_owned()),
+                ),
+                ("b".to_owned(), Value::Str("C:\\sprites\\".to_owned())),
+            ],
+            ..Manifest::new(ViewExtent::new(1, 1, 1))
+        };
+
+        let mut buf = Vec::new();
+        manifest.write(&mut buf).unwrap();
+
+        assert_eq!(String::from_utf8_lossy(&buf).lines().count(), 3);
+        assert_eq!(Manifest::read(buf.as_slice()).unwrap(), manifest);
+
+        assert_eq!(unescape("\"a\" b"), None);
+        assert_eq!(unescape("\"a"), None);
+        assert_eq!(unescape("\"\\x\""), None);
+    }
+
+    #[test]
+    fn test_archive_roundtrip() {
+        let extent = ViewExtent::new(2, 1, 2);
+        let archive = Archive {
+            layers: vec![vec![Rgba8::RED, Rgba8::GREEN, Rgba8::BLUE, Rgba8::WHITE]],
+            manifest: Manifest {
+                palette: Some(vec![Rgba8::RED]),
+                delays: Some(vec![100, 200]),
+                ..Manifest::new(extent)
+            },
+        };
+
+        let mut buf = Vec::new();
+        archive.write(&mut buf).unwrap();
+
+        let read = Archive::read(buf.as_slice()).unwrap();
+        assert_eq!(read.manifest, archive.manifest);
+        assert_eq!(read.layers, archive.layers);
+
+        let mut buf = Vec::new();
+        Archive {
+            layers: vec![vec![Rgba8::RED; 3]],
+            manifest: Manifest::new(extent),
+        }
+        .write(&mut buf)
+        .unwrap();
+        assert!(Archive::read(buf.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_raw_encoding() {
+        #[rustfmt::skip]
+        let pixels = [
+            Rgba8::RED, Rgba8::GREEN, Rgba8::BLUE, Rgba8::WHITE,
+            Rgba8::BLACK, Rgba8::RED, Rgba8::GREEN, Rgba8::BLUE,
+        ];
+        let frames = frames(ViewExtent::new(2, 2, 2), &pixels);
+
+        assert_eq!(
+            frames,
+            vec![
+                Rgba8::RED,
+                Rgba8::GREEN,
+                Rgba8::BLACK,
+                Rgba8::RED,
+                Rgba8::BLUE,
+                Rgba8::WHITE,
+                Rgba8::GREEN,
+                Rgba8::BLUE
+            ]
+        );
+        assert_eq!(
+            encode(&frames[..3], RawFormat::Rgb565),
+            vec![0x00, 0xf8, 0xe0, 0x07, 0x00, 0x00]
+        );
+        assert_eq!(
+            encode(&[Rgba8::new(1, 2, 3, 4)], RawFormat::Rgba8),
+            vec![1, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn test_source_export() {
+        let pixels = [Rgba8::RED, Rgba8::new(1, 2, 3, 4)];
+        let extent = ViewExtent::new(1, 1, 2);
+
+        let mut out = Vec::new();
+        write_source(&mut out, "HERO", Language::C, extent, &pixels).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "#define HERO_WIDTH 1\n\
+             #define HERO_HEIGHT 1\n\
+             #define HERO_FRAMES 2\n\
+             \n\
+             static const unsigned char HERO[8] = {\n    \
+             0xff, 0x00, 0x00, 0xff, 0x01, 0x02, 0x03, 0x04,\n\
+             };\n"
+        );
+
+        let mut out = Vec::new();
+        write_source(&mut out, "HERO", Language::Rust, extent, &pixels).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "pub const HERO_WIDTH: usize = 1;\n\
+             pub const HERO_HEIGHT: usize = 1;\n\
+             pub const HERO_FRAMES: usize = 2;\n\
+             \n\
+             pub static HERO: [u8; 8] = [\n    \
+             0xff, 0x00, 0x00, 0xff, 0x01, 0x02, 0x03, 0x04,\n\
+             ];\n"
+        );
+
+        assert_eq!(identifier("hero-walk.2"), "HERO_WALK_2");
+        assert_eq!(identifier("8ball"), "_8BALL");
+        assert_eq!(identifier(""), "SPRITE");
+    }
+
+    #[test]
+    fn test_load_raw() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("rx-raw-{}.{}", std::process::id(), RAW_FORMAT));
+        let pixels = [Rgba8::RED, Rgba8::new(1, 2, 3, 4)];
+
+        fs::write(&path, util::align_u8(&pixels)).unwrap();
+        assert_eq!(load_raw(&path, 2, 1).unwrap(), pixels);
+        assert_eq!(
+            load_raw(&path, 2, 2).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        fs::remove_file(&path).unwrap();
+
+        for ext in ["png", "gif", "rxz", "bin"] {
+            let path = dir.join(format!("sprite.{}", ext));
+            assert_eq!(
+                load_raw(path, 2, 1).unwrap_err().kind(),
+                io::ErrorKind::InvalidInput
+            );
+        }
+        assert_eq!(
+            load_raw(dir.join("sprite"), 2, 1).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+
+    #[test]
+    fn test_manifest_backwards_compatible() {
+        let manifest = Manifest::read("extent 8 8 2\nfnord 1 2 3\n".as_bytes()).unwrap();
+
+        assert_eq!(manifest, Manifest::new(ViewExtent::new(8, 8, 2)));
+        assert!(Manifest::read("color #ff0000\n".as_bytes()).is_err());
+    }
+}
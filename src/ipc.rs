@@ -0,0 +1,220 @@
+//! Remote control over a local socket.
+//!
+//! Other programs can connect to the socket and send commands, one per line,
+//! as they would be typed on the command line, eg. `:export out.png`. Each
+//! command gets a one-line reply: `ok`, followed by the message the command
+//! displayed, if any, or `error: ` followed by the reason it failed.
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time;
+
+/// How often the main loop checks for requests while the socket is open.
+pub const POLL_INTERVAL: time::Duration = time::Duration::from_millis(50);
+
+/// A command received over the socket, awaiting a reply.
+#[derive(Debug)]
+pub struct Request {
+    /// The command line, eg. `:w`.
+    pub input: String,
+
+    reply: mpsc::Sender<Result<String, String>>,
+}
+
+impl Request {
+    /// Reply to the request with the outcome of the command.
+    pub fn reply(self, result: Result<String, String>) {
+        self.reply.send(result).ok();
+    }
+}
+
+/// Listens on a socket for commands, on a separate thread.
+#[derive(Debug)]
+pub struct Server {
+    pub path: PathBuf,
+
+    requests: mpsc::Receiver<Request>,
+}
+
+impl Server {
+    /// Listen on a UNIX socket at the given path. A stale socket left behind
+    /// by a previous session is replaced.
+    #[cfg(unix)]
+    pub fn bind<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        use std::io::{BufRead, BufReader, Write};
+        use std::os::unix::net::{UnixListener, UnixStream};
+        use std::thread;
+
+        let path = path.as_ref().to_path_buf();
+
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is in use by another session", path.display()),
+                ));
+            }
+            std::fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        let (tx, requests) = mpsc::channel();
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let tx = tx.clone();
+
+                thread::spawn(move || {
+                    let mut writer = match stream.try_clone() {
+                        Ok(w) => w,
+                        Err(_) => return,
+                    };
+                    for input in BufReader::new(stream).lines().map_while(Result::ok) {
+                        let input = input.trim();
+
+                        if input.is_empty() {
+                            continue;
+                        }
+                        let (reply, rx) = mpsc::channel();
+                        let req = Request {
+                            input: input.to_owned(),
+                            reply,
+                        };
+                        if tx.send(req).is_err() {
+                            return;
+                        }
+                        let line = match rx.recv() {
+                            Ok(Ok(msg)) if msg.is_empty() => String::from("ok"),
+                            Ok(Ok(msg)) => format!("ok: {}", msg),
+                            Ok(Err(err)) => format!("error: {}", err),
+                            Err(_) => return,
+                        };
+                        if writeln!(writer, "{}", line).is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+
+        Ok(Self { path, requests })
+    }
+
+    #[cfg(not(unix))]
+    pub fn bind<P: AsRef<Path>>(_path: P) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "remote control is only supported on UNIX platforms",
+        ))
+    }
+
+    /// Get the requests received since the last call, without blocking.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.try_iter().collect()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+/// Send the commands read from `input` to the session listening at `path`,
+/// and write the replies to `output`. Fails if any of the commands failed.
+#[cfg(unix)]
+pub fn send<P: AsRef<Path>, R: io::BufRead, W: io::Write>(
+    path: P,
+    input: R,
+    mut output: W,
+) -> io::Result<()> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    let mut stream = UnixStream::connect(path)?;
+    let mut replies = BufReader::new(stream.try_clone()?).lines();
+    let mut failed = 0;
+
+    for line in input.lines() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+        writeln!(stream, "{}", line)?;
+
+        let reply = replies.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "session closed the connection",
+            )
+        })??;
+
+        if reply.starts_with("error") {
+            failed += 1;
+        }
+        writeln!(output, "{}", reply)?;
+    }
+    if failed > 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("{} command(s) failed", failed),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn send<P: AsRef<Path>, R: io::BufRead, W: io::Write>(
+    _path: P,
+    _input: R,
+    _output: W,
+) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "remote control is only supported on UNIX platforms",
+    ))
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_send() {
+        let path = std::env::temp_dir().join(format!("rx-test-{}.sock", std::process::id()));
+        let server = Server::bind(&path).unwrap();
+        let client = {
+            let path = path.clone();
+            thread::spawn(move || {
+                let mut output = Vec::new();
+                let result = send(&path, ":w\n\n:export out.png\n".as_bytes(), &mut output);
+
+                (result.is_ok(), String::from_utf8(output).unwrap())
+            })
+        };
+
+        for expected in [":w", ":export out.png"] {
+            let req = loop {
+                if let Some(req) = server.requests().pop() {
+                    break req;
+                }
+                thread::sleep(time::Duration::from_millis(1));
+            };
+            assert_eq!(req.input, expected);
+
+            if expected == ":w" {
+                req.reply(Ok(String::new()));
+            } else {
+                req.reply(Err(String::from("png only")));
+            }
+        }
+        let (ok, output) = client.join().unwrap();
+
+        assert!(!ok);
+        assert_eq!(output, "ok\nerror: png only\n");
+
+        drop(server);
+        assert!(!path.exists());
+    }
+}
@@ -15,8 +15,10 @@
 )]
 
 pub mod data;
+pub mod engine;
 pub mod execution;
 pub mod gfx;
+pub mod ipc;
 pub mod logger;
 pub mod session;
 
@@ -24,23 +26,38 @@ mod alloc;
 mod autocomplete;
 mod brush;
 mod cmd;
+mod collab;
 mod color;
+mod config;
 mod draw;
 mod event;
 mod flood;
 mod font;
+mod fuzz;
 mod gl;
 mod history;
+mod hook;
 mod image;
 mod io;
+mod lut;
 mod palette;
+mod panel;
 mod parser;
+mod pattern;
 mod pixels;
 mod platform;
+mod plugin;
+mod preview;
 mod renderer;
+mod search;
+mod soft;
 mod sprite;
+mod theme;
+mod timelapse;
 mod timer;
 mod view;
+mod workspace;
+mod zip;
 
 #[macro_use]
 pub mod util;
@@ -54,6 +71,9 @@ use session::*;
 use timer::FrameTimer;
 use view::FileStatus;
 
+pub use engine::Engine;
+pub use renderer::Backend;
+
 #[macro_use]
 extern crate log;
 
@@ -79,6 +99,9 @@ pub struct Options<'a> {
     pub exec: ExecutionMode,
     pub glyphs: &'a [u8],
     pub debug: bool,
+    pub backend: Backend,
+    /// Path of the socket to listen on for remote commands, if any.
+    pub socket: Option<PathBuf>,
 }
 
 impl<'a> Default for Options<'a> {
@@ -92,6 +115,8 @@ impl<'a> Default for Options<'a> {
             exec: ExecutionMode::Normal,
             glyphs: data::GLYPHS,
             debug: false,
+            backend: Backend::default(),
+            socket: None,
         }
     }
 }
@@ -101,17 +126,16 @@ pub fn init<P: AsRef<Path>>(paths: &[P], options: Options<'_>) -> std::io::Resul
 
     debug!("options: {:?}", options);
 
+    let context = match options.backend {
+        Backend::Gl => platform::GraphicsContext::Gl,
+        Backend::Soft => platform::GraphicsContext::None,
+    };
+
     let hints = &[
         WindowHint::Resizable(options.resizable),
         WindowHint::Visible(!options.headless),
     ];
-    let (mut win, mut events) = platform::init(
-        "rx",
-        options.width,
-        options.height,
-        hints,
-        platform::GraphicsContext::Gl,
-    )?;
+    let (mut win, events) = platform::init("rx", options.width, options.height, hints, context)?;
 
     let scale_factor = win.scale_factor();
     let win_size = win.size();
@@ -141,12 +165,18 @@ pub fn init<P: AsRef<Path>>(paths: &[P], options: Options<'_>) -> std::io::Resul
             .expect("'debug' is a bool'");
     }
 
-    let mut execution = match options.exec {
+    let execution = match options.exec {
         ExecutionMode::Normal => Execution::normal(),
-        ExecutionMode::Replay(path, digest) => Execution::replaying(path, digest),
-        ExecutionMode::Record(path, digest, gif) => {
-            Execution::recording(path, digest, win_w as u16, win_h as u16, gif)
-        }
+        ExecutionMode::Replay(path, digest) => Execution::replaying(path, digest, options.backend),
+        ExecutionMode::Fuzz(seed, frames) => Execution::fuzzing(seed, frames),
+        ExecutionMode::Record(path, digest, gif) => Execution::recording(
+            path,
+            digest,
+            win_w as u16,
+            win_h as u16,
+            gif,
+            options.backend,
+        ),
     }?;
 
     // When working with digests, certain settings need to be overwritten
@@ -159,13 +189,76 @@ pub fn init<P: AsRef<Path>>(paths: &[P], options: Options<'_>) -> std::io::Resul
                 .settings
                 .set("animation", Value::Bool(false))
                 .expect("'animation' is a bool");
+            // These are shown or hidden after a delay, which depends on how
+            // fast frames are rendered.
+            session
+                .settings
+                .set("ui/fill-preview", Value::Bool(false))
+                .expect("'ui/fill-preview' is a bool");
+            session
+                .settings
+                .set("ui/brush-hud", Value::Bool(false))
+                .expect("'ui/brush-hud' is a bool");
+            // Recordings predate the pixel grid being shown by default.
+            session
+                .settings
+                .set("grid/pixels", Value::Bool(false))
+                .expect("'grid/pixels' is a bool");
+            // The system theme depends on the host.
+            session.theme = theme::Theme::DARK;
+        }
+        Execution::Fuzzing { fuzzer } => {
+            let seed = fuzzer.seed;
+
+            info!("fuzzing: seed = {}", seed);
+
+            // Make sure the seed is printed if the session panics, so that
+            // the failure can be reproduced.
+            let hook = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                eprintln!("rx: fuzzing failed with seed {}", seed);
+                hook(info);
+            }));
         }
         _ => {}
     }
 
-    let wait_events = execution.is_normal() || execution.is_recording();
+    let server = options.socket.map(ipc::Server::bind).transpose()?;
 
-    let mut renderer: gl::Renderer = Renderer::new(&mut win, win_size, scale_factor, assets)?;
+    if let Some(server) = &server {
+        info!("listening for commands on {}", server.path.display());
+    }
+
+    match options.backend {
+        Backend::Gl => {
+            let renderer: gl::Renderer = Renderer::new(&mut win, win_size, scale_factor, assets)?;
+            self::run(renderer, win, events, session, execution, server, paths)
+        }
+        Backend::Soft => {
+            let renderer: soft::Renderer = Renderer::new(&mut win, win_size, scale_factor, assets)?;
+            self::run(renderer, win, events, session, execution, server, paths)
+        }
+    }
+}
+
+/// Run the main loop with the given renderer, until the session is closed.
+fn run<'a, R, P>(
+    mut renderer: R,
+    mut win: platform::backend::Window,
+    mut events: platform::backend::Events,
+    mut session: Session,
+    mut execution: Execution,
+    server: Option<ipc::Server>,
+    paths: &[P],
+) -> std::io::Result<()>
+where
+    R: Renderer<'a>,
+    R::Error: std::fmt::Display,
+    P: AsRef<Path>,
+{
+    use std::io;
+
+    let wait_events = execution.is_normal() || execution.is_recording();
 
     if let Err(e) = session.edit(paths) {
         session.message(format!("Error loading path(s): {}", e), MessageType::Error);
@@ -188,10 +281,9 @@ pub fn init<P: AsRef<Path>>(paths: &[P], options: Options<'_>) -> std::io::Resul
     let mut delta;
 
     while !win.is_closing() {
-        match session.animation_delay() {
-            Some(delay) if session.is_running() => {
-                // How much time is left until the next animation frame?
-                let remaining = delay - session.accumulator;
+        // How much time is left until the next animation frame?
+        match session.animation_remaining() {
+            Some(remaining) if session.is_running() => {
                 // If more than 1ms remains, let's wait.
                 if remaining.as_millis() > 1 {
                     events.wait_timeout(remaining);
@@ -199,6 +291,10 @@ pub fn init<P: AsRef<Path>>(paths: &[P], options: Options<'_>) -> std::io::Resul
                     events.poll();
                 }
             }
+            // Check for remote edits regularly while collaborating.
+            _ if session.collab.is_some() => events.wait_timeout(collab::POLL_INTERVAL),
+            // Check for remote commands regularly while listening.
+            _ if server.is_some() => events.wait_timeout(ipc::POLL_INTERVAL),
             _ if wait_events => events.wait(),
             _ => events.poll(),
         }
@@ -275,6 +371,12 @@ pub fn init<P: AsRef<Path>>(paths: &[P], options: Options<'_>) -> std::io::Resul
                 WindowEvent::MouseWheel { delta, .. } => {
                     session_events.push(Event::MouseWheel(delta));
                 }
+                WindowEvent::TouchpadScroll { delta, .. } => {
+                    session_events.push(Event::TouchpadScroll(delta));
+                }
+                WindowEvent::TouchpadMagnify { delta } => {
+                    session_events.push(Event::TouchpadMagnify(delta));
+                }
                 WindowEvent::KeyboardInput(input) => match input {
                     // Intercept `<insert>` key for pasting.
                     //
@@ -304,6 +406,15 @@ pub fn init<P: AsRef<Path>>(paths: &[P], options: Options<'_>) -> std::io::Resul
             session.handle_resized(win.size());
         }
 
+        // Remote commands are run even while the session is paused, since
+        // the window usually isn't focused when they're sent.
+        if let Some(server) = &server {
+            for req in server.requests() {
+                let result = session.remote(&req.input);
+                req.reply(result);
+            }
+        }
+
         delta = last.elapsed();
         last += delta;
 
@@ -316,16 +427,18 @@ pub fn init<P: AsRef<Path>>(paths: &[P], options: Options<'_>) -> std::io::Resul
         let effects =
             update_timer.run(|avg| session.update(&mut session_events, &mut execution, delta, avg));
 
-        render_timer.run(|avg| {
-            renderer
-                .frame(&mut session, &mut execution, effects, &avg)
-                .unwrap_or_else(|err| {
-                    log::error!("{}", err);
-                });
-        });
-
+        // Skip rendering entirely if nothing changed since the last frame.
+        if session.dirty {
+            render_timer.run(|avg| {
+                renderer
+                    .frame(&mut session, &mut execution, effects, &avg)
+                    .unwrap_or_else(|err| {
+                        log::error!("{}", err);
+                    });
+            });
+            win.present();
+        }
         session.cleanup();
-        win.present();
 
         match session.state {
             State::Closing(ExitReason::Normal) => {
@@ -0,0 +1,107 @@
+//! Lookup textures, for swapping the palette of sprites.
+//!
+//! A lookup texture (LUT) is a view holding the colors used by a sprite. The
+//! sprite's intermediate representation (IR) replaces each of its colors with
+//! the position of that color in the LUT, encoded in the red (column) and
+//! green (row, from the top) channels. Resolving the IR through a LUT that has
+//! different colors in the same positions swaps the sprite's palette.
+use crate::gfx::Rgba8;
+
+use std::collections::BTreeMap;
+
+/// Maximum width and height of a LUT, so that positions fit in a channel.
+pub const MAX_SIZE: u32 = 256;
+
+/// Generate the IR of the given pixels, using the LUT of the given width.
+/// Transparent pixels are kept as they are. Returns the IR, along with the
+/// number of pixels whose color isn't in the LUT, which are made transparent.
+/// Only the first [`MAX_SIZE`] rows and columns of the LUT are used.
+pub fn ir(pixels: &[Rgba8], lut: &[Rgba8], width: u32) -> (Vec<Rgba8>, usize) {
+    let mut positions = BTreeMap::new();
+
+    for (i, c) in lut.iter().enumerate().filter(|(_, c)| c.a != 0) {
+        let (x, y) = (i as u32 % width, i as u32 / width);
+
+        if x >= MAX_SIZE || y >= MAX_SIZE {
+            continue;
+        }
+        positions
+            .entry(*c)
+            .or_insert_with(|| Rgba8::new(x as u8, y as u8, 0x0, 0xff));
+    }
+    self::map(pixels, |p| positions.get(&p).copied())
+}
+
+/// Resolve an IR through the LUT of the given width. Transparent pixels are
+/// kept as they are. Returns the resolved pixels, along with the number of
+/// pixels that point outside the LUT, which are made transparent.
+pub fn resolve(ir: &[Rgba8], lut: &[Rgba8], width: u32) -> (Vec<Rgba8>, usize) {
+    let height = lut.len() as u32 / width;
+
+    self::map(ir, |p| {
+        let (x, y) = (p.r as u32, p.g as u32);
+
+        if x < width && y < height {
+            Some(lut[(y * width + x) as usize])
+        } else {
+            None
+        }
+    })
+}
+
+fn map(pixels: &[Rgba8], f: impl Fn(Rgba8) -> Option<Rgba8>) -> (Vec<Rgba8>, usize) {
+    let mut missing = 0;
+    let output = pixels
+        .iter()
+        .map(|p| {
+            if p.a == 0 {
+                return *p;
+            }
+            f(*p).unwrap_or_else(|| {
+                missing += 1;
+                Rgba8::TRANSPARENT
+            })
+        })
+        .collect();
+
+    (output, missing)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ir_resolve() {
+        let (r, g, b, t) = (Rgba8::RED, Rgba8::GREEN, Rgba8::BLUE, Rgba8::TRANSPARENT);
+        let lut = [r, g, t, b];
+        let swapped = [b, r, t, g];
+
+        let (ir, missing) = ir(&[g, t, b, Rgba8::WHITE], &lut, 2);
+        assert_eq!(missing, 1);
+        assert_eq!(
+            ir,
+            vec![Rgba8::new(1, 0, 0, 0xff), t, Rgba8::new(1, 1, 0, 0xff), t]
+        );
+
+        let (pixels, missing) = resolve(&ir, &swapped, 2);
+        assert_eq!(missing, 0);
+        assert_eq!(pixels, vec![r, t, g, t]);
+
+        let (pixels, missing) = resolve(&[Rgba8::new(2, 0, 0, 0xff)], &lut, 2);
+        assert_eq!(missing, 1);
+        assert_eq!(pixels, vec![t]);
+    }
+
+    #[test]
+    fn test_ir_max_size() {
+        let mut lut = vec![Rgba8::TRANSPARENT; MAX_SIZE as usize + 1];
+        lut[1] = Rgba8::GREEN;
+        lut[MAX_SIZE as usize] = Rgba8::RED;
+
+        // Colors past the last column can't be encoded.
+        let (ir, missing) = ir(&[Rgba8::GREEN, Rgba8::RED], &lut, MAX_SIZE + 1);
+        assert_eq!(missing, 1);
+        assert_eq!(ir, vec![Rgba8::new(1, 0, 0, 0xff), Rgba8::TRANSPARENT]);
+    }
+}
@@ -5,6 +5,7 @@ use rx::logger;
 use std::io;
 use std::path::PathBuf;
 use std::process;
+use std::time;
 
 const HEADER: &str = r#"
 Alexis Sellier <self@cloudhead.io>
@@ -24,9 +25,14 @@ OPTIONS
 
     --record <dir>       Record user input to a directory
     --replay <dir>       Replay user input from a directory
+    --fuzz <frames>      Feed randomized input to the session for a number of frames
+    --seed <seed>        Set the seed used by '--fuzz'
     --width <width>      Set the window width
     --height <height>    Set the window height
     --debug              Set debug mode
+    --backend <backend>  Set the rendering backend (gl, soft)
+    --socket <path>      Listen for commands on a UNIX socket
+    --remote <path>      Send commands read from stdin to the session listening on <path>
 "#;
 
 fn main() {
@@ -51,10 +57,18 @@ fn execute(mut args: pico_args::Arguments) -> Result<(), Box<dyn std::error::Err
         return Ok(());
     }
 
+    if let Some(path) = args.opt_value_from_str::<_, PathBuf>("--remote")? {
+        let stdin = io::stdin();
+        rx::ipc::send(path, stdin.lock(), io::stdout())?;
+
+        return Ok(());
+    }
+
     let verbose = args.contains("-v");
     let debug = args.contains("--debug");
     let width = args.opt_value_from_str("--width")?;
     let height = args.opt_value_from_str("--height")?;
+    let backend = args.opt_value_from_str("--backend")?;
     let record_digests = args.contains("--record-digests");
     let record_gif = args.contains("--record-gif");
     let verify_digests = args.contains("--verify-digests");
@@ -62,11 +76,24 @@ fn execute(mut args: pico_args::Arguments) -> Result<(), Box<dyn std::error::Err
     let source = args.opt_value_from_str::<_, PathBuf>("-u")?;
     let replay = args.opt_value_from_str::<_, PathBuf>("--replay")?;
     let record = args.opt_value_from_str::<_, PathBuf>("--record")?;
-    let resizable = width.is_none() && height.is_none() && replay.is_none() && record.is_none();
+    let fuzz = args.opt_value_from_str::<_, u64>("--fuzz")?;
+    let seed = args.opt_value_from_str::<_, u64>("--seed")?;
+    let socket = args.opt_value_from_str::<_, PathBuf>("--socket")?;
+    let resizable = width.is_none()
+        && height.is_none()
+        && replay.is_none()
+        && record.is_none()
+        && fuzz.is_none();
 
     if replay.is_some() && record.is_some() {
         return Err("'--replay' and '--record' can't both be specified".into());
     }
+    if fuzz.is_some() && (replay.is_some() || record.is_some()) {
+        return Err("'--fuzz' can't be combined with '--replay' or '--record'".into());
+    }
+    if seed.is_some() && fuzz.is_none() {
+        return Err("'--seed' has no effect without '--fuzz'".into());
+    }
 
     let digest_mode = if record_digests && !verify_digests {
         DigestMode::Record
@@ -105,6 +132,14 @@ fn execute(mut args: pico_args::Arguments) -> Result<(), Box<dyn std::error::Err
         ExecutionMode::Replay(path, digest_mode)
     } else if let Some(path) = record {
         ExecutionMode::Record(path, digest_mode, gif_mode)
+    } else if let Some(frames) = fuzz {
+        let seed = seed.unwrap_or_else(|| {
+            time::SystemTime::now()
+                .duration_since(time::UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or_default()
+        });
+        ExecutionMode::Fuzz(seed, frames)
     } else {
         ExecutionMode::Normal
     };
@@ -120,6 +155,8 @@ fn execute(mut args: pico_args::Arguments) -> Result<(), Box<dyn std::error::Err
         exec,
         glyphs,
         debug,
+        backend: backend.unwrap_or(default.backend),
+        socket,
     };
 
     match args.free() {
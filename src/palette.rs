@@ -1,11 +1,30 @@
+use crate::parser;
 use crate::session::SessionCoords;
 
 use crate::gfx::Rgba8;
 use arrayvec::ArrayVec;
 
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Name of the palette directory, inside the config directory.
+pub const DIR: &str = "palettes";
+
+/// File extension of palette files.
+pub const EXTENSION: &str = "palette";
+
+/// Names of project palette files, in order of preference. These are loaded
+/// from the directories files are opened from.
+pub const PROJECT_FILES: &[&str] = &["palette.rx", "palette.gpl"];
+
 pub struct Palette {
     pub colors: ArrayVec<[Rgba8; 256]>,
     pub hover: Option<Rgba8>,
+    /// Index of the color being dragged, if any.
+    pub drag: Option<usize>,
     pub cellsize: f32,
     pub height: usize,
     pub x: f32,
@@ -17,6 +36,7 @@ impl Palette {
         Self {
             colors: ArrayVec::new(),
             hover: None,
+            drag: None,
             cellsize,
             height,
             x: 0.,
@@ -51,6 +71,19 @@ impl Palette {
 
     pub fn clear(&mut self) {
         self.colors.clear();
+        self.drag = None;
+    }
+
+    /// Move a color to another index, shifting the colors in between.
+    /// Returns `false` if either index is out of range.
+    pub fn move_color(&mut self, from: usize, to: usize) -> bool {
+        if from >= self.size() || to >= self.size() {
+            return false;
+        }
+        let color = self.colors.remove(from);
+        self.colors.insert(to, color);
+
+        true
     }
 
     pub fn size(&self) -> usize {
@@ -58,6 +91,11 @@ impl Palette {
     }
 
     pub fn handle_cursor_moved(&mut self, p: SessionCoords) {
+        self.hover = self.index(p).map(|i| self.colors[i]);
+    }
+
+    /// Index of the color at the given position, if any.
+    pub fn index(&self, p: SessionCoords) -> Option<usize> {
         let (x, y) = (p.x, p.y);
         let mut x = x as i32 - self.x as i32;
         let mut y = y as i32 - self.y as i32;
@@ -74,8 +112,7 @@ impl Palette {
         let height = i32::min(size, height) * cellsize;
 
         if x >= width || y >= height || x < 0 || y < 0 {
-            self.hover = None;
-            return;
+            return None;
         }
 
         x /= cellsize;
@@ -83,14 +120,335 @@ impl Palette {
 
         let index = y + x * (height / cellsize);
 
-        self.hover = if index < size {
+        if index < size {
             // We index from the back because the palette is reversed
             // before it is displayed, due to the Y axis pointing up,
             // where as the palette is created starting at the top
             // and going down.
-            Some(self.colors[self.size() - index as usize - 1])
+            Some(self.size() - index as usize - 1)
         } else {
             None
+        }
+    }
+}
+
+/// The named palettes of a session. The colors of the active palette live in
+/// the session's [`Palette`], while the others are kept here, and loaded from
+/// the palette directory the first time they are switched to.
+///
+/// Palettes other than the default one are written to `<name>.palette` in the
+/// palette directory when they are switched away from, and when the session
+/// quits. Palette files are scripts, so they can also be sourced.
+#[derive(Debug)]
+pub struct Palettes {
+    /// Name of the active palette.
+    pub active: String,
+    /// Colors of the inactive palettes that have been loaded.
+    stored: BTreeMap<String, Vec<Rgba8>>,
+    /// Directory palettes are persisted to.
+    dir: PathBuf,
+}
+
+impl Palettes {
+    /// Name of the palette set up by the init script, which isn't persisted.
+    pub const DEFAULT: &'static str = "default";
+
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            active: String::from(Self::DEFAULT),
+            stored: BTreeMap::new(),
+            dir,
+        }
+    }
+
+    /// Names of all palettes, including the ones not loaded yet, in order.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().map_or(false, |e| e == EXTENSION))
+            .filter_map(|p| p.file_stem().map(|s| s.to_string_lossy().into_owned()))
+            .chain(self.stored.keys().cloned())
+            .chain([self.active.clone(), String::from(Self::DEFAULT)])
+            .collect();
+
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Check whether a palette exists, in memory or on disk.
+    pub fn exists(&self, name: &str) -> bool {
+        name == self.active
+            || name == Self::DEFAULT
+            || self.stored.contains_key(name)
+            || self.path(name).exists()
+    }
+
+    /// Switch to the named palette, given the colors of the active one.
+    /// Returns the colors of the named palette, which is empty if it doesn't
+    /// exist yet.
+    pub fn switch(&mut self, name: &str, colors: &[Rgba8]) -> io::Result<Vec<Rgba8>> {
+        let next = match self.stored.remove(name) {
+            Some(colors) => colors,
+            None if self.path(name).exists() => self.read(name)?,
+            None => Vec::new(),
         };
+        self.save(colors)?;
+
+        let prev = std::mem::replace(&mut self.active, name.to_owned());
+        self.stored.insert(prev, colors.to_vec());
+
+        Ok(next)
+    }
+
+    /// Persist the active palette, given its colors.
+    pub fn save(&self, colors: &[Rgba8]) -> io::Result<()> {
+        if self.active == Self::DEFAULT {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir)?;
+
+        let mut f = io::BufWriter::new(fs::File::create(self.path(&self.active))?);
+
+        writeln!(f, "p/clear")?;
+        for c in colors {
+            writeln!(f, "{}", c)?;
+        }
+        f.flush()
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", name, EXTENSION))
+    }
+
+    /// Read the colors of a palette file. Lines other than colors, eg.
+    /// comments and `p/clear`, are ignored.
+    fn read(&self, name: &str) -> io::Result<Vec<Rgba8>> {
+        self::colors(io::BufReader::new(fs::File::open(self.path(name))?))
+    }
+}
+
+/// Read the colors of a palette file. GIMP palettes (`.gpl`) are supported,
+/// and any other file is read as an rx script, of which only the colors are
+/// kept.
+pub fn read_file(path: &Path) -> io::Result<Vec<Rgba8>> {
+    let r = io::BufReader::new(fs::File::open(path)?);
+
+    if path.extension().map_or(false, |e| e == "gpl") {
+        self::gpl(r)
+    } else {
+        self::colors(r)
+    }
+}
+
+/// Read the colors of an rx script, one per line. Other lines are skipped.
+fn colors<R: BufRead>(r: R) -> io::Result<Vec<Rgba8>> {
+    let parser = parser::color();
+    let mut colors = Vec::new();
+
+    for line in r.lines() {
+        if let Ok((c, _)) = parser.parse(line?.trim()) {
+            colors.push(c);
+        }
+    }
+    Ok(colors)
+}
+
+/// Read the colors of a GIMP palette. After the header, each line has the
+/// red, green and blue components of a color, optionally followed by a name.
+fn gpl<R: BufRead>(r: R) -> io::Result<Vec<Rgba8>> {
+    let mut lines = r.lines();
+    let mut colors = Vec::new();
+
+    if lines.next().transpose()?.as_deref().map(str::trim) != Some("GIMP Palette") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a GIMP palette",
+        ));
+    }
+    for line in lines {
+        let line = line?;
+        let mut components = line.split_whitespace().map(|c| c.parse::<u8>());
+
+        // Skips comments, as well as the `Name:` and `Columns:` lines.
+        if let (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) =
+            (components.next(), components.next(), components.next())
+        {
+            colors.push(Rgba8::new(r, g, b, 0xff));
+        }
+    }
+    Ok(colors)
+}
+
+/// Reduce the given colors to at most `n` representative colors, using median
+/// cut. Colors are repeatedly split in half along the widest channel of the
+/// widest group, until there are `n` groups, each of which is then averaged.
+/// Colors that occur more often have more weight.
+pub fn quantize(colors: &[Rgba8], n: usize) -> Vec<Rgba8> {
+    fn channels(c: &Rgba8) -> [u8; 4] {
+        [c.r, c.g, c.b, c.a]
+    }
+
+    /// The widest channel of a group, and its range.
+    fn widest(group: &[Rgba8]) -> (usize, u8) {
+        (0..4)
+            .map(|i| {
+                let values = group.iter().map(|c| channels(c)[i]);
+                let min = values.clone().min().unwrap_or_default();
+                let max = values.max().unwrap_or_default();
+
+                (i, max - min)
+            })
+            .max_by_key(|(_, range)| *range)
+            .unwrap_or_default()
+    }
+
+    if colors.is_empty() || n == 0 {
+        return Vec::new();
+    }
+    let mut groups = vec![colors.to_vec()];
+
+    while groups.len() < n {
+        let (index, (channel, range)) = groups
+            .iter()
+            .map(|g| widest(g))
+            .enumerate()
+            .max_by_key(|(_, (_, range))| *range)
+            .expect("there is at least one group");
+
+        if range == 0 {
+            break;
+        }
+        let mut group = groups.swap_remove(index);
+        group.sort_unstable_by_key(|c| channels(c)[channel]);
+
+        let upper = group.split_off(group.len() / 2);
+        groups.push(group);
+        groups.push(upper);
+    }
+
+    let mut result: Vec<_> = groups
+        .iter()
+        .map(|g| {
+            let mut sum = [0u64; 4];
+            for c in g {
+                for (s, v) in sum.iter_mut().zip(channels(c)) {
+                    *s += v as u64;
+                }
+            }
+            let len = g.len() as u64;
+            let [r, g, b, a] = sum.map(|s| ((s + len / 2) / len) as u8);
+
+            Rgba8::new(r, g, b, a)
+        })
+        .collect();
+
+    result.sort_unstable();
+    result.dedup();
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_move_color() {
+        let colors = [
+            Rgba8::new(1, 0, 0, 0xff),
+            Rgba8::new(2, 0, 0, 0xff),
+            Rgba8::new(3, 0, 0, 0xff),
+        ];
+        let mut p = Palette::new(8., 16);
+        for c in colors {
+            p.add(c);
+        }
+
+        assert!(p.move_color(0, 2));
+        assert_eq!(p.colors.as_slice(), &[colors[1], colors[2], colors[0]]);
+        assert!(p.move_color(2, 1));
+        assert_eq!(p.colors.as_slice(), &[colors[1], colors[0], colors[2]]);
+        assert!(!p.move_color(1, 3));
+    }
+
+    #[test]
+    fn test_palettes_switch() {
+        let dir = std::env::temp_dir().join(format!("rx-palettes-{}", std::process::id()));
+        let (r, b) = (Rgba8::new(0xff, 0, 0, 0xff), Rgba8::new(0, 0, 0xff, 0xff));
+        let mut palettes = Palettes::new(dir.clone());
+
+        assert_eq!(palettes.switch("skin", &[r]).unwrap(), vec![]);
+        assert_eq!(palettes.active, "skin");
+        assert_eq!(palettes.switch("default", &[b]).unwrap(), vec![r]);
+
+        // The named palette was persisted, but not the default one.
+        assert!(dir.join("skin.palette").exists());
+        assert!(!dir.join("default.palette").exists());
+
+        // Palettes that weren't loaded yet are read from disk.
+        let mut palettes = Palettes::new(dir.clone());
+        assert_eq!(palettes.names(), vec!["default", "skin"]);
+        assert_eq!(palettes.switch("skin", &[]).unwrap(), vec![b]);
+
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_read() {
+        let palette = "\
+GIMP Palette
+Name: Sweetie 16
+Columns: 8
+#
+ 26  28  44\tBlack
+255 205 117 Yellow
+";
+        assert_eq!(
+            self::gpl(palette.as_bytes()).unwrap(),
+            vec![
+                Rgba8::new(26, 28, 44, 0xff),
+                Rgba8::new(255, 205, 117, 0xff)
+            ]
+        );
+        assert!(self::gpl("#1a1c2c\n".as_bytes()).is_err());
+
+        let script = "p/clear\n#1a1c2c\n-- Yellow\n#ffcd75\n";
+        assert_eq!(
+            self::colors(script.as_bytes()).unwrap(),
+            vec![
+                Rgba8::new(0x1a, 0x1c, 0x2c, 0xff),
+                Rgba8::new(0xff, 0xcd, 0x75, 0xff)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quantize() {
+        let (r, b) = (Rgba8::new(0xff, 0, 0, 0xff), Rgba8::new(0, 0, 0xff, 0xff));
+
+        assert_eq!(quantize(&[], 4), vec![]);
+        assert_eq!(quantize(&[r, r, r], 4), vec![r]);
+        assert_eq!(quantize(&[r, r, b, b], 2), vec![b, r]);
+        assert_eq!(
+            quantize(&[r, r, b, b], 1),
+            vec![Rgba8::new(0x80, 0, 0x80, 0xff)]
+        );
+
+        // Shades of red and blue collapse into one color each.
+        let colors = [
+            r,
+            Rgba8::new(0xf0, 0, 0, 0xff),
+            Rgba8::new(0xe0, 0, 0, 0xff),
+            b,
+            Rgba8::new(0, 0, 0xf0, 0xff),
+            Rgba8::new(0, 0, 0xe0, 0xff),
+        ];
+        assert_eq!(
+            quantize(&colors, 2),
+            vec![Rgba8::new(0, 0, 0xf0, 0xff), Rgba8::new(0xf0, 0, 0, 0xff)]
+        );
     }
 }
@@ -0,0 +1,130 @@
+//! Settings panel, with numeric fields that can be adjusted with the mouse.
+use crate::cmd::{Command, Op, Value};
+use crate::session::{SessionCoords, Settings};
+
+use crate::gfx::rect::Rect;
+
+/// A numeric field in the settings panel.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Field {
+    AnimationDelay,
+    GridSpacingX,
+    GridSpacingY,
+    BrushSize,
+}
+
+impl Field {
+    /// All fields, in display order, from top to bottom.
+    pub const ALL: &'static [Field] = &[
+        Field::AnimationDelay,
+        Field::GridSpacingX,
+        Field::GridSpacingY,
+        Field::BrushSize,
+    ];
+
+    /// Field label.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::AnimationDelay => "animation/delay",
+            Self::GridSpacingX => "grid/spacing x",
+            Self::GridSpacingY => "grid/spacing y",
+            Self::BrushSize => "brush/size",
+        }
+    }
+
+    /// Current value of the field.
+    pub fn value(&self, settings: &Settings, brush_size: usize) -> u32 {
+        match self {
+            Self::AnimationDelay => settings["animation/delay"].to_u64() as u32,
+            Self::GridSpacingX => <(u32, u32)>::from(settings["grid/spacing"].clone()).0,
+            Self::GridSpacingY => <(u32, u32)>::from(settings["grid/spacing"].clone()).1,
+            Self::BrushSize => brush_size as u32,
+        }
+    }
+
+    /// Command which adjusts the field by the given amount.
+    pub fn adjust(&self, settings: &Settings, brush_size: usize, delta: i32) -> Command {
+        let value = self.value(settings, brush_size) as i32 + delta;
+
+        match self {
+            Self::AnimationDelay => Command::Set(
+                "animation/delay".to_owned(),
+                Value::U32(value.clamp(1, 1000) as u32),
+            ),
+            Self::GridSpacingX | Self::GridSpacingY => {
+                let (x, y) = settings["grid/spacing"].clone().into();
+                let value = value.max(1) as u32;
+                let spacing = if *self == Self::GridSpacingX {
+                    Value::U32Tuple(value, y)
+                } else {
+                    Value::U32Tuple(x, value)
+                };
+                Command::Set("grid/spacing".to_owned(), spacing)
+            }
+            Self::BrushSize => Command::BrushSize(Op::Set(value.max(1) as f32)),
+        }
+    }
+
+    /// Command-line input used to type in a new value for the field.
+    pub fn input(&self) -> &'static str {
+        match self {
+            Self::AnimationDelay => "set animation/delay = ",
+            Self::GridSpacingX | Self::GridSpacingY => "set grid/spacing = ",
+            Self::BrushSize => "brush/size ",
+        }
+    }
+}
+
+/// A field being dragged with the mouse.
+#[derive(Debug, Copy, Clone)]
+pub struct Drag {
+    /// The field being dragged.
+    pub field: Field,
+    /// Cursor position at which the field was last adjusted.
+    pub x: f32,
+    /// Whether the field was adjusted during this drag.
+    pub moved: bool,
+}
+
+pub struct Panel {
+    /// Field under the cursor, if any.
+    pub hover: Option<Field>,
+    /// Field being dragged, if any.
+    pub drag: Option<Drag>,
+    /// Position of the top-left corner of the panel.
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub row_height: f32,
+}
+
+impl Panel {
+    /// Horizontal distance the cursor has to travel while dragging
+    /// to adjust a field by one.
+    pub const DRAG_STEP: f32 = 4.;
+
+    pub fn new(width: f32, row_height: f32) -> Self {
+        Self {
+            hover: None,
+            drag: None,
+            x: 0.,
+            y: 0.,
+            width,
+            row_height,
+        }
+    }
+
+    /// The area occupied by the field at the given index.
+    pub fn rect(&self, index: usize) -> Rect<f32> {
+        let y = self.y - (index + 1) as f32 * self.row_height;
+        Rect::new(self.x, y, self.x + self.width, y + self.row_height)
+    }
+
+    pub fn handle_cursor_moved(&mut self, p: SessionCoords) {
+        self.hover = Field::ALL
+            .iter()
+            .enumerate()
+            .find(|(i, _)| self.rect(*i).contains(*p))
+            .map(|(_, f)| *f);
+    }
+}
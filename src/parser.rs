@@ -4,9 +4,11 @@ use memoir::*;
 use directories as dirs;
 
 use crate::brush::BrushMode;
+use crate::color::Shift;
 use crate::gfx::Rgba8;
+use crate::pattern::Dither;
 use crate::platform;
-use crate::session::{Direction, Mode, VisualState};
+use crate::session::{Direction, Input, Mode, VisualState, Wheel};
 
 use std::ffi::OsString;
 use std::str::FromStr;
@@ -44,6 +46,48 @@ pub fn scale() -> Parser<u32> {
         .map(|(_, scale)| scale)
 }
 
+/// A palette name. Since palettes are stored in files of the same name, it
+/// can't contain path separators, or start with a `.`.
+pub fn palette_name() -> Parser<String> {
+    token()
+        .try_map(|name: String| {
+            if name.is_empty() {
+                Err(String::from("expected palette name"))
+            } else if name.starts_with('.') || name.contains(['/', '\\']) {
+                Err(format!("{:?} is not a valid palette name", name))
+            } else {
+                Ok(name)
+            }
+        })
+        .label("<name>")
+}
+
+/// A workspace name.
+pub fn workspace_name() -> Parser<String> {
+    token()
+        .try_map(|name: String| {
+            if name.is_empty() {
+                Err(String::from("expected workspace name"))
+            } else {
+                Ok(name)
+            }
+        })
+        .label("<name>")
+}
+
+/// A view checkpoint name.
+pub fn checkpoint_name() -> Parser<String> {
+    token()
+        .try_map(|name: String| {
+            if name.is_empty() {
+                Err(String::from("expected checkpoint name"))
+            } else {
+                Ok(name)
+            }
+        })
+        .label("<name>")
+}
+
 pub fn path() -> Parser<String> {
     token()
         .map(|input: String| {
@@ -90,6 +134,7 @@ impl Parse for platform::Key {
                 "ctrl" => platform::Key::Control,
                 "alt" => platform::Key::Alt,
                 "shift" => platform::Key::Shift,
+                "super" => platform::Key::Super,
                 "space" => platform::Key::Space,
                 "return" => platform::Key::Return,
                 "backspace" => platform::Key::Backspace,
@@ -105,6 +150,32 @@ impl Parse for platform::Key {
     }
 }
 
+/// Mouse button or wheel input, eg. `<mouse-right>` or `<wheel-up>`.
+/// The left mouse button can't be used, since it's reserved for tools.
+pub fn mouse() -> Parser<Input> {
+    peek(
+        between(
+            '<',
+            '>',
+            many::<_, String>(satisfy(
+                |c| c.is_ascii_alphanumeric() || c == '-',
+                "<mouse>",
+            )),
+        )
+        .try_map(|input| match input.as_str() {
+            "mouse-right" => Ok(Input::Mouse(platform::MouseButton::Right)),
+            "mouse-middle" => Ok(Input::Mouse(platform::MouseButton::Middle)),
+            "wheel-up" => Ok(Input::Wheel(Wheel::Up)),
+            "wheel-down" => Ok(Input::Wheel(Wheel::Down)),
+            other => match other.strip_prefix("mouse-").map(u8::from_str) {
+                Some(Ok(n)) if n > 3 => Ok(Input::Mouse(platform::MouseButton::Other(n))),
+                _ => Err(format!("unknown mouse input <{}>", other)),
+            },
+        }),
+    )
+    .label("<mouse>")
+}
+
 impl Parse for platform::InputState {
     fn parser() -> Parser<Self> {
         word().try_map(|w| match w.as_str() {
@@ -143,8 +214,12 @@ pub fn color() -> Parser<Rgba8> {
                     return Err(format!("{:?} is not a valid color value", input));
                 }
                 let (s, alpha) = input.split_at(7);
+                let hex = alpha.len() == 2 && alpha.bytes().all(|b| b.is_ascii_hexdigit());
 
                 match Rgba8::from_str(s) {
+                    Ok(color) if hex => {
+                        Ok(color.alpha(u8::from_str_radix(alpha, 16).unwrap_or(0xff)))
+                    }
                     Ok(color) => {
                         if let Ok((a, _)) = symbol('/')
                             .then(rational::<f64>())
@@ -163,6 +238,15 @@ pub fn color() -> Parser<Rgba8> {
     )
 }
 
+/// Parses a color mapping entry, eg. `#ff0000 #00ff00`, with an optional
+/// trailing comment.
+pub fn color_mapping() -> Parser<(Rgba8, Rgba8)> {
+    tuple::<Rgba8>(color().label("<from>"), color().label("<to>"))
+        .skip(optional(whitespace()))
+        .skip(optional(comment()))
+        .end()
+}
+
 impl Parse for BrushMode {
     fn parser() -> Parser<Self> {
         Parser::new(
@@ -175,10 +259,15 @@ impl Parse for BrushMode {
                     "xsym" => Ok((BrushMode::XSym, p)),
                     "ysym" => Ok((BrushMode::YSym, p)),
                     "xray" => Ok((BrushMode::XRay, p)),
+                    "recolor" => Ok((BrushMode::Recolor, p)),
                     "line" => optional(whitespace())
                         .then(optional(natural()))
                         .parse(p)
                         .map(|((_, snap), p)| (BrushMode::Line(snap), p)),
+                    "dither" => optional(whitespace())
+                        .then(optional(Dither::parser()))
+                        .parse(p)
+                        .map(|((_, d), p)| (BrushMode::Dither(d.unwrap_or_default()), p)),
                     mode => Err((
                         memoir::result::Error::new(format!("unknown brush mode '{}'", mode)),
                         input,
@@ -190,6 +279,49 @@ impl Parse for BrushMode {
     }
 }
 
+impl Parse for Dither {
+    fn parser() -> Parser<Self> {
+        let levels = Dither::BAYER_SIZE * Dither::BAYER_SIZE;
+
+        identifier()
+            .skip(whitespace())
+            .then(natural::<u32>())
+            .try_map(move |(pattern, n)| match pattern.as_str() {
+                "checker" if n > 0 => Ok(Dither::Checker(n)),
+                "checker" => Err(String::from("checker size must be greater than zero")),
+                "bayer" if (1..levels).contains(&n) => Ok(Dither::Bayer(n)),
+                "bayer" => Err(format!(
+                    "bayer level must be between 1 and {}, got {}",
+                    levels - 1,
+                    n
+                )),
+                other => Err(format!("unknown dithering pattern '{}'", other)),
+            })
+            .label("<pattern>")
+    }
+}
+
+impl Parse for Shift {
+    fn parser() -> Parser<Self> {
+        let sign = character().try_map(|c| match c {
+            '+' => Ok(1.),
+            '-' => Ok(-1.),
+            _ => Err("shift must start with `+` or `-`"),
+        });
+        let component = character().try_map(|c| match c {
+            'h' => Ok(Shift::Hue as fn(f32) -> Shift),
+            's' => Ok(Shift::Saturation),
+            'l' => Ok(Shift::Lightness),
+            _ => Err("shift component must be one of `h`, `s` or `l`"),
+        });
+
+        sign.then(component)
+            .then(rational::<f32>())
+            .map(|((sign, shift), n)| shift(sign * n))
+            .label("<shift>")
+    }
+}
+
 impl Parse for Mode {
     fn parser() -> Parser<Self> {
         Parser::new(
@@ -200,6 +332,7 @@ impl Parse for Mode {
                     "normal" => Ok((Mode::Normal, p)),
                     "visual" => Ok((Mode::Visual(VisualState::default()), p)),
                     "present" => Ok((Mode::Present, p)),
+                    "search" => Ok((Mode::Search, p)),
                     mode => Err((
                         memoir::result::Error::new(format!("unknown mode: {}", mode)),
                         input,
@@ -245,10 +378,43 @@ mod test {
     fn test_color() {
         let p = color().skip(whitespace()).then(color());
 
-        let ((a, b), rest) = p.parse("#ffaa44/0.5 #141414").unwrap();
+        let ((a, b), rest) = p.parse("#ffaa44/0.5 #141414/1").unwrap();
 
         assert_eq!(rest, "");
         assert_eq!(a, Rgba8::new(0xff, 0xaa, 0x44, 127));
         assert_eq!(b, Rgba8::new(0x14, 0x14, 0x14, 255));
+
+        let (c, rest) = color().parse("#ffaa4480").unwrap();
+
+        assert_eq!(rest, "");
+        assert_eq!(c, Rgba8::new(0xff, 0xaa, 0x44, 0x80));
+    }
+
+    #[test]
+    fn test_color_mapping() {
+        let p = color_mapping();
+
+        let ((from, to), rest) = p.parse("#ff0000 #00ff00 -- red to green").unwrap();
+
+        assert_eq!(rest, "");
+        assert_eq!(from, Rgba8::new(0xff, 0, 0, 255));
+        assert_eq!(to, Rgba8::new(0, 0xff, 0, 255));
+
+        assert!(p.parse("#ff0000").is_err());
+        assert!(p.parse("#ff0000 #00ff00 #0000ff").is_err());
+    }
+
+    #[test]
+    fn test_shift() {
+        let p = Shift::parser().skip(whitespace()).then(Shift::parser());
+
+        let ((a, b), rest) = p.parse("+h10 -s5.5").unwrap();
+
+        assert_eq!(rest, "");
+        assert_eq!(a, Shift::Hue(10.));
+        assert_eq!(b, Shift::Saturation(-5.5));
+
+        assert!(Shift::parser().parse("h10").is_err());
+        assert!(Shift::parser().parse("+x10").is_err());
     }
 }
@@ -0,0 +1,210 @@
+//! Procedural patterns, for filling selections and dithering brush strokes.
+//!
+//! Patterns paint each pixel with either the foreground or the background
+//! color. Checkers and Bayer matrices are aligned to the view, so that
+//! adjacent fills and strokes line up.
+use crate::gfx::Rect;
+
+use std::fmt;
+
+/// Largest supported Bayer matrix.
+pub const MAX_BAYER_SIZE: u32 = 16;
+
+/// A procedural pattern.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Pattern {
+    /// Random noise, with the given fraction of foreground pixels, and an
+    /// optional seed.
+    Noise(f32, Option<u64>),
+    /// Checkerboard, with squares of the given size.
+    Checker(u32),
+    /// Ordered dithering from the foreground to the background color, from
+    /// left to right, using a Bayer matrix of the given size.
+    Bayer(u32),
+}
+
+impl Pattern {
+    /// Get a function which tells whether the pixel at the given position,
+    /// in view coordinates, is painted with the foreground color. The area is
+    /// the one covered by the pattern, and the seed is used for noise which
+    /// doesn't have its own.
+    pub fn sampler(self, area: Rect<i32>, seed: u64) -> impl FnMut(i32, i32) -> bool {
+        let seed = match self {
+            Self::Noise(_, Some(seed)) => seed,
+            _ => seed,
+        };
+        // The *xorshift64\** state must be non-zero.
+        let mut state = seed | 1;
+
+        move |x, y| match self {
+            Self::Noise(density, _) => {
+                state ^= state >> 12;
+                state ^= state << 25;
+                state ^= state >> 27;
+
+                let n = state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 40;
+
+                (n as f32 / (1 << 24) as f32) < density
+            }
+            Self::Checker(size) => Dither::Checker(size).covers(x, y),
+            Self::Bayer(size) => {
+                let size = size.clamp(2, MAX_BAYER_SIZE);
+                let (mx, my) = (x.rem_euclid(size as i32), y.rem_euclid(size as i32));
+                let threshold =
+                    (self::bayer(size, mx as u32, my as u32) as f32 + 0.5) / (size * size) as f32;
+                // Goes from zero on the leftmost column, to one on the rightmost.
+                let level = (x - area.x1) as f32 / (area.width() - 1).max(1) as f32;
+
+                threshold > level
+            }
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Noise(density, None) => write!(f, "noise {}", density),
+            Self::Noise(density, Some(seed)) => write!(f, "noise {} {}", density, seed),
+            Self::Checker(size) => write!(f, "checker {}", size),
+            Self::Bayer(size) => write!(f, "bayer {}", size),
+        }
+    }
+}
+
+/// A dithering pattern, which masks brush strokes.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug)]
+pub enum Dither {
+    /// Checkerboard, with squares of the given size.
+    Checker(u32),
+    /// Ordered dithering, covering the given number of pixels out of every
+    /// [`Dither::BAYER_SIZE`] by [`Dither::BAYER_SIZE`] block.
+    Bayer(u32),
+}
+
+impl Dither {
+    /// Size of the Bayer matrix used for ordered dithering.
+    pub const BAYER_SIZE: u32 = 4;
+
+    /// Check whether the pattern covers the pixel at the given position, in
+    /// view coordinates.
+    pub fn covers(self, x: i32, y: i32) -> bool {
+        match self {
+            Self::Checker(size) => {
+                let size = size.max(1) as i32;
+
+                (x.div_euclid(size) + y.div_euclid(size)) % 2 == 0
+            }
+            Self::Bayer(level) => {
+                let n = Self::BAYER_SIZE as i32;
+
+                self::bayer(
+                    Self::BAYER_SIZE,
+                    x.rem_euclid(n) as u32,
+                    y.rem_euclid(n) as u32,
+                ) < level
+            }
+        }
+    }
+}
+
+impl Default for Dither {
+    fn default() -> Self {
+        Self::Checker(1)
+    }
+}
+
+impl fmt::Display for Dither {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Checker(size) => write!(f, "checker {}", size),
+            Self::Bayer(level) => write!(f, "bayer {}", level),
+        }
+    }
+}
+
+/// Value of the Bayer matrix of the given size at the given position. The
+/// size must be a power of two.
+fn bayer(size: u32, x: u32, y: u32) -> u32 {
+    if size <= 1 {
+        return 0;
+    }
+    let half = size / 2;
+    let quadrant = [[0, 2], [3, 1]][(y / half) as usize][(x / half) as usize];
+
+    4 * self::bayer(half, x % half, y % half) + quadrant
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bayer() {
+        let m: Vec<u32> = (0..4)
+            .flat_map(|y| (0..4).map(move |x| bayer(4, x, y)))
+            .collect();
+
+        assert_eq!(
+            m,
+            vec![0, 8, 2, 10, 12, 4, 14, 6, 3, 11, 1, 9, 15, 7, 13, 5]
+        );
+    }
+
+    #[test]
+    fn test_sampler() {
+        let area = Rect::new(0, 0, 4, 4);
+
+        let mut checker = Pattern::Checker(2).sampler(area, 0);
+        assert!(checker(0, 0) && checker(1, 1) && checker(2, 2));
+        assert!(!checker(2, 0) && !checker(0, 3) && !checker(-1, 0));
+
+        let mut noise = Pattern::Noise(0.25, None).sampler(area, 7);
+        let n = (0..10_000).filter(|_| noise(0, 0)).count();
+        assert!((2000..3000).contains(&n), "{}", n);
+
+        // The same seed gives the same noise.
+        let sample = |pattern: Pattern, seed| {
+            let mut noise = pattern.sampler(area, seed);
+            (0..64).map(|_| noise(0, 0)).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            sample(Pattern::Noise(0.5, None), 3),
+            sample(Pattern::Noise(0.5, None), 3)
+        );
+        assert_eq!(
+            sample(Pattern::Noise(0.5, Some(3)), 9),
+            sample(Pattern::Noise(0.5, None), 3)
+        );
+        assert_ne!(
+            sample(Pattern::Noise(0.5, None), 3),
+            sample(Pattern::Noise(0.5, None), 4)
+        );
+
+        let mut bayer = Pattern::Bayer(2).sampler(area, 0);
+        let column = |x: i32, bayer: &mut dyn FnMut(i32, i32) -> bool| {
+            (0..2).filter(|y| bayer(x, *y)).count()
+        };
+        assert_eq!(column(0, &mut bayer) + column(1, &mut bayer), 4);
+        assert_eq!(column(2, &mut bayer) + column(3, &mut bayer), 1);
+    }
+
+    #[test]
+    fn test_dither() {
+        let covered = |d: Dither| {
+            (0..8)
+                .flat_map(|y| (0..8).map(move |x| (x, y)))
+                .filter(|(x, y)| d.covers(*x, *y))
+                .count()
+        };
+        assert_eq!(covered(Dither::Checker(1)), 32);
+        assert_eq!(covered(Dither::Checker(2)), 32);
+        assert_eq!(covered(Dither::Bayer(4)), 16);
+        assert_eq!(covered(Dither::Bayer(15)), 60);
+
+        // Patterns are aligned to the view, including at negative coordinates.
+        assert!(Dither::Checker(2).covers(-2, -2));
+        assert!(!Dither::Checker(2).covers(-1, 0));
+        assert!(Dither::Bayer(1).covers(-4, 4));
+    }
+}
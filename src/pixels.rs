@@ -1,3 +1,8 @@
+use crate::gfx::Rect;
+
+use std::fmt;
+use std::str::FromStr;
+
 /// A view into a pixel buffer.
 pub struct Pixels<'a, T> {
     width: usize,
@@ -75,3 +80,493 @@ pub fn scale<T: Default + Clone + Copy>(
     }
     output_buf
 }
+
+/// Offset an image by the given amount, wrapping the pixels that exit one
+/// edge around to the opposite edge.
+pub fn wrap<T: Copy>(image: &[T], width: u32, height: u32, dx: i32, dy: i32) -> Vec<T> {
+    assert_eq!(image.len(), (width * height) as usize);
+
+    let input = Pixels::new(image, width as usize, height as usize);
+    let (w, h) = (width as i32, height as i32);
+
+    (0..h)
+        .flat_map(|y| (0..w).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let x = (x - dx).rem_euclid(w) as usize;
+            let y = (y - dy).rem_euclid(h) as usize;
+
+            *input.get(x, y).unwrap()
+        })
+        .collect()
+}
+
+/// Get the smallest rectangle containing all the pixels of an image that
+/// aren't equal to `empty`, in image coordinates, or `None` if there are none.
+pub fn bounds<T: PartialEq>(image: &[T], width: u32, height: u32, empty: &T) -> Option<Rect<u32>> {
+    assert_eq!(image.len(), (width * height) as usize);
+
+    let mut bounds: Option<Rect<u32>> = None;
+
+    for (i, _) in image.iter().enumerate().filter(|(_, p)| *p != empty) {
+        let (x, y) = (i as u32 % width, i as u32 / width);
+
+        bounds = Some(match bounds {
+            Some(r) => Rect::new(r.x1.min(x), r.y1.min(y), r.x2.max(x + 1), r.y2.max(y + 1)),
+            None => Rect::new(x, y, x + 1, y + 1),
+        });
+    }
+    bounds
+}
+
+/// Resize an image to the given dimensions using the nearest-neighbor
+/// algorithm. Each output pixel takes the value of the input pixel under
+/// its center.
+pub fn resize<T: Default + Clone + Copy>(
+    image: &[T],
+    width: u32,
+    height: u32,
+    w: u32,
+    h: u32,
+) -> Vec<T> {
+    assert_eq!(image.len(), (width * height) as usize);
+
+    let input = Pixels::new(image, width as usize, height as usize);
+    let (w, h) = (w as usize, h as usize);
+
+    let mut output_buf = vec![T::default(); w * h];
+    let mut output = PixelsMut::new(&mut output_buf, w, h);
+
+    for (x, y, pixel) in output.iter_mut() {
+        let x = (2 * x + 1) * width as usize / (2 * w);
+        let y = (2 * y + 1) * height as usize / (2 * h);
+
+        *pixel = *input.get(x, y).unwrap();
+    }
+    output_buf
+}
+
+/// Fit an image into a square of the given size using the nearest-neighbor
+/// algorithm, preserving its aspect ratio. The image is centered, and the
+/// remaining area is filled with the default value.
+pub fn fit<T: Default + Clone + Copy>(image: &[T], width: u32, height: u32, size: u32) -> Vec<T> {
+    assert_eq!(image.len(), (width * height) as usize);
+
+    let input = Pixels::new(image, width as usize, height as usize);
+    let longest = width.max(height) as usize;
+    let size = size as usize;
+
+    // Dimensions of the scaled image, and its offset within the square.
+    let (w, h) = (
+        (width as usize * size / longest).max(1),
+        (height as usize * size / longest).max(1),
+    );
+    let (ox, oy) = ((size - w) / 2, (size - h) / 2);
+
+    let mut output_buf = vec![T::default(); size * size];
+    let mut output = PixelsMut::new(&mut output_buf, size, size);
+
+    for (x, y, pixel) in output.iter_mut() {
+        if x < ox || y < oy || x >= ox + w || y >= oy + h {
+            continue;
+        }
+        let x = (x - ox) * width as usize / w;
+        let y = (y - oy) * height as usize / h;
+
+        *pixel = *input.get(x, y).unwrap();
+    }
+    output_buf
+}
+
+/// Scale an image by a factor of two using the Scale2x algorithm, which
+/// smooths diagonal edges without introducing new colors.
+pub fn scale2x<T: Copy + PartialEq>(image: &[T], width: u32, height: u32) -> Vec<T> {
+    assert_eq!(image.len(), (width * height) as usize);
+
+    let (w, h) = (width as usize, height as usize);
+    let input = Pixels::new(image, w, h);
+    let get = |x: usize, y: usize| *input.get(x, y).unwrap();
+    let mut output = Vec::with_capacity(image.len() * 4);
+    let mut bottom = Vec::with_capacity(w * 2);
+
+    for y in 0..h {
+        bottom.clear();
+
+        for x in 0..w {
+            let p = get(x, y);
+            let a = get(x, y.saturating_sub(1));
+            let b = get((x + 1).min(w - 1), y);
+            let c = get(x.saturating_sub(1), y);
+            let d = get(x, (y + 1).min(h - 1));
+
+            if a != d && c != b {
+                output.push(if c == a { a } else { p });
+                output.push(if a == b { b } else { p });
+                bottom.push(if c == d { c } else { p });
+                bottom.push(if b == d { d } else { p });
+            } else {
+                output.extend_from_slice(&[p, p]);
+                bottom.extend_from_slice(&[p, p]);
+            }
+        }
+        output.extend_from_slice(&bottom);
+    }
+    output
+}
+
+/// Scale an image by a factor of three using the Scale3x algorithm, which
+/// smooths diagonal edges without introducing new colors.
+pub fn scale3x<T: Copy + PartialEq>(image: &[T], width: u32, height: u32) -> Vec<T> {
+    assert_eq!(image.len(), (width * height) as usize);
+
+    let (w, h) = (width as usize, height as usize);
+    let input = Pixels::new(image, w, h);
+    let get = |x: usize, y: usize| *input.get(x, y).unwrap();
+    let mut output = Vec::with_capacity(image.len() * 9);
+    let mut rows = [Vec::with_capacity(w * 3), Vec::with_capacity(w * 3)];
+
+    for y in 0..h {
+        rows.iter_mut().for_each(|r| r.clear());
+
+        let (y0, y1) = (y.saturating_sub(1), (y + 1).min(h - 1));
+
+        for x in 0..w {
+            let (x0, x1) = (x.saturating_sub(1), (x + 1).min(w - 1));
+            let [a, b, c] = [get(x0, y0), get(x, y0), get(x1, y0)];
+            let [d, e, f] = [get(x0, y), get(x, y), get(x1, y)];
+            let [g, h, i] = [get(x0, y1), get(x, y1), get(x1, y1)];
+
+            if b != h && d != f {
+                let top = (d == b && e != c) || (b == f && e != a);
+                let left = (d == b && e != g) || (d == h && e != a);
+                let right = (b == f && e != i) || (h == f && e != c);
+                let bottom = (d == h && e != i) || (h == f && e != g);
+
+                output.extend_from_slice(&[
+                    if d == b { d } else { e },
+                    if top { b } else { e },
+                    if b == f { f } else { e },
+                ]);
+                rows[0].extend_from_slice(&[
+                    if left { d } else { e },
+                    e,
+                    if right { f } else { e },
+                ]);
+                rows[1].extend_from_slice(&[
+                    if d == h { d } else { e },
+                    if bottom { h } else { e },
+                    if h == f { f } else { e },
+                ]);
+            } else {
+                output.extend_from_slice(&[e, e, e]);
+                rows[0].extend_from_slice(&[e, e, e]);
+                rows[1].extend_from_slice(&[e, e, e]);
+            }
+        }
+        output.extend_from_slice(&rows[0]);
+        output.extend_from_slice(&rows[1]);
+    }
+    output
+}
+
+/// A smoothing filter used when scaling images up.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ScaleFilter {
+    /// Repeated Scale2x. Only scales by powers of two.
+    Scale2x,
+    /// Repeated Scale3x. Only scales by powers of three.
+    Scale3x,
+}
+
+impl ScaleFilter {
+    /// The factor the filter scales by in a single pass.
+    pub fn factor(&self) -> u32 {
+        match self {
+            Self::Scale2x => 2,
+            Self::Scale3x => 3,
+        }
+    }
+
+    /// Scale an image made of `nframes` frames side by side, by the given factor.
+    /// Each frame is filtered on its own, so that frames don't bleed into each other.
+    pub fn apply<T: Copy + PartialEq>(
+        &self,
+        image: &[T],
+        width: u32,
+        height: u32,
+        nframes: usize,
+        factor: u32,
+    ) -> Result<Vec<T>, String> {
+        assert_eq!(image.len(), (width * height) as usize);
+        assert_eq!(width as usize % nframes, 0);
+
+        let mut passes = 0;
+        let mut n = factor;
+        while n > 1 && n % self.factor() == 0 {
+            n /= self.factor();
+            passes += 1;
+        }
+        if n != 1 {
+            return Err(format!(
+                "{} can't scale by {}, the scale must be a power of {}",
+                self,
+                factor,
+                self.factor()
+            ));
+        }
+
+        let (fw, fh) = (width as usize / nframes, height as usize);
+        let mut frames: Vec<Vec<T>> = (0..nframes)
+            .map(|i| {
+                (0..fh)
+                    .flat_map(|y| {
+                        let start = y * width as usize + i * fw;
+                        image[start..start + fw].iter().copied()
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let (mut w, mut h) = (fw as u32, fh as u32);
+        for _ in 0..passes {
+            for frame in frames.iter_mut() {
+                *frame = match self {
+                    Self::Scale2x => self::scale2x(frame, w, h),
+                    Self::Scale3x => self::scale3x(frame, w, h),
+                };
+            }
+            w *= self.factor();
+            h *= self.factor();
+        }
+
+        let (w, h) = (w as usize, h as usize);
+        let mut output = Vec::with_capacity(w * h * nframes);
+        for y in 0..h {
+            for frame in frames.iter() {
+                output.extend_from_slice(&frame[y * w..(y + 1) * w]);
+            }
+        }
+        Ok(output)
+    }
+}
+
+impl fmt::Display for ScaleFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Scale2x => "scale2x".fmt(f),
+            Self::Scale3x => "scale3x".fmt(f),
+        }
+    }
+}
+
+impl FromStr for ScaleFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "scale2x" => Ok(Self::Scale2x),
+            "scale3x" => Ok(Self::Scale3x),
+            _ => Err(format!(
+                "unknown filter {:?}, must be 'scale2x' or 'scale3x'",
+                s
+            )),
+        }
+    }
+}
+
+/// Rotate an image counter-clockwise around its center, by the given angle in degrees,
+/// using the RotSprite algorithm: the image is upscaled with Scale2x, rotated with
+/// nearest-neighbor sampling, and scaled back down. The output has the same size
+/// as the input. Parts rotated outside of the image are dropped, and uncovered
+/// pixels are set to the default value.
+pub fn rotsprite<T: Default + Copy + PartialEq>(
+    image: &[T],
+    width: u32,
+    height: u32,
+    degrees: f32,
+) -> Vec<T> {
+    assert_eq!(image.len(), (width * height) as usize);
+
+    const FACTOR: u32 = 8;
+
+    let mut scaled = image.to_vec();
+    let (mut sw, mut sh) = (width, height);
+    while sw < width * FACTOR {
+        scaled = self::scale2x(&scaled, sw, sh);
+        sw *= 2;
+        sh *= 2;
+    }
+    let scaled = Pixels::new(&scaled, sw as usize, sh as usize);
+
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (cx, cy) = (width as f32 / 2., height as f32 / 2.);
+    let mut output_buf = vec![T::default(); image.len()];
+    let mut output = PixelsMut::new(&mut output_buf, width as usize, height as usize);
+
+    // Rows are stored top to bottom, hence the inverted rotation for `y`.
+    for (x, y, pixel) in output.iter_mut() {
+        let dx = x as f32 + 0.5 - cx;
+        let dy = y as f32 + 0.5 - cy;
+        let sx = ((dx * cos - dy * sin + cx) * FACTOR as f32).floor();
+        let sy = ((dx * sin + dy * cos + cy) * FACTOR as f32).floor();
+
+        if sx >= 0. && sy >= 0. && sx < sw as f32 && sy < sh as f32 {
+            *pixel = *scaled.get(sx as usize, sy as usize).unwrap();
+        }
+    }
+    output_buf
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wrap() {
+        #[rustfmt::skip]
+        let image = [
+            0, 1, 2,
+            3, 4, 5,
+        ];
+        #[rustfmt::skip]
+        assert_eq!(wrap(&image, 3, 2, 1, 0), vec![
+            2, 0, 1,
+            5, 3, 4,
+        ]);
+        #[rustfmt::skip]
+        assert_eq!(wrap(&image, 3, 2, -1, 1), vec![
+            4, 5, 3,
+            1, 2, 0,
+        ]);
+        assert_eq!(wrap(&image, 3, 2, 3, -2), image);
+    }
+
+    #[test]
+    fn test_bounds() {
+        #[rustfmt::skip]
+        let image = [
+            0, 0, 0, 0,
+            0, 0, 1, 0,
+            0, 1, 0, 0,
+        ];
+        assert_eq!(bounds(&image, 4, 3, &0), Some(Rect::new(1, 1, 3, 3)));
+        assert_eq!(bounds(&image, 4, 3, &1), Some(Rect::new(0, 0, 4, 3)));
+        assert_eq!(bounds(&[0; 4], 2, 2, &0), None);
+    }
+
+    #[test]
+    fn test_resize() {
+        #[rustfmt::skip]
+        let image = [
+            0, 1,
+            2, 3,
+        ];
+        #[rustfmt::skip]
+        assert_eq!(resize(&image, 2, 2, 3, 2), vec![
+            0, 1, 1,
+            2, 3, 3,
+        ]);
+        assert_eq!(resize(&resize(&image, 2, 2, 6, 6), 6, 6, 2, 2), image);
+        assert_eq!(resize(&image, 2, 2, 1, 1), vec![3]);
+    }
+
+    #[test]
+    fn test_scale2x() {
+        #[rustfmt::skip]
+        let image = [
+            0, 1,
+            1, 1,
+        ];
+        #[rustfmt::skip]
+        assert_eq!(scale2x(&image, 2, 2), vec![
+            0, 0, 1, 1,
+            0, 1, 1, 1,
+            1, 1, 1, 1,
+            1, 1, 1, 1,
+        ]);
+    }
+
+    #[test]
+    fn test_scale3x() {
+        #[rustfmt::skip]
+        let image = [
+            0, 1,
+            1, 1,
+        ];
+        #[rustfmt::skip]
+        assert_eq!(scale3x(&image, 2, 2), vec![
+            0, 0, 0, 1, 1, 1,
+            0, 0, 1, 1, 1, 1,
+            0, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1,
+        ]);
+    }
+
+    #[test]
+    fn test_scale_filter() {
+        #[rustfmt::skip]
+        let image = [
+            1, 2,
+            3, 4,
+        ];
+        // Two frames of a single column each, which don't bleed into each other.
+        #[rustfmt::skip]
+        assert_eq!(ScaleFilter::Scale2x.apply(&image, 2, 2, 2, 2), Ok(vec![
+            1, 1, 2, 2,
+            1, 1, 2, 2,
+            3, 3, 4, 4,
+            3, 3, 4, 4,
+        ]));
+        assert_eq!(
+            ScaleFilter::Scale2x.apply(&image, 2, 2, 1, 1),
+            Ok(image.to_vec())
+        );
+        assert_eq!(
+            ScaleFilter::Scale2x
+                .apply(&image, 2, 2, 1, 4)
+                .map(|v| v.len()),
+            Ok(64)
+        );
+        assert!(ScaleFilter::Scale2x.apply(&image, 2, 2, 1, 3).is_err());
+        assert!(ScaleFilter::Scale3x.apply(&image, 2, 2, 1, 6).is_err());
+    }
+
+    #[test]
+    fn test_fit() {
+        #[rustfmt::skip]
+        let image = [
+            1, 2,
+            3, 4,
+        ];
+        #[rustfmt::skip]
+        assert_eq!(fit(&image, 2, 2, 4), vec![
+            1, 1, 2, 2,
+            1, 1, 2, 2,
+            3, 3, 4, 4,
+            3, 3, 4, 4,
+        ]);
+        assert_eq!(fit(&scale(&image, 2, 2, 2), 4, 4, 2), image.to_vec());
+
+        #[rustfmt::skip]
+        assert_eq!(fit(&[1, 2], 2, 1, 4), vec![
+            0, 0, 0, 0,
+            1, 1, 2, 2,
+            1, 1, 2, 2,
+            0, 0, 0, 0,
+        ]);
+    }
+
+    #[test]
+    fn test_rotsprite() {
+        #[rustfmt::skip]
+        let image = [
+            1, 2,
+            3, 4,
+        ];
+        assert_eq!(rotsprite(&image, 2, 2, 0.), image.to_vec());
+        assert_eq!(rotsprite(&image, 2, 2, 360.), image.to_vec());
+        assert_eq!(rotsprite(&image, 2, 2, 90.), vec![2, 4, 1, 3]);
+        assert_eq!(rotsprite(&image, 2, 2, 180.), vec![4, 3, 2, 1]);
+    }
+}
@@ -1,16 +1,9 @@
 use crate::platform::{GraphicsContext, LogicalSize, WindowEvent, WindowHint};
 
-use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
 use std::io;
 
 pub struct DummyWindow(());
 
-unsafe impl HasRawWindowHandle for DummyWindow {
-    fn raw_window_handle(&self) -> RawWindowHandle {
-        unreachable!()
-    }
-}
-
 pub struct Events {
     handle: (),
 }
@@ -27,14 +20,14 @@ impl Events {
     }
 }
 
+/// A window which is never shown. Only usable without a graphics context.
 pub struct Window {
     handle: DummyWindow,
+    size: LogicalSize,
 }
 
 impl Window {
-    pub fn request_redraw(&self) {
-        unreachable!()
-    }
+    pub fn request_redraw(&self) {}
 
     pub fn handle(&self) -> &DummyWindow {
         &self.handle
@@ -44,16 +37,14 @@ impl Window {
         unreachable!()
     }
 
-    pub fn set_cursor_visible(&mut self, _visible: bool) {
-        unreachable!()
-    }
+    pub fn set_cursor_visible(&mut self, _visible: bool) {}
 
     pub fn scale_factor(&self) -> f64 {
-        unreachable!()
+        1.
     }
 
     pub fn size(&self) -> LogicalSize {
-        unreachable!()
+        self.size
     }
 
     pub fn is_focused(&self) -> bool {
@@ -73,10 +64,22 @@ impl Window {
 
 pub fn init(
     _title: &str,
-    _w: u32,
-    _h: u32,
+    w: u32,
+    h: u32,
     _hints: &[WindowHint],
-    _context: GraphicsContext,
+    context: GraphicsContext,
 ) -> io::Result<(Window, Events)> {
-    panic!("`dummy` platform initialized");
+    if context != GraphicsContext::None {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "the `dummy` platform doesn't support graphics contexts",
+        ));
+    }
+    Ok((
+        Window {
+            handle: DummyWindow(()),
+            size: LogicalSize::new(w as f64, h as f64),
+        },
+        Events { handle: () },
+    ))
 }
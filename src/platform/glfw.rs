@@ -192,6 +192,8 @@ impl From<glfw::WindowEvent> for WindowEvent {
                 button: button.into(),
                 modifiers: modifiers.into(),
             },
+            // GLFW doesn't report whether scrolls are precise, so they're
+            // all treated as mouse wheel scrolls.
             Glfw::Scroll(x, y) => WindowEvent::MouseWheel {
                 delta: LogicalDelta { x, y },
             },
@@ -241,10 +243,12 @@ impl From<glfw::Key> for Key {
             Glfw::LeftBracket => Key::LBracket,
             Glfw::LeftControl => Key::Control,
             Glfw::LeftShift => Key::Shift,
+            Glfw::LeftSuper => Key::Super,
             Glfw::RightAlt => Key::Alt,
             Glfw::RightBracket => Key::RBracket,
             Glfw::RightControl => Key::Control,
             Glfw::RightShift => Key::Shift,
+            Glfw::RightSuper => Key::Super,
             Glfw::Tab => Key::Tab,
 
             _ => {
@@ -3,14 +3,20 @@ use std::io;
 
 use std::fmt;
 
-#[cfg(not(feature = "glfw"))]
+#[cfg(not(any(feature = "glfw", feature = "winit")))]
 #[path = "dummy.rs"]
 pub mod backend;
 
-#[cfg(feature = "glfw")]
+#[cfg(all(feature = "glfw", not(feature = "winit")))]
 #[path = "glfw.rs"]
 pub mod backend;
 
+// The winit backend takes precedence, so that it can be selected with
+// `--features winit` without disabling the default features.
+#[cfg(feature = "winit")]
+#[path = "winit.rs"]
+pub mod backend;
+
 /// Initialize the platform.
 pub fn init(
     title: &str,
@@ -86,6 +92,14 @@ pub enum WindowEvent {
     /// The mouse wheel has been used.
     MouseWheel { delta: LogicalDelta },
 
+    /// A precise scroll has been received, usually from a trackpad.
+    /// Pinch gestures are often reported as precise scrolls with `<ctrl>` held.
+    TouchpadScroll { delta: LogicalDelta },
+
+    /// A pinch gesture has been received from a trackpad. Positive deltas
+    /// magnify.
+    TouchpadMagnify { delta: f64 },
+
     /// The OS or application has requested that the window be redrawn.
     RedrawRequested,
 
@@ -172,7 +186,7 @@ pub enum Key {
     Slash, Backslash,
 
     // Modifiers.
-    Alt, Control, Shift,
+    Alt, Control, Shift, Super,
 
     // Math keys.
     Equal, Minus,
@@ -257,6 +271,7 @@ impl fmt::Display for Key {
             Key::Control => "<ctrl>".fmt(f),
             Key::Shift => "<shift>".fmt(f),
             Key::Alt => "<alt>".fmt(f),
+            Key::Super => "<super>".fmt(f),
             Key::Up => "<up>".fmt(f),
             Key::Down => "<down>".fmt(f),
             Key::Left => "<left>".fmt(f),
@@ -285,7 +300,7 @@ impl fmt::Display for Key {
 
 impl Key {
     pub fn is_modifier(self) -> bool {
-        matches!(self, Key::Alt | Key::Control | Key::Shift)
+        matches!(self, Key::Alt | Key::Control | Key::Shift | Key::Super)
     }
 }
 
@@ -0,0 +1,440 @@
+use crate::platform::{
+    GraphicsContext, InputState, Key, KeyboardInput, LogicalDelta, LogicalPosition, LogicalSize,
+    ModifiersState, MouseButton, PhysicalPosition, WindowEvent, WindowHint,
+};
+
+use glutin::config::ConfigTemplateBuilder;
+use glutin::context::{
+    ContextApi, ContextAttributesBuilder, GlProfile, PossiblyCurrentContext, Version,
+};
+use glutin::display::GetGlDisplay;
+use glutin::prelude::*;
+use glutin::surface::{Surface, SurfaceAttributesBuilder, SwapInterval, WindowSurface};
+use glutin_winit::{DisplayBuilder, GlWindow};
+use raw_window_handle_05::HasRawWindowHandle;
+
+use winit::event::{ElementState, Event, MouseScrollDelta};
+use winit::event_loop::EventLoop;
+use winit::keyboard::{Key as WinitKey, NamedKey};
+use winit::platform::modifier_supplement::KeyEventExtModifierSupplement;
+use winit::platform::pump_events::{EventLoopExtPumpEvents, PumpStatus};
+use winit::window::WindowBuilder;
+
+use std::cell::{Cell, RefCell};
+use std::ffi::CString;
+use std::num::NonZeroU32;
+use std::{io, time};
+
+/// Number of pixels of a precise scroll that amount to one scroll step.
+const PIXELS_PER_STEP: f64 = 10.;
+
+///////////////////////////////////////////////////////////////////////////////
+
+pub fn init(
+    title: &str,
+    w: u32,
+    h: u32,
+    hints: &[WindowHint],
+    context: GraphicsContext,
+) -> io::Result<(Window, Events)> {
+    let event_loop = EventLoop::new().map_err(self::error)?;
+    let mut builder = WindowBuilder::new()
+        .with_title(title)
+        .with_inner_size(winit::dpi::PhysicalSize::new(w, h))
+        .with_resizable(true)
+        .with_visible(true);
+
+    for hint in hints {
+        builder = match *hint {
+            WindowHint::Resizable(b) => builder.with_resizable(b),
+            WindowHint::Visible(b) => builder.with_visible(b),
+        };
+    }
+
+    let (handle, gl) = match context {
+        GraphicsContext::None => {
+            let handle = builder.build(&event_loop).map_err(self::error)?;
+
+            (handle, None)
+        }
+        GraphicsContext::Gl => {
+            let (handle, config) = DisplayBuilder::new()
+                .with_window_builder(Some(builder))
+                .build(&event_loop, ConfigTemplateBuilder::new(), |mut configs| {
+                    configs.next().expect("there is at least one config")
+                })
+                .map_err(|e| self::error(e.to_string()))?;
+            let handle =
+                handle.ok_or_else(|| self::error("winit: error creating window".to_owned()))?;
+
+            let display = config.display();
+            let attrs = ContextAttributesBuilder::new()
+                .with_context_api(ContextApi::OpenGl(Some(Version::new(3, 3))))
+                .with_profile(GlProfile::Core)
+                .build(Some(handle.raw_window_handle()));
+            let surface_attrs =
+                handle.build_surface_attributes(SurfaceAttributesBuilder::default());
+
+            // Safety: the window outlives the context and surface, since they
+            // are dropped first. See `Window`.
+            let (context, surface) = unsafe {
+                let context = display
+                    .create_context(&config, &attrs)
+                    .map_err(self::error)?;
+                let surface = display
+                    .create_window_surface(&config, &surface_attrs)
+                    .map_err(self::error)?;
+
+                (context, surface)
+            };
+            let context = context.make_current(&surface).map_err(self::error)?;
+
+            surface
+                .set_swap_interval(&context, SwapInterval::DontWait)
+                .map_err(self::error)?;
+
+            (handle, Some(Gl { context, surface }))
+        }
+    };
+    let scale_factor = handle.scale_factor();
+    let size = handle.inner_size();
+
+    Ok((
+        Window {
+            handle,
+            gl,
+            size: (size.width, size.height),
+            clipboard: RefCell::new(arboard::Clipboard::new().ok()),
+        },
+        Events {
+            event_loop,
+            queue: Cell::new(Vec::new()),
+            modifiers: ModifiersState::default(),
+            scale_factor,
+        },
+    ))
+}
+
+fn error<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+pub struct Events {
+    event_loop: EventLoop<()>,
+    queue: Cell<Vec<WindowEvent>>,
+    modifiers: ModifiersState,
+    scale_factor: f64,
+}
+
+impl Events {
+    pub fn wait(&mut self) {
+        self.pump(None);
+    }
+
+    pub fn wait_timeout(&mut self, timeout: time::Duration) {
+        self.pump(Some(timeout));
+    }
+
+    pub fn poll(&mut self) {
+        self.pump(Some(time::Duration::ZERO));
+    }
+
+    pub fn flush(&self) -> impl Iterator<Item = WindowEvent> + '_ {
+        self.queue.take().into_iter()
+    }
+
+    /// Dispatch pending events, waiting at most for the given timeout for
+    /// new events to arrive. Events are queued until they are flushed.
+    fn pump(&mut self, timeout: Option<time::Duration>) {
+        let Self {
+            event_loop,
+            queue,
+            modifiers,
+            scale_factor,
+        } = self;
+        let queue = queue.get_mut();
+
+        let status = event_loop.pump_events(timeout, |event, _| {
+            if let Event::WindowEvent { event, .. } = event {
+                self::convert(event, queue, modifiers, scale_factor);
+            }
+        });
+        if let PumpStatus::Exit(_) = status {
+            queue.push(WindowEvent::CloseRequested);
+        }
+    }
+}
+
+struct Gl {
+    context: PossiblyCurrentContext,
+    surface: Surface<WindowSurface>,
+}
+
+pub struct Window {
+    // Nb. The GL context and surface must be dropped before the window.
+    gl: Option<Gl>,
+    pub handle: winit::window::Window,
+    /// Size of the GL surface, in physical pixels.
+    size: (u32, u32),
+    clipboard: RefCell<Option<arboard::Clipboard>>,
+}
+
+impl Window {
+    pub fn handle(&self) -> &winit::window::Window {
+        &self.handle
+    }
+
+    pub fn get_proc_address(&mut self, s: &str) -> *const std::ffi::c_void {
+        let gl = self.gl.as_ref().expect("a GL context was requested");
+        let s = CString::new(s).expect("symbol names don't contain nul bytes");
+
+        gl.context.display().get_proc_address(s.as_c_str())
+    }
+
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        self.handle.set_cursor_visible(visible);
+    }
+
+    pub fn scale_factor(&self) -> f64 {
+        self.handle.scale_factor()
+    }
+
+    pub fn size(&self) -> LogicalSize {
+        let size = self.handle.inner_size();
+
+        LogicalSize::from_physical((size.width, size.height), self.scale_factor())
+    }
+
+    pub fn present(&mut self) {
+        if let Some(gl) = &self.gl {
+            // Unlike on other platforms, the surface isn't resized along with the
+            // window on Wayland, so we keep it in sync here.
+            let size = self.handle.inner_size();
+
+            if (size.width, size.height) != self.size {
+                if let (Some(w), Some(h)) =
+                    (NonZeroU32::new(size.width), NonZeroU32::new(size.height))
+                {
+                    gl.surface.resize(&gl.context, w, h);
+                }
+                self.size = (size.width, size.height);
+            }
+            if let Err(e) = gl.surface.swap_buffers(&gl.context) {
+                warn!("winit: error swapping buffers: {}", e);
+            }
+        }
+    }
+
+    pub fn is_closing(&self) -> bool {
+        // Close requests are reported as events.
+        false
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.handle.has_focus()
+    }
+
+    pub fn clipboard(&self) -> Option<String> {
+        self.clipboard
+            .borrow_mut()
+            .as_mut()
+            .and_then(|c| c.get_text().ok())
+    }
+}
+
+/// Convert a winit event, and add the result to the queue.
+fn convert(
+    event: winit::event::WindowEvent,
+    queue: &mut Vec<WindowEvent>,
+    modifiers: &mut ModifiersState,
+    scale_factor: &mut f64,
+) {
+    use winit::event::WindowEvent as Winit;
+
+    let event = match event {
+        Winit::Resized(size) => WindowEvent::Resized(LogicalSize::from_physical(
+            (size.width, size.height),
+            *scale_factor,
+        )),
+        Winit::Moved(pos) => WindowEvent::Moved(LogicalPosition::from_physical(
+            PhysicalPosition::new(pos.x as f64, pos.y as f64),
+            *scale_factor,
+        )),
+        Winit::Occluded(true) => WindowEvent::Minimized,
+        Winit::Occluded(false) => WindowEvent::Restored,
+        Winit::CloseRequested => WindowEvent::CloseRequested,
+        Winit::Destroyed => WindowEvent::Destroyed,
+        Winit::RedrawRequested => WindowEvent::RedrawRequested,
+        Winit::Focused(b) => WindowEvent::Focused(b),
+        Winit::ScaleFactorChanged {
+            scale_factor: factor,
+            ..
+        } => {
+            *scale_factor = factor;
+
+            WindowEvent::ScaleFactorChanged(factor)
+        }
+        Winit::ModifiersChanged(mods) => {
+            let state = mods.state();
+
+            *modifiers = ModifiersState {
+                shift: state.shift_key(),
+                ctrl: state.control_key(),
+                alt: state.alt_key(),
+                meta: state.super_key(),
+            };
+            return;
+        }
+        Winit::KeyboardInput { event, .. } => {
+            let state = match (event.state, event.repeat) {
+                (ElementState::Pressed, true) => InputState::Repeated,
+                (ElementState::Pressed, false) => InputState::Pressed,
+                (ElementState::Released, _) => InputState::Released,
+            };
+            queue.push(WindowEvent::KeyboardInput(KeyboardInput {
+                key: Some(self::key(&event.key_without_modifiers())),
+                state,
+                modifiers: *modifiers,
+            }));
+
+            if event.state == ElementState::Pressed {
+                if let Some(text) = &event.text {
+                    for c in text.chars().filter(|c| !c.is_control()) {
+                        queue.push(WindowEvent::ReceivedCharacter(c, *modifiers));
+                    }
+                }
+            }
+            return;
+        }
+        Winit::CursorEntered { .. } => WindowEvent::CursorEntered,
+        Winit::CursorLeft { .. } => WindowEvent::CursorLeft,
+        Winit::CursorMoved { position, .. } => WindowEvent::CursorMoved {
+            position: LogicalPosition::from_physical(
+                PhysicalPosition::new(position.x, position.y),
+                *scale_factor,
+            ),
+        },
+        Winit::MouseInput { state, button, .. } => WindowEvent::MouseInput {
+            state: match state {
+                ElementState::Pressed => InputState::Pressed,
+                ElementState::Released => InputState::Released,
+            },
+            button: match button {
+                winit::event::MouseButton::Left => MouseButton::Left,
+                winit::event::MouseButton::Right => MouseButton::Right,
+                winit::event::MouseButton::Middle => MouseButton::Middle,
+                winit::event::MouseButton::Back => MouseButton::Other(4),
+                winit::event::MouseButton::Forward => MouseButton::Other(5),
+                winit::event::MouseButton::Other(n) => MouseButton::Other(n as u8),
+            },
+            modifiers: *modifiers,
+        },
+        Winit::MouseWheel {
+            delta: MouseScrollDelta::LineDelta(x, y),
+            ..
+        } => WindowEvent::MouseWheel {
+            delta: LogicalDelta {
+                x: x as f64,
+                y: y as f64,
+            },
+        },
+        Winit::MouseWheel {
+            delta: MouseScrollDelta::PixelDelta(delta),
+            ..
+        } => WindowEvent::TouchpadScroll {
+            delta: LogicalDelta {
+                x: delta.x / PIXELS_PER_STEP,
+                y: delta.y / PIXELS_PER_STEP,
+            },
+        },
+        Winit::TouchpadMagnify { delta, .. } => WindowEvent::TouchpadMagnify { delta },
+        _ => return,
+    };
+    queue.push(event);
+}
+
+/// Convert a winit key, ignoring modifiers.
+fn key(key: &WinitKey) -> Key {
+    match key {
+        WinitKey::Named(named) => match named {
+            NamedKey::Escape => Key::Escape,
+            NamedKey::Insert => Key::Insert,
+            NamedKey::Home => Key::Home,
+            NamedKey::Delete => Key::Delete,
+            NamedKey::End => Key::End,
+            NamedKey::PageDown => Key::PageDown,
+            NamedKey::PageUp => Key::PageUp,
+            NamedKey::ArrowLeft => Key::Left,
+            NamedKey::ArrowUp => Key::Up,
+            NamedKey::ArrowRight => Key::Right,
+            NamedKey::ArrowDown => Key::Down,
+            NamedKey::Backspace => Key::Backspace,
+            NamedKey::Enter => Key::Return,
+            NamedKey::Space => Key::Space,
+            NamedKey::Tab => Key::Tab,
+            NamedKey::Alt => Key::Alt,
+            NamedKey::Control => Key::Control,
+            NamedKey::Shift => Key::Shift,
+            NamedKey::Super => Key::Super,
+            _ => Key::Unknown,
+        },
+        WinitKey::Character(s) => s
+            .chars()
+            .next()
+            .map_or(Key::Unknown, |c| Key::from(c.to_ascii_lowercase())),
+        _ => Key::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use winit::dpi::PhysicalPosition as WinitPosition;
+    use winit::event::{DeviceId, TouchPhase, WindowEvent as Winit};
+
+    fn wheel(delta: MouseScrollDelta) -> Winit {
+        Winit::MouseWheel {
+            // SAFETY: The device id is only compared, never used to query a device.
+            device_id: unsafe { DeviceId::dummy() },
+            delta,
+            phase: TouchPhase::Moved,
+        }
+    }
+
+    fn converted(event: Winit) -> Vec<WindowEvent> {
+        let mut queue = Vec::new();
+        convert(event, &mut queue, &mut ModifiersState::default(), &mut 1.);
+        queue
+    }
+
+    #[test]
+    fn test_convert_scroll() {
+        assert_eq!(
+            converted(wheel(MouseScrollDelta::LineDelta(0., -1.))),
+            vec![WindowEvent::MouseWheel {
+                delta: LogicalDelta { x: 0., y: -1. }
+            }]
+        );
+        assert_eq!(
+            converted(wheel(MouseScrollDelta::PixelDelta(WinitPosition::new(
+                20., -5.
+            )))),
+            vec![WindowEvent::TouchpadScroll {
+                delta: LogicalDelta { x: 2., y: -0.5 }
+            }]
+        );
+    }
+
+    #[test]
+    fn test_convert_magnify() {
+        assert_eq!(
+            converted(Winit::TouchpadMagnify {
+                device_id: unsafe { DeviceId::dummy() },
+                delta: 0.25,
+                phase: TouchPhase::Moved,
+            }),
+            vec![WindowEvent::TouchpadMagnify { delta: 0.25 }]
+        );
+    }
+}
@@ -0,0 +1,296 @@
+//! External pixel filter plugins.
+//!
+//! A plugin is an executable in the `plugins` directory of the rx config
+//! directory, and is run with `:plugin <name> [<arg>..]`. It operates on the
+//! selection, or on the whole view if nothing is selected.
+//!
+//! Plugins are separate processes which run in the background: a header line
+//! of the form `<width> <height>` is written to their standard input, followed
+//! by the pixels as 8-bit RGBA, top row first. They must write the same number
+//! of pixels back to their standard output, and are killed if they run for
+//! longer than [`TIMEOUT`].
+//!
+//! Plugins are trusted: they aren't sandboxed, and run with the user's
+//! permissions, with full access to the file system and network. Only install
+//! plugins from sources you trust. Running them in a WASM sandbox would lift
+//! this requirement, but isn't supported, as it needs an embedded runtime.
+use crate::gfx::{Rect, Rgba8};
+use crate::util;
+use crate::view::resource::EditId;
+use crate::view::ViewId;
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time;
+
+/// Name of the plugin directory, inside the config directory.
+pub const DIR: &str = "plugins";
+
+/// Maximum time a plugin is allowed to run for.
+pub const TIMEOUT: time::Duration = time::Duration::from_secs(30);
+
+/// Interval at which running plugins are checked for completion.
+pub const POLL_INTERVAL: time::Duration = time::Duration::from_millis(50);
+
+/// Outcome of a plugin run.
+#[derive(Debug)]
+pub struct PluginResult {
+    /// Plugin name.
+    pub name: String,
+    /// View the plugin was run on.
+    pub view: ViewId,
+    /// Area of the view the plugin was run on.
+    pub area: Rect<i32>,
+    /// Edit of the view the plugin was given the pixels of.
+    pub edit: EditId,
+    /// Pixels output by the plugin.
+    pub result: io::Result<Vec<Rgba8>>,
+}
+
+/// Runs plugins, each on its own thread.
+#[derive(Debug)]
+pub struct Plugins {
+    tx: mpsc::Sender<PluginResult>,
+    results: mpsc::Receiver<PluginResult>,
+    /// Number of plugins still running.
+    running: usize,
+}
+
+impl Plugins {
+    pub fn new() -> Self {
+        let (tx, results) = mpsc::channel();
+
+        Self {
+            tx,
+            results,
+            running: 0,
+        }
+    }
+
+    /// Run the plugin on the given area of a view, whose pixels are given.
+    pub fn run(
+        &mut self,
+        plugin: Plugin,
+        args: Vec<String>,
+        view: ViewId,
+        area: Rect<i32>,
+        edit: EditId,
+        pixels: Vec<Rgba8>,
+    ) {
+        let tx = self.tx.clone();
+        let (w, h) = (area.width() as u32, area.height() as u32);
+        self.running += 1;
+
+        thread::spawn(move || {
+            let result = plugin.execute(&args, w, h, &pixels);
+
+            tx.send(PluginResult {
+                name: plugin.name,
+                view,
+                area,
+                edit,
+                result,
+            })
+            .ok();
+        });
+    }
+
+    /// Get the results of the plugins completed since the last call, without blocking.
+    pub fn results(&mut self) -> Vec<PluginResult> {
+        let results: Vec<_> = self.results.try_iter().collect();
+        self.running -= results.len();
+
+        results
+    }
+
+    /// Check whether any plugins are still running.
+    pub fn is_running(&self) -> bool {
+        self.running > 0
+    }
+}
+
+impl Default for Plugins {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An external plugin.
+#[derive(Debug, Clone)]
+pub struct Plugin {
+    /// Plugin name, used to run it.
+    pub name: String,
+    /// Path to the plugin executable.
+    pub path: PathBuf,
+}
+
+impl Plugin {
+    /// Find the plugin with the given name in a directory.
+    pub fn find<P: AsRef<Path>>(dir: P, name: &str) -> io::Result<Self> {
+        self::discover(dir)?
+            .into_iter()
+            .find(|p| p.name == name)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("plugin `{}` not found", name),
+                )
+            })
+    }
+
+    /// Run the plugin on the given pixels, and return the pixels it outputs.
+    /// Blocks until the plugin exits, or is killed after [`TIMEOUT`].
+    pub fn execute(
+        &self,
+        args: &[String],
+        w: u32,
+        h: u32,
+        pixels: &[Rgba8],
+    ) -> io::Result<Vec<Rgba8>> {
+        let mut child = Command::new(&self.path)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let mut input = format!("{} {}\n", w, h).into_bytes();
+        input.extend_from_slice(util::align_u8(pixels));
+
+        // Write from a separate thread, so that a plugin which writes its output
+        // before it's done reading can't block us.
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let writer = thread::spawn(move || stdin.write_all(&input));
+
+        // Likewise, read the outputs as they're written.
+        let stdout = self::drain(child.stdout.take().expect("stdout is piped"));
+        let stderr = self::drain(child.stderr.take().expect("stderr is piped"));
+
+        let start = time::Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if start.elapsed() >= TIMEOUT {
+                child.kill().ok();
+                child.wait().ok();
+
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!(
+                        "plugin `{}` timed out after {}s",
+                        self.name,
+                        TIMEOUT.as_secs()
+                    ),
+                ));
+            }
+            thread::sleep(time::Duration::from_millis(10));
+        };
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+
+        if !status.success() {
+            let stderr = String::from_utf8_lossy(&stderr);
+            let stderr = stderr.trim();
+
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                if stderr.is_empty() {
+                    format!("plugin `{}` failed with {}", self.name, status)
+                } else {
+                    format!("plugin `{}` failed: {}", self.name, stderr)
+                },
+            ));
+        }
+        writer
+            .join()
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "plugin writer panicked"))??;
+
+        if stdout.len() != pixels.len() * 4 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "plugin `{}` output {} byte(s), expected {}",
+                    self.name,
+                    stdout.len(),
+                    pixels.len() * 4
+                ),
+            ));
+        }
+        Ok(Rgba8::align(&stdout).to_vec())
+    }
+}
+
+/// Read everything from the given reader on a separate thread.
+fn drain<R: Read + Send + 'static>(mut r: R) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf).ok();
+        buf
+    })
+}
+
+/// Discover the plugins in a directory, sorted by name. Plugins are named after
+/// their file name, without the extension.
+pub fn discover<P: AsRef<Path>>(dir: P) -> io::Result<Vec<Plugin>> {
+    let mut plugins = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if !path.is_file() {
+            continue;
+        }
+        if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+            plugins.push(Plugin {
+                name: name.to_owned(),
+                path: path.clone(),
+            });
+        }
+    }
+    plugins.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(plugins)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(unix)]
+    fn plugin(dir: &Path, name: &str, script: &str) -> Plugin {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        Plugin::find(dir, name).unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_execute() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pixels = vec![Rgba8::RED, Rgba8::new(0xa, 0xa, 0xa, 0xa)];
+
+        let echo = plugin(tmp.path(), "echo", "tail -n +2");
+        assert_eq!(echo.execute(&[], 2, 1, &pixels).unwrap(), pixels);
+
+        let fail = plugin(tmp.path(), "fail", "cat >/dev/null; echo oops >&2; exit 1");
+        assert_eq!(
+            fail.execute(&[], 2, 1, &pixels).unwrap_err().to_string(),
+            "plugin `fail` failed: oops"
+        );
+
+        let short = plugin(tmp.path(), "short", "cat >/dev/null; printf abcd");
+        assert_eq!(
+            short.execute(&[], 2, 1, &pixels).unwrap_err().to_string(),
+            "plugin `short` output 4 byte(s), expected 8"
+        );
+    }
+}
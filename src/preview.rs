@@ -0,0 +1,259 @@
+//! Display previews. These change how the workspace is displayed, without
+//! modifying pixel data, and are applied in the renderer's screen pass.
+use crate::gfx::Rgba8;
+
+use std::fmt;
+use std::str::FromStr;
+
+/// A classic hardware palette, which displayed colors can be remapped to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HardwarePalette {
+    /// The original Game Boy's four shades of green.
+    GameBoy,
+    /// The NES' PPU palette.
+    Nes,
+    /// The Commodore 64's sixteen colors.
+    C64,
+}
+
+impl HardwarePalette {
+    /// Maximum number of colors in a hardware palette.
+    pub const MAX_COLORS: usize = 64;
+
+    /// The palette colors.
+    pub fn colors(&self) -> &'static [Rgba8] {
+        match self {
+            Self::GameBoy => GAMEBOY,
+            Self::Nes => NES,
+            Self::C64 => C64,
+        }
+    }
+}
+
+impl fmt::Display for HardwarePalette {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::GameBoy => "gb".fmt(f),
+            Self::Nes => "nes".fmt(f),
+            Self::C64 => "c64".fmt(f),
+        }
+    }
+}
+
+impl FromStr for HardwarePalette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gb" => Ok(Self::GameBoy),
+            "nes" => Ok(Self::Nes),
+            "c64" => Ok(Self::C64),
+            _ => Err(format!(
+                "unknown palette {:?}, must be 'gb', 'nes' or 'c64'",
+                s
+            )),
+        }
+    }
+}
+
+/// A type of color vision deficiency, which can be simulated.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Colorblindness {
+    /// Missing red cones.
+    Protanopia,
+    /// Missing green cones.
+    Deuteranopia,
+    /// Missing blue cones.
+    Tritanopia,
+}
+
+impl Colorblindness {
+    /// The simulation matrix, from Machado et al. (2009), to be applied to
+    /// linear RGB colors. Each row yields one output component.
+    #[rustfmt::skip]
+    pub fn matrix(&self) -> [[f32; 3]; 3] {
+        match self {
+            Self::Protanopia => [
+                [ 0.152286,  1.052583, -0.204868],
+                [ 0.114503,  0.786281,  0.099216],
+                [-0.003882, -0.048116,  1.051998],
+            ],
+            Self::Deuteranopia => [
+                [ 0.367322,  0.860646, -0.227968],
+                [ 0.280085,  0.672501,  0.047413],
+                [-0.011820,  0.042940,  0.968881],
+            ],
+            Self::Tritanopia => [
+                [ 1.255528, -0.076749, -0.178779],
+                [-0.078411,  0.930809,  0.147602],
+                [ 0.004733,  0.691367,  0.303900],
+            ],
+        }
+    }
+}
+
+impl fmt::Display for Colorblindness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Protanopia => "protanopia".fmt(f),
+            Self::Deuteranopia => "deuteranopia".fmt(f),
+            Self::Tritanopia => "tritanopia".fmt(f),
+        }
+    }
+}
+
+impl FromStr for Colorblindness {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "protanopia" => Ok(Self::Protanopia),
+            "deuteranopia" => Ok(Self::Deuteranopia),
+            "tritanopia" => Ok(Self::Tritanopia),
+            _ => Err(format!(
+                "unknown color vision deficiency {:?}, must be \
+                 'deuteranopia', 'protanopia' or 'tritanopia'",
+                s
+            )),
+        }
+    }
+}
+
+/// Maximum number of value bands the display can be posterized into.
+pub const MAX_VALUE_BANDS: u32 = 16;
+
+/// Relative luminance of a color, from `0.0` to `1.0`, using the Rec. 709
+/// luma coefficients on linear values, as used by the screen shader.
+pub fn luminance(c: Rgba8) -> f32 {
+    0.2126 * self::linearize(c.r) + 0.7152 * self::linearize(c.g) + 0.0722 * self::linearize(c.b)
+}
+
+/// Convert an sRGB color component to a linear value.
+fn linearize(c: u8) -> f32 {
+    let c = c as f32 / 255.;
+
+    if c < 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Convert a linear value to an sRGB color component, from `0.0` to `1.0`.
+pub fn encode(value: f32) -> f32 {
+    if value < 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// Count the non-transparent pixels of each luminance range, from darkest to
+/// lightest, using the given number of bins.
+pub fn histogram(pixels: &[Rgba8], bins: usize) -> Vec<u32> {
+    let mut counts = vec![0; bins];
+
+    if bins == 0 {
+        return counts;
+    }
+    for p in pixels.iter().filter(|p| p.a != 0) {
+        let bin = (self::luminance(*p) * bins as f32) as usize;
+        counts[bin.min(bins - 1)] += 1;
+    }
+    counts
+}
+
+/// Quantize a luminance to one of the given number of bands. Bands are
+/// spread evenly from black to white, so that the darkest band is shown as
+/// black and the lightest as white.
+pub fn posterize(value: f32, bands: u32) -> f32 {
+    if bands < 2 {
+        return value;
+    }
+    let band = (value * bands as f32).floor().min(bands as f32 - 1.);
+
+    band / (bands - 1) as f32
+}
+
+const fn rgb(hex: u32) -> Rgba8 {
+    Rgba8::new((hex >> 16) as u8, (hex >> 8) as u8, hex as u8, 0xff)
+}
+
+#[rustfmt::skip]
+const GAMEBOY: &[Rgba8] = &[
+    rgb(0x0f380f), rgb(0x306230), rgb(0x8bac0f), rgb(0x9bbc0f),
+];
+
+#[rustfmt::skip]
+const NES: &[Rgba8] = &[
+    rgb(0x7c7c7c), rgb(0x0000fc), rgb(0x0000bc), rgb(0x4428bc), rgb(0x940084), rgb(0xa80020),
+    rgb(0xa81000), rgb(0x881400), rgb(0x503000), rgb(0x007800), rgb(0x006800), rgb(0x005800),
+    rgb(0x004058), rgb(0x000000), rgb(0xbcbcbc), rgb(0x0078f8), rgb(0x0058f8), rgb(0x6844fc),
+    rgb(0xd800cc), rgb(0xe40058), rgb(0xf83800), rgb(0xe45c10), rgb(0xac7c00), rgb(0x00b800),
+    rgb(0x00a800), rgb(0x00a844), rgb(0x008888), rgb(0xf8f8f8), rgb(0x3cbcfc), rgb(0x6888fc),
+    rgb(0x9878f8), rgb(0xf878f8), rgb(0xf85898), rgb(0xf87858), rgb(0xfca044), rgb(0xf8b800),
+    rgb(0xb8f818), rgb(0x58d854), rgb(0x58f898), rgb(0x00e8d8), rgb(0x787878), rgb(0xfcfcfc),
+    rgb(0xa4e4fc), rgb(0xb8b8f8), rgb(0xd8b8f8), rgb(0xf8b8f8), rgb(0xf8a4c0), rgb(0xf0d0b0),
+    rgb(0xfce0a8), rgb(0xf8d878), rgb(0xd8f878), rgb(0xb8f8b8), rgb(0xb8f8d8), rgb(0x00fcfc),
+    rgb(0xf8d8f8),
+];
+
+#[rustfmt::skip]
+const C64: &[Rgba8] = &[
+    rgb(0x000000), rgb(0xffffff), rgb(0x68372b), rgb(0x70a4b2),
+    rgb(0x6f3d86), rgb(0x588d43), rgb(0x352879), rgb(0xb8c76f),
+    rgb(0x6f4f25), rgb(0x433900), rgb(0x9a6759), rgb(0x444444),
+    rgb(0x6c6c6c), rgb(0x9ad284), rgb(0x6c5eb5), rgb(0x959595),
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let pixels = [
+            Rgba8::BLACK,
+            Rgba8::WHITE,
+            Rgba8::WHITE,
+            Rgba8::TRANSPARENT,
+            Rgba8::new(0x80, 0x80, 0x80, 0xff),
+        ];
+        // Mid-grey is about a fifth as luminous as white.
+        assert_eq!(histogram(&pixels, 4), vec![2, 0, 0, 2]);
+        assert_eq!(histogram(&pixels, 0), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn test_luminance() {
+        assert_eq!(luminance(Rgba8::BLACK), 0.);
+        assert!((luminance(Rgba8::WHITE) - 1.).abs() < 1e-6);
+        assert!((luminance(Rgba8::new(0x80, 0x80, 0x80, 0xff)) - 0.216).abs() < 1e-3);
+
+        for c in [0x00, 0x20, 0x80, 0xff] {
+            assert_eq!((encode(linearize(c)) * 255.).round() as u8, c);
+        }
+    }
+
+    #[test]
+    fn test_posterize() {
+        assert_eq!(posterize(0.0, 3), 0.0);
+        assert_eq!(posterize(0.5, 3), 0.5);
+        assert_eq!(posterize(1.0, 3), 1.0);
+        assert_eq!(posterize(0.3, 2), 0.0);
+        assert_eq!(posterize(0.3, 0), 0.3);
+    }
+
+    #[test]
+    fn test_colorblindness_preserves_white() {
+        for c in [
+            Colorblindness::Protanopia,
+            Colorblindness::Deuteranopia,
+            Colorblindness::Tritanopia,
+        ] {
+            for row in c.matrix() {
+                assert!((row.iter().sum::<f32>() - 1.).abs() < 1e-3, "{}", c);
+            }
+        }
+    }
+}
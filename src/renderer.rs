@@ -3,8 +3,41 @@ use crate::execution::Execution;
 use crate::platform::{self, LogicalSize};
 use crate::session::{self, Effect, Session};
 
+use std::fmt;
+use std::str::FromStr;
 use std::time;
 
+/// Rendering backend, selected at startup.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// OpenGL 3.3, via `luminance`.
+    #[default]
+    Gl,
+    /// Software rendering on the CPU. Nothing is shown in the window.
+    Soft,
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Gl => write!(f, "gl"),
+            Self::Soft => write!(f, "soft"),
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gl" => Ok(Self::Gl),
+            "soft" => Ok(Self::Soft),
+            _ => Err(format!("unknown backend '{}', expected 'gl' or 'soft'", s)),
+        }
+    }
+}
+
 pub trait Renderer<'a>: std::marker::Sized {
     type Error;
 
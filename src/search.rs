@@ -0,0 +1,175 @@
+//! Searchable command palette, listing commands and key bindings, or the
+//! command history.
+use crate::autocomplete::fuzzy_match;
+use crate::cmd::Command;
+
+/// What happens when an entry is chosen.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Run the named command. If it requires arguments, it is instead
+    /// opened in the command line.
+    Command(&'static str),
+    /// Run the command of a key binding.
+    Binding(Command),
+    /// Run a command line, eg. from the command history.
+    Line(String),
+}
+
+/// A searchable entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Command name or key, eg. `f/add` or `<return>`.
+    pub name: String,
+    /// Description of the entry.
+    pub help: String,
+    pub action: Action,
+}
+
+impl Entry {
+    pub fn new(name: impl Into<String>, help: impl Into<String>, action: Action) -> Self {
+        Self {
+            name: name.into(),
+            help: help.into(),
+            action,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Search {
+    /// Search input.
+    pub input: String,
+    /// All entries, unfiltered.
+    entries: Vec<Entry>,
+    /// Indices of the entries matching the input, best match first.
+    matches: Vec<usize>,
+    /// Index into the matches of the highlighted entry.
+    pub selected: usize,
+}
+
+impl Search {
+    /// Maximum number of matches shown at once.
+    pub const MAX_ROWS: usize = 16;
+
+    pub fn new(entries: Vec<Entry>) -> Self {
+        let mut search = Self {
+            entries,
+            ..Self::default()
+        };
+        search.update();
+        search
+    }
+
+    pub fn putc(&mut self, c: char) {
+        self.input.push(c);
+        self.update();
+    }
+
+    pub fn delc(&mut self) {
+        self.input.pop();
+        self.update();
+    }
+
+    /// Highlight the next match, wrapping around.
+    pub fn next(&mut self) {
+        if !self.matches.is_empty() {
+            self.selected = (self.selected + 1) % self.matches.len();
+        }
+    }
+
+    /// Highlight the previous match, wrapping around.
+    pub fn prev(&mut self) {
+        if !self.matches.is_empty() {
+            self.selected = (self.selected + self.matches.len() - 1) % self.matches.len();
+        }
+    }
+
+    /// Entries matching the input, best match first.
+    pub fn matches(&self) -> impl Iterator<Item = &Entry> + '_ {
+        self.matches.iter().map(move |i| &self.entries[*i])
+    }
+
+    /// The highlighted entry, if any.
+    pub fn selected(&self) -> Option<&Entry> {
+        self.matches.get(self.selected).map(|i| &self.entries[*i])
+    }
+
+    /// Re-compute the matches. Entries are matched on their name, and
+    /// if that fails, on their description.
+    fn update(&mut self) {
+        let input = self.input.to_lowercase();
+        let mut matches: Vec<_> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, e)| {
+                fuzzy_match(&input, &e.name.to_lowercase())
+                    .or_else(|| {
+                        // Prefer name matches over description matches.
+                        fuzzy_match(&input, &e.help.to_lowercase()).map(|s| s + e.name.len() + 1)
+                    })
+                    .map(|score| (score, i))
+            })
+            .collect();
+
+        // Sorting is stable, so entries with equal scores keep their order.
+        matches.sort_by_key(|(score, _)| *score);
+
+        self.matches = matches.into_iter().map(|(_, i)| i).collect();
+        self.selected = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_search() {
+        let mut search = Search::new(vec![
+            Entry::new("f/add", "Add a blank frame", Action::Command("f/add")),
+            Entry::new(
+                "f/remove",
+                "Remove the last frame",
+                Action::Command("f/remove"),
+            ),
+            Entry::new(
+                "<return>",
+                "Add a blank frame",
+                Action::Binding(Command::FrameAdd),
+            ),
+            Entry::new("undo", "Undo the last edit", Action::Command("undo")),
+        ]);
+        assert_eq!(search.matches().count(), 4);
+
+        for c in "frem".chars() {
+            search.putc(c);
+        }
+        assert_eq!(
+            search
+                .matches()
+                .map(|e| e.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["f/remove"]
+        );
+
+        search.input.clear();
+        for c in "add".chars() {
+            search.putc(c);
+        }
+        assert_eq!(
+            search
+                .matches()
+                .map(|e| e.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["f/add", "<return>"]
+        );
+
+        search.next();
+        assert_eq!(search.selected().map(|e| e.name.as_str()), Some("<return>"));
+        search.next();
+        assert_eq!(search.selected().map(|e| e.name.as_str()), Some("f/add"));
+        search.prev();
+        assert_eq!(search.selected().map(|e| e.name.as_str()), Some("<return>"));
+    }
+}
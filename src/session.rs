@@ -2,22 +2,39 @@
 //! Session
 use crate::autocomplete::FileCompleter;
 use crate::brush::*;
-use crate::cmd::{self, Command, CommandLine, KeyMapping, Op, Value};
+use crate::cmd::{self, Axis, Command, CommandLine, KeyMapping, Op, Value};
+use crate::collab::{self, Collab};
 use crate::color;
+use crate::config;
 use crate::data;
 use crate::event::{Event, TimedEvent};
 use crate::execution::{DigestMode, DigestState, Execution};
 use crate::flood::FloodFiller;
+use crate::font::Font;
+use crate::fuzz;
 use crate::hashmap;
+use crate::history;
+use crate::hook::Hooks;
+use crate::lut;
 use crate::palette::*;
+use crate::panel::{Drag, Panel};
+use crate::parser;
+use crate::pattern::Pattern;
+use crate::pixels::ScaleFilter;
 use crate::platform::{self, InputState, Key, KeyboardInput, LogicalSize, ModifiersState};
+use crate::plugin::{self, Plugin, PluginResult, Plugins};
+use crate::preview::{Colorblindness, HardwarePalette};
+use crate::search::{self, Search};
+use crate::theme::{Theme, ThemeChoice};
+use crate::timelapse::Timelapse;
 use crate::util;
 use crate::view::path;
 use crate::view::resource::ViewResource;
 use crate::view::{
-    self, FileStatus, FileStorage, View, ViewCoords, ViewExtent, ViewId, ViewManager, ViewOp,
-    ViewState,
+    self, Edit, EditId, FileStatus, FileStorage, Resource, SaveJob, SaveResult, Saver, View,
+    ViewCoords, ViewExtent, ViewId, ViewManager, ViewOp, ViewState,
 };
+use crate::workspace::Workspaces;
 
 use crate::gfx::math::*;
 use crate::gfx::rect::Rect;
@@ -29,15 +46,18 @@ use arrayvec::ArrayVec;
 use directories as dirs;
 use nonempty::NonEmpty;
 
-use std::collections::{HashMap, HashSet};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::convert::TryFrom;
 use std::fmt;
 use std::fs::File;
 use std::io;
-use std::io::Write;
+use std::io::{BufRead, Write};
 
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
 use std::time;
 
 /// Settings help string.
@@ -45,14 +65,55 @@ pub const SETTINGS: &str = r#"
 SETTINGS
 
 debug             on/off             Debug mode
+input/timeout     0..10000           Time allowed between the keys of a key sequence (ms)
+input/gestures    on/off             Trackpad scrolling pans, and pinching (or <ctrl> + scrolling) zooms
 checker           on/off             Alpha checker toggle
-scale             1.0..4.0           UI scale
+scale             1.0..4.0           UI scale, fractional scales are supported
+ui/text-scale     1..4               Scale of UI text only, on top of `scale`
+font              "<path>"           UI font, as a .uf2 file ("" = built-in)
 animation         on/off             View animation toggle
 animation/delay   1..1000            View animation delay (ms)
+reference/opacity 0.0..1.0           Opacity of the reference animation set with `:reference/anim`
+history/checkpoint 0..3600           Seconds between view checkpoints used by `:back` (0 = off)
+history/limit     <n>                Edits each view keeps for undo, oldest are discarded first (0 = no limit)
+history/palette   on/off             Undo palette edits, eg. `:p/clear`, along with view edits
+history/selection on/off             Undo selection changes made in visual mode
 background        #000000..#ffffff   Set background appearance to <color>
 grid              on/off             Grid display
 grid/color        #000000..#ffffff   Grid color
 grid/spacing      <x> <y>            Grid spacing
+grid/major        0..1024            Major grid line every <n> cells (0 = off)
+grid/pixels       on/off             Pixel grid display, when zoomed in
+grid/pixels-zoom  1..128             Zoom level above which the pixel grid is shown
+grid/pixels-color #000000..#ffffff   Pixel grid color
+guide/color       #000000..#ffffff   Guide color
+brush/opacity     0..255             Opacity of brush strokes, which are blended onto the view
+brush/stabilize   0..64              Number of recent cursor positions averaged to smooth strokes (0 = off)
+brush/grid        on/off             Paint whole grid cells, when the grid is shown
+paint/all-frames  on/off             Paint brush strokes onto every frame of the view, at the same position
+clip/selection    on/off             Only paint and fill within the last selection made in visual mode
+snap              on/off             Snap brush, selection and paste to guides (<alt> to bypass)
+snap/distance     0..64              Maximum distance to a guide when snapping, in pixels
+layout            <layout>           View layout: vertical, horizontal, grid or free (<super> + drag moves views)
+resize/lock-aspect on/off            Preserve aspect ratio when resizing selection or frames (<shift> to lock)
+export/indexed    on/off             Export PNGs as indexed images, using the palette
+export/depth      0/1/2/4/8          Bit depth of indexed PNG exports (0 = smallest that fits)
+pan/smooth        on/off             Pan smoothly while a `:pan` key is held
+pan/inertia       on/off             Ease smooth panning in and out
+ui/settings       on/off             Settings panel display
+ui/crosshair      on/off             Full-width/height crosshair at the cursor
+ui/coords         on/off             Coordinates of the pixel under the cursor, next to the cursor
+ui/tooltip        on/off             Coordinates, color and palette index of the pixel under the cursor
+ui/brush-outline  on/off             Brush footprint outline at the cursor
+ui/brush-hud      on/off             Brush size and footprint at the cursor, shortly after the size changes
+ui/fill-preview   on/off             Translucent preview of the flood fill under the cursor
+ui/histogram      on/off             Luminance histogram of the active view, in the bottom-right corner
+ui/preview        on/off             Looping preview of the active view's animation, in the top-right corner
+ui/preview-scale  1..16              Scale of the animation preview
+view/fps          0..1000            Active view frame rate (0 = use animation/delay)
+timelapse/interval 1..1000          Capture a timelapse frame every <n> rendered frames
+hook/write        "<command>"        Shell command run after a view is written, `{}` is the path
+collab/bind       "<address>"        Address `:collab/host` listens on ("0.0.0.0" = all interfaces)
 "#;
 
 #[derive(Copy, Clone, Debug)]
@@ -82,6 +143,9 @@ pub enum Mode {
     Present,
     /// Activated with the `:help` command.
     Help,
+    /// Searchable list of commands and key bindings.
+    /// Activated with the `:search` command.
+    Search,
 }
 
 impl fmt::Display for Mode {
@@ -94,6 +158,7 @@ impl fmt::Display for Mode {
             Self::Command => "command".fmt(f),
             Self::Present => "present".fmt(f),
             Self::Help => "help".fmt(f),
+            Self::Search => "search".fmt(f),
         }
     }
 }
@@ -162,6 +227,194 @@ impl Deref for Selection {
     }
 }
 
+/// A per-pixel selection, eg. of all pixels of a given color. Masks are only
+/// applied while the selection they were made with is unchanged.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct Mask {
+    /// View the mask belongs to.
+    pub view: ViewId,
+    /// Selection enclosing the masked pixels.
+    pub selection: Selection,
+    /// Whether each pixel within the selection bounds is masked, bottom row first.
+    pixels: Vec<bool>,
+}
+
+impl Mask {
+    /// Create a mask of the pixels within `area` for which `f` returns `true`.
+    /// The selection is shrunk to fit the masked pixels. Returns `None` if no
+    /// pixels are masked.
+    pub fn from_fn(view: ViewId, area: Rect<i32>, f: impl Fn(i32, i32) -> bool) -> Option<Self> {
+        let points: Vec<_> = (area.y1..area.y2)
+            .flat_map(|y| (area.x1..area.x2).map(move |x| (x, y)))
+            .filter(|(x, y)| f(*x, *y))
+            .collect();
+
+        let x1 = points.iter().map(|(x, _)| *x).min()?;
+        let x2 = points.iter().map(|(x, _)| *x).max()?;
+        let y1 = points.iter().map(|(_, y)| *y).min()?;
+        let y2 = points.iter().map(|(_, y)| *y).max()?;
+
+        let selection = Selection::new(x1, y1, x2 + 1, y2 + 1);
+        let mut mask = Self {
+            view,
+            selection,
+            pixels: vec![false; ((x2 - x1 + 1) * (y2 - y1 + 1)) as usize],
+        };
+        for (x, y) in points {
+            let i = mask.index(x, y);
+            mask.pixels[i] = true;
+        }
+        Some(mask)
+    }
+
+    /// Create a mask of the pixels matching the given color, in a view of the
+    /// given size. Pixels are ordered top row first. Returns `None` if no pixels
+    /// match.
+    pub fn from_color(
+        view: ViewId,
+        w: u32,
+        h: u32,
+        pixels: &[Rgba8],
+        color: Rgba8,
+    ) -> Option<Self> {
+        Self::from_fn(view, Rect::origin(w as i32, h as i32), |x, y| {
+            pixels[(h as i32 - y - 1) as usize * w as usize + x as usize] == color
+        })
+    }
+
+    /// Create a mask of all the pixels of a rectangle.
+    pub fn from_rect(view: ViewId, r: Rect<i32>) -> Option<Self> {
+        Self::from_fn(view, r, |_, _| true)
+    }
+
+    /// Grow the mask by `n` pixels in all directions, including diagonals,
+    /// within the given bounds.
+    pub fn grow(&self, n: u32, bounds: Rect<i32>) -> Option<Self> {
+        let mut mask = self.clone();
+
+        for _ in 0..n {
+            let area = mask
+                .selection
+                .bounds()
+                .expand(1, 1, 1, 1)
+                .intersection(bounds);
+            let grown = Self::from_fn(self.view, area, |x, y| mask.neighbours(x, y).any(|m| m))?;
+
+            mask = grown;
+        }
+        Some(mask)
+    }
+
+    /// Shrink the mask by `n` pixels in all directions, including diagonals.
+    /// Returns `None` if no pixels are left.
+    pub fn shrink(&self, n: u32) -> Option<Self> {
+        let mut mask = self.clone();
+
+        for _ in 0..n {
+            let area = mask.selection.bounds();
+            let shrunk = Self::from_fn(self.view, area, |x, y| mask.neighbours(x, y).all(|m| m))?;
+
+            mask = shrunk;
+        }
+        Some(mask)
+    }
+
+    /// Mask the pixels within the given bounds that aren't masked, and unmask
+    /// the ones that are. Returns `None` if all pixels were masked.
+    pub fn invert(&self, bounds: Rect<i32>) -> Option<Self> {
+        Self::from_fn(self.view, bounds, |x, y| !self.contains(x, y))
+    }
+
+    /// Whether all pixels within the selection are masked, ie. the mask is
+    /// a plain rectangle.
+    pub fn is_full(&self) -> bool {
+        self.pixels.iter().all(|p| *p)
+    }
+
+    /// Whether the given pixel, in view coordinates, is masked.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        self.selection.bounds().contains(Point2::new(x, y)) && self.pixels[self.index(x, y)]
+    }
+
+    /// Constrain the given shapes, in view coordinates, to the masked pixels.
+    /// Rectangles are split into pixels, of which only the masked ones are kept.
+    pub fn clip(&self, shapes: Vec<Shape>) -> Vec<Shape> {
+        let mut clipped = Vec::new();
+
+        for shape in shapes {
+            match shape {
+                Shape::Rectangle(r, z, rotation, stroke, fill) => {
+                    let (x1, x2) = (r.x1.min(r.x2).floor() as i32, r.x1.max(r.x2).ceil() as i32);
+                    let (y1, y2) = (r.y1.min(r.y2).floor() as i32, r.y1.max(r.y2).ceil() as i32);
+
+                    for y in y1..y2 {
+                        for x in (x1..x2).filter(|x| self.contains(*x, y)) {
+                            let (x, y) = (x as f32, y as f32);
+
+                            clipped.push(Shape::Rectangle(
+                                Rect::new(x, y, x + 1., y + 1.),
+                                z,
+                                rotation.clone(),
+                                stroke,
+                                fill,
+                            ));
+                        }
+                    }
+                }
+                other => clipped.push(other),
+            }
+        }
+        clipped
+    }
+
+    /// The masked pixels, in view coordinates.
+    pub fn points(&self) -> impl Iterator<Item = Point2<i32>> + '_ {
+        let r = self.selection.bounds();
+
+        (r.y1..r.y2)
+            .flat_map(move |y| (r.x1..r.x2).map(move |x| Point2::new(x, y)))
+            .filter(move |p| self.contains(p.x, p.y))
+    }
+
+    /// The masked pixels, in view coordinates, merged into horizontal runs of
+    /// one pixel in height.
+    pub fn spans(&self) -> Vec<Rect<i32>> {
+        let r = self.selection.bounds();
+        let mut spans = Vec::new();
+
+        for y in r.y1..r.y2 {
+            let mut start = None;
+
+            for x in r.x1..=r.x2 {
+                match (start, x < r.x2 && self.contains(x, y)) {
+                    (None, true) => start = Some(x),
+                    (Some(x1), false) => {
+                        spans.push(Rect::new(x1, y, x, y + 1));
+                        start = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        spans
+    }
+
+    /// Number of masked pixels.
+    pub fn count(&self) -> usize {
+        self.pixels.iter().filter(|p| **p).count()
+    }
+
+    /// Whether each pixel of the 3x3 square centered on the given pixel is masked.
+    fn neighbours(&self, x: i32, y: i32) -> impl Iterator<Item = bool> + '_ {
+        (-1..=1).flat_map(move |dy| (-1..=1).map(move |dx| self.contains(x + dx, y + dy)))
+    }
+
+    fn index(&self, x: i32, y: i32) -> usize {
+        let r = self.selection.bounds();
+        ((y - r.y1) * r.width() + (x - r.x1)) as usize
+    }
+}
+
 /// Session effects. Eg. view creation/destruction.
 /// Anything the renderer might want to know.
 #[derive(Clone, Debug)]
@@ -170,6 +423,8 @@ pub enum Effect {
     SessionResized(LogicalSize),
     /// When the session UI scale has changed.
     SessionScaled(f64),
+    /// When the UI font has changed. `None` means the built-in font.
+    FontChanged(Option<Font>),
     /// When a view has been activated.
     ViewActivated(ViewId),
     /// When a view has been added.
@@ -233,6 +488,81 @@ pub enum Tool {
     Sampler,
     /// Used to pan the workspace.
     Pan(PanState),
+    /// Used to measure the distance between two pixels.
+    Measure(Option<Ruler>),
+}
+
+/// Pixels covered by an erase stroke, as they were before the stroke.
+#[derive(Debug, Clone)]
+struct Erased {
+    /// The view that was erased.
+    view: ViewId,
+    /// The bounds of the stroke.
+    rect: Rect<i32>,
+    /// The pixels covered by the stroke, top row first. Other pixels of the
+    /// area are transparent.
+    pixels: Vec<Rgba8>,
+}
+
+/// Palette and selection, as of the last time their changes were recorded
+/// in the view history.
+#[derive(Debug, Clone, Default)]
+struct Recorded {
+    /// Name of the active palette.
+    palette: String,
+    /// Colors of the active palette.
+    colors: Vec<Rgba8>,
+    selection: Option<Selection>,
+}
+
+/// Preview of the flood fill starting at the pixel under the cursor.
+#[derive(Debug)]
+struct FillPreview {
+    /// View, starting pixel, view edit and fill color the preview is for.
+    key: (ViewId, ViewCoords<u32>, EditId, Rgba8),
+    /// Time spent on the pixel, which delays the fill until the cursor rests.
+    idle: time::Duration,
+    /// Fill shapes, once computed. Empty if the fill wouldn't change anything.
+    shapes: Option<Vec<Shape>>,
+}
+
+/// A view mirroring another, so that the same pixels can be edited in two
+/// places, at different zoom levels. Both views share the same resource, and
+/// thus the same pixels and history.
+#[derive(Debug, Clone, Copy)]
+struct Mirror {
+    source: ViewId,
+    mirror: ViewId,
+    /// Current edit of the shared resource, as of the last sync.
+    edit: EditId,
+}
+
+/// A measurement between two pixels, in view coordinates.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Ruler {
+    pub from: ViewCoords<i32>,
+    pub to: ViewCoords<i32>,
+}
+
+impl Ruler {
+    pub fn new(from: ViewCoords<i32>, to: ViewCoords<i32>) -> Self {
+        Self { from, to }
+    }
+
+    /// Horizontal distance, in pixels.
+    pub fn dx(&self) -> i32 {
+        (self.to.x - self.from.x).abs()
+    }
+
+    /// Vertical distance, in pixels.
+    pub fn dy(&self) -> i32 {
+        (self.to.y - self.from.y).abs()
+    }
+
+    /// Diagonal distance, in pixels.
+    pub fn distance(&self) -> f32 {
+        ((self.dx() * self.dx() + self.dy() * self.dy()) as f32).sqrt()
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
@@ -266,6 +596,36 @@ impl From<Direction> for i32 {
     }
 }
 
+/// How views are arranged in the workspace.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Layout {
+    /// Views are stacked on top of each other.
+    Vertical,
+    /// Views are placed side by side.
+    Horizontal,
+    /// Views are placed in rows, so that the grid is about as wide as it's tall.
+    Grid,
+    /// Views stay where they were placed, and can be moved with `<ctrl>` + drag.
+    Free,
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vertical" => Ok(Self::Vertical),
+            "horizontal" => Ok(Self::Horizontal),
+            "grid" => Ok(Self::Grid),
+            "free" => Ok(Self::Free),
+            _ => Err(format!(
+                "unknown layout {:?}, must be 'vertical', 'horizontal', 'grid' or 'free'",
+                s
+            )),
+        }
+    }
+}
+
 /// A message to the user, displayed in the session.
 pub struct Message {
     /// The message string.
@@ -369,6 +729,8 @@ type Error = String;
 pub enum Input {
     Key(Key),
     Character(char),
+    Mouse(platform::MouseButton),
+    Wheel(Wheel),
 }
 
 impl fmt::Display for Input {
@@ -376,10 +738,23 @@ impl fmt::Display for Input {
         match self {
             Self::Key(k) => write!(f, "{}", k),
             Self::Character(c) => write!(f, "{}", c),
+            Self::Mouse(platform::MouseButton::Left) => write!(f, "<mouse-left>"),
+            Self::Mouse(platform::MouseButton::Right) => write!(f, "<mouse-right>"),
+            Self::Mouse(platform::MouseButton::Middle) => write!(f, "<mouse-middle>"),
+            Self::Mouse(platform::MouseButton::Other(n)) => write!(f, "<mouse-{}>", n),
+            Self::Wheel(Wheel::Up) => write!(f, "<wheel-up>"),
+            Self::Wheel(Wheel::Down) => write!(f, "<wheel-down>"),
         }
     }
 }
 
+/// Mouse wheel direction.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Wheel {
+    Up,
+    Down,
+}
+
 /// A key binding.
 #[derive(PartialEq, Clone, Debug)]
 pub struct KeyBinding {
@@ -389,6 +764,9 @@ pub struct KeyBinding {
     pub modifiers: ModifiersState,
     /// Input expected to trigger the binding.
     pub input: Input,
+    /// Keys which must be pressed, in order, before `input`.
+    /// Empty unless this binding is for a key sequence.
+    pub prefix: Vec<Key>,
     /// Whether the key should be pressed or released.
     pub state: InputState,
     /// The `Command` to run when this binding is triggered.
@@ -426,11 +804,26 @@ impl KeyBinding {
                     && self.state == state
                     && self.modifiers.ctrl == modifiers.ctrl
             }
+            (Input::Mouse(a), Input::Mouse(b)) => {
+                a == b && self.modes.contains(&mode) && self.state == state
+            }
+            (Input::Wheel(a), Input::Wheel(b)) => a == b && self.modes.contains(&mode),
             _ => false,
         }
     }
 }
 
+/// Result of looking up a key sequence.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Sequence {
+    /// The sequence is complete and triggers this binding.
+    Complete(KeyBinding),
+    /// The sequence is the start of at least one binding.
+    Pending,
+    /// The sequence doesn't match any binding.
+    None,
+}
+
 /// Manages a list of key bindings.
 #[derive(Debug, Default)]
 pub struct KeyBindings {
@@ -441,8 +834,10 @@ impl KeyBindings {
     /// Add a key binding.
     pub fn add(&mut self, binding: KeyBinding) {
         for mode in binding.modes.iter() {
-            self.elems
-                .retain(|kb| !kb.is_match(binding.input, binding.state, binding.modifiers, *mode));
+            self.elems.retain(|kb| {
+                kb.prefix != binding.prefix
+                    || !kb.is_match(binding.input, binding.state, binding.modifiers, *mode)
+            });
         }
         self.elems.push(binding);
     }
@@ -456,6 +851,7 @@ impl KeyBindings {
     }
 
     /// Find a key binding based on some input state.
+    /// Key sequence bindings are never returned, see [`KeyBindings::find_sequence`].
     pub fn find(
         &self,
         input: Input,
@@ -467,7 +863,28 @@ impl KeyBindings {
             .iter()
             .rev()
             .cloned()
-            .find(|kb| kb.is_match(input, state, modifiers, mode))
+            .find(|kb| kb.prefix.is_empty() && kb.is_match(input, state, modifiers, mode))
+    }
+
+    /// Look up the key sequence made of the `pending` keys followed by `key`.
+    /// A binding which completes the sequence takes precedence over longer
+    /// bindings starting with it.
+    pub fn find_sequence(&self, pending: &[Key], key: Key, mode: Mode) -> Sequence {
+        let mut result = Sequence::None;
+
+        for kb in self.elems.iter().rev().filter(|kb| {
+            !kb.prefix.is_empty()
+                && kb.state == InputState::Pressed
+                && kb.modes.contains(&mode)
+                && kb.prefix.starts_with(pending)
+        }) {
+            match kb.prefix.get(pending.len()) {
+                None if kb.input == Input::Key(key) => return Sequence::Complete(kb.clone()),
+                Some(k) if *k == key => result = Sequence::Pending,
+                _ => {}
+            }
+        }
+        result
     }
 
     /// Iterate over all key bindings.
@@ -479,7 +896,7 @@ impl KeyBindings {
 ///////////////////////////////////////////////////////////////////////////////
 
 /// A dictionary used to store session settings.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Settings {
     map: HashMap<String, Value>,
 }
@@ -492,6 +909,14 @@ impl Settings {
         self.map.get(setting)
     }
 
+    /// Names of all settings, excluding deprecated ones.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.map
+            .keys()
+            .map(|k| k.as_str())
+            .filter(|k| !Self::DEPRECATED.contains(k))
+    }
+
     /// Set an existing setting to a new value. Returns `Err` if there is a type
     /// mismatch or the setting isn't found. Otherwise, returns `Ok` with the
     /// old value.
@@ -521,19 +946,61 @@ impl Default for Settings {
                 "checker" => Value::Bool(false),
                 "background" => Value::Rgba8(color::TRANSPARENT),
                 "input/mouse" => Value::Bool(true),
+                "input/gestures" => Value::Bool(true),
+                "input/timeout" => Value::U32(1000),
                 "scale" => Value::F64(1.0),
+                "font" => Value::Str(String::new()),
                 "animation" => Value::Bool(true),
                 "animation/delay" => Value::U32(160),
+                "reference/opacity" => Value::F64(0.5),
+                "history/checkpoint" => Value::U32(60),
+                "history/limit" => Value::U32(0),
+                "history/palette" => Value::Bool(true),
+                "history/selection" => Value::Bool(false),
                 "ui/palette" => Value::Bool(true),
                 "ui/status" => Value::Bool(true),
                 "ui/cursor" => Value::Bool(true),
                 "ui/message" => Value::Bool(true),
                 "ui/switcher" => Value::Bool(true),
                 "ui/view-info" => Value::Bool(true),
+                "ui/settings" => Value::Bool(false),
+                "view/fps" => Value::U32(0),
+                "ui/text-scale" => Value::U32(1),
+                "ui/crosshair" => Value::Bool(false),
+                "ui/coords" => Value::Bool(false),
+                "ui/tooltip" => Value::Bool(false),
+                "ui/brush-outline" => Value::Bool(false),
+                "ui/fill-preview" => Value::Bool(true),
+                "ui/brush-hud" => Value::Bool(true),
+                "ui/histogram" => Value::Bool(false),
+                "ui/preview" => Value::Bool(false),
+                "ui/preview-scale" => Value::U32(1),
 
                 "grid" => Value::Bool(false),
                 "grid/color" => Value::Rgba8(color::BLUE),
                 "grid/spacing" => Value::U32Tuple(8, 8),
+                "grid/major" => Value::U32(0),
+                "grid/pixels" => Value::Bool(true),
+                "grid/pixels-zoom" => Value::U32(8),
+                "grid/pixels-color" => Value::Rgba8(color::DARK_GREY),
+
+                "guide/color" => Value::Rgba8(color::LIGHT_GREEN),
+                "snap" => Value::Bool(false),
+                "snap/distance" => Value::U32(2),
+                "brush/opacity" => Value::U32(255),
+                "brush/stabilize" => Value::U32(0),
+                "brush/grid" => Value::Bool(false),
+                "paint/all-frames" => Value::Bool(false),
+                "clip/selection" => Value::Bool(false),
+                "layout" => Value::Ident(String::from("vertical")),
+                "resize/lock-aspect" => Value::Bool(false),
+                "export/indexed" => Value::Bool(false),
+                "export/depth" => Value::U32(0),
+                "pan/smooth" => Value::Bool(true),
+                "pan/inertia" => Value::Bool(false),
+                "timelapse/interval" => Value::U32(10),
+                "hook/write" => Value::Str(String::new()),
+                "collab/bind" => Value::Str(String::from("127.0.0.1")),
 
                 "p/height" => Value::U32(Session::PALETTE_HEIGHT),
 
@@ -573,6 +1040,9 @@ pub struct Session {
     pub width: f32,
     /// The height of the session workspace.
     pub height: f32,
+    /// The size of the window, in logical pixels. Differs from the workspace
+    /// size when the session is scaled.
+    window: LogicalSize,
     /// The current working directory.
     pub cwd: PathBuf,
 
@@ -583,6 +1053,8 @@ pub struct Session {
     pub hover_color: Option<Rgba8>,
     /// The view under the cursor, if any.
     pub hover_view: Option<ViewId>,
+    /// Time left to display the brush size at the cursor, after it changed.
+    pub brush_hud: time::Duration,
 
     /// The workspace offset. Views are offset by this vector.
     pub offset: Vector2<f32>,
@@ -595,6 +1067,8 @@ pub struct Session {
     pub fg: Rgba8,
     /// The session background color.
     pub bg: Rgba8,
+    /// Recently used foreground colors, most recent first.
+    pub color_history: VecDeque<Rgba8>,
 
     /// The current frame number.
     frame_number: u64,
@@ -610,30 +1084,106 @@ pub struct Session {
     keys_pressed: HashSet<platform::Key>,
     /// The list of all active key bindings.
     pub key_bindings: KeyBindings,
+    /// Keys of a key sequence typed so far, and when the last one was pressed.
+    key_sequence: (Vec<platform::Key>, time::Instant),
+    /// Keys held for smooth panning, and their pan direction.
+    pan_keys: HashMap<platform::Key, (i32, i32)>,
+    /// Smooth panning velocity, in pixels per second.
+    pan_velocity: Vector2<f32>,
+    /// Sub-pixel panning distance not yet applied to the offset.
+    pan_remainder: Vector2<f32>,
+    /// Saves views in the background.
+    saver: Saver,
+    /// Runs shell hooks, eg. `hook/write`.
+    hooks: Hooks,
+    /// Runs plugins in the background.
+    plugins: Plugins,
 
     /// Current pixel selection.
     pub selection: Option<Selection>,
+    /// Per-pixel mask of the current selection, if any.
+    pub mask: Option<Mask>,
+    /// Horizontal and vertical guides, in view coordinates.
+    pub guides: Vec<(Axis, u32)>,
+    /// The last selection made in visual mode, which painting is constrained
+    /// to when `clip/selection` is on.
+    clip: Option<Mask>,
 
     /// The session's current settings.
     pub settings: Settings,
     /// Settings recently changed.
     pub settings_changed: HashSet<String>,
+    /// Settings as configured on startup, before the generated config is sourced.
+    /// Only settings that differ from these are saved.
+    settings_init: Settings,
+    /// Key mappings made from the command line, or sourced from the generated config.
+    mappings: Vec<String>,
+    /// Project script that was found but not sourced, since it isn't trusted,
+    /// along with the contents that were read.
+    untrusted: Option<(PathBuf, Vec<u8>)>,
+    /// Palette file of the project being edited, if any. This is where the
+    /// palette is written to by default.
+    project_palette: Option<PathBuf>,
 
     /// Views loaded in the session.
-    pub views: ViewManager<ViewResource>,
+    pub views: ViewManager<Resource>,
     /// Effects produced by the session. Cleared at the beginning of every
     /// update.
     pub effects: Vec<Effect>,
-    /// Animation frame time accumulator.
-    pub accumulator: time::Duration,
+    /// Animation clock, shared by all views, so that views with the same
+    /// frame rate animate in step.
+    animation_clock: time::Duration,
 
     /// The current state of the command line.
     pub cmdline: CommandLine,
     /// The color palette.
     pub palette: Palette,
+    /// The named palettes, one of which is active.
+    pub palettes: Palettes,
+    /// View designated as the lookup texture, if any.
+    pub lut: Option<ViewId>,
+    /// View whose animation is overlaid on the active view, if any.
+    pub reference: Option<ViewId>,
+    /// View overlaid on the active view with `:compare`, to spot differences.
+    pub compare: Option<ViewId>,
+    /// Whether the compared view is shown. Toggled with `:compare`.
+    pub comparing: bool,
+    /// Views mirroring other views.
+    mirrors: Vec<Mirror>,
+    /// View workspaces. Only the views of the active workspace are displayed.
+    pub workspaces: Workspaces,
+    /// The settings panel.
+    pub panel: Panel,
+    /// The UI color theme.
+    pub theme: Theme,
+    /// The command search, active in search mode.
+    pub search: Search,
 
     /// Average time it takes for a session update.
     pub avg_time: time::Duration,
+    /// Whether the session changed visually since the last frame, and needs
+    /// to be redrawn. Reset at the end of every frame.
+    pub dirty: bool,
+    /// Timelapse being recorded, if any.
+    pub timelapse: Option<Timelapse>,
+    /// Collaborative editing session, if any.
+    pub collab: Option<Collab>,
+    /// Path to the fragment shader views are displayed through, if any.
+    pub filter: Option<PathBuf>,
+    /// Modification time of the view filter source, when it was last checked.
+    filter_modified: Option<time::SystemTime>,
+    /// Time since the view filter source was last checked for changes.
+    filter_checked: time::Duration,
+    /// Whether the view filter depends on time, and must be redrawn every frame.
+    filter_animated: bool,
+    /// Hardware palette displayed colors are remapped to, if any.
+    pub preview_palette: Option<HardwarePalette>,
+    /// Color vision deficiency simulated on display, if any.
+    pub preview_colorblind: Option<Colorblindness>,
+    /// Number of value bands the display is posterized into, if any.
+    pub preview_values: Option<u32>,
+    /// When view checkpoints were last taken.
+    checkpoint_time: time::Instant,
 
     /// The current tool. Only used in `Normal` mode.
     pub tool: Tool,
@@ -644,6 +1194,26 @@ pub struct Session {
 
     /// Input state of the mouse.
     mouse_state: InputState,
+    /// Mouse button bound to a tool, which is used in place of the left button
+    /// while it's held.
+    mouse_tool: Option<platform::MouseButton>,
+    /// Pixels removed by the last erase stroke, if any.
+    erased: Option<Erased>,
+    /// Palette and selection changes are recorded relative to this.
+    recorded: Recorded,
+    /// Flood fill preview under the cursor, if any.
+    fill_preview: Option<FillPreview>,
+    /// Cursor position and selection at the start of a selection drag.
+    selection_drag: Option<(ViewCoords<i32>, Selection)>,
+    /// Width and height of the selection when its aspect ratio was locked,
+    /// while selecting.
+    selection_aspect: Option<(i32, i32)>,
+    /// View being moved, and where it was grabbed, relative to its origin.
+    view_drag: Option<(ViewId, Vector2<f32>)>,
+    /// Size of the paste buffer.
+    paste_size: (i32, i32),
+    /// Corner of the floating selection that stays in place while scaling it.
+    paste_anchor: Option<Point2<i32>>,
 
     /// Internal command bus. Used to send internal messages asynchronously.
     /// We do this when we want the renderer to have a chance to run before
@@ -664,16 +1234,50 @@ impl Session {
     const VIEW_MARGIN: f32 = 24.;
     /// Size of palette cells, in pixels.
     const PALETTE_CELL_SIZE: f32 = 24.;
+    /// Width of the settings panel, in pixels.
+    const PANEL_WIDTH: f32 = 200.;
+    /// Height of a settings panel row, in pixels.
+    const PANEL_ROW_HEIGHT: f32 = 18.;
+    /// Margin between the settings panel and the edge of the workspace.
+    const PANEL_MARGIN: f32 = 10.;
     /// Default palette height in cells.
     const PALETTE_HEIGHT: u32 = 16;
+    /// Maximum number of colors in the foreground color history.
+    pub const COLOR_HISTORY: usize = 8;
     /// Distance to pan when using keyboard.
     const PAN_PIXELS: i32 = 32;
+    /// Speed of smooth panning when holding a pan key, in pixels per second.
+    const PAN_SPEED: f32 = 768.;
+    /// Rate at which smooth panning reaches its speed, or comes to a halt,
+    /// when `pan/inertia` is set. Higher is snappier.
+    const PAN_EASING: f32 = 8.;
     /// Minimum brush size.
     const MIN_BRUSH_SIZE: usize = 1;
     /// Maximum frame width or height.
     const MAX_FRAME_SIZE: u32 = 4096;
+    /// Number of colors listed by the `stats` command.
+    const STATS_COLORS: usize = 8;
+    /// Time the cursor rests on a pixel before the flood fill preview is computed.
+    const FILL_PREVIEW_DELAY: time::Duration = time::Duration::from_millis(80);
+    /// Time the brush size is displayed at the cursor, after it changed.
+    const BRUSH_HUD_DURATION: time::Duration = time::Duration::from_millis(1000);
+    /// Interval at which the view filter source is checked for changes.
+    const FILTER_POLL_INTERVAL: time::Duration = time::Duration::from_millis(250);
+    /// Interval at which views are redrawn when the view filter depends on time.
+    const FILTER_FRAME_INTERVAL: time::Duration = time::Duration::from_millis(16);
+    /// Opacity of the flood fill preview.
+    const FILL_PREVIEW_OPACITY: f32 = 0.5;
     /// Maximum zoom amount as a multiplier.
     const MAX_ZOOM: f32 = 128.0;
+    /// Workspace pixels panned per unit of touchpad scroll.
+    const TOUCHPAD_PAN_SPEED: f32 = 8.;
+    /// Zoom factor exponent per unit of touchpad pinch.
+    const PINCH_ZOOM_SPEED: f32 = 0.1;
+    /// Zoom factor exponent per notch of `<ctrl>` + mouse wheel.
+    const WHEEL_ZOOM_SPEED: f32 = 0.25;
+    /// Smooth zoom snaps to whole levels within this distance, so that
+    /// pixels line up with the screen again.
+    const ZOOM_SNAP: f32 = 0.05;
     /// Zoom levels used when zooming in/out.
     const ZOOM_LEVELS: &'static [f32] = &[
         1.,
@@ -692,6 +1296,14 @@ impl Session {
         Self::MAX_ZOOM,
     ];
 
+    /// Directory in which view filters are looked up, inside the config directory.
+    const FILTERS: &'static str = "filters";
+
+    /// Settings saved in archives.
+    const ARCHIVE_SETTINGS: &'static [&'static str] = &["animation/delay", "grid", "grid/spacing"];
+    /// Default path of timelapse recordings.
+    const TIMELAPSE: &'static str = "timelapse.gif";
+
     /// Name of rx initialization script.
     const INIT: &'static str = "init.rx";
 
@@ -704,12 +1316,14 @@ impl Session {
         base_dirs: dirs::BaseDirs,
     ) -> Self {
         let history_path = proj_dirs.data_dir().join("history");
+        let palettes_dir = proj_dirs.config_dir().join(crate::palette::DIR);
         let cwd = cwd.as_ref().to_path_buf();
 
         Self {
             state: State::Initializing,
             width: w as f32,
             height: h as f32,
+            window: LogicalSize::new(w as f64, h as f64),
             cwd: cwd.clone(),
             cursor: SessionCoords::new(0., 0.),
             base_dirs,
@@ -719,26 +1333,72 @@ impl Session {
             tool: Tool::default(),
             prev_tool: Option::default(),
             mouse_state: InputState::Released,
+            mouse_tool: None,
+            erased: None,
+            recorded: Recorded::default(),
+            fill_preview: None,
+            selection_drag: None,
+            selection_aspect: None,
+            view_drag: None,
+            paste_size: (0, 0),
+            paste_anchor: None,
             hover_color: Option::default(),
             hover_view: Option::default(),
+            brush_hud: time::Duration::ZERO,
             fg: color::WHITE,
             bg: color::BLACK,
+            color_history: VecDeque::with_capacity(Self::COLOR_HISTORY),
             brush: Brush::default(),
             settings: Settings::default(),
+            settings_init: Settings::default(),
+            mappings: Vec::new(),
+            untrusted: None,
+            project_palette: None,
             settings_changed: HashSet::new(),
             views: ViewManager::new(),
             effects: Vec::new(),
-            accumulator: time::Duration::from_secs(0),
+            animation_clock: time::Duration::ZERO,
             palette: Palette::new(Self::PALETTE_CELL_SIZE, Self::PALETTE_HEIGHT as usize),
+            palettes: Palettes::new(palettes_dir),
+            lut: None,
+            reference: None,
+            compare: None,
+            comparing: false,
+            mirrors: Vec::new(),
+            workspaces: Workspaces::new(),
+            panel: Panel::new(Self::PANEL_WIDTH, Self::PANEL_ROW_HEIGHT),
+            theme: ThemeChoice::default().theme(),
+            search: Search::default(),
             key_bindings: KeyBindings::default(),
             keys_pressed: HashSet::new(),
+            key_sequence: (Vec::new(), time::Instant::now()),
+            pan_keys: HashMap::new(),
+            pan_velocity: Vector2::zero(),
+            pan_remainder: Vector2::zero(),
+            saver: Saver::new(),
+            hooks: Hooks::new(),
+            plugins: Plugins::new(),
+            dirty: true,
+            timelapse: None,
+            collab: None,
+            filter: None,
+            filter_modified: None,
+            filter_checked: time::Duration::ZERO,
+            filter_animated: false,
+            preview_palette: None,
+            preview_colorblind: None,
+            preview_values: None,
             ignore_received_characters: false,
             cmdline: CommandLine::new(cwd, history_path, path::SUPPORTED_READ_FORMATS),
             mode: Mode::Normal,
             prev_mode: Option::default(),
             selection: Option::default(),
+            mask: Option::default(),
+            clip: Option::default(),
+            guides: Vec::new(),
             message: Message::default(),
             avg_time: time::Duration::from_secs(0),
+            checkpoint_time: time::Instant::now(),
             frame_number: 0,
             queue: Vec::new(),
         }
@@ -754,6 +1414,7 @@ impl Session {
             if init.as_os_str() != "-" {
                 self.source_path(&init)?;
             }
+            self.settings_init = self.settings.clone();
         } else {
             let dir = self.proj_dirs.config_dir().to_owned();
             let cfg = dir.join(Self::INIT);
@@ -761,11 +1422,20 @@ impl Session {
             if cfg.exists() {
                 self.source_path(cfg)?;
             }
+            self.settings_init = self.settings.clone();
+
+            let generated = dir.join(config::GENERATED);
+            if generated.exists() {
+                self.source_path(generated)?;
+            }
         }
 
-        self.source_dir(self.cwd.clone()).ok();
         self.cmdline.history.load()?;
         self.message(format!("rx v{}", crate::VERSION), MessageType::Debug);
+        // Sourced last, so that its warning isn't replaced.
+        self.source_dir(self.cwd.clone()).ok();
+        // Changes made while initializing can't be undone.
+        self.recorded = self.recorded();
 
         Ok(self)
     }
@@ -803,6 +1473,7 @@ impl Session {
             | (State::Running, State::Closing(_)) => {
                 debug!("state: {:?} -> {:?}", self.state, to);
                 self.state = to;
+                self.dirty = true;
             }
             _ => {}
         }
@@ -819,24 +1490,95 @@ impl Session {
     ) -> Vec<Effect> {
         self.settings_changed.clear();
         self.avg_time = avg_time;
+        self.dirty |= !events.is_empty() || !self.queue.is_empty() || !exec.is_normal();
 
         if let Tool::Brush = self.tool {
             self.brush.update();
         }
+        // The `view/fps` setting reflects the active view, which may have changed.
+        if let Some(fps) = self.views.active().map(|v| Value::U32(v.fps.unwrap_or(0))) {
+            self.settings.set("view/fps", fps).ok();
+        }
+        // The brush mirrors strokes onto the active view's frame pairs.
+        if let Some(v) = self.views.active() {
+            if self.brush.pairs != v.pairs {
+                self.brush.pairs = v.pairs.clone();
+            }
+        }
 
-        if let Some(delay) = self.animation_delay() {
-            self.accumulator += delta;
-            if self.accumulator >= delay {
-                for v in self.views.iter_mut() {
-                    v.animation.step();
-                }
-                self.accumulator = time::Duration::from_secs(0);
+        if self.settings["animation"].is_set() {
+            let default = time::Duration::from_millis(self.settings["animation/delay"].to_u64());
+            let (from, to) = (self.animation_clock, self.animation_clock + delta);
+
+            for v in self.views.iter_mut().filter(|v| v.animation.len() > 1) {
+                let delay = v.delay(default);
+                let index = v.animation.index;
+
+                v.animation.advance(from, to, delay);
+                self.dirty |= v.animation.index != index;
             }
+            self.animation_clock = to;
         }
         if self.ignore_received_characters {
             self.ignore_received_characters = false;
         }
 
+        for result in self.saver.results() {
+            self.handle_save_result(result);
+            self.dirty = true;
+        }
+        for hook in self.hooks.results() {
+            if let Err(e) = hook.result {
+                self.message(format!("Error: {}: {}", hook.name, e), MessageType::Error);
+                self.dirty = true;
+            }
+        }
+        for result in self.plugins.results() {
+            self.handle_plugin_result(result);
+            self.dirty = true;
+        }
+        self.collaborate();
+        self.sync_mirrors();
+        self.watch_filter(delta);
+
+        let interval = self.settings["history/checkpoint"].to_u64();
+        if interval > 0 && self.checkpoint_time.elapsed() >= time::Duration::from_secs(interval) {
+            let now = time::Instant::now();
+
+            for v in self.views.iter_mut() {
+                v.resource.borrow_mut().checkpoint(now);
+            }
+            self.checkpoint_time = now;
+        }
+
+        if let Execution::Fuzzing { fuzzer } = exec {
+            let seed = fuzzer.seed;
+            let done = fuzzer.frames == 0;
+            let inputs = fuzzer.step(self.width, self.height);
+
+            if done {
+                info!("fuzzing: ok (seed {})", seed);
+                self.quit(ExitReason::Normal);
+            } else {
+                for input in inputs {
+                    match input {
+                        fuzz::Input::Event(event) => self.handle_event(event, exec),
+                        fuzz::Input::Command(cmd) => match self.cmdline.parse(&format!(":{}", cmd))
+                        {
+                            Ok(cmd) => self.command(cmd),
+                            Err(e) => error!("fuzzing: invalid command `{}`: {}", cmd, e),
+                        },
+                    }
+                }
+                if let Err(e) = self.check_invariants() {
+                    self.quit(ExitReason::Error(format!(
+                        "fuzzing failed with seed {}: {}",
+                        seed, e
+                    )));
+                }
+            }
+        }
+
         // TODO: This whole block needs refactoring..
         if let Execution::Replaying {
             events: recording,
@@ -860,7 +1602,7 @@ impl Session {
 
                 let verify_ended = mode == DigestMode::Verify && result.is_done() && end.is_none();
                 let replay_ended = mode != DigestMode::Verify && end.is_none();
-                let verify_failed = result.is_err();
+                let verify_failed = result.is_aborted();
 
                 // Replay is over.
                 if verify_ended || replay_ended || verify_failed {
@@ -921,15 +1663,55 @@ impl Session {
                 self.handle_event(event, exec);
             }
         }
+        self.update_pan(delta);
+        self.record_edits();
+
+        let limit = self.settings["history/limit"].to_u64() as usize;
+        if limit > 0 {
+            for v in self.views.iter_mut() {
+                v.resource.borrow_mut().history_trim(limit);
+            }
+        }
+
+        self.update_fill_preview(delta);
+
+        if !self.brush_hud.is_zero() {
+            self.brush_hud = self.brush_hud.saturating_sub(delta);
+            // Redraw once more, to hide it.
+            self.dirty |= self.brush_hud.is_zero();
+        }
 
         if let Tool::Brush = self.tool {
             let brush = &self.brush;
-            let output = brush.output(
-                Stroke::NONE,
-                Fill::Solid(brush.color.into()),
-                1.0,
-                Align::BottomLeft,
-            );
+            let output = if !brush.is_drawing() {
+                vec![]
+            } else if brush.is_blended() {
+                // Already constrained to the clipping mask.
+                // Blended strokes depend on the pixels underneath, so they're
+                // previewed exactly as they will be painted.
+                self.stroke_pixels()
+                    .into_iter()
+                    .map(|(p, c)| {
+                        let (x, y) = (p.x as f32, p.y as f32);
+
+                        Shape::Rectangle(
+                            Rect::new(x, y, x + 1., y + 1.),
+                            ZDepth::ZERO,
+                            Rotation::ZERO,
+                            Stroke::NONE,
+                            Fill::Solid(c.into()),
+                        )
+                    })
+                    .collect()
+            } else {
+                let output = brush.output(
+                    Stroke::NONE,
+                    Fill::Solid(brush.color.into()),
+                    1.0,
+                    Align::BottomLeft,
+                );
+                self.clip_shapes(self.frame_shapes(output))
+            };
             if !output.is_empty() {
                 match brush.state {
                     // If we're erasing, we can't use the staging framebuffer, since we
@@ -944,6 +1726,8 @@ impl Session {
                     BrushState::DrawStarted(_) | BrushState::Drawing(_) => {
                         self.effects.push(Effect::ViewPaintDraft(output));
                     }
+                    // Blended strokes were already painted onto the view.
+                    BrushState::DrawEnded(_) if brush.is_blended() => {}
                     // Once we're done drawing, we can render into the real buffer.
                     BrushState::DrawEnded(_) => {
                         self.effects.extend_from_slice(&[
@@ -996,6 +1780,11 @@ impl Session {
         // Make sure we don't have rounding errors
         debug_assert_eq!(self.offset, self.offset.map(|a| a.floor()));
 
+        self.dirty |= !self.effects.is_empty()
+            || self.pan_velocity != Vector2::zero()
+            || self.settings["debug"].is_set()
+            || self.views.iter().any(|v| v.state != ViewState::Okay);
+
         // Return and drain accumulated effects
         self.effects.drain(..).collect()
     }
@@ -1005,6 +1794,180 @@ impl Session {
         for v in self.views.iter_mut() {
             v.okay();
         }
+        self.dirty = false;
+    }
+
+    /// Advance the timelapse, if one is being recorded, and return whether
+    /// the current frame should be captured.
+    pub fn timelapse_due(&mut self) -> bool {
+        let interval = self.settings["timelapse/interval"].to_u64();
+
+        self.timelapse.as_mut().map_or(false, |t| t.tick(interval))
+    }
+
+    /// Run a command received from another program, eg. over the remote
+    /// control socket. Returns the message the command displayed, or the
+    /// error it failed with.
+    pub fn remote(&mut self, input: &str) -> Result<String, String> {
+        let input = if input.starts_with(':') {
+            input.to_owned()
+        } else {
+            format!(":{}", input)
+        };
+        let cmd = self.cmdline.parse(&input).map_err(|e| e.to_string())?;
+
+        self.record_mapping(&input, &cmd);
+        self.message_clear();
+        self.command(cmd);
+        self.dirty = true;
+
+        // Files are written in the background. Wait for them, so that they're
+        // up to date by the time we reply, and so that errors are reported.
+        if let Some(err) = self.wait_for_saves() {
+            return Err(err);
+        }
+        match self.message.message_type {
+            MessageType::Error => Err(self.message.string.trim_start_matches("Error: ").to_owned()),
+            _ => Ok(self.message.string.clone()),
+        }
+    }
+
+    /// Exchange edits of the active view with collaborators, if any. Remote
+    /// edits are applied as they arrive, while local edits are found by
+    /// comparing the view snapshot with what was last exchanged.
+    fn collaborate(&mut self) {
+        if self.views.is_empty() {
+            return;
+        }
+        let mut collab = match self.collab.take() {
+            Some(c) => c,
+            None => return,
+        };
+        let mut received = false;
+
+        for msg in collab.receive() {
+            match msg {
+                collab::Message::Joined(peer) => {
+                    // Bring the new peer up to date with our view.
+                    self.active_view().with_pixels(|extent, pixels| {
+                        collab.send_snapshot(extent, pixels, Some(peer));
+                    });
+                    self.message(
+                        format!("Collaborator joined ({} connected)", collab.peers()),
+                        MessageType::Info,
+                    );
+                }
+                collab::Message::Left(_) if collab.role == collab::Role::Guest => {
+                    self.message("Error: collab: host disconnected", MessageType::Error);
+                    return;
+                }
+                collab::Message::Left(_) => {
+                    self.message(
+                        format!("Collaborator left ({} connected)", collab.peers()),
+                        MessageType::Info,
+                    );
+                }
+                // Only the host's view is synced, including its size.
+                collab::Message::Snapshot(_, extent, pixels)
+                    if collab.role == collab::Role::Guest =>
+                {
+                    let v = self.active_view_mut();
+
+                    if v.extent() == extent {
+                        v.paint_pixels(pixels.clone());
+                    } else {
+                        v.reshape(pixels.clone(), extent);
+                    }
+                    collab.reset(v.id, extent.width(), extent.height(), &pixels);
+                    received = true;
+                }
+                collab::Message::Snapshot(..) => {}
+                collab::Message::Line(peer, line) => {
+                    // Only edits are accepted from peers.
+                    if let Ok(Command::PaintColor(c, x, y)) =
+                        self.cmdline.parse(&format!(":{}", line))
+                    {
+                        self.active_view_mut().paint_color(c, x, y);
+                        collab.received(c, x, y);
+
+                        if collab.role == collab::Role::Host {
+                            collab.send(&[line], Some(peer));
+                        }
+                        received = true;
+                    }
+                }
+            }
+        }
+        if received {
+            self.active_view_mut().touch();
+        }
+
+        let v = self.active_view();
+
+        v.with_pixels(|extent, pixels| {
+            match collab.diff(v.id, extent.width(), extent.height(), pixels) {
+                Some(changed) => {
+                    let lines = changed
+                        .into_iter()
+                        .map(|(c, x, y)| String::from(Command::PaintColor(c, x, y)))
+                        .collect::<Vec<_>>();
+
+                    collab.send(&lines, None);
+                }
+                // The host's view was switched or resized: send it over again.
+                None if collab.role == collab::Role::Host => {
+                    collab.send_snapshot(extent, pixels, None);
+                }
+                None => {}
+            }
+        });
+
+        self.collab = Some(collab);
+    }
+
+    /// Capture a frame of the timelapse from the screen framebuffer texels.
+    pub fn record_timelapse(&mut self, texels: &[Rgba8], width: u32, height: u32) {
+        if let Some(timelapse) = &mut self.timelapse {
+            if let Err(e) = timelapse.capture(texels, width, height) {
+                self.timelapse = None;
+                self.message(format!("Error: timelapse: {}", e), MessageType::Error);
+            }
+        }
+    }
+
+    /// Check that the session is in a consistent state. Used when fuzzing.
+    fn check_invariants(&self) -> Result<(), String> {
+        if !self.views.is_empty() && self.views.active().is_none() {
+            return Err(format!(
+                "active view {} doesn't exist",
+                self.views.active_id
+            ));
+        }
+        for v in self.views.iter() {
+            if v.animation.len() == 0 || v.animation.index >= v.animation.len() {
+                return Err(format!(
+                    "view {} has animation index {} with {} frame(s)",
+                    v.id,
+                    v.animation.index,
+                    v.animation.len()
+                ));
+            }
+            if v.fw == 0 || v.fh == 0 {
+                return Err(format!("view {} has an empty frame size", v.id));
+            }
+            let (extent, len) = v.with_pixels(|extent, pixels| (extent, pixels.len()));
+
+            if len != (extent.width() * extent.height()) as usize {
+                return Err(format!(
+                    "view {} has {} pixel(s) for a {}x{} snapshot",
+                    v.id,
+                    len,
+                    extent.width(),
+                    extent.height()
+                ));
+            }
+        }
+        Ok(())
     }
 
     /// Quit the session.
@@ -1015,6 +1978,12 @@ impl Session {
                 self.cmdline.history.path.display()
             );
         }
+        if let Err(e) = self.palettes.save(&self.palette.colors) {
+            error!(
+                "Error: couldn't save palette '{}': {}",
+                self.palettes.active, e
+            );
+        }
         self.transition(State::Closing(r));
     }
 
@@ -1034,17 +2003,34 @@ impl Session {
         .floor()
     }
 
-    /// Get the current animation delay. Returns `None` if animations aren't playing,
-    /// or if none of the views have more than one frame.
-    pub fn animation_delay(&self) -> Option<time::Duration> {
-        let animations = self.views.iter().any(|v| v.animation.len() > 1);
+    /// Get the time remaining until the next animation frame of any view. Returns `None`
+    /// if animations aren't playing, or if none of the views have more than one frame.
+    pub fn animation_remaining(&self) -> Option<time::Duration> {
+        // A pending flood fill preview needs a frame once the cursor rests,
+        // the brush size display needs one to be hidden, and running plugins
+        // need to be checked on.
+        let preview = self
+            .fill_preview
+            .as_ref()
+            .filter(|p| p.shapes.is_none())
+            .map(|p| Self::FILL_PREVIEW_DELAY.saturating_sub(p.idle))
+            .into_iter()
+            .chain(Some(self.brush_hud).filter(|d| !d.is_zero()))
+            .chain(self.filter_remaining())
+            .chain(Some(plugin::POLL_INTERVAL).filter(|_| self.plugins.is_running()))
+            .min();
 
-        if self.settings["animation"].is_set() && animations {
-            let delay = self.settings["animation/delay"].to_u64();
-            Some(time::Duration::from_millis(delay))
-        } else {
-            None
+        if !self.settings["animation"].is_set() {
+            return preview;
         }
+        let default = time::Duration::from_millis(self.settings["animation/delay"].to_u64());
+
+        self.views
+            .iter()
+            .filter(|v| v.animation.len() > 1)
+            .map(|v| v.animation_remaining(self.animation_clock, default))
+            .chain(preview)
+            .min()
     }
 
     /// Check whether the session is running.
@@ -1085,6 +2071,45 @@ impl Session {
         self.cursor_dirty();
     }
 
+    /// Advance smooth panning by the given time delta. Keys held for panning
+    /// move the view at a constant speed, independent of the frame rate.
+    fn update_pan(&mut self, delta: time::Duration) {
+        let dt = delta.as_secs_f32();
+        let direction = self
+            .pan_keys
+            .values()
+            .fold(Vector2::zero(), |acc: Vector2<f32>, (x, y)| {
+                acc + Vector2::new(*x as f32, *y as f32)
+            });
+        let target = direction * -Self::PAN_SPEED;
+
+        if self.settings["pan/inertia"].is_set() {
+            let t = 1. - (-Self::PAN_EASING * dt).exp();
+            self.pan_velocity = self.pan_velocity + (target - self.pan_velocity) * t;
+
+            if target == Vector2::zero() && self.pan_velocity.magnitude() < 1. {
+                self.pan_velocity = Vector2::zero();
+            }
+        } else {
+            self.pan_velocity = target;
+        }
+
+        if self.pan_velocity == Vector2::zero() {
+            self.pan_remainder = Vector2::zero();
+            return;
+        }
+
+        // Only pan by whole pixels, to keep the views pixel-aligned.
+        let d = self.pan_remainder + self.pan_velocity * dt;
+        let (x, y) = (d.x.trunc(), d.y.trunc());
+
+        self.pan_remainder = Vector2::new(d.x - x, d.y - y);
+
+        if x != 0. || y != 0. {
+            self.pan(x, y);
+        }
+    }
+
     /// Re-compute state related to the cursor position. This is useful
     /// when the cursor hasn't moved relative to the session, but things
     /// within the session have moved relative to the cursor.
@@ -1098,6 +2123,12 @@ impl Session {
         self.palette.handle_cursor_moved(cursor);
         self.hover_view = None;
 
+        if self.settings["ui/settings"].is_set() {
+            self.panel.handle_cursor_moved(cursor);
+        } else {
+            self.panel.hover = None;
+        }
+
         let gained_palette_focus = !palette_hover && self.palette.hover.is_some();
 
         match &self.tool {
@@ -1118,7 +2149,7 @@ impl Session {
             _ => {}
         }
 
-        for v in self.views.iter_mut() {
+        for v in self.views.iter().filter(|v| self.workspaces.contains(v.id)) {
             let p = cursor - self.offset;
             if v.contains(p) {
                 self.hover_view = Some(v.id);
@@ -1130,7 +2161,7 @@ impl Session {
             self.palette.hover
         } else if let Some(v) = self.hover_view {
             let p = self.view_coords(v, cursor).into();
-            self.view(v).color_at(p).cloned()
+            self.view(v).color_at(p)
         } else {
             None
         };
@@ -1152,8 +2183,29 @@ impl Session {
                 // from the window coordinates. Currently, cursor position
                 // is stored only in `SessionCoords`, which would have
                 // to change.
-                self.rescale(old.to_f64(), new.to_f64());
+                self.rescale(new.to_f64());
+            }
+            "layout" => match new.to_string().parse::<Layout>() {
+                Ok(_) => self.organize_views(),
+                Err(e) => {
+                    self.settings.set(name, old.clone()).ok();
+                    self.message(format!("Error: {}", e), MessageType::Error);
+                }
+            },
+            "view/fps" => {
+                if let Some(v) = self.views.active_mut() {
+                    v.fps = Some(new.to_u64() as u32).filter(|fps| *fps > 0);
+                }
             }
+            "font" => match self.load_font(&new.to_string()) {
+                Ok(font) => {
+                    self.effects.push(Effect::FontChanged(font));
+                }
+                Err(e) => {
+                    self.settings.set(name, old.clone()).ok();
+                    self.message(format!("Error: font: {}", e), MessageType::Error);
+                }
+            },
             _ => {}
         }
     }
@@ -1183,6 +2235,14 @@ impl Session {
 
         match new {
             Mode::Normal => {
+                if let (Mode::Visual(VisualState::Selecting { .. }), Some(s)) =
+                    (old, self.selection)
+                {
+                    self.clip = self
+                        .selection_mask()
+                        .cloned()
+                        .or_else(|| Mask::from_rect(self.views.active_id, s.abs().bounds()));
+                }
                 self.selection = None;
             }
             Mode::Command => {
@@ -1192,11 +2252,24 @@ impl Session {
                 // text input to the command line. To avoid this, we have to ignore
                 // all such input until the end of the current upate.
                 self.ignore_received_characters = true;
+                self.cmdline.set_colors(&self.palette.colors);
                 self.cmdline_handle_input(':');
             }
+            Mode::Search => {
+                // See above.
+                self.ignore_received_characters = true;
+                self.search = Search::new(self.search_entries());
+            }
+            Mode::Visual(VisualState::Pasting) => {
+                if let Some(s) = self.selection {
+                    let r = s.abs().bounds();
+                    self.paste_size = (r.width(), r.height());
+                }
+            }
             _ => {}
         }
 
+        self.paste_anchor = None;
         self.release_inputs();
         self.prev_mode = Some(self.mode);
         self.mode = new;
@@ -1224,6 +2297,18 @@ impl Session {
     /// Messages
     ///////////////////////////////////////////////////////////////////////////////
 
+    /// Keys currently pressed, in a stable order.
+    pub fn keys_pressed(&self) -> Vec<platform::Key> {
+        let mut keys: Vec<_> = self.keys_pressed.iter().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    /// Check whether the mouse button is currently pressed.
+    pub fn is_mouse_pressed(&self) -> bool {
+        self.mouse_state == InputState::Pressed
+    }
+
     /// Display a message to the user. Also logs.
     pub fn message<D: fmt::Display>(&mut self, msg: D, t: MessageType) {
         self.message = Message::new(msg, t);
@@ -1247,7 +2332,7 @@ impl Session {
     /// # Panics
     ///
     /// Panics if the view isn't found.
-    pub fn view(&self, id: ViewId) -> &View<ViewResource> {
+    pub fn view(&self, id: ViewId) -> &View<Resource> {
         self.views
             .get(id)
             .expect(&format!("view #{} must exist", id))
@@ -1258,18 +2343,34 @@ impl Session {
     /// # Panics
     ///
     /// Panics if the view isn't found.
-    pub fn view_mut(&mut self, id: ViewId) -> &mut View<ViewResource> {
+    pub fn view_mut(&mut self, id: ViewId) -> &mut View<Resource> {
         self.views
             .get_mut(id)
             .expect(&format!("view #{} must exist", id))
     }
 
-    /// Get the currently active view.
-    ///
-    /// # Panics
+    /// The view whose animation is overlaid on the active view, unless it's
+    /// the active view itself.
+    pub fn reference_view(&self) -> Option<&View<Resource>> {
+        self.reference
+            .filter(|id| *id != self.views.active_id)
+            .and_then(|id| self.views.get(id))
+    }
+
+    /// The view compared with the active view, if it's shown, and isn't the
+    /// active view itself.
+    pub fn compare_view(&self) -> Option<&View<Resource>> {
+        self.compare
+            .filter(|id| self.comparing && *id != self.views.active_id)
+            .and_then(|id| self.views.get(id))
+    }
+
+    /// Get the currently active view.
+    ///
+    /// # Panics
     ///
     /// Panics if there is no active view.
-    pub fn active_view(&self) -> &View<ViewResource> {
+    pub fn active_view(&self) -> &View<Resource> {
         assert!(
             self.views.active_id != ViewId::default(),
             "fatal: no active view"
@@ -1282,7 +2383,7 @@ impl Session {
     /// # Panics
     ///
     /// Panics if there is no active view.
-    pub fn active_view_mut(&mut self) -> &mut View<ViewResource> {
+    pub fn active_view_mut(&mut self) -> &mut View<Resource> {
         assert!(
             self.views.active_id != ViewId::default(),
             "fatal: no active view"
@@ -1354,6 +2455,284 @@ impl Session {
         self.view_coords(self.views.active_id, p)
     }
 
+    /// Snap a point in view coordinates to the nearest guides, if snapping is enabled.
+    pub fn snap_to_guides(&self, p: ViewCoords<i32>) -> ViewCoords<i32> {
+        ViewCoords::new(
+            p.x + self.guide_delta(p.x, Axis::Vertical).unwrap_or(0),
+            p.y + self.guide_delta(p.y, Axis::Horizontal).unwrap_or(0),
+        )
+    }
+
+    /// Move a selection so that its nearest edges snap to guides, if snapping is enabled.
+    pub fn snap_selection_to_guides(&self, s: Selection) -> Selection {
+        let r = s.abs().bounds();
+        let nearest = |a: i32, b: i32, axis: Axis| {
+            [a, b]
+                .iter()
+                .filter_map(|n| self.guide_delta(*n, axis))
+                .min_by_key(|d| d.abs())
+                .unwrap_or(0)
+        };
+        let dx = nearest(r.x1, r.x2, Axis::Vertical);
+        let dy = nearest(r.y1, r.y2, Axis::Horizontal);
+
+        Selection::from(s.bounds() + Vector2::new(dx, dy))
+    }
+
+    /// Offset from the given position to the nearest guide on the given axis, if
+    /// snapping is enabled and a guide is within the snapping distance. Snapping
+    /// is bypassed while `<alt>` is held.
+    fn guide_delta(&self, n: i32, axis: Axis) -> Option<i32> {
+        if !self.settings["snap"].is_set() || self.keys_pressed.contains(&Key::Alt) {
+            return None;
+        }
+        let distance = self.settings["snap/distance"].to_u64() as i32;
+
+        self.guides
+            .iter()
+            .filter(|(a, _)| *a == axis)
+            .map(|(_, g)| *g as i32 - n)
+            .filter(|d| d.abs() <= distance)
+            .min_by_key(|d| d.abs())
+    }
+
+    /// The mask of the current selection, if the selection hasn't changed
+    /// since the mask was made.
+    pub fn selection_mask(&self) -> Option<&Mask> {
+        self.mask
+            .as_ref()
+            .filter(|m| m.view == self.views.active_id && self.selection == Some(m.selection))
+    }
+
+    /// The mask painting is constrained to, if `clip/selection` is on and the
+    /// last selection was made in the active view.
+    pub fn clip_mask(&self) -> Option<&Mask> {
+        if !self.settings["clip/selection"].is_set() {
+            return None;
+        }
+        self.clip
+            .as_ref()
+            .filter(|m| m.view == self.views.active_id)
+    }
+
+    /// Constrain the given shapes to the clipping mask, if any.
+    fn clip_shapes(&self, shapes: Vec<Shape>) -> Vec<Shape> {
+        match self.clip_mask() {
+            Some(mask) => mask.clip(shapes),
+            None => shapes,
+        }
+    }
+
+    /// Horizontal offsets from the given position, in view coordinates, to the
+    /// same position in every frame of the active view, if `paint/all-frames` is
+    /// on. Otherwise, or if the position is outside the view, the only offset
+    /// is zero.
+    fn frame_offsets(&self, x: i32) -> Vec<i32> {
+        let v = self.active_view();
+        let (fw, n) = (v.fw as i32, v.animation.len() as i32);
+        let frame = x.div_euclid(fw.max(1));
+
+        if !self.settings["paint/all-frames"].is_set() || !(0..n).contains(&frame) {
+            return vec![0];
+        }
+        (0..n).map(|f| (f - frame) * fw).collect()
+    }
+
+    /// Copy the given shapes, in view coordinates, onto every frame of the
+    /// active view, if `paint/all-frames` is on.
+    fn frame_shapes(&self, shapes: Vec<Shape>) -> Vec<Shape> {
+        if !self.settings["paint/all-frames"].is_set() {
+            return shapes;
+        }
+        let mut copies = Vec::new();
+
+        for shape in shapes {
+            match shape {
+                Shape::Rectangle(r, z, rotation, stroke, fill) => {
+                    for dx in self.frame_offsets(r.x1.min(r.x2).floor() as i32) {
+                        copies.push(Shape::Rectangle(
+                            r + Vector2::new(dx as f32, 0.),
+                            z,
+                            rotation.clone(),
+                            stroke,
+                            fill,
+                        ));
+                    }
+                }
+                other => copies.push(other),
+            }
+        }
+        copies
+    }
+
+    /// Shapes covering the current selection, or only its masked pixels.
+    fn selection_shapes(&self, fill: Fill) -> Vec<Shape> {
+        let shape = |r: Rect<i32>| {
+            Shape::Rectangle(
+                r.map(|n| n as f32),
+                ZDepth::default(),
+                Rotation::ZERO,
+                Stroke::NONE,
+                fill,
+            )
+        };
+        if let Some(mask) = self.selection_mask() {
+            mask.points()
+                .map(|p| shape(Rect::new(p.x, p.y, p.x + 1, p.y + 1)))
+                .collect()
+        } else if let Some(s) = self.selection {
+            vec![shape(s.abs().bounds())]
+        } else {
+            vec![]
+        }
+    }
+
+    /// The current selection as a mask, clipped to the active view.
+    fn current_mask(&self) -> Option<Mask> {
+        if let Some(mask) = self.selection_mask() {
+            return Some(mask.clone());
+        }
+        let v = self.active_view();
+        let s = self.selection?;
+
+        Mask::from_rect(v.id, s.abs().bounds().intersection(v.layer_bounds()))
+    }
+
+    /// Fill the current selection with the given pattern, using the foreground
+    /// and background colors, as a single edit.
+    fn generate(&mut self, pattern: Pattern) {
+        let mask = match self.current_mask() {
+            Some(mask) => mask,
+            None => return,
+        };
+        let (fg, bg) = (self.fg, self.bg);
+        // Noise without a seed is seeded with the current edit, so that it
+        // changes from one fill to the next, but replays identically.
+        let seed = self.active_view().current_edit() as u64;
+        let mut sample = pattern.sampler(mask.selection.abs().bounds(), seed);
+
+        let v = self.active_view_mut();
+        let (extent, mut pixels) = v.pixels();
+
+        for p in mask.points() {
+            let color = if sample(p.x, p.y) { fg } else { bg };
+
+            if let Some(i) = extent.coord_to_index(ViewCoords::new(p.x as u32, p.y as u32)) {
+                pixels[i] = color;
+            }
+        }
+        v.paint_pixels(pixels);
+    }
+
+    /// Pixels covered by the current brush stroke on the active view, including
+    /// its copies on other frames, within the view and the clipping mask.
+    fn stroke_points(&self) -> Vec<ViewCoords<i32>> {
+        let extent = self.active_view().extent();
+        let (w, h) = (extent.width() as i32, extent.height() as i32);
+        let clip = self.clip_mask();
+
+        // Points copied onto other frames may overlap with the stroke, and must
+        // only be included once.
+        let points: BTreeSet<(i32, i32)> = self
+            .brush
+            .coverage()
+            .into_iter()
+            .flat_map(|p| {
+                self.frame_offsets(p.x)
+                    .into_iter()
+                    .map(move |dx| (p.x + dx, p.y))
+            })
+            .collect();
+
+        points
+            .into_iter()
+            .map(|(x, y)| ViewCoords::new(x, y))
+            .filter(|p| (0..w).contains(&p.x) && (0..h).contains(&p.y))
+            .filter(|p| clip.map_or(true, |m| m.contains(p.x, p.y)))
+            .collect()
+    }
+
+    /// Pixels painted by the current brush stroke onto the active view, blended
+    /// with the pixels they're painted over. Each pixel is only blended once.
+    fn stroke_pixels(&self) -> Vec<(ViewCoords<i32>, Rgba8)> {
+        let brush = &self.brush;
+        let points = self.stroke_points();
+
+        self.active_view().with_pixels(|extent, pixels| {
+            points
+                .into_iter()
+                .filter_map(|p| {
+                    let i = extent.coord_to_index(ViewCoords::new(p.x as u32, p.y as u32))?;
+                    brush.blend(p, pixels[i]).map(|c| (p, c))
+                })
+                .collect()
+        })
+    }
+
+    /// Blend the finished brush stroke onto the active view, as a single edit.
+    fn blend_stroke(&mut self) {
+        let stroke = self.stroke_pixels();
+
+        let v = self.active_view_mut();
+        let (extent, mut pixels) = v.pixels();
+
+        for (p, color) in stroke {
+            if let Some(i) = extent.coord_to_index(ViewCoords::new(p.x as u32, p.y as u32)) {
+                pixels[i] = color;
+            }
+        }
+        v.paint_pixels(pixels);
+    }
+
+    /// Select the pixels of the given mask, in visual mode. Masks that cover
+    /// their whole selection are kept as plain rectangular selections.
+    fn select_mask(&mut self, mask: Mask) {
+        if !matches!(self.mode, Mode::Visual(VisualState::Selecting { .. })) {
+            self.switch_mode(Mode::Visual(VisualState::Selecting { dragging: false }));
+        }
+        self.selection = Some(mask.selection);
+        self.mask = if mask.is_full() { None } else { Some(mask) };
+    }
+
+    /// Select all pixels of the given color in the active view.
+    fn select_color(&mut self, color: Rgba8) {
+        let v = self.active_view();
+        let mask = v.with_pixels(|extent, pixels| {
+            Mask::from_color(v.id, extent.width(), extent.height(), pixels, color)
+        });
+
+        match mask {
+            Some(mask) => {
+                let n = mask.count();
+
+                self.select_mask(mask);
+                self.message(format!("{} pixel(s) selected", n), MessageType::Info);
+            }
+            None => {
+                self.message(
+                    format!("Error: no pixels of color {}", color),
+                    MessageType::Error,
+                );
+            }
+        }
+    }
+
+    /// Whether resizing should preserve the aspect ratio. This is the case when
+    /// the `resize/lock-aspect` setting is on, or while `<shift>` is held.
+    fn aspect_locked(&self) -> bool {
+        self.settings["resize/lock-aspect"].is_set() || self.keys_pressed.contains(&Key::Shift)
+    }
+
+    /// Given a new size `n` for a dimension whose size was `from`, return the
+    /// size of the other dimension, `to`, such that the aspect ratio is preserved.
+    /// The result is never smaller than `1`.
+    fn aspect_dimension(n: i32, from: i32, to: i32) -> i32 {
+        if from == 0 {
+            return n.max(1);
+        }
+        ((n * to) as f32 / from as f32).round().max(1.) as i32
+    }
+
     /// Check whether a point is inside the selection, if any.
     pub fn is_selected(&self, p: ViewCoords<i32>) -> bool {
         if let Some(s) = self.selection {
@@ -1387,7 +2766,11 @@ impl Session {
                         continue;
                     }
 
-                    if path.file_name() == Some(OsStr::new(".rxrc")) {
+                    if path.file_name() == Some(OsStr::new(config::PROJECT))
+                        || PROJECT_FILES
+                            .iter()
+                            .any(|f| path.file_name() == Some(OsStr::new(f)))
+                    {
                         continue;
                     }
 
@@ -1399,7 +2782,12 @@ impl Session {
                     success_count += 1;
                 }
                 self.source_dir(path).ok();
+                self.source_palette_dir(path);
             } else {
+                match path.parent() {
+                    Some(dir) if !dir.as_os_str().is_empty() => self.source_palette_dir(dir),
+                    _ => self.source_palette_dir(Path::new(".")),
+                }
                 if path.exists() {
                     self.load_view(path)?;
                 } else if !path.exists() && path.with_extension("png").exists() {
@@ -1509,10 +2897,16 @@ impl Session {
     /// Save the given view to disk with the current file name. Returns
     /// an error if the view has no file name.
     pub fn save_view(&mut self, id: ViewId) -> io::Result<(FileStorage, usize)> {
-        let view = self.view_mut(id);
+        // Background saves must complete first, since they may write to the same files.
+        self.wait_for_saves();
 
-        if let Some(f) = view.file_storage().cloned() {
-            view.save_as(&f).map(|w| (f, w))
+        if let Some(f) = self.view(id).file_storage().cloned() {
+            let job = self.save_job(id, &f)?;
+            let written = job.run()?;
+
+            self.view_mut(id).mark_saved(job.edit_id, &job.storage);
+
+            Ok((f, written))
         } else {
             Err(io::Error::new(io::ErrorKind::Other, "no file name given"))
         }
@@ -1520,6 +2914,90 @@ impl Session {
 
     /// Private ///////////////////////////////////////////////////////////////////
 
+    /// Save a view to the given storage in the background. The outcome is
+    /// reported once the save completes.
+    fn save_view_as(&mut self, id: ViewId, storage: &FileStorage) -> io::Result<()> {
+        let job = self.save_job(id, storage)?;
+
+        self.saver.save(job)
+    }
+
+    /// Prepare a save of the given view. Archives are saved along with the
+    /// palette, the frame delays and the view settings.
+    fn save_job(&self, id: ViewId, storage: &FileStorage) -> io::Result<SaveJob> {
+        let v = self.view(id);
+        let mut job = v.save_job(storage)?;
+        let delay = v
+            .delay(time::Duration::from_millis(
+                self.settings["animation/delay"].to_u64(),
+            ))
+            .as_millis() as u64;
+
+        for manifest in job.files.iter_mut().filter_map(|f| f.manifest.as_mut()) {
+            manifest.palette = Some(self.palette.colors.iter().cloned().collect());
+            manifest.delays = Some(vec![delay; manifest.extent.nframes]);
+            manifest.settings = Self::ARCHIVE_SETTINGS
+                .iter()
+                .map(|k| (k.to_string(), self.settings[k].clone()))
+                .collect();
+        }
+        Ok(job)
+    }
+
+    /// Wait for background saves to complete, and handle their outcome.
+    /// Returns the first error encountered, if any.
+    pub fn wait_for_saves(&mut self) -> Option<String> {
+        let mut error = None;
+
+        for result in self.saver.wait() {
+            if let Err(err) = &result.result {
+                error.get_or_insert_with(|| err.to_string());
+            }
+            self.handle_save_result(result);
+        }
+        error
+    }
+
+    /// Report the outcome of a background save, and mark the view as saved.
+    fn handle_save_result(&mut self, result: SaveResult) {
+        let SaveResult {
+            id,
+            storage,
+            edit_id,
+            result,
+        } = result;
+
+        match result {
+            Ok(written) => {
+                if let Some(v) = self.views.get_mut(id) {
+                    v.mark_saved(edit_id, &storage);
+                }
+                self.message(
+                    format!("\"{}\" {} pixels written", storage, written),
+                    MessageType::Info,
+                );
+                self.written(&storage);
+            }
+            Err(err) => self.message(format!("Error: {}", err), MessageType::Error),
+        }
+    }
+
+    /// Run the `hook/write` hook, if set, for each file written.
+    fn written(&self, storage: &FileStorage) {
+        let command = self.settings["hook/write"].to_string();
+
+        if command.is_empty() {
+            return;
+        }
+        let paths = match storage {
+            FileStorage::Single(path) => vec![path],
+            FileStorage::Range(paths) => paths.iter().collect(),
+        };
+        for path in paths {
+            self.hooks.run("hook/write", &command, path, &self.cwd);
+        }
+    }
+
     /// Export a view in a specific format.
     fn export_as(&mut self, id: ViewId, path: &Path, scale: u32) -> io::Result<()> {
         let ext = path.extension().ok_or_else(|| {
@@ -1533,12 +3011,61 @@ impl Session {
             "gif" => {
                 let palette = self.colors();
                 let view = self.view(id);
-                let delay = time::Duration::from_millis(self.settings["animation/delay"].to_u64());
+                let delay = view.delay(time::Duration::from_millis(
+                    self.settings["animation/delay"].to_u64(),
+                ));
+
+                view.resource
+                    .borrow()
+                    .save_gif(path, delay, &palette, scale)?
+            }
+            "svg" => self.view(id).resource.borrow().save_svg(path, scale)?,
+            "bmp" | "ico" => {
+                let view = self.view(id);
+                let extent = view.extent();
+                // Icons are made from the current animation frame only, and
+                // scaled to the icon sizes.
+                let (rect, scale) = if ext == "ico" {
+                    (extent.frame(view.animation.index), 1)
+                } else {
+                    (extent.rect(), scale)
+                };
+                let pixels = view
+                    .pixels_rect(&rect.map(|n| n as i32))
+                    .expect("rect should be within view");
 
-                view.save_gif(path, delay, &palette, scale)?
+                crate::image::save_as(path, rect.width(), rect.height(), scale, &pixels)?;
+
+                pixels.len() * (scale * scale) as usize
+            }
+            "c" | "h" | "rs" => {
+                let language = if ext == "rs" {
+                    crate::io::Language::Rust
+                } else {
+                    crate::io::Language::C
+                };
+                self.view(id).with_pixels(|extent, pixels| {
+                    crate::io::export_source(path, language, extent, pixels, scale)
+                })?
+            }
+            "png" if self.settings["export/indexed"].is_set() => {
+                let palette = self.colors();
+                let bits = match self.settings["export/depth"].to_u64() {
+                    0 => None,
+                    n @ (1 | 2 | 4 | 8) => Some(n as u8),
+                    n => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("invalid bit depth {}, must be 0, 1, 2, 4 or 8", n),
+                        ));
+                    }
+                };
+                self.view(id)
+                    .resource
+                    .borrow()
+                    .save_png_indexed(path, &palette, bits, scale)?
             }
-            "svg" => self.view(id).save_svg(path, scale)?,
-            "png" => self.view(id).save_png(path, scale)?,
+            "png" => self.view(id).resource.borrow().save_png(path, scale)?,
             _ => {
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidInput,
@@ -1553,6 +3080,42 @@ impl Session {
         Ok(())
     }
 
+    /// Export a view as a PNG, scaled up with a smoothing filter.
+    fn export_filtered(
+        &mut self,
+        id: ViewId,
+        path: &Path,
+        filter: ScaleFilter,
+        scale: u32,
+    ) -> io::Result<()> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("png") if self.settings["export/indexed"].is_set() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "filters can't be used with indexed exports",
+                ));
+            }
+            Some("png") => {}
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "filters can only be used when exporting to png",
+                ));
+            }
+        }
+        let written = self
+            .view(id)
+            .resource
+            .borrow()
+            .save_png_filtered(path, filter, scale)?;
+
+        self.message(
+            format!("\"{}\" {} pixels written", path.display(), written),
+            MessageType::Info,
+        );
+        Ok(())
+    }
+
     /// Load a view into the session.
     fn load_view<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
         let path = path.as_ref();
@@ -1586,13 +3149,111 @@ impl Session {
                     MessageType::Info,
                 );
             }
+            view::Format::Ora | view::Format::Psd => {
+                let (width, height, pixels) = crate::io::load_image(&*path)?;
+
+                // Views only have one layer, so saving over the original
+                // would lose its layers. A new file name must be given.
+                self.add_view(FileStatus::NoFile, width, height, vec![pixels]);
+                self.message(
+                    format!(
+                        "\"{}\" {} pixels read, layers flattened",
+                        path.display(),
+                        width * height
+                    ),
+                    MessageType::Info,
+                );
+            }
+            view::Format::Archive => {
+                let archive = crate::io::Archive::load(&*path)?;
+                let crate::io::Manifest {
+                    extent,
+                    palette,
+                    delays,
+                    settings,
+                } = archive.manifest;
+                let ViewExtent { fw, fh, nframes } = extent;
+
+                // Views only have one layer.
+                let pixels = &archive.layers[0];
+                let frames = util::split_frames(pixels, fw as usize, fh as usize, nframes);
+                let id = self.add_view(
+                    FileStatus::Saved(FileStorage::Single((*path).into())),
+                    fw,
+                    fh,
+                    frames,
+                );
+
+                for (k, v) in settings {
+                    self.command(Command::Set(k, v));
+                }
+                if let Some(colors) = palette {
+                    let capacity = self.palette.colors.capacity();
+
+                    self.palette.clear();
+                    self.palette
+                        .colors
+                        .extend(colors.into_iter().take(capacity));
+                    self.palette.hover = None;
+                    self.center_palette();
+                }
+                // Frames share a delay, so it's only kept if all frames have the same one.
+                if let Some(delay) = delays.and_then(|d| {
+                    let first = d.first().copied()?;
+                    d.iter().all(|n| *n == first).then_some(first)
+                }) {
+                    if delay != self.settings["animation/delay"].to_u64() && delay > 0 {
+                        self.view_mut(id).fps = Some((1000 / delay).max(1) as u32);
+                    }
+                }
+                self.message(
+                    format!("\"{}\" {} pixels read", path.display(), pixels.len()),
+                    MessageType::Info,
+                );
+            }
             view::Format::Gif => {
                 return Err(io::Error::new(
                     io::ErrorKind::Other,
                     "gif files are not supported",
                 ));
             }
+            view::Format::Rgba => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "raw images require a size, eg. `--size 64x64`",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Load a raw RGBA image of the given size into the session, and start editing it.
+    pub fn edit_raw<P: AsRef<Path>>(&mut self, path: P, width: u32, height: u32) -> io::Result<()> {
+        let path = path.as_ref();
+
+        // View is already loaded.
+        if let Some(View { id, .. }) = self
+            .views
+            .find(|v| v.file_storage().map_or(false, |f| f.contains(path)))
+        {
+            let id = *id;
+            self.activate(id);
+            return Ok(());
         }
+        let pixels = crate::io::load_raw(path, width, height)?;
+        let id = self.add_view(
+            FileStatus::Saved(FileStorage::Single(path.into())),
+            width,
+            height,
+            vec![pixels],
+        );
+        self.message(
+            format!("\"{}\" {} pixels read", path.display(), width * height),
+            MessageType::Info,
+        );
+        self.organize_views();
+        self.edit_view(id);
 
         Ok(())
     }
@@ -1604,9 +3265,6 @@ impl Session {
         fh: u32,
         frames: Vec<Vec<Rgba8>>,
     ) -> ViewId {
-        let nframes = frames.len();
-        assert!(nframes >= 1);
-
         // Replace the active view if it's a scratch pad.
         if let Some(v) = self.views.active() {
             let id = v.id;
@@ -1615,49 +3273,244 @@ impl Session {
                 self.destroy_view(id);
             }
         }
+        self.insert_view(file_status, fw, fh, frames)
+    }
+
+    /// Add a view, without replacing the active scratch pad.
+    fn insert_view(
+        &mut self,
+        file_status: FileStatus,
+        fw: u32,
+        fh: u32,
+        frames: Vec<Vec<Rgba8>>,
+    ) -> ViewId {
+        let nframes = frames.len();
+        assert!(nframes >= 1);
 
         let pixels = util::stitch_frames(frames, fw as usize, fh as usize, Rgba8::TRANSPARENT);
-        let resource = ViewResource::new(pixels, ViewExtent::new(fw, fh, nframes));
+        let extent = ViewExtent::new(fw, fh, nframes);
+        let resource = ViewResource::new(pixels, extent);
+
+        self.add_resource_view(file_status, extent, Rc::new(RefCell::new(resource)))
+    }
+
+    /// Add a view of the given resource to the session.
+    fn add_resource_view(
+        &mut self,
+        file_status: FileStatus,
+        extent: ViewExtent,
+        resource: Resource,
+    ) -> ViewId {
+        let ViewExtent { fw, fh, nframes } = extent;
+        // In the free layout, views aren't re-positioned, so place the new view
+        // to the right of the last one.
+        let offset = self
+            .views
+            .iter()
+            .filter(|v| self.workspaces.contains(v.id))
+            .last()
+            .map(|v| v.offset + Vector2::new(v.width() as f32 * v.zoom + Self::VIEW_MARGIN, 0.));
         let id = self.views.add(file_status, fw, fh, nframes, resource);
 
+        if let (Layout::Free, Some(offset)) = (self.layout(), offset) {
+            self.view_mut(id).offset = offset;
+        }
+        self.workspaces.add(id);
         self.effects.push(Effect::ViewAdded(id));
 
         id
     }
 
-    /// Destroys the resources associated with a view.
-    fn destroy_view(&mut self, id: ViewId) {
-        assert!(!self.views.is_empty());
+    /// Move or copy a range of frames from the given view into a new view.
+    fn split_view(&mut self, id: ViewId, from: usize, to: usize, copy: bool) -> ViewId {
+        let (extent, mut frames) = self.view(id).with_pixels(|extent, pixels| {
+            let ViewExtent { fw, fh, nframes } = extent;
 
-        self.views.remove(id);
-        self.effects.push(Effect::ViewRemoved(id));
-    }
+            (
+                extent,
+                util::split_frames(pixels, fw as usize, fh as usize, nframes),
+            )
+        });
+        let ViewExtent { fw, fh, .. } = extent;
 
-    /// Quit the view.
-    fn quit_view(&mut self, id: ViewId) {
-        self.destroy_view(id);
+        let split: Vec<_> = if copy {
+            frames[from..=to].to_vec()
+        } else {
+            frames.drain(from..=to).collect()
+        };
 
-        if !self.views.is_empty() {
-            self.organize_views();
-            self.center_active_view();
-        }
-    }
+        if !copy {
+            let extent = ViewExtent::new(fw, fh, frames.len());
+            let pixels = util::stitch_frames(frames, fw as usize, fh as usize, Rgba8::TRANSPARENT);
 
-    /// Quit view if it has been saved. Otherwise, display an error.
-    fn quit_view_safe(&mut self, id: ViewId) {
-        let v = self.view(id);
-        match &v.file_status {
-            FileStatus::Modified(_) | FileStatus::New(_) => {
-                self.message(
-                    "Error: no write since last change (enter `:q!` to quit without saving)",
-                    MessageType::Error,
-                );
-            }
-            _ => self.quit_view(id),
+            self.view_mut(id).reshape(pixels, extent);
         }
+        let fps = self.view(id).fps;
+        let split = self.insert_view(FileStatus::NoFile, fw, fh, split);
+        self.view_mut(split).fps = fps;
+
+        split
     }
 
-    fn colors(&self) -> ArrayVec<[Rgba8; 256]> {
+    /// Append the frames of one view to another. Both views must have
+    /// the same frame size.
+    fn append_view(&mut self, id: ViewId, other: ViewId) -> Result<(), String> {
+        let split = |extent: ViewExtent, pixels: &[Rgba8]| {
+            let ViewExtent { fw, fh, nframes } = extent;
+            util::split_frames(pixels, fw as usize, fh as usize, nframes)
+        };
+        let (ViewExtent { fw, fh, .. }, appended) = self
+            .view(other)
+            .with_pixels(|extent, pixels| (extent, split(extent, pixels)));
+        let extent = self.view(id).extent();
+
+        if (extent.fw, extent.fh) != (fw, fh) {
+            return Err(format!(
+                "frame size {}x{} doesn't match {}x{}",
+                fw, fh, extent.fw, extent.fh
+            ));
+        }
+        let mut frames = self.view(id).with_pixels(split);
+        frames.extend(appended);
+
+        let extent = ViewExtent::new(fw, fh, frames.len());
+        let pixels = util::stitch_frames(frames, fw as usize, fh as usize, Rgba8::TRANSPARENT);
+
+        self.view_mut(id).reshape(pixels, extent);
+
+        Ok(())
+    }
+
+    /// Open a view in a second view, which mirrors its pixels. The mirror isn't
+    /// backed by a file: the source view is the one to save.
+    fn mirror_view(&mut self, id: ViewId) -> ViewId {
+        let v = self.view(id);
+        let (extent, resource, fps, edit) =
+            (v.extent(), v.resource.clone(), v.fps, v.current_edit());
+
+        let mirror = self.add_resource_view(FileStatus::NoFile, extent, resource);
+        self.view_mut(mirror).fps = fps;
+
+        self.mirrors.push(Mirror {
+            source: id,
+            mirror,
+            edit,
+        });
+        self.organize_views();
+
+        mirror
+    }
+
+    /// Refresh mirrored views whose shared resource was edited since the last call,
+    /// from the other view of the mirror, or by undoing or redoing.
+    fn sync_mirrors(&mut self) {
+        for i in 0..self.mirrors.len() {
+            let m = self.mirrors[i];
+            let edit = self.view(m.source).current_edit();
+
+            if edit == m.edit {
+                continue;
+            }
+            let extent = self.view(m.source).resource.borrow().extent;
+
+            // Views that are being painted on or restored are left alone. The
+            // view the edit was made in is refreshed too, which is harmless.
+            for id in [m.source, m.mirror] {
+                let v = self.view_mut(id);
+
+                if !v.is_okay() {
+                    continue;
+                }
+                if v.extent() == extent {
+                    v.restore(edit);
+                } else {
+                    v.restore_extent(edit, extent);
+                }
+            }
+            self.mirrors[i].edit = edit;
+            self.dirty = true;
+        }
+    }
+
+    /// Switch to the given workspace, and activate its most recently active view.
+    fn switch_workspace(&mut self, name: &str) {
+        self.workspaces.active = name.to_owned();
+
+        let ws = &self.workspaces;
+        let id = self
+            .views
+            .recent_matching(|id| ws.contains(id))
+            .or_else(|| self.views.range(..).find(|id| ws.contains(*id)));
+
+        if let Some(id) = id {
+            self.activate(id);
+        }
+        self.organize_views();
+        self.center_active_view();
+        self.message(format!("Workspace '{}'", name), MessageType::Info);
+    }
+
+    /// Destroys the resources associated with a view.
+    fn destroy_view(&mut self, id: ViewId) {
+        assert!(!self.views.is_empty());
+
+        self.views.remove(id);
+        self.effects.push(Effect::ViewRemoved(id));
+
+        if self.lut == Some(id) {
+            self.lut = None;
+        }
+        if self.reference == Some(id) {
+            self.reference = None;
+        }
+        if self.compare == Some(id) {
+            self.compare = None;
+        }
+        self.mirrors.retain(|m| m.source != id && m.mirror != id);
+        self.workspaces.remove(id);
+
+        // Prefer activating a view of the same workspace.
+        if !self.workspaces.contains(self.views.active_id) {
+            let ws = &self.workspaces;
+
+            if let Some(id) = self.views.recent_matching(|id| ws.contains(id)) {
+                self.activate(id);
+            }
+        }
+    }
+
+    /// Quit the view.
+    fn quit_view(&mut self, id: ViewId) {
+        self.destroy_view(id);
+
+        if !self.views.is_empty() {
+            // If this was the last view of its workspace, follow the active view.
+            if let Some(ws) = self.workspaces.get(self.views.active_id) {
+                self.workspaces.active = ws.to_owned();
+            }
+            self.organize_views();
+            self.center_active_view();
+        }
+    }
+
+    /// Quit view if it has been saved. Otherwise, display an error.
+    fn quit_view_safe(&mut self, id: ViewId) {
+        // A save that is still in progress may be what marks the view as saved.
+        self.wait_for_saves();
+
+        let v = self.view(id);
+        match &v.file_status {
+            FileStatus::Modified(_) | FileStatus::New(_) => {
+                self.message(
+                    "Error: no write since last change (enter `:q!` to quit without saving)",
+                    MessageType::Error,
+                );
+            }
+            _ => self.quit_view(id),
+        }
+    }
+
+    fn colors(&self) -> ArrayVec<[Rgba8; 256]> {
         let mut palette = self.palette.colors.clone();
 
         palette.push(self.fg);
@@ -1676,23 +3529,48 @@ impl Session {
         if self.views.is_empty() {
             return;
         }
-        let first = self
+        let layout = self.layout();
+        let ws = &self.workspaces;
+        let mut views: Vec<_> = self
             .views
-            .first_mut()
-            .expect("view list should never be empty");
+            .iter_mut()
+            .filter(|v| ws.contains(v.id))
+            .collect();
+        let columns = match layout {
+            Layout::Vertical => 1,
+            Layout::Horizontal => views.len(),
+            Layout::Grid => (views.len() as f32).sqrt().ceil() as usize,
+            // Views keep their positions.
+            Layout::Free => 0,
+        };
 
-        first.offset.y = 0.;
+        if columns > 0 {
+            let mut y = 0.;
 
-        // TODO: We need a way to distinguish view content size with real (rendered) size.
-        let mut offset = first.height() as f32 * first.zoom + Self::VIEW_MARGIN;
+            for row in views.chunks_mut(columns) {
+                let mut x = 0.;
+                let mut height: f32 = 0.;
 
-        for v in self.views.iter_mut().skip(1) {
-            v.offset.y = offset;
-            offset += v.height() as f32 * v.zoom + Self::VIEW_MARGIN;
+                // TODO: We need a way to distinguish view content size with real (rendered) size.
+                for v in row.iter_mut() {
+                    v.offset = Vector2::new(x, y);
+                    x += v.width() as f32 * v.zoom + Self::VIEW_MARGIN;
+                    height = height.max(v.height() as f32 * v.zoom);
+                }
+                y += height + Self::VIEW_MARGIN;
+            }
         }
         self.cursor_dirty();
     }
 
+    /// The view layout setting.
+    pub fn layout(&self) -> Layout {
+        self.settings["layout"]
+            .to_string()
+            .parse()
+            .unwrap_or(Layout::Vertical)
+    }
+
     /// Check the current selection and invalidate it if necessary.
     fn check_selection(&mut self) {
         let v = self.active_view();
@@ -1734,11 +3612,146 @@ impl Session {
     }
 
     fn restore_view_snapshot(&mut self, id: ViewId, dir: Direction) {
-        self.view_mut(id).restore_snapshot(dir);
+        // Changes made since the last update are undone first.
+        self.record_edits();
+
+        loop {
+            match self.view_mut(id).restore_snapshot(dir) {
+                Some(Edit::PaletteChanged(name, from, to)) if name == self.palettes.active => {
+                    let colors = if dir == Direction::Backward { from } else { to };
+                    let capacity = self.palette.colors.capacity();
+
+                    self.palette.clear();
+                    self.palette
+                        .colors
+                        .extend(colors.into_iter().take(capacity));
+                    self.palette.hover = None;
+                    self.center_palette();
+                }
+                Some(Edit::SelectionChanged(from, to)) if matches!(self.mode, Mode::Visual(_)) => {
+                    self.selection = if dir == Direction::Backward { from } else { to };
+                }
+                // Edits to other palettes, and selection changes outside of
+                // visual mode, wouldn't be visible, so they're skipped.
+                Some(Edit::PaletteChanged(..)) | Some(Edit::SelectionChanged(..)) => continue,
+                _ => {}
+            }
+            break;
+        }
+        self.recorded = self.recorded();
         self.organize_views();
         self.cursor_dirty();
     }
 
+    /// Get the palette and selection, to record changes relative to.
+    fn recorded(&self) -> Recorded {
+        Recorded {
+            palette: self.palettes.active.clone(),
+            colors: self.palette.colors.to_vec(),
+            selection: self.selection,
+        }
+    }
+
+    /// Update the flood fill preview, and paint it into the view's staging buffer.
+    /// The fill is only computed once the cursor has rested on a pixel for
+    /// [`Session::FILL_PREVIEW_DELAY`], since it can be costly on large views.
+    fn update_fill_preview(&mut self, delta: time::Duration) {
+        let key = match self.tool {
+            Tool::FloodFill
+                if self.mode == Mode::Normal
+                    && self.mouse_state == InputState::Released
+                    && self.settings["ui/fill-preview"].is_set()
+                    && self.hover_view.is_some()
+                    && self.hover_view == self.views.active().map(|v| v.id) =>
+            {
+                let v = self.active_view();
+                let mut p = self.active_view_coords(self.cursor);
+                let p: ViewCoords<u32> = p.floor().into();
+
+                v.color_at(p).map(|_| (v.id, p, v.current_edit(), self.fg))
+            }
+            _ => None,
+        };
+        let key = if let Some(key) = key {
+            key
+        } else {
+            self.fill_preview = None;
+            return;
+        };
+
+        let mut preview = match self.fill_preview.take() {
+            Some(mut preview) if preview.key == key => {
+                preview.idle += delta;
+                preview
+            }
+            _ => FillPreview {
+                key,
+                idle: time::Duration::ZERO,
+                shapes: None,
+            },
+        };
+        if preview.shapes.is_none() && preview.idle >= Self::FILL_PREVIEW_DELAY {
+            let (id, p, _, color) = preview.key;
+            let v = self.views.get(id).expect("the view must exist");
+            // Filling with the color already under the cursor changes nothing.
+            let shapes = if v.color_at(p) == Some(color) {
+                Vec::new()
+            } else {
+                let color = color.alpha((color.a as f32 * Self::FILL_PREVIEW_OPACITY) as u8);
+
+                FloodFiller::new(v, ViewCoords::new(p.x as f32, p.y as f32), color)
+                    .and_then(|f| f.run())
+                    .unwrap_or_default()
+            };
+            preview.shapes = Some(self.clip_shapes(shapes));
+            self.dirty = true;
+        }
+        let preview = self.fill_preview.insert(preview);
+
+        if let Some(shapes) = preview.shapes.as_ref().filter(|s| !s.is_empty()) {
+            self.effects.push(Effect::ViewPaintDraft(shapes.clone()));
+        }
+    }
+
+    /// Record the palette and selection changes made since the last call in
+    /// the active view's history, so that they can be undone. Nothing is
+    /// recorded while the mouse is pressed, so that a drag is undone as a whole.
+    fn record_edits(&mut self) {
+        if self.mouse_state == InputState::Pressed || self.views.is_empty() {
+            return;
+        }
+        let current = self.recorded();
+        let recorded = std::mem::replace(&mut self.recorded, current.clone());
+        let mut edits = Vec::new();
+
+        // Switching palettes isn't undoable, and neither is selecting from
+        // scratch, or leaving visual mode.
+        if current.palette == recorded.palette
+            && current.colors != recorded.colors
+            && self.settings["history/palette"].is_set()
+        {
+            edits.push(Edit::PaletteChanged(
+                current.palette,
+                recorded.colors,
+                current.colors,
+            ));
+        }
+        if recorded.selection.is_some()
+            && current.selection != recorded.selection
+            && matches!(self.mode, Mode::Visual(_))
+            && self.settings["history/selection"].is_set()
+        {
+            edits.push(Edit::SelectionChanged(
+                recorded.selection,
+                current.selection,
+            ));
+        }
+        let v = self.active_view_mut();
+        for edit in edits {
+            v.resource.borrow_mut().history_record(edit);
+        }
+    }
+
     ///////////////////////////////////////////////////////////////////////////
     // Internal command handler
     ///////////////////////////////////////////////////////////////////////////
@@ -1790,6 +3803,16 @@ impl Session {
                     self.handle_mouse_wheel(delta);
                 }
             }
+            Event::TouchpadScroll(delta) => {
+                if self.settings["input/mouse"].is_set() {
+                    self.handle_touchpad_scroll(delta);
+                }
+            }
+            Event::TouchpadMagnify(delta) => {
+                if self.settings["input/mouse"].is_set() {
+                    self.handle_touchpad_magnify(delta);
+                }
+            }
             Event::CursorMoved(position) => {
                 if self.settings["input/mouse"].is_set() {
                     let coords = self.window_to_session_coords(position);
@@ -1803,30 +3826,33 @@ impl Session {
     }
 
     pub fn resize(&mut self, size: platform::LogicalSize, scale: f64) {
-        let (w, h) = (size.width / scale, size.height / scale);
+        // With fractional scales, round down so that the UI stays aligned to
+        // whole pixels. The renderer's screen buffer is sized the same way.
+        let (w, h) = ((size.width / scale).floor(), (size.height / scale).floor());
 
         self.width = w as f32;
         self.height = h as f32;
 
         // TODO: Reset session cursor coordinates
         self.center_palette();
+        self.center_panel();
         self.center_active_view();
     }
 
-    pub fn rescale(&mut self, old: f64, new: f64) {
-        let (w, h) = (self.width as f64 * old, self.height as f64 * old);
-
-        self.resize(platform::LogicalSize::new(w, h), new);
+    pub fn rescale(&mut self, new: f64) {
+        self.resize(self.window, new);
         self.effects.push(Effect::SessionScaled(new));
     }
 
     pub fn handle_resized(&mut self, size: platform::LogicalSize) {
+        self.window = size;
         self.resize(size, self.settings["scale"].to_f64());
         self.effects.push(Effect::SessionResized(size));
     }
 
     fn handle_mouse_input(&mut self, button: platform::MouseButton, state: platform::InputState) {
         if button != platform::MouseButton::Left {
+            self.handle_mouse_binding(button, state);
             return;
         }
         self.mouse_state = state;
@@ -1849,12 +3875,24 @@ impl Session {
 
         match state {
             InputState::Pressed => {
+                // Click on settings panel.
+                if let Some(field) = self.panel.hover {
+                    self.panel.drag = Some(Drag {
+                        field,
+                        x: self.cursor.x,
+                        moved: false,
+                    });
+                    return;
+                }
+
                 // Click on palette.
                 if let Some(color) = self.palette.hover {
                     if self.mode == Mode::Command {
                         self.cmdline.puts(&Rgb8::from(color).to_string());
                     } else {
                         self.pick_color(color);
+                        // Colors can be dragged to reorder them.
+                        self.palette.drag = self.palette.index(self.cursor);
                     }
                     return;
                 }
@@ -1866,30 +3904,76 @@ impl Session {
                         self.cmdline_hide();
                         return;
                     }
+                    if self.mode == Mode::Search {
+                        self.search_hide();
+                        return;
+                    }
+                    // Move views around in the free layout.
+                    if self.mode == Mode::Normal
+                        && self.layout() == Layout::Free
+                        && self.keys_pressed.contains(&Key::Super)
+                    {
+                        let offset = self.view(id).offset;
+                        let grab = Vector2::new(
+                            self.cursor.x - self.offset.x - offset.x,
+                            self.cursor.y - self.offset.y - offset.y,
+                        );
+                        self.activate(id);
+                        self.view_drag = Some((id, grab));
+
+                        return;
+                    }
                     if self.is_active(id) {
                         let v = self.view(id);
                         let p = self.active_view_coords(self.cursor);
+                        let snapped = self.snap_to_guides(p.into());
 
                         let extent = v.extent();
 
                         match self.mode {
                             Mode::Normal => match self.tool {
                                 Tool::Brush => {
+                                    let opacity = self.settings["brush/opacity"].to_u64();
+                                    let opacity =
+                                        |c: Rgba8| c.alpha((c.a as u64 * opacity / 255) as u8);
                                     let color = if self.brush.is_set(BrushMode::Erase) {
                                         Rgba8::TRANSPARENT
                                     } else {
-                                        self.fg
+                                        opacity(self.fg)
+                                    };
+                                    self.brush.stabilize =
+                                        self.settings["brush/stabilize"].to_u64() as usize;
+                                    self.brush.grid = if self.settings["grid"].is_set()
+                                        && self.settings["brush/grid"].is_set()
+                                    {
+                                        Some(self.settings["grid/spacing"].clone().into())
+                                    } else {
+                                        None
                                     };
-                                    self.brush.start_drawing(p.into(), color, extent);
+                                    self.brush.start_drawing(
+                                        snapped,
+                                        color,
+                                        opacity(self.bg),
+                                        extent,
+                                    );
+                                }
+                                Tool::Sampler if self.keys_pressed.contains(&Key::Alt) => {
+                                    if let Some(color) = self.active_view().color_at(p.into()) {
+                                        self.select_color(color);
+                                    }
                                 }
                                 Tool::Sampler => {
                                     self.sample_color();
                                 }
                                 Tool::Pan(_) => {}
+                                Tool::Measure(_) => {
+                                    self.tool = Tool::Measure(Some(Ruler::new(snapped, snapped)));
+                                }
                                 Tool::FloodFill => {
                                     let start_time = time::Instant::now();
                                     let filler = FloodFiller::new(self.active_view(), p, self.fg);
                                     if let Some(shapes) = filler.and_then(|f| f.run()) {
+                                        let shapes = self.clip_shapes(shapes);
                                         self.effects.push(Effect::ViewPaintFinal(shapes));
                                         self.active_view_mut().touch();
                                     }
@@ -1901,11 +3985,17 @@ impl Session {
                             }
                             Mode::Visual(VisualState::Selecting { ref mut dragging }) => {
                                 let p = p.map(|n| n as i32);
-                                let unit = Selection::new(p.x, p.y, p.x + 1, p.y + 1);
+                                let unit = Selection::new(
+                                    snapped.x,
+                                    snapped.y,
+                                    snapped.x + 1,
+                                    snapped.y + 1,
+                                );
 
                                 if let Some(s) = &mut self.selection {
                                     if s.abs().bounds().contains(p) {
                                         *dragging = true;
+                                        self.selection_drag = Some((ViewCoords::new(p.x, p.y), *s));
                                     } else {
                                         self.selection = Some(unit);
                                     }
@@ -1914,11 +4004,15 @@ impl Session {
                                 }
                             }
                             Mode::Visual(VisualState::Pasting) => {
-                                // Re-center the selection in-case we've switched layer.
-                                self.center_selection(self.cursor);
-                                self.command(Command::SelectionPaste);
+                                if let Some(anchor) = self.paste_corner(p.map(|n| n as i32)) {
+                                    self.paste_anchor = Some(anchor);
+                                } else {
+                                    // Re-center the selection in-case we've switched layer.
+                                    self.center_selection(self.cursor);
+                                    self.command(Command::SelectionPaste);
+                                }
                             }
-                            Mode::Present | Mode::Help => {}
+                            Mode::Present | Mode::Help | Mode::Search => {}
                         }
                     } else {
                         self.activate(id);
@@ -1935,16 +4029,47 @@ impl Session {
                     }
                 }
             }
+            InputState::Released if self.view_drag.is_some() => {
+                self.view_drag = None;
+            }
+            InputState::Released if self.paste_anchor.is_some() => {
+                self.paste_anchor = None;
+            }
+            InputState::Released if self.palette.drag.is_some() => {
+                self.palette.drag = None;
+            }
+            InputState::Released if self.panel.drag.is_some() => {
+                if let Some(Drag {
+                    field,
+                    moved: false,
+                    ..
+                }) = self.panel.drag.take()
+                {
+                    // The field was clicked without dragging, let the user type in a value.
+                    self.switch_mode(Mode::Command);
+                    self.cmdline.puts(field.input());
+                }
+            }
             InputState::Released => match self.mode {
                 Mode::Visual(VisualState::Selecting { ref mut dragging }) => {
                     *dragging = false;
+                    self.selection_drag = None;
+                    self.selection_aspect = None;
                 }
                 Mode::Normal => {
                     if let Tool::Brush = self.tool {
                         match self.brush.state {
                             BrushState::Drawing { .. } | BrushState::DrawStarted { .. } => {
+                                if self.brush.is_set(BrushMode::Erase) {
+                                    self.save_erased();
+                                }
                                 self.brush.stop_drawing();
-                                self.active_view_mut().touch();
+
+                                if self.brush.is_blended() {
+                                    self.blend_stroke();
+                                } else {
+                                    self.active_view_mut().touch();
+                                }
                             }
                             _ => {}
                         }
@@ -1956,17 +4081,121 @@ impl Session {
         }
     }
 
+    /// Run the key binding for a mouse button other than the left one, if any.
+    /// When the binding switches tools, the button is then used with the tool
+    /// as if it were the left button, eg. to drag with the pan tool.
+    fn handle_mouse_binding(&mut self, button: platform::MouseButton, state: InputState) {
+        let binding = self.key_bindings.find(
+            Input::Mouse(button),
+            ModifiersState::default(),
+            state,
+            self.mode,
+        );
+
+        match state {
+            InputState::Pressed => {
+                if let Some(kb) = binding {
+                    let is_tool = matches!(kb.command, Command::Tool(_));
+
+                    self.command(kb.command);
+
+                    if is_tool && self.mouse_state == InputState::Released {
+                        self.mouse_tool = Some(button);
+                        self.handle_mouse_input(platform::MouseButton::Left, state);
+                    }
+                }
+            }
+            InputState::Released => {
+                if self.mouse_tool == Some(button) {
+                    self.mouse_tool = None;
+                    self.handle_mouse_input(platform::MouseButton::Left, state);
+                }
+                if let Some(kb) = binding {
+                    self.command(kb.command);
+                }
+            }
+            InputState::Repeated => {}
+        }
+    }
+
     fn handle_mouse_wheel(&mut self, delta: platform::LogicalDelta) {
-        if delta.y > 0. {
+        let wheel = if delta.y > 0. {
+            Wheel::Up
+        } else if delta.y < 0. {
+            Wheel::Down
+        } else {
+            return;
+        };
+
+        if let Some(field) = self.panel.hover {
+            let delta = if wheel == Wheel::Up { 1 } else { -1 };
+            self.command(field.adjust(&self.settings, self.brush.size, delta));
+            return;
+        }
+
+        if self.keys_pressed.contains(&Key::Control) {
             if let Some(v) = self.hover_view {
                 self.activate(v);
             }
-            self.zoom_in(self.cursor);
-        } else if delta.y < 0. {
-            self.zoom_out(self.cursor);
+            self.zoom_smooth(delta.y as f32 * Self::WHEEL_ZOOM_SPEED, self.cursor);
+            return;
+        }
+
+        if let Some(kb) = self.key_bindings.find(
+            Input::Wheel(wheel),
+            ModifiersState::default(),
+            InputState::Pressed,
+            self.mode,
+        ) {
+            self.command(kb.command);
+            return;
+        }
+
+        match wheel {
+            Wheel::Up => {
+                if let Some(v) = self.hover_view {
+                    self.activate(v);
+                }
+                self.zoom_in(self.cursor);
+            }
+            Wheel::Down => {
+                self.zoom_out(self.cursor);
+            }
+        }
+    }
+
+    /// Two-finger scrolling pans the workspace, and pinching zooms smoothly
+    /// around the cursor. Pinches are reported as scrolls with `<ctrl>` held.
+    fn handle_touchpad_scroll(&mut self, delta: platform::LogicalDelta) {
+        if !self.settings["input/gestures"].is_set() || self.panel.hover.is_some() {
+            self.handle_mouse_wheel(delta);
+            return;
+        }
+
+        if self.keys_pressed.contains(&Key::Control) {
+            if let Some(v) = self.hover_view {
+                self.activate(v);
+            }
+            self.zoom_smooth(delta.y as f32 * Self::PINCH_ZOOM_SPEED, self.cursor);
+        } else {
+            self.pan(
+                -delta.x as f32 * Self::TOUCHPAD_PAN_SPEED,
+                -delta.y as f32 * Self::TOUCHPAD_PAN_SPEED,
+            );
         }
     }
 
+    /// Pinching zooms smoothly around the cursor.
+    fn handle_touchpad_magnify(&mut self, delta: f64) {
+        if !self.settings["input/gestures"].is_set() || self.panel.hover.is_some() {
+            return;
+        }
+        if let Some(v) = self.hover_view {
+            self.activate(v);
+        }
+        self.zoom_smooth((1. + delta as f32).max(f32::EPSILON).ln(), self.cursor);
+    }
+
     fn handle_cursor_moved(&mut self, cursor: SessionCoords) {
         if self.cursor == cursor {
             return;
@@ -1980,6 +4209,43 @@ impl Session {
         self.cursor = cursor;
         self.cursor_dirty();
 
+        let snapped = self.snap_to_guides(p.into());
+
+        // Adjust the settings panel field being dragged.
+        if let Some(drag) = &mut self.panel.drag {
+            let steps = ((cursor.x - drag.x) / Panel::DRAG_STEP) as i32;
+
+            if steps != 0 {
+                let field = drag.field;
+
+                drag.x += steps as f32 * Panel::DRAG_STEP;
+                drag.moved = true;
+
+                self.command(field.adjust(&self.settings, self.brush.size, steps));
+            }
+            return;
+        }
+
+        // Move the view being dragged along with the cursor.
+        if let Some((id, grab)) = self.view_drag {
+            let offset = Vector2::new(cursor.x - self.offset.x, cursor.y - self.offset.y) - grab;
+
+            self.view_mut(id).offset = offset.map(f32::floor);
+            return;
+        }
+
+        // Move the palette color being dragged to the position under the cursor.
+        if let Some(from) = self.palette.drag {
+            match self.palette.index(cursor) {
+                Some(to) if to != from => {
+                    self.palette.move_color(from, to);
+                    self.palette.drag = Some(to);
+                }
+                _ => {}
+            }
+            return;
+        }
+
         match self.tool {
             Tool::Pan(PanState::Panning) => {
                 self.pan(cursor.x - prev_cursor.x, cursor.y - prev_cursor.y);
@@ -1993,7 +4259,7 @@ impl Session {
                         Tool::Brush if p != prev_p => match self.brush.state {
                             BrushState::DrawStarted { .. } | BrushState::Drawing { .. } => {
                                 let brush = &mut self.brush;
-                                let mut p: ViewCoords<i32> = p.into();
+                                let mut p = snapped;
 
                                 if brush.is_set(BrushMode::Multi) {
                                     p.clamp(Rect::new(
@@ -2007,12 +4273,44 @@ impl Session {
                             }
                             _ => {}
                         },
+                        Tool::Measure(Some(ref mut ruler))
+                            if self.mouse_state == InputState::Pressed =>
+                        {
+                            ruler.to = snapped;
+                        }
                         _ => {}
                     },
                     Mode::Visual(VisualState::Selecting { dragging: false }) => {
                         if self.mouse_state == InputState::Pressed {
+                            let mut end = self
+                                .snap_to_guides(ViewCoords::new(p.x as i32 + 1, p.y as i32 + 1));
+                            let locked = self.aspect_locked();
+
                             if let Some(ref mut s) = self.selection {
-                                *s = Selection::new(s.x1, s.y1, p.x as i32 + 1, p.y as i32 + 1);
+                                if locked {
+                                    // Keep the aspect ratio the selection had when it was
+                                    // locked, growing the shorter side towards the cursor.
+                                    let r = s.abs().bounds();
+                                    let (w, h) = *self
+                                        .selection_aspect
+                                        .get_or_insert((r.width(), r.height()));
+                                    let (dx, dy) = (end.x - 1 - s.x1, end.y - 1 - s.y1);
+                                    let (mut sw, mut sh) = (dx.abs() + 1, dy.abs() + 1);
+                                    let sign = |d: i32| if d < 0 { -1 } else { 1 };
+
+                                    if sw * h >= sh * w {
+                                        sh = Self::aspect_dimension(sw, w, h);
+                                    } else {
+                                        sw = Self::aspect_dimension(sh, h, w);
+                                    }
+                                    end = ViewCoords::new(
+                                        s.x1 + (sw - 1) * sign(dx) + 1,
+                                        s.y1 + (sh - 1) * sign(dy) + 1,
+                                    );
+                                } else {
+                                    self.selection_aspect = None;
+                                }
+                                *s = Selection::new(s.x1, s.y1, end.x, end.y);
                             }
                         }
                     }
@@ -2021,20 +4319,32 @@ impl Session {
 
                         // Resize selection.
                         if self.mouse_state == InputState::Pressed && p != prev_p {
-                            if let Some(ref mut s) = self.selection {
-                                // TODO: (rgx) Better API.
-                                let delta = *p - Vector2::new(prev_p.x, prev_p.y);
-                                let delta = Vector2::new(delta.x as i32, delta.y as i32);
-                                let t = Selection::from(s.bounds() + delta);
+                            // Move relative to where the drag started, so that snapping
+                            // doesn't hold the selection back.
+                            if let (Some(_), Some((origin, start))) =
+                                (self.selection, self.selection_drag)
+                            {
+                                let delta =
+                                    Vector2::new(p.x as i32 - origin.x, p.y as i32 - origin.y);
+                                let t = self.snap_selection_to_guides(Selection::from(
+                                    start.bounds() + delta,
+                                ));
 
                                 if view.intersects(t.abs().bounds()) {
-                                    *s = t;
+                                    self.selection = Some(t);
                                 }
                             }
                         }
                     }
                     Mode::Visual(VisualState::Pasting) => {
-                        self.center_selection(cursor);
+                        if let Some(anchor) = self.paste_anchor {
+                            if self.mouse_state == InputState::Pressed {
+                                let p = Point2::new(p.x as i32, p.y as i32);
+                                self.selection = Some(self.scale_paste(anchor, p));
+                            }
+                        } else if !self.is_scaling_paste() {
+                            self.center_selection(cursor);
+                        }
                     }
                     _ => {}
                 }
@@ -2054,6 +4364,13 @@ impl Session {
                 return;
             }
             self.cmdline_handle_input(c);
+        } else if self.mode == Mode::Search {
+            if c.is_control() || self.ignore_received_characters {
+                return;
+            }
+            self.search.putc(c);
+        } else if self.ignore_received_characters {
+            // The key was used as part of a key sequence.
         } else if let Some(kb) =
             self.key_bindings
                 .find(Input::Character(c), mods, InputState::Pressed, self.mode)
@@ -2074,8 +4391,17 @@ impl Session {
         let state = if repeat { InputState::Pressed } else { state };
 
         if let Some(key) = key {
-            // While the mouse is down, don't accept keyboard input.
+            // While the mouse is down, don't accept keyboard input. Modifiers are
+            // still tracked, since they change how a drag behaves on its next update,
+            // eg. `<alt>` bypasses snapping.
             if self.mouse_state == InputState::Pressed {
+                if key.is_modifier() {
+                    if state == InputState::Pressed {
+                        self.keys_pressed.insert(key);
+                    } else if state == InputState::Released {
+                        self.keys_pressed.remove(&key);
+                    }
+                }
                 return;
             }
 
@@ -2085,6 +4411,7 @@ impl Session {
                 if !self.keys_pressed.remove(&key) {
                     return;
                 }
+                self.pan_keys.remove(&key);
             }
 
             match self.mode {
@@ -2144,16 +4471,57 @@ impl Session {
                         return;
                     }
                 }
-                _ => {}
-            }
-
-            if let Some(kb) = self
-                .key_bindings
-                .find(Input::Key(key), modifiers, state, self.mode)
-            {
-                // For toggle-like key bindings, we don't want to run the command
-                // on key repeats. For regular key bindings, we run the command
-                // depending on if it's supposed to repeat.
+                Mode::Search => {
+                    if state == InputState::Pressed {
+                        match key {
+                            platform::Key::Up => {
+                                self.search.prev();
+                            }
+                            platform::Key::Down | platform::Key::Tab => {
+                                self.search.next();
+                            }
+                            platform::Key::Backspace => {
+                                self.search.delc();
+                            }
+                            platform::Key::Return => {
+                                self.search_handle_enter();
+                            }
+                            platform::Key::Escape => {
+                                self.search_hide();
+                            }
+                            _ => {}
+                        }
+                    }
+                    return;
+                }
+                _ => {}
+            }
+
+            if state == InputState::Pressed
+                && !repeat
+                && !key.is_modifier()
+                && self.handle_key_sequence(key)
+            {
+                return;
+            }
+
+            if let Some(kb) = self
+                .key_bindings
+                .find(Input::Key(key), modifiers, state, self.mode)
+            {
+                // When panning smoothly, the key is tracked until it is released,
+                // and repeats are ignored. See `Session::update_pan`.
+                if let Command::Pan(x, y) = kb.command {
+                    if self.settings["pan/smooth"].is_set() && state == InputState::Pressed {
+                        if repeat {
+                            return;
+                        }
+                        self.pan_keys.insert(key, (x, y));
+                    }
+                }
+                // For toggle-like key bindings, we don't want to run the command
+                // on key repeats. For regular key bindings, we run the command
+                // depending on if it's supposed to repeat.
                 if (repeat && kb.command.repeats() && !kb.is_toggle) || !repeat {
                     self.command(kb.command);
                 }
@@ -2170,6 +4538,45 @@ impl Session {
         }
     }
 
+    /// Advance the current key sequence with the given key. Returns whether the
+    /// key was used by the sequence. The first key of a sequence isn't used up,
+    /// so that its own binding, if any, still runs.
+    fn handle_key_sequence(&mut self, key: platform::Key) -> bool {
+        let timeout = time::Duration::from_millis(self.settings["input/timeout"].to_u64());
+        let (pending, last) = &mut self.key_sequence;
+
+        if last.elapsed() > timeout {
+            pending.clear();
+        }
+
+        match self.key_bindings.find_sequence(pending, key, self.mode) {
+            Sequence::Complete(kb) => {
+                pending.clear();
+                self.ignore_received_characters = true;
+                self.command(kb.command);
+
+                true
+            }
+            Sequence::Pending => {
+                let first = pending.is_empty();
+
+                pending.push(key);
+                *last = time::Instant::now();
+
+                if !first {
+                    self.ignore_received_characters = true;
+                }
+                !first
+            }
+            Sequence::None if !pending.is_empty() => {
+                // Start over, in case this key starts a new sequence.
+                pending.clear();
+                self.handle_key_sequence(key)
+            }
+            Sequence::None => false,
+        }
+    }
+
     ///////////////////////////////////////////////////////////////////////////
     /// Sourcing
     ///////////////////////////////////////////////////////////////////////////
@@ -2191,14 +4598,110 @@ impl Session {
             })
     }
 
-    /// Source a directory which contains a `.rxrc` script. Returns an
-    /// error if the script wasn't found or couldn't be sourced.
+    /// Source a directory which contains a `.rxrc` script, if the user trusts it.
+    /// Returns an error if the script wasn't found or couldn't be sourced.
+    /// Settings changed by the script are treated as configured on startup, so
+    /// that they aren't saved along with the user's settings.
     fn source_dir<P: AsRef<Path>>(&mut self, dir: P) -> io::Result<()> {
-        self.source_path(dir.as_ref().join(".rxrc"))
+        let script = dir.as_ref().join(config::PROJECT).canonicalize()?;
+        let contents = std::fs::read(&script)?;
+        let trusted = config::Trusted::load(self.proj_dirs.config_dir().join(config::TRUSTED))?;
+
+        if !trusted.contains(&script, &contents) {
+            self.message(
+                format!(
+                    "Warning: {} isn't trusted, and wasn't sourced (`:rxrc/trust` to trust it)",
+                    script.display()
+                ),
+                MessageType::Warning,
+            );
+            self.untrusted = Some((script, contents));
+
+            return Ok(());
+        }
+        self.source_project(&script, contents)
+    }
+
+    /// Source the given contents of a project script.
+    fn source_project(&mut self, script: &Path, contents: Vec<u8>) -> io::Result<()> {
+        let before = self.settings.clone();
+        let result = self
+            .source_reader(io::Cursor::new(contents), script)
+            .map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("error sourcing {}: {}", script.display(), e),
+                )
+            });
+
+        for k in self.settings.names() {
+            if self.settings.get(k) != before.get(k) {
+                self.settings_init.set(k, self.settings[k].clone()).ok();
+            }
+        }
+        result
+    }
+
+    /// Load the project palette in the given directory, if there is one, and
+    /// it isn't already loaded.
+    fn source_palette_dir(&mut self, dir: &Path) {
+        let path = match PROJECT_FILES
+            .iter()
+            .map(|f| dir.join(f))
+            .find(|p| p.is_file())
+        {
+            Some(path) => path.canonicalize().unwrap_or(path),
+            None => return,
+        };
+        if self.project_palette.as_ref() == Some(&path) {
+            return;
+        }
+
+        match read_file(&path) {
+            Ok(colors) => {
+                let capacity = self.palette.colors.capacity();
+
+                self.palette.clear();
+                self.palette
+                    .colors
+                    .extend(colors.into_iter().take(capacity));
+                self.palette.hover = None;
+                self.center_palette();
+                self.message(
+                    format!(
+                        "Palette loaded from {} ({} colors)",
+                        path.display(),
+                        self.palette.size()
+                    ),
+                    MessageType::Info,
+                );
+                self.project_palette = Some(path);
+            }
+            Err(e) => self.message(
+                format!("Error: {}: {}", path.display(), e),
+                MessageType::Error,
+            ),
+        }
+    }
+
+    /// Trust the given project script with the given contents, as they were read
+    /// when the script was found, and source them.
+    fn trust_script(&mut self, script: &Path, contents: Vec<u8>) -> io::Result<()> {
+        let dir = self.proj_dirs.config_dir();
+        let path = dir.join(config::TRUSTED);
+        let mut trusted = config::Trusted::load(&path)?;
+
+        trusted.insert(script.to_path_buf(), &contents);
+        std::fs::create_dir_all(dir)?;
+        trusted.save(&path)?;
+
+        self.source_project(script, contents)
     }
 
     /// Source a script from an [`io::BufRead`].
-    fn source_reader<P: AsRef<Path>, R: io::BufRead>(&mut self, r: R, _path: P) -> io::Result<()> {
+    fn source_reader<P: AsRef<Path>, R: io::BufRead>(&mut self, r: R, path: P) -> io::Result<()> {
+        let generated = path.as_ref() == self.proj_dirs.config_dir().join(config::GENERATED);
+
         for (i, line) in r.lines().enumerate() {
             let line = line?;
 
@@ -2212,16 +4715,421 @@ impl Session {
                         format!("{} on line {}", e, i + 1),
                     ))
                 }
-                Ok(cmd) => self.command(cmd),
+                Ok(cmd) => {
+                    if generated {
+                        self.record_mapping(&line, &cmd);
+                    }
+                    self.command(cmd);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Keep the key mappings made from the command line or sourced from the
+    /// generated config, so that they can be saved.
+    fn record_mapping(&mut self, input: &str, cmd: &Command) {
+        let input = input.trim_start_matches(':').to_owned();
+
+        match cmd {
+            // Mappings made before clearing don't need to be kept.
+            Command::MapClear => self.mappings = vec![input],
+            Command::Map(_) => self.mappings.push(input),
+            _ => {}
+        }
+    }
+
+    /// Save the settings that changed since startup and the recorded key mappings
+    /// to the generated config. Returns the path of the config.
+    fn save_settings(&self) -> io::Result<PathBuf> {
+        let dir = self.proj_dirs.config_dir();
+        let path = dir.join(config::GENERATED);
+        let mut names: Vec<&str> = self
+            .settings
+            .names()
+            .filter(|k| self.settings.get(k) != self.settings_init.get(k))
+            // View settings belong to the active view.
+            .filter(|k| !k.starts_with("view/"))
+            .collect();
+        names.sort_unstable();
+
+        let settings = names
+            .iter()
+            .map(|k| format!("set {} = {}", k, config::value(&self.settings[*k])))
+            .collect();
+        let script = match std::fs::read_to_string(&path) {
+            Ok(script) => script,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(
+            &path,
+            config::update(
+                &script,
+                &[("settings", settings), ("mappings", self.mappings.clone())],
+            ),
+        )?;
+
+        Ok(path)
+    }
+
+    /// Save the pixels covered by the current erase stroke, before they are erased.
+    /// The view snapshot isn't updated until the stroke ends, so it still holds them.
+    fn save_erased(&mut self) {
+        let v = self.active_view();
+        let points: Vec<_> = self
+            .stroke_points()
+            .into_iter()
+            .filter(|p| self.brush.color_at(*p).is_some())
+            .collect();
+
+        let (x1, x2) = (
+            points.iter().map(|p| p.x).min(),
+            points.iter().map(|p| p.x).max(),
+        );
+        let (y1, y2) = (
+            points.iter().map(|p| p.y).min(),
+            points.iter().map(|p| p.y).max(),
+        );
+        if let (Some(x1), Some(y1), Some(x2), Some(y2)) = (x1, y1, x2, y2) {
+            let rect = Rect::new(x1, y1, x2 + 1, y2 + 1);
+
+            if let Some(buffer) = v.pixels_rect(&rect) {
+                // Pixels the stroke didn't cover are left out, as transparent,
+                // so that they aren't restored.
+                let mut pixels = vec![Rgba8::TRANSPARENT; buffer.len()];
+
+                for p in points {
+                    let i = ((rect.y2 - 1 - p.y) * rect.width() + p.x - rect.x1) as usize;
+                    pixels[i] = buffer[i];
+                }
+                self.erased = Some(Erased {
+                    view: v.id,
+                    rect,
+                    pixels,
+                });
+            }
+        }
+    }
+
+    /// Add the colors of an image file to the palette, without opening it in a view.
+    /// If a count is given, the colors are first reduced to at most that many.
+    /// Returns the number of colors added.
+    fn sample_palette_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        count: Option<usize>,
+    ) -> io::Result<usize> {
+        let (_, _, pixels) = crate::io::load_image(path)?;
+        let pixels: Vec<_> = pixels
+            .into_iter()
+            .filter(|c| *c != Rgba8::TRANSPARENT)
+            .collect();
+
+        let colors = match count {
+            Some(n) => crate::palette::quantize(&pixels, n),
+            None => {
+                let mut unique = pixels;
+                unique.sort_unstable();
+                unique.dedup();
+                unique
+            }
+        };
+        let new: Vec<_> = colors
+            .into_iter()
+            .filter(|c| !self.palette.colors.contains(c))
+            .collect();
+        let available = self.palette.colors.capacity() - self.palette.size();
+
+        if new.len() > available {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!(
+                    "{} new colors don't fit in the palette, try `:p/sample <path> {}`",
+                    new.len(),
+                    available
+                ),
+            ));
+        }
+        for c in &new {
+            self.palette.add(*c);
+        }
+        Ok(new.len())
+    }
+
+    /// Recolor the active view using the color mapping file at the given path.
+    /// Each line of the file maps one color to another, eg. `#ff0000 #00ff00`.
+    /// Returns the number of pixels changed.
+    fn recolor_path<P: AsRef<Path>>(&mut self, path: P) -> io::Result<usize> {
+        let path = path.as_ref();
+        let r = io::BufReader::new(File::open(path)?);
+        let parser = parser::color_mapping();
+        let mut mapping = BTreeMap::new();
+
+        for (i, line) in r.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with(cmd::COMMENT) {
+                continue;
+            }
+            match parser.parse(line) {
+                Ok(((from, to), _)) => {
+                    mapping.insert(from, to);
+                }
+                Err((e, _)) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}: {} on line {}", path.display(), e, i + 1),
+                    ))
+                }
+            }
+        }
+        Ok(self.active_view_mut().recolor(&mapping))
+    }
+
+    /// Check whether the view filter source changed, so that it's reloaded, and keep
+    /// redrawing while the filter depends on time.
+    fn watch_filter(&mut self, delta: time::Duration) {
+        let path = match &self.filter {
+            Some(path) => path,
+            None => return,
+        };
+        self.filter_checked += delta;
+
+        if self.filter_checked >= Self::FILTER_POLL_INTERVAL {
+            self.filter_checked = time::Duration::ZERO;
+
+            let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+            if modified != self.filter_modified {
+                self.filter_modified = modified;
+                self.filter_animated = std::fs::read_to_string(path)
+                    .map(|source| Self::is_animated_filter(&source))
+                    .unwrap_or(false);
+                self.dirty = true;
             }
         }
+        self.dirty |= self.filter_animated;
+    }
+
+    /// Get the time remaining until the view filter needs a frame, either to be
+    /// checked for changes, or because it depends on time.
+    fn filter_remaining(&self) -> Option<time::Duration> {
+        self.filter.as_ref()?;
+
+        if self.filter_animated {
+            Some(Self::FILTER_FRAME_INTERVAL)
+        } else {
+            Some(Self::FILTER_POLL_INTERVAL.saturating_sub(self.filter_checked))
+        }
+    }
+
+    /// Check whether a view filter source declares the `time` uniform.
+    fn is_animated_filter(source: &str) -> bool {
+        source.lines().any(|line| {
+            let tokens = line
+                .split(|c: char| c.is_whitespace() || c == ';')
+                .filter(|t| !t.is_empty())
+                .collect::<Vec<_>>();
+
+            tokens.first() == Some(&"uniform") && tokens.last() == Some(&"time")
+        })
+    }
+
+    /// Find a view filter, either relative to the current directory, or in the
+    /// filters directory.
+    fn filter_path(&self, path: &str) -> Option<PathBuf> {
+        [
+            self.cwd.join(path),
+            self.proj_dirs.config_dir().join(Self::FILTERS).join(path),
+        ]
+        .into_iter()
+        .find(|p| p.is_file())
+    }
+
+    /// Load a UI font from a path relative to the working directory. An empty
+    /// path means the built-in font.
+    fn load_font(&self, path: &str) -> io::Result<Option<Font>> {
+        if path.is_empty() {
+            return Ok(None);
+        }
+        let path = self.cwd.join(path);
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("uf2") => Font::from_uf2(&std::fs::read(path)?).map(Some),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only .uf2 fonts are supported",
+            )),
+        }
+    }
+
+    /// Run the named plugin in the background, on the selection, or on the active
+    /// view if there is no selection.
+    fn run_plugin(&mut self, name: &str, args: &[String]) -> io::Result<()> {
+        let plugin = Plugin::find(self.proj_dirs.config_dir().join(plugin::DIR), name)?;
+        let area = self.transform_area();
+        let view = self.active_view();
+        let pixels = view
+            .pixels_rect(&area)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid area"))?;
+        let (id, edit) = (view.id, view.current_edit());
+
+        self.plugins
+            .run(plugin, args.to_vec(), id, area, edit, pixels);
+
         Ok(())
     }
 
+    /// Apply the output of a plugin to the view it was run on, unless the view
+    /// was edited in the meantime.
+    fn handle_plugin_result(&mut self, result: PluginResult) {
+        let PluginResult {
+            name,
+            view,
+            area,
+            edit,
+            result,
+        } = result;
+
+        let output = match result {
+            Ok(output) => output,
+            Err(e) => {
+                self.message(format!("Error: {}", e), MessageType::Error);
+                return;
+            }
+        };
+        let v = match self.views.get_mut(view) {
+            Some(v) if v.current_edit() == edit => v,
+            Some(_) => {
+                self.message(
+                    format!(
+                        "Error: {}: view was edited while the plugin was running",
+                        name
+                    ),
+                    MessageType::Error,
+                );
+                return;
+            }
+            None => return,
+        };
+        let n = v.replace_area(area, &output);
+
+        self.message(
+            format!("{}: {} pixel(s) changed", name, n),
+            MessageType::Info,
+        );
+    }
+
+    /// Get the pixels of the lookup texture view, and its width.
+    fn lut_pixels(&self) -> io::Result<(Vec<Rgba8>, u32)> {
+        let v = self.lut.and_then(|id| self.views.get(id)).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "no lookup texture, designate one with `:lut/set`",
+            )
+        })?;
+        let (extent, pixels) = v.pixels();
+
+        // The view may have been resized since it was designated.
+        if extent.width() > lut::MAX_SIZE || extent.height() > lut::MAX_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "lookup textures can't be larger than {}x{}",
+                    lut::MAX_SIZE,
+                    lut::MAX_SIZE
+                ),
+            ));
+        }
+        Ok((pixels, extent.width()))
+    }
+
+    /// Generate the IR of the active view into a new view, using the lookup
+    /// texture. Returns the number of pixels whose color isn't in the texture.
+    fn generate_lut_ir(&mut self) -> io::Result<usize> {
+        let (lut, width) = self.lut_pixels()?;
+        let v = self.active_view();
+        let (extent, (ir, missing)) =
+            v.with_pixels(|extent, pixels| (extent, lut::ir(pixels, &lut, width)));
+        let ViewExtent { fw, fh, nframes } = extent;
+        let frames = util::split_frames(&ir, fw as usize, fh as usize, nframes);
+        let fps = v.fps;
+
+        let id = self.insert_view(FileStatus::NoFile, fw, fh, frames);
+        self.view_mut(id).fps = fps;
+        self.organize_views();
+        self.edit_view(id);
+
+        Ok(missing)
+    }
+
+    /// Resolve the active view, which holds an IR, through the lookup texture,
+    /// as a single edit. Returns the number of pixels outside the texture.
+    fn apply_lut(&mut self) -> io::Result<usize> {
+        let (lut, width) = self.lut_pixels()?;
+        let (pixels, missing) = self
+            .active_view()
+            .with_pixels(|_, pixels| lut::resolve(pixels, &lut, width));
+
+        self.active_view_mut().paint_pixels(pixels);
+
+        Ok(missing)
+    }
+
     ///////////////////////////////////////////////////////////////////////////
     /// Centering
     ///////////////////////////////////////////////////////////////////////////
 
+    /// Position the settings panel in the top-right corner of the workspace.
+    fn center_panel(&mut self) {
+        self.panel.x = self.width - self.panel.width - Self::PANEL_MARGIN;
+        self.panel.y = self.height - Self::PANEL_MARGIN * 4.;
+    }
+
+    /// Switch to the named palette. The active palette is kept, so that it
+    /// can be switched back to.
+    fn switch_palette(&mut self, name: &str) {
+        let colors = self.palette.colors.to_vec();
+
+        match self.palettes.switch(name, &colors) {
+            Ok(colors) => {
+                let capacity = self.palette.colors.capacity();
+
+                self.palette.clear();
+                self.palette
+                    .colors
+                    .extend(colors.into_iter().take(capacity));
+                self.palette.hover = None;
+                self.center_palette();
+                self.message(
+                    format!("Palette '{}' ({} colors)", name, self.palette.size()),
+                    MessageType::Info,
+                );
+            }
+            Err(e) => self.message(
+                format!("Error: palette '{}': {}", name, e),
+                MessageType::Error,
+            ),
+        }
+    }
+
+    /// Switch to the palette `n` places away from the active one, in order of name.
+    fn cycle_palette(&mut self, n: isize) {
+        let names = self.palettes.names();
+
+        if names.len() > 1 {
+            let i = names
+                .iter()
+                .position(|name| *name == self.palettes.active)
+                .unwrap_or_default() as isize;
+            let i = (i + n).rem_euclid(names.len() as isize) as usize;
+
+            self.switch_palette(&names[i]);
+        }
+    }
+
     /// Center the palette in the workspace.
     fn center_palette(&mut self) {
         let h = self.settings["p/height"].to_u64() as usize;
@@ -2270,62 +5178,203 @@ impl Session {
         }
     }
 
+    /// Center the workspace on the given pixel of the active view, and move
+    /// the cursor to it.
+    fn goto(&mut self, x: i32, y: i32) {
+        let (w, h) = self.active_view().size();
+        let (w, h) = (w as i32, h as i32);
+
+        if !(0..w).contains(&x) || !(0..h).contains(&y) {
+            self.message(
+                format!("Error: pixel {},{} is outside of the view", x, y),
+                MessageType::Error,
+            );
+            return;
+        }
+        let v = self.active_view();
+        // Center of the pixel, relative to the view origin.
+        let px = if v.flip_x { w - x - 1 } else { x };
+        let py = if v.flip_y { h - y - 1 } else { y };
+        let p = Vector2::new(px as f32 + 0.5, py as f32 + 0.5) * v.zoom + v.offset;
+        let center = self.center();
+
+        self.offset = Vector2::new(center.x - p.x, center.y - p.y).map(f32::floor);
+        self.cursor_dirty();
+
+        let cursor = SessionCoords::new(self.offset.x + p.x, self.offset.y + p.y).floor();
+        self.handle_cursor_moved(cursor);
+    }
+
+    /// Move the cursor by the given number of pixels of the active view.
+    fn move_cursor(&mut self, x: i32, y: i32) {
+        let zoom = self.active_view().zoom;
+        let cursor = SessionCoords::new(
+            self.cursor.x + x as f32 * zoom,
+            self.cursor.y + y as f32 * zoom,
+        );
+        self.handle_cursor_moved(cursor);
+    }
+
     /// The session center.
     fn center(&self) -> SessionCoords {
         SessionCoords::new(self.width / 2., self.height / 2.)
     }
 
-    /// Center the selection to the given session coordinates.
-    fn center_selection(&mut self, p: SessionCoords) {
-        let c = self.active_view_coords(p);
-        if let Some(ref mut s) = self.selection {
-            let r = s.abs().bounds();
-            let (w, h) = (r.width(), r.height());
-            let (x, y) = (c.x as i32 - w / 2, c.y as i32 - h / 2);
-            *s = Selection::new(x, y, x + w, y + h);
+    /// Area of the active view to transform: the selection if there is one,
+    /// or the whole view otherwise.
+    fn transform_area(&self) -> Rect<i32> {
+        let bounds = self.active_view().layer_bounds();
+
+        match (self.mode, self.selection) {
+            (Mode::Visual(VisualState::Selecting { .. }), Some(s))
+                if s.abs().bounds().intersects(bounds) =>
+            {
+                s.abs().bounds().intersection(bounds)
+            }
+            _ => bounds,
         }
     }
 
-    ///////////////////////////////////////////////////////////////////////////
-    /// Zoom functions
-    ///////////////////////////////////////////////////////////////////////////
+    /// Whether the floating selection can be scaled by dragging its corners,
+    /// ie. while pasting with `<ctrl>` held. The selection stays in place meanwhile.
+    pub fn is_scaling_paste(&self) -> bool {
+        self.mode == Mode::Visual(VisualState::Pasting) && self.keys_pressed.contains(&Key::Control)
+    }
 
-    /// Zoom the active view in.
-    fn zoom_in(&mut self, center: SessionCoords) {
-        let view = self.active_view_mut();
-        let lvls = Self::ZOOM_LEVELS;
+    /// If the given pixel is a corner of the floating selection, and it can be
+    /// scaled, return the opposite corner, which stays in place while scaling.
+    fn paste_corner(&self, p: Point2<i32>) -> Option<Point2<i32>> {
+        if !self.is_scaling_paste() {
+            return None;
+        }
+        let r = self.selection?.abs().bounds();
+        let opposite = |n: i32, min: i32, max: i32| {
+            if n == min {
+                Some(max)
+            } else if n == max - 1 {
+                Some(min)
+            } else {
+                None
+            }
+        };
+        Some(Point2::new(
+            opposite(p.x, r.x1, r.x2)?,
+            opposite(p.y, r.y1, r.y2)?,
+        ))
+    }
 
-        for (i, zoom) in lvls.iter().enumerate() {
-            if view.zoom <= *zoom {
-                if let Some(z) = lvls.get(i + 1) {
-                    self.zoom(*z, center);
-                } else {
-                    self.message("Maximum zoom level reached", MessageType::Hint);
-                }
-                return;
+    /// The floating selection spanning from the fixed corner to the dragged pixel.
+    /// Its size snaps to integer multiples of the paste buffer size, unless
+    /// `<alt>` is held, and keeps the paste buffer's aspect ratio if locked.
+    fn scale_paste(&self, anchor: Point2<i32>, p: Point2<i32>) -> Selection {
+        let (pw, ph) = self.paste_size;
+        let snap = !self.keys_pressed.contains(&Key::Alt);
+        let (mut w, mut h) = (
+            Self::scale_dimension((p.x - anchor.x).abs() + (p.x >= anchor.x) as i32, pw, snap),
+            Self::scale_dimension((p.y - anchor.y).abs() + (p.y >= anchor.y) as i32, ph, snap),
+        );
+        if self.aspect_locked() {
+            if w * ph >= h * pw {
+                h = Self::aspect_dimension(w, pw, ph);
+            } else {
+                w = Self::aspect_dimension(h, ph, pw);
             }
         }
+        let (x1, x2) = if p.x >= anchor.x {
+            (anchor.x, anchor.x + w)
+        } else {
+            (anchor.x - w, anchor.x)
+        };
+        let (y1, y2) = if p.y >= anchor.y {
+            (anchor.y, anchor.y + h)
+        } else {
+            (anchor.y - h, anchor.y)
+        };
+        Selection::new(x1, y1, x2, y2)
+    }
+
+    /// Snap a dimension to the nearest multiple of `unit`, if it's within a
+    /// quarter of `unit` of it. The result is never smaller than `1`.
+    fn scale_dimension(n: i32, unit: i32, snap: bool) -> i32 {
+        if snap && unit > 0 {
+            let k = ((n as f32 / unit as f32).round() as i32).max(1);
+
+            if (n - k * unit).abs() <= unit / 4 {
+                return k * unit;
+            }
+        }
+        n.max(1)
+    }
+
+    /// Center the selection to the given session coordinates.
+    fn center_selection(&mut self, p: SessionCoords) {
+        let c = self.active_view_coords(p);
+        if let Some(s) = self.selection {
+            let r = s.abs().bounds();
+            let (w, h) = (r.width(), r.height());
+            let (x, y) = (c.x as i32 - w / 2, c.y as i32 - h / 2);
+
+            self.selection =
+                Some(self.snap_selection_to_guides(Selection::new(x, y, x + w, y + h)));
+        }
+    }
+
+    ///////////////////////////////////////////////////////////////////////////
+    /// Zoom functions
+    ///////////////////////////////////////////////////////////////////////////
+
+    /// Zoom the active view in.
+    fn zoom_in(&mut self, center: SessionCoords) {
+        let zoom = self.active_view().zoom;
+
+        // The view may be zoomed in between levels, eg. after a pinch.
+        if let Some(z) = Self::ZOOM_LEVELS.iter().find(|z| **z > zoom) {
+            self.zoom(*z, center);
+        } else {
+            self.message("Maximum zoom level reached", MessageType::Hint);
+        }
     }
 
     /// Zoom the active view out.
     fn zoom_out(&mut self, center: SessionCoords) {
-        let view = self.active_view_mut();
-        let lvls = Self::ZOOM_LEVELS;
-
-        for (i, zoom) in lvls.iter().enumerate() {
-            if view.zoom <= *zoom {
-                if i == 0 {
-                    self.message("Minimum zoom level reached", MessageType::Hint);
-                } else if let Some(z) = lvls.get(i - 1) {
-                    self.zoom(*z, center);
-                } else {
-                    unreachable!();
-                }
-                return;
-            }
+        let zoom = self.active_view().zoom;
+
+        if let Some(z) = Self::ZOOM_LEVELS.iter().rev().find(|z| **z < zoom) {
+            self.zoom(*z, center);
+        } else {
+            self.message("Minimum zoom level reached", MessageType::Hint);
         }
     }
 
+    /// Zoom the active view by a factor of `e^amount`, to any level in between
+    /// the fixed zoom levels. Takes a center to zoom to.
+    fn zoom_smooth(&mut self, amount: f32, center: SessionCoords) {
+        let z = (self.active_view().zoom * amount.exp()).clamp(1., Self::MAX_ZOOM);
+        let z = if (z - z.round()).abs() < Self::ZOOM_SNAP {
+            z.round()
+        } else {
+            z
+        };
+        self.zoom(z, center);
+    }
+
+    /// Zoom the active view to the largest whole level at which it fits in
+    /// the workspace, and center it.
+    fn zoom_fit(&mut self) {
+        let v = self.active_view();
+        let (w, h) = (
+            self.width - Self::VIEW_MARGIN * 2.,
+            self.height - Self::VIEW_MARGIN * 2.,
+        );
+        let z = (w / v.width() as f32)
+            .min(h / v.height() as f32)
+            .floor()
+            .clamp(1., Self::MAX_ZOOM);
+
+        self.zoom(z, self.center());
+        self.center_active_view();
+    }
+
     /// Set the active view zoom. Takes a center to zoom to.
     fn zoom(&mut self, z: f32, center: SessionCoords) {
         let px = center.x - self.offset.x;
@@ -2361,13 +5410,21 @@ impl Session {
     ///////////////////////////////////////////////////////////////////////////
 
     /// Process a command.
-    fn command(&mut self, cmd: Command) {
+    pub(crate) fn command(&mut self, cmd: Command) {
         debug!("command: {:?}", cmd);
 
         match cmd {
             Command::Mode(m) => {
                 self.toggle_mode(m);
             }
+            Command::History => {
+                if self.cmdline.history.is_empty() {
+                    self.message("Error: command history is empty", MessageType::Error);
+                } else {
+                    self.switch_mode(Mode::Search);
+                    self.search = Search::new(self.history_entries());
+                }
+            }
             Command::Quit => {
                 self.quit_view_safe(self.views.active_id);
             }
@@ -2378,171 +5435,570 @@ impl Session {
                     self.quit_view_safe(id);
                 }
             }
-            Command::SwapColors => {
-                std::mem::swap(&mut self.fg, &mut self.bg);
+            Command::ColorMix(a, b, t, fg) => {
+                self.color_result(color::mix(a, b, t), fg);
+            }
+            Command::ColorShift(c, ref shifts, fg) => {
+                self.color_result(color::shift(c, shifts), fg);
+            }
+            Command::SwapColors => {
+                std::mem::swap(&mut self.fg, &mut self.bg);
+            }
+            Command::ColorPrev => {
+                self.cycle_color(true);
+            }
+            Command::ColorNext => {
+                self.cycle_color(false);
+            }
+            Command::BrushSet(mode) => {
+                self.brush.set(mode);
+            }
+            Command::BrushUnset(mode) => {
+                self.brush.unset(mode);
+            }
+            Command::BrushToggle(mode) => {
+                self.brush.toggle(mode);
+            }
+            Command::Brush => {
+                self.unimplemented();
+            }
+            Command::BrushSize(op) => {
+                let b = &mut self.brush;
+                match op {
+                    Op::Incr => {
+                        b.size += 1;
+                        b.size += b.size % 2;
+                    }
+                    Op::Decr => {
+                        b.size -= 1;
+                        b.size -= b.size % 2;
+                    }
+                    Op::Set(s) => {
+                        b.size = s as usize;
+                    }
+                }
+                if b.size < Self::MIN_BRUSH_SIZE {
+                    b.size = Self::MIN_BRUSH_SIZE;
+                }
+                if self.settings["ui/brush-hud"].is_set() {
+                    self.brush_hud = Self::BRUSH_HUD_DURATION;
+                }
+            }
+            Command::FrameResize(mut fw, mut fh) => {
+                if self.aspect_locked() {
+                    let v = self.active_view();
+
+                    // Derive the dimension that wasn't changed from the one that was.
+                    if fw != v.fw {
+                        fh = Self::aspect_dimension(fw as i32, v.fw as i32, v.fh as i32) as u32;
+                    } else {
+                        fw = Self::aspect_dimension(fh as i32, v.fh as i32, v.fw as i32) as u32;
+                    }
+                }
+                if fw == 0 || fh == 0 {
+                    self.message(
+                        "Error: cannot set frame dimension to `0`",
+                        MessageType::Error,
+                    );
+                    return;
+                }
+                if fw > Self::MAX_FRAME_SIZE || fh > Self::MAX_FRAME_SIZE {
+                    self.message(
+                        format!(
+                            "Error: maximum frame size is {}x{}",
+                            Self::MAX_FRAME_SIZE,
+                            Self::MAX_FRAME_SIZE,
+                        ),
+                        MessageType::Error,
+                    );
+                    return;
+                }
+
+                let v = self.active_view_mut();
+                v.resize_frames(fw, fh);
+
+                self.check_selection();
+                self.organize_views();
+            }
+            Command::FramePair(a, b) => {
+                let nframes = self.active_view().animation.len();
+
+                if a == b {
+                    self.message("Error: can't pair a frame with itself", MessageType::Error);
+                } else if a >= nframes || b >= nframes {
+                    self.message(
+                        format!("Error: view only has {} frame(s)", nframes),
+                        MessageType::Error,
+                    );
+                } else {
+                    self.active_view_mut().pairs.insert((a.min(b), a.max(b)));
+                }
+            }
+            Command::FrameUnpair => {
+                self.active_view_mut().pairs.clear();
+            }
+            Command::Guide(axis, offset) => {
+                if !self.guides.contains(&(axis, offset)) {
+                    self.guides.push((axis, offset));
+                }
+            }
+            Command::GuideClear => {
+                self.guides.clear();
+            }
+            Command::FramePrev => {
+                let v = self.active_view().extent();
+                let center = self.active_view_coords(self.center());
+
+                if center.x >= 0. {
+                    let frame = v.to_frame(center.into()).min(v.nframes);
+                    self.center_active_view_frame(frame.saturating_sub(1));
+                }
+            }
+            Command::FrameNext => {
+                let v = self.active_view().extent();
+                let center = self.active_view_coords(self.center());
+                let frame = v.to_frame(center.into());
+
+                if center.x < 0. {
+                    self.center_active_view_frame(0);
+                } else if frame < v.nframes {
+                    self.center_active_view_frame((frame + 1).min(v.nframes - 1));
+                }
+            }
+            Command::ForceQuit => self.quit_view(self.views.active_id),
+            Command::ForceQuitAll => self.quit(ExitReason::Normal),
+            Command::Echo(ref v) => {
+                let result = match v {
+                    Value::Str(s) => Ok(Value::Str(s.clone())),
+                    Value::Ident(s) => match s.as_str() {
+                        "config/dir" => Ok(Value::Str(format!(
+                            "{}",
+                            self.proj_dirs.config_dir().display()
+                        ))),
+                        "s/cwd" | "cwd" => Ok(Value::Str(self.cwd.display().to_string())),
+                        "s/offset" => Ok(Value::F32Tuple(self.offset.x, self.offset.y)),
+                        "v/offset" => {
+                            let v = self.active_view();
+                            Ok(Value::F32Tuple(v.offset.x, v.offset.y))
+                        }
+                        "v/zoom" => Ok(Value::F64(self.active_view().zoom as f64)),
+                        _ => match self.settings.get(s) {
+                            None => Err(format!("Error: {} is undefined", s)),
+                            Some(result) => Ok(Value::Str(format!("{} = {}", v.clone(), result))),
+                        },
+                    },
+                    _ => Err(format!("Error: argument cannot be echoed")),
+                };
+                match result {
+                    Ok(v) => self.message(v, MessageType::Echo),
+                    Err(e) => self.message(e, MessageType::Error),
+                }
+            }
+            Command::PaletteAdd(rgba) => {
+                self.palette.add(rgba);
+                self.center_palette();
+            }
+            Command::PaletteClear => {
+                self.palette.clear();
+            }
+            Command::PaletteGradient(colorstart, colorend, steps) => {
+                self.palette.gradient(colorstart, colorend, steps);
+                self.center_palette();
+            }
+            Command::PaletteSort => {
+                // Sort by total luminosity. This is pretty lame, but it's
+                // something to work with.
+                self.palette.colors.sort_by(|a, b| {
+                    (a.r as u32 + a.g as u32 + a.b as u32)
+                        .cmp(&(b.r as u32 + b.g as u32 + b.b as u32))
+                });
+            }
+            Command::PaletteSample => {
+                {
+                    let id = self.views.active_id;
+                    let palette = &mut self.palette;
+
+                    self.views
+                        .get(id)
+                        .expect(&format!("view #{} must exist", id))
+                        .with_pixels(|_, pixels| {
+                            for pixel in pixels.iter().cloned() {
+                                if pixel != Rgba8::TRANSPARENT {
+                                    palette.add(pixel);
+                                }
+                            }
+                        });
+                }
+                self.command(Command::PaletteSort);
+                self.center_palette();
+            }
+            Command::PaletteSampleFile(ref path, count) => {
+                match self.sample_palette_file(path, count) {
+                    Ok(n) => {
+                        self.command(Command::PaletteSort);
+                        self.center_palette();
+                        self.message(
+                            format!("{} color(s) sampled from {}", n, path),
+                            MessageType::Info,
+                        );
+                    }
+                    Err(e) => self.message(format!("Error: {}: {}", path, e), MessageType::Error),
+                }
+            }
+            Command::Stats => {
+                let v = self.active_view();
+                let bounds = v.layer_bounds();
+                let rect = match (self.mode, self.selection) {
+                    (Mode::Visual(_), Some(s)) if s.abs().bounds().intersects(bounds) => {
+                        s.abs().bounds().intersection(bounds)
+                    }
+                    _ => bounds,
+                };
+                let pixels = v.pixels_rect(&rect).expect("rect should be within view");
+                let usage = color::usage(&pixels);
+                let opaque: usize = usage.iter().map(|(_, n)| n).sum();
+                let top = usage
+                    .iter()
+                    .take(Self::STATS_COLORS)
+                    .map(|(c, n)| format!("{} ({})", c, n))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+
+                self.message(
+                    format!("{} color(s), {} pixel(s): {}", usage.len(), opaque, top),
+                    MessageType::Info,
+                );
+            }
+            Command::PaletteNew(name) => {
+                if self.palettes.exists(&name) {
+                    self.message(
+                        format!("Error: palette '{}' already exists", name),
+                        MessageType::Error,
+                    );
+                } else {
+                    self.switch_palette(&name);
+                }
+            }
+            Command::PaletteSwitch(name) => {
+                if name == self.palettes.active {
+                    return;
+                }
+                if self.palettes.exists(&name) {
+                    self.switch_palette(&name);
+                } else {
+                    self.message(
+                        format!("Error: no palette named '{}'", name),
+                        MessageType::Error,
+                    );
+                }
+            }
+            Command::PaletteMove(from, to) => {
+                if !self.palette.move_color(from, to) {
+                    self.message(
+                        format!(
+                            "Error: palette index out of range, must be less than {}",
+                            self.palette.size()
+                        ),
+                        MessageType::Error,
+                    );
+                }
+            }
+            Command::PaletteNext => {
+                self.cycle_palette(1);
+            }
+            Command::PalettePrev => {
+                self.cycle_palette(-1);
+            }
+            Command::PaletteWrite(path) => {
+                // Without a path, the project palette is written, or created
+                // in the current directory.
+                let path = match (path, &self.project_palette) {
+                    (Some(path), _) => PathBuf::from(path),
+                    (None, Some(p)) if p.extension().map_or(false, |e| e == "rx") => p.clone(),
+                    (None, Some(p)) => p.with_file_name(PROJECT_FILES[0]),
+                    (None, None) => PathBuf::from(PROJECT_FILES[0]),
+                };
+                match File::create(&path) {
+                    Ok(mut f) => {
+                        for color in self.palette.colors.iter() {
+                            writeln!(&mut f, "{}", color).ok();
+                        }
+                        self.message(
+                            format!(
+                                "Palette written to {} ({} colors)",
+                                path.display(),
+                                self.palette.size()
+                            ),
+                            MessageType::Info,
+                        );
+                    }
+                    Err(err) => {
+                        self.message(
+                            format!("Error: `{}`: {}", path.display(), err),
+                            MessageType::Error,
+                        );
+                    }
+                }
+            }
+            Command::Unerase => match self.erased.take() {
+                Some(Erased { view, rect, pixels }) if self.views.get(view).is_some() => {
+                    let n = self.view_mut(view).unerase(rect, &pixels);
+                    self.message(format!("{} pixel(s) restored", n), MessageType::Info);
+                }
+                _ => {
+                    self.message("Error: nothing to unerase", MessageType::Error);
+                }
+            },
+            Command::Recolor(ref path) => match self.recolor_path(path) {
+                Ok(n) => {
+                    self.message(format!("{} pixel(s) recolored", n), MessageType::Info);
+                }
+                Err(e) => {
+                    self.message(format!("Error: {}", e), MessageType::Error);
+                }
+            },
+            Command::Plugin(ref name, ref args) => match self.run_plugin(name, args) {
+                Ok(()) => {
+                    self.message(format!("Running {}...", name), MessageType::Info);
+                }
+                Err(e) => {
+                    self.message(format!("Error: {}", e), MessageType::Error);
+                }
+            },
+            Command::PluginList => {
+                let dir = self.proj_dirs.config_dir().join(plugin::DIR);
+
+                match plugin::discover(&dir) {
+                    Ok(plugins) if plugins.is_empty() => {
+                        self.message(
+                            format!("No plugins found in {}", dir.display()),
+                            MessageType::Info,
+                        );
+                    }
+                    Ok(plugins) => {
+                        let names: Vec<_> = plugins.iter().map(|p| p.name.as_str()).collect();
+                        self.message(names.join(" "), MessageType::Info);
+                    }
+                    Err(e) => {
+                        self.message(
+                            format!("Error: {}: {}", dir.display(), e),
+                            MessageType::Error,
+                        );
+                    }
+                }
+            }
+            Command::ColorKey(color) => {
+                let mapping = BTreeMap::from([(color, Rgba8::TRANSPARENT)]);
+                let n = self.active_view_mut().recolor(&mapping);
+
+                self.message(
+                    format!("{} pixel(s) made transparent", n),
+                    MessageType::Info,
+                );
+            }
+            Command::LutSet => {
+                let v = self.active_view();
+                let (id, w, h) = (v.id, v.width(), v.fh);
+
+                if w > lut::MAX_SIZE || h > lut::MAX_SIZE {
+                    self.message(
+                        format!(
+                            "Error: lookup textures can't be larger than {}x{}",
+                            lut::MAX_SIZE,
+                            lut::MAX_SIZE
+                        ),
+                        MessageType::Error,
+                    );
+                } else {
+                    self.lut = Some(id);
+                    self.message("View set as lookup texture", MessageType::Info);
+                }
+            }
+            Command::LutClear => {
+                self.lut = None;
+            }
+            Command::LutIr => match self.generate_lut_ir() {
+                Ok(0) => {}
+                Ok(n) => {
+                    self.message(
+                        format!("Warning: {} pixel(s) not in the lookup texture", n),
+                        MessageType::Warning,
+                    );
+                }
+                Err(e) => {
+                    self.message(format!("Error: {}", e), MessageType::Error);
+                }
+            },
+            Command::LutApply => match self.apply_lut() {
+                Ok(0) => {}
+                Ok(n) => {
+                    self.message(
+                        format!("Warning: {} pixel(s) outside the lookup texture", n),
+                        MessageType::Warning,
+                    );
+                }
+                Err(e) => {
+                    self.message(format!("Error: {}", e), MessageType::Error);
+                }
+            },
+            Command::Align(align) => {
+                let v = self.active_view().extent();
+                let center = self.active_view_coords(self.center());
+                let frame = if center.x < 0. {
+                    0
+                } else {
+                    v.to_frame(center.into()).min(v.nframes - 1)
+                };
+
+                match self.active_view_mut().align(frame, align) {
+                    Some((0, 0)) => {
+                        self.message(
+                            format!("Frame {} is already aligned", frame),
+                            MessageType::Info,
+                        );
+                    }
+                    Some((x, y)) => {
+                        self.message(
+                            format!("Frame {} content moved by {},{}", frame, x, y),
+                            MessageType::Info,
+                        );
+                    }
+                    None => {
+                        self.message(
+                            format!("Error: frame {} is empty", frame),
+                            MessageType::Error,
+                        );
+                    }
+                }
+            }
+            Command::Shift(x, y) => {
+                if self.active_view_mut().shift(x, y) {
+                    self.message(format!("View shifted by {},{}", x, y), MessageType::Info);
+                }
             }
-            Command::BrushSet(mode) => {
-                self.brush.set(mode);
+            Command::PreviewPalette(palette) => {
+                self.preview_palette = palette;
             }
-            Command::BrushUnset(mode) => {
-                self.brush.unset(mode);
+            Command::PreviewColorblind(c) => {
+                self.preview_colorblind = c;
             }
-            Command::BrushToggle(mode) => {
-                self.brush.toggle(mode);
+            Command::PreviewValues(n) => {
+                self.preview_values = n;
             }
-            Command::Brush => {
-                self.unimplemented();
+            Command::Filter(None) => {
+                self.filter = None;
             }
-            Command::BrushSize(op) => {
-                let b = &mut self.brush;
-                match op {
-                    Op::Incr => {
-                        b.size += 1;
-                        b.size += b.size % 2;
-                    }
-                    Op::Decr => {
-                        b.size -= 1;
-                        b.size -= b.size % 2;
-                    }
-                    Op::Set(s) => {
-                        b.size = s as usize;
-                    }
+            Command::Filter(Some(path)) => match self.filter_path(&path) {
+                Some(path) if self.filter.as_ref() == Some(&path) => {
+                    self.filter = None;
                 }
-                if b.size < Self::MIN_BRUSH_SIZE {
-                    b.size = Self::MIN_BRUSH_SIZE;
+                Some(path) => {
+                    self.message(
+                        format!("Filtering views through {}", path.display()),
+                        MessageType::Info,
+                    );
+                    self.filter = Some(path);
+                    self.filter_modified = None;
+                    self.filter_checked = Self::FILTER_POLL_INTERVAL;
                 }
-            }
-            Command::FrameResize(fw, fh) => {
-                if fw == 0 || fh == 0 {
+                None => {
                     self.message(
-                        "Error: cannot set frame dimension to `0`",
+                        format!("Error: filter {} not found", path),
                         MessageType::Error,
                     );
-                    return;
                 }
-                if fw > Self::MAX_FRAME_SIZE || fh > Self::MAX_FRAME_SIZE {
+            },
+            Command::RecordStart(path) => {
+                if let Some(t) = &self.timelapse {
                     self.message(
-                        format!(
-                            "Error: maximum frame size is {}x{}",
-                            Self::MAX_FRAME_SIZE,
-                            Self::MAX_FRAME_SIZE,
-                        ),
+                        format!("Error: already recording to {}", t.path.display()),
                         MessageType::Error,
                     );
                     return;
                 }
+                let path = path.unwrap_or_else(|| String::from(Self::TIMELAPSE));
 
-                let v = self.active_view_mut();
-                v.resize_frames(fw, fh);
-
-                self.check_selection();
-                self.organize_views();
-            }
-            Command::FramePrev => {
-                let v = self.active_view().extent();
-                let center = self.active_view_coords(self.center());
-
-                if center.x >= 0. {
-                    let frame = v.to_frame(center.into()).min(v.nframes);
-                    self.center_active_view_frame(frame.saturating_sub(1));
+                match Timelapse::new(&path) {
+                    Ok(t) => {
+                        self.timelapse = Some(t);
+                        self.message(
+                            format!("Recording timelapse to {}", path),
+                            MessageType::Info,
+                        );
+                    }
+                    Err(e) => self.message(format!("Error: {}: {}", path, e), MessageType::Error),
                 }
             }
-            Command::FrameNext => {
-                let v = self.active_view().extent();
-                let center = self.active_view_coords(self.center());
-                let frame = v.to_frame(center.into());
-
-                if center.x < 0. {
-                    self.center_active_view_frame(0);
-                } else if frame < v.nframes {
-                    self.center_active_view_frame((frame + 1).min(v.nframes - 1));
+            Command::RecordStop => {
+                if let Some(t) = self.timelapse.take() {
+                    let path = t.path.clone();
+
+                    match t.finish() {
+                        Ok(frames) => self.message(
+                            format!(
+                                "Timelapse saved to {} ({} frame(s))",
+                                path.display(),
+                                frames
+                            ),
+                            MessageType::Info,
+                        ),
+                        Err(e) => self.message(
+                            format!("Error: {}: {}", path.display(), e),
+                            MessageType::Error,
+                        ),
+                    }
+                } else {
+                    self.message("Error: not recording a timelapse", MessageType::Error);
                 }
             }
-            Command::ForceQuit => self.quit_view(self.views.active_id),
-            Command::ForceQuitAll => self.quit(ExitReason::Normal),
-            Command::Echo(ref v) => {
-                let result = match v {
-                    Value::Str(s) => Ok(Value::Str(s.clone())),
-                    Value::Ident(s) => match s.as_str() {
-                        "config/dir" => Ok(Value::Str(format!(
-                            "{}",
-                            self.proj_dirs.config_dir().display()
-                        ))),
-                        "s/cwd" | "cwd" => Ok(Value::Str(self.cwd.display().to_string())),
-                        "s/offset" => Ok(Value::F32Tuple(self.offset.x, self.offset.y)),
-                        "v/offset" => {
-                            let v = self.active_view();
-                            Ok(Value::F32Tuple(v.offset.x, v.offset.y))
-                        }
-                        "v/zoom" => Ok(Value::F64(self.active_view().zoom as f64)),
-                        _ => match self.settings.get(s) {
-                            None => Err(format!("Error: {} is undefined", s)),
-                            Some(result) => Ok(Value::Str(format!("{} = {}", v.clone(), result))),
-                        },
-                    },
-                    _ => Err(format!("Error: argument cannot be echoed")),
-                };
-                match result {
-                    Ok(v) => self.message(v, MessageType::Echo),
-                    Err(e) => self.message(e, MessageType::Error),
+            Command::CollabHost(port) => {
+                if self.collab.is_some() {
+                    self.message("Error: already collaborating", MessageType::Error);
+                    return;
                 }
-            }
-            Command::PaletteAdd(rgba) => {
-                self.palette.add(rgba);
-                self.center_palette();
-            }
-            Command::PaletteClear => {
-                self.palette.clear();
-            }
-            Command::PaletteGradient(colorstart, colorend, steps) => {
-                self.palette.gradient(colorstart, colorend, steps);
-                self.center_palette();
-            }
-            Command::PaletteSort => {
-                // Sort by total luminosity. This is pretty lame, but it's
-                // something to work with.
-                self.palette.colors.sort_by(|a, b| {
-                    (a.r as u32 + a.g as u32 + a.b as u32)
-                        .cmp(&(b.r as u32 + b.g as u32 + b.b as u32))
-                });
-            }
-            Command::PaletteSample => {
-                {
-                    let v = self.active_view();
-                    let (_, pixels) = self
-                        .views
-                        .get(v.id)
-                        .expect(&format!("view #{} must exist", v.id))
-                        .layer
-                        .current_snapshot();
-
-                    for pixel in pixels.iter().cloned() {
-                        if pixel != Rgba8::TRANSPARENT {
-                            self.palette.add(pixel);
-                        }
+                let bind = self.settings["collab/bind"].to_string();
+                let token = collab::token();
+
+                match Collab::host(&bind, port.unwrap_or(collab::DEFAULT_PORT), &token) {
+                    Ok(c) => {
+                        self.message(
+                            format!(
+                                "Hosting collaborative session on {} with token {}",
+                                c.addr, token
+                            ),
+                            MessageType::Info,
+                        );
+                        self.collab = Some(c);
                     }
+                    Err(e) => self.message(format!("Error: collab: {}", e), MessageType::Error),
                 }
-                self.command(Command::PaletteSort);
-                self.center_palette();
             }
-            Command::PaletteWrite(path) => match File::create(&path) {
-                Ok(mut f) => {
-                    for color in self.palette.colors.iter() {
-                        writeln!(&mut f, "{}", color).ok();
+            Command::CollabJoin(addr, token) => {
+                if self.collab.is_some() {
+                    self.message("Error: already collaborating", MessageType::Error);
+                    return;
+                }
+                match Collab::join(&addr, &token) {
+                    Ok(c) => {
+                        self.message(
+                            format!("Joined collaborative session at {}", c.addr),
+                            MessageType::Info,
+                        );
+                        self.collab = Some(c);
                     }
-                    self.message(
-                        format!(
-                            "Palette written to {} ({} colors)",
-                            path,
-                            self.palette.size()
-                        ),
-                        MessageType::Info,
-                    );
+                    Err(e) => self.message(
+                        format!("Error: collab: {}: {}", addr, e),
+                        MessageType::Error,
+                    ),
                 }
-                Err(err) => {
-                    self.message(format!("Error: `{}`: {}", path, err), MessageType::Error);
+            }
+            Command::CollabLeave => {
+                if self.collab.take().is_some() {
+                    self.message("Left collaborative session", MessageType::Info);
+                } else {
+                    self.message("Error: not collaborating", MessageType::Error);
                 }
-            },
+            }
             Command::Zoom(op) => {
                 let center = if let Some(s) = self.selection {
                     let v = self.active_view();
@@ -2570,6 +6026,9 @@ impl Session {
                     }
                 }
             }
+            Command::ZoomFit => {
+                self.zoom_fit();
+            }
             Command::Reset => {
                 if let Err(e) = self.reset() {
                     self.message(format!("Error: {}", e), MessageType::Error);
@@ -2592,31 +6051,190 @@ impl Session {
             }
             Command::ViewNext => {
                 let id = self.views.active_id;
+                let ws = &self.workspaces;
 
-                if let Some(id) = self
+                let next = self
                     .views
-                    .after(id)
-                    .or_else(|| self.views.first().map(|v| v.id))
-                {
+                    .range(id..)
+                    .skip(1)
+                    .find(|id| ws.contains(*id))
+                    .or_else(|| self.views.range(..).find(|id| ws.contains(*id)));
+
+                if let Some(id) = next {
                     self.activate(id);
                     self.center_active_view();
                 }
             }
             Command::ViewPrev => {
                 let id = self.views.active_id;
+                let ws = &self.workspaces;
 
-                if let Some(id) = self
+                let prev = self
                     .views
-                    .before(id)
-                    .or_else(|| self.views.last().map(|v| v.id))
-                {
+                    .range(..id)
+                    .rev()
+                    .find(|id| ws.contains(*id))
+                    .or_else(|| self.views.range(..).rev().find(|id| ws.contains(*id)));
+
+                if let Some(id) = prev {
                     self.activate(id);
                     self.center_active_view();
                 }
             }
+            Command::ViewAppend(other) => {
+                let id = self.views.active_id;
+
+                if other == id {
+                    self.message("Error: can't append a view to itself", MessageType::Error);
+                } else if self.views.get(other).is_none() {
+                    self.message(
+                        format!("Error: view #{} not found", other),
+                        MessageType::Error,
+                    );
+                } else if let Err(e) = self.append_view(id, other) {
+                    self.message(format!("Error: {}", e), MessageType::Error);
+                } else {
+                    self.organize_views();
+                }
+            }
+            Command::ViewMirror => {
+                let id = self.views.active_id;
+                let mirror = self.mirror_view(id);
+
+                self.message(
+                    format!("View #{} mirrored in view #{}", id, mirror),
+                    MessageType::Info,
+                );
+            }
+            Command::WorkspaceNew(name) => {
+                if self.workspaces.exists(&name) {
+                    self.message(
+                        format!("Error: workspace '{}' already exists", name),
+                        MessageType::Error,
+                    );
+                } else {
+                    let (w, h) = (Self::DEFAULT_VIEW_W, Self::DEFAULT_VIEW_H);
+                    let frames = vec![vec![Rgba8::TRANSPARENT; w as usize * h as usize]];
+
+                    self.workspaces.active = name.clone();
+                    let id = self.insert_view(FileStatus::NoFile, w, h, frames);
+                    self.organize_views();
+                    self.edit_view(id);
+                    self.message(format!("Workspace '{}'", name), MessageType::Info);
+                }
+            }
+            Command::WorkspaceSwitch(name) => {
+                if name == self.workspaces.active {
+                    return;
+                }
+                if self.workspaces.exists(&name) {
+                    self.switch_workspace(&name);
+                } else {
+                    self.message(
+                        format!("Error: no workspace named '{}'", name),
+                        MessageType::Error,
+                    );
+                }
+            }
+            Command::WorkspaceMove(name) => {
+                let id = self.views.active_id;
+
+                if name == self.workspaces.active {
+                    return;
+                }
+                self.workspaces.insert(id, &name);
+
+                let ws = &self.workspaces;
+                match self.views.recent_matching(|id| ws.contains(id)) {
+                    Some(other) => {
+                        self.activate(other);
+                        self.organize_views();
+                        self.center_active_view();
+                    }
+                    None => self.switch_workspace(&name),
+                }
+                self.message(
+                    format!("View #{} moved to workspace '{}'", id, name),
+                    MessageType::Info,
+                );
+            }
+            Command::WorkspaceNext => {
+                let name = self.workspaces.next();
+
+                if name != self.workspaces.active {
+                    self.switch_workspace(&name);
+                }
+            }
+            Command::WorkspacePrev => {
+                let name = self.workspaces.prev();
+
+                if name != self.workspaces.active {
+                    self.switch_workspace(&name);
+                }
+            }
+            Command::ReferenceAnim(None) => {
+                self.reference = None;
+            }
+            Command::Compare(Some(id)) => {
+                if self.views.get(id).is_none() {
+                    self.message(format!("Error: view #{} not found", id), MessageType::Error);
+                } else if id == self.views.active_id {
+                    self.message(
+                        "Error: can't compare a view with itself",
+                        MessageType::Error,
+                    );
+                } else {
+                    self.compare = Some(id);
+                    self.comparing = true;
+                }
+            }
+            Command::Compare(None) => {
+                if self.compare.is_some() {
+                    self.comparing = !self.comparing;
+                } else {
+                    self.message(
+                        "Error: no view to compare with (`:compare <view>` to set one)",
+                        MessageType::Error,
+                    );
+                }
+            }
+            Command::ReferenceAnim(Some(id)) => {
+                if self.views.get(id).is_none() {
+                    self.message(format!("Error: view #{} not found", id), MessageType::Error);
+                } else {
+                    self.reference = Some(id);
+                }
+            }
+            Command::ViewSplit(from, to, copy) => {
+                let v = self.active_view();
+                let (id, nframes) = (v.id, v.animation.len());
+
+                if from > to || to >= nframes {
+                    self.message(
+                        format!("Error: invalid frame range {}-{}", from, to),
+                        MessageType::Error,
+                    );
+                } else if !copy && to - from + 1 == nframes {
+                    self.message(
+                        "Error: can't move all frames out of a view",
+                        MessageType::Error,
+                    );
+                } else {
+                    let id = self.split_view(id, from, to, copy);
+
+                    self.organize_views();
+                    self.edit_view(id);
+                }
+            }
             Command::ViewCenter => {
                 self.center_active_view();
             }
+            Command::Goto(x, y) => {
+                self.goto(x, y);
+            }
+            Command::CursorMove(x, y) => {
+                self.move_cursor(x, y);
+            }
             Command::FrameAdd => {
                 self.active_view_mut().extend();
             }
@@ -2636,6 +6254,36 @@ impl Session {
                 self.active_view_mut().shrink();
                 self.check_selection();
             }
+            Command::Trim(padding) => {
+                let v = self.active_view_mut();
+                let p = padding as i32;
+
+                match v.content_bounds() {
+                    Some(r) => {
+                        let area = Rect::new(r.x1 - p, r.y1 - p, r.x2 + p, r.y2 + p);
+
+                        if v.crop(area) {
+                            self.organize_views();
+                            self.check_selection();
+                            self.message(
+                                format!(
+                                    "View frames cropped to {}x{} at {},{}",
+                                    area.width(),
+                                    area.height(),
+                                    area.x1,
+                                    area.y1
+                                ),
+                                MessageType::Info,
+                            );
+                        } else {
+                            self.message("View is already trimmed", MessageType::Info);
+                        }
+                    }
+                    None => {
+                        self.message("Error: view is empty", MessageType::Error);
+                    }
+                }
+            }
             Command::Slice(None) => {
                 let v = self.active_view_mut();
                 v.slice(1);
@@ -2649,6 +6297,30 @@ impl Session {
                     );
                 }
             }
+            Command::Set(ref k, Value::U32(n)) if k == "view/fps" && n > 1000 => {
+                self.message(
+                    "Error: `view/fps` must be a number between 0 and 1000",
+                    MessageType::Error,
+                );
+            }
+            Command::Set(ref k, Value::U32(n)) if k == "brush/stabilize" && n > 64 => {
+                self.message(
+                    "Error: `brush/stabilize` must be a number between 0 and 64",
+                    MessageType::Error,
+                );
+            }
+            Command::Set(ref k, Value::U32(n)) if k == "brush/opacity" && n > 255 => {
+                self.message(
+                    "Error: `brush/opacity` must be a number between 0 and 255",
+                    MessageType::Error,
+                );
+            }
+            Command::Set(ref k, Value::U32(n)) if k == "ui/text-scale" && !(1..=4).contains(&n) => {
+                self.message(
+                    "Error: `ui/text-scale` must be a number between 1 and 4",
+                    MessageType::Error,
+                );
+            }
             Command::Set(ref k, ref v) => {
                 if Settings::DEPRECATED.contains(&k.as_str()) {
                     self.message(
@@ -2728,6 +6400,11 @@ impl Session {
                     }
                 }
             }
+            Command::EditRaw(ref path, w, h) => {
+                if let Err(e) = self.edit_raw(path, w, h) {
+                    self.message(format!("Error loading path: {}", e), MessageType::Error);
+                }
+            }
             Command::EditFrames(ref paths) => {
                 if !paths.is_empty() {
                     if let Err(e) = self.edit_frames(paths) {
@@ -2738,29 +6415,51 @@ impl Session {
                     }
                 }
             }
-            Command::Export(scale, path) => {
+            Command::Export(scale, filter, path) => {
                 let view = self.active_view();
                 let id = view.id;
-                let scale = scale.unwrap_or(view.zoom as u32);
+                let result = if let Some(filter) = filter {
+                    let scale = scale.unwrap_or_else(|| filter.factor());
+                    self.export_filtered(id, Path::new(&path), filter, scale)
+                } else {
+                    let scale = scale.unwrap_or(view.zoom as u32);
+                    self.export_as(id, Path::new(&path), scale)
+                };
 
-                if let Err(e) = self.export_as(id, Path::new(&path), scale) {
+                if let Err(e) = result {
                     self.message(format!("Error: {}", e), MessageType::Error);
                 }
             }
-            Command::Write(None) => match self.save_view(self.views.active_id) {
-                Ok((storage, written)) => self.message(
-                    format!("\"{}\" {} pixels written", storage, written),
-                    MessageType::Info,
-                ),
-                Err(err) => self.message(format!("Error: {}", err), MessageType::Error),
-            },
-            Command::Write(Some(ref path)) => {
-                match self.active_view_mut().save_as(&Path::new(path).into()) {
+            Command::ExportRaw(ref path, format, palette) => {
+                let colors = self.colors();
+                let result = self.active_view().with_pixels(|extent, pixels| {
+                    crate::io::export_raw(Path::new(path), extent, pixels, format, &colors, palette)
+                });
+
+                match result {
                     Ok(written) => self.message(
                         format!("\"{}\" {} pixels written", path, written),
                         MessageType::Info,
                     ),
-                    Err(err) => self.message(format!("Error: {}", err), MessageType::Error),
+                    Err(e) => self.message(format!("Error: {}", e), MessageType::Error),
+                }
+            }
+            Command::Write(None) => {
+                let id = self.views.active_id;
+
+                if let Some(storage) = self.view(id).file_storage().cloned() {
+                    if let Err(err) = self.save_view_as(id, &storage) {
+                        self.message(format!("Error: {}", err), MessageType::Error);
+                    }
+                } else {
+                    self.message("Error: no file name given", MessageType::Error);
+                }
+            }
+            Command::Write(Some(ref path)) => {
+                let id = self.views.active_id;
+
+                if let Err(err) = self.save_view_as(id, &Path::new(path).into()) {
+                    self.message(format!("Error: {}", err), MessageType::Error);
                 }
             }
             Command::WriteFrames(None) => {
@@ -2777,42 +6476,55 @@ impl Session {
                 let paths = NonEmpty::from_slice(paths.as_slice())
                     .expect("views always have at least one frame");
 
-                let view = self.active_view_mut();
+                let id = self.views.active_id;
                 let fs = FileStorage::Range(paths);
 
-                match view.save_as(&fs) {
-                    Ok(written) => self.message(
-                        format!("{} {} pixels written", fs, written),
-                        MessageType::Info,
-                    ),
-                    Err(e) => self.message(format!("Error: {}", e), MessageType::Error),
+                if let Err(e) = self.save_view_as(id, &fs) {
+                    self.message(format!("Error: {}", e), MessageType::Error);
                 }
             }
             Command::WriteQuit => {
-                if self.save_view(self.views.active_id).is_ok() {
+                if let Ok((storage, _)) = self.save_view(self.views.active_id) {
+                    self.written(&storage);
                     self.quit_view(self.views.active_id);
                 }
             }
             Command::Map(map) => {
                 let KeyMapping {
                     input,
+                    prefix,
                     press,
                     release,
                     modes,
                 } = *map;
 
+                if !prefix.is_empty() && release.is_some() {
+                    self.message(
+                        "Error: key sequences can't have a release command",
+                        MessageType::Error,
+                    );
+                    return;
+                }
+                let display = prefix
+                    .iter()
+                    .map(|k| format!("{} ", k))
+                    .chain(Some(input.to_string()))
+                    .collect();
+
                 self.key_bindings.add(KeyBinding {
                     input,
+                    prefix,
                     modes: modes.clone(),
                     command: press,
                     state: InputState::Pressed,
                     modifiers: platform::ModifiersState::default(),
                     is_toggle: release.is_some(),
-                    display: Some(format!("{}", input)),
+                    display: Some(display),
                 });
                 if let Some(cmd) = release {
                     self.key_bindings.add(KeyBinding {
                         input,
+                        prefix: Vec::new(),
                         modes,
                         command: cmd,
                         state: InputState::Released,
@@ -2825,28 +6537,131 @@ impl Session {
             Command::MapClear => {
                 self.key_bindings = KeyBindings::default();
             }
+            Command::RxrcTrust => match self.untrusted.take() {
+                Some((script, contents)) => match self.trust_script(&script, contents) {
+                    Ok(()) => {
+                        self.message(
+                            format!("{} trusted and sourced", script.display()),
+                            MessageType::Info,
+                        );
+                    }
+                    Err(e) => {
+                        self.message(format!("Error: {}", e), MessageType::Error);
+                    }
+                },
+                None => {
+                    self.message("Error: no untrusted script to trust", MessageType::Error);
+                }
+            },
+            Command::SettingsSave => match self.save_settings() {
+                Ok(path) => {
+                    self.message(
+                        format!("Settings saved to {}", path.display()),
+                        MessageType::Info,
+                    );
+                }
+                Err(e) => {
+                    self.message(
+                        format!("Error: couldn't save settings: {}", e),
+                        MessageType::Error,
+                    );
+                }
+            },
+            Command::Back(ago) => {
+                let v = self.active_view_mut();
+                // Keep the current state, so that it can itself be returned to.
+                v.resource.borrow_mut().checkpoint(time::Instant::now());
+
+                match v.restore_checkpoint(ago) {
+                    Some(age) => {
+                        self.organize_views();
+                        self.message(
+                            format!(
+                                "View restored to its state from {}m{:02}s ago",
+                                age.as_secs() / 60,
+                                age.as_secs() % 60
+                            ),
+                            MessageType::Info,
+                        );
+                    }
+                    None => {
+                        self.message("Error: no checkpoints to restore", MessageType::Error);
+                    }
+                }
+            }
+            Command::CheckpointSave(name) => {
+                let edit = self.active_view_mut().resource.borrow_mut().label(&name);
+
+                self.message(
+                    format!("Checkpoint '{}' saved at edit #{}", name, edit),
+                    MessageType::Info,
+                );
+            }
+            Command::CheckpointRestore(name) => match self.active_view_mut().restore_label(&name) {
+                Some(true) => {
+                    self.organize_views();
+                    self.message(
+                        format!("View restored to checkpoint '{}'", name),
+                        MessageType::Info,
+                    );
+                }
+                Some(false) => {
+                    self.message(
+                        format!("View is already at checkpoint '{}'", name),
+                        MessageType::Info,
+                    );
+                }
+                None => {
+                    self.message(
+                        format!("Error: no checkpoint named '{}'", name),
+                        MessageType::Error,
+                    );
+                }
+            },
             Command::Undo => {
                 self.undo(self.views.active_id);
             }
             Command::Redo => {
                 self.redo(self.views.active_id);
             }
+            Command::HistoryClear => {
+                let n = self.active_view_mut().resource.borrow_mut().history_clear();
+                self.message(format!("{} edit(s) cleared", n), MessageType::Info);
+            }
             Command::Tool(t) => {
                 self.tool(t);
             }
             Command::ToolPrev => {
                 self.prev_tool();
             }
-            Command::Crop(_) => {
-                self.unimplemented();
+            Command::Theme(choice) => {
+                self.theme = choice.theme();
+            }
+            Command::Crop(area) => {
+                if self.active_view_mut().crop(area.map(|n| n as i32)) {
+                    self.organize_views();
+                    self.check_selection();
+                }
             }
             Command::SelectionMove(x, y) => {
                 if let Some(ref mut s) = self.selection {
                     s.translate(x, y);
                 }
             }
-            Command::SelectionResize(x, y) => {
+            Command::SelectionResize(mut x, mut y) => {
+                let locked = self.aspect_locked();
+
                 if let Some(ref mut s) = self.selection {
+                    if locked {
+                        let r = s.abs().bounds();
+                        let (w, h) = (r.width(), r.height());
+
+                        if x != 0 {
+                            y = Self::aspect_dimension(w + x, w, h) - h;
+                        } else {
+                            x = Self::aspect_dimension(h + y, h, w) - w;
+                        }
+                    }
                     s.resize(x, y);
                 }
             }
@@ -2911,7 +6726,11 @@ impl Session {
             }
             Command::SelectionPaste => {
                 if let (Mode::Visual(VisualState::Pasting), Some(s)) = (self.mode, self.selection) {
-                    self.active_view_mut().paste(s.abs().bounds());
+                    let r = s.abs().bounds();
+
+                    // Scaled pastes are resampled, and the result becomes the paste buffer.
+                    self.paste_size = (r.width(), r.height());
+                    self.active_view_mut().paste(r);
                 } else {
                     // TODO: Enter paste mode?
                 }
@@ -2953,50 +6772,121 @@ impl Session {
                 }
             }
             Command::SelectionFill(color) => {
-                if let Some(s) = self.selection {
-                    self.effects
-                        .push(Effect::ViewPaintFinal(vec![Shape::Rectangle(
-                            s.abs().bounds().map(|n| n as f32),
-                            ZDepth::default(),
-                            Rotation::ZERO,
-                            Stroke::NONE,
-                            Fill::Solid(color.unwrap_or(self.fg).into()),
-                        )]));
+                if self.selection.is_some() {
+                    let shapes =
+                        self.selection_shapes(Fill::Solid(color.unwrap_or(self.fg).into()));
+
+                    self.effects.push(Effect::ViewPaintFinal(shapes));
                     self.active_view_mut().touch();
                 }
             }
+            Command::SelectionRotate(deg) => {
+                let area = self.transform_area();
+                let v = self.active_view_mut();
+
+                // Like flipping, rotation goes through the paste buffer.
+                v.rotate(area, deg);
+                v.paste(area);
+
+                self.selection = Some(Selection::from(area));
+                self.switch_mode(Mode::Visual(VisualState::Pasting));
+                self.command(Command::SelectionErase);
+                self.command(Command::Mode(Mode::Visual(VisualState::Selecting {
+                    dragging: false,
+                })));
+            }
+            Command::PreviewRotate(deg) => {
+                let area = self.transform_area();
+
+                // Load the rotated pixels into the paste buffer without touching the view.
+                self.active_view_mut().rotate(area, deg);
+                self.selection = Some(Selection::from(area));
+                self.switch_mode(Mode::Visual(VisualState::Pasting));
+                self.message(
+                    "Previewing rotation, click to paste or <esc> to cancel",
+                    MessageType::Hint,
+                );
+            }
             Command::SelectionErase => {
-                if let Some(s) = self.selection {
+                if self.selection.is_some() {
+                    let shapes = self.selection_shapes(Fill::Solid(Rgba8::TRANSPARENT.into()));
+
                     self.effects.extend_from_slice(&[
                         Effect::ViewBlendingChanged(Blending::Constant),
-                        Effect::ViewPaintFinal(vec![Shape::Rectangle(
-                            s.abs().bounds().map(|n| n as f32),
-                            ZDepth::default(),
-                            Rotation::ZERO,
-                            Stroke::NONE,
-                            Fill::Solid(Rgba8::TRANSPARENT.into()),
-                        )]),
+                        Effect::ViewPaintFinal(shapes),
                     ]);
                     self.active_view_mut().touch();
                 }
             }
+            Command::SelectionGrow(n) => {
+                let bounds = self.active_view().layer_bounds();
+
+                if let Some(mask) = self.current_mask().and_then(|m| m.grow(n, bounds)) {
+                    self.select_mask(mask);
+                }
+            }
+            Command::SelectionShrink(n) => {
+                if let Some(mask) = self.current_mask() {
+                    match mask.shrink(n) {
+                        Some(mask) => self.select_mask(mask),
+                        None => self.message(
+                            "Error: selection is too small to shrink",
+                            MessageType::Error,
+                        ),
+                    }
+                }
+            }
+            Command::Generate(pattern) => {
+                if self.selection.is_some() {
+                    self.generate(pattern);
+                } else {
+                    self.message("Error: nothing is selected", MessageType::Error);
+                }
+            }
+            Command::SelectionInvert => {
+                let v = self.active_view();
+                let (id, bounds) = (v.id, v.layer_bounds());
+                let inverted = match self.current_mask() {
+                    Some(mask) => mask.invert(bounds),
+                    None => Mask::from_rect(id, bounds),
+                };
+                match inverted {
+                    Some(mask) => self.select_mask(mask),
+                    None => self.selection = None,
+                }
+            }
+            Command::SelectColor(color) => {
+                self.select_color(color.unwrap_or(self.fg));
+            }
             Command::PaintColor(rgba, x, y) => {
-                self.active_view_mut().paint_color(rgba, x, y);
+                let v = self.active_view_mut();
+
+                v.paint_color(rgba, x, y);
+                v.touch();
             }
             Command::PaintLine(rgba, x1, y1, x2, y2) => {
                 let mut stroke = vec![];
                 Brush::line(Point2::new(x1, y1), Point2::new(x2, y2), &mut stroke);
+
+                let v = self.active_view_mut();
                 for pt in stroke {
-                    self.active_view_mut().paint_color(rgba, pt.x, pt.y);
+                    v.paint_color(rgba, pt.x, pt.y);
                 }
+                v.touch();
             }
             Command::PaintForeground(x, y) => {
                 let fg = self.fg;
-                self.active_view_mut().paint_color(fg, x, y);
+                let v = self.active_view_mut();
+
+                v.paint_color(fg, x, y);
+                v.touch();
             }
             Command::PaintBackground(x, y) => {
                 let bg = self.bg;
-                self.active_view_mut().paint_color(bg, x, y);
+                let v = self.active_view_mut();
+
+                v.paint_color(bg, x, y);
+                v.touch();
             }
             Command::PaintPalette(i, x, y) => {
                 let c = self.palette.colors.to_vec();
@@ -3004,6 +6894,7 @@ impl Session {
 
                 if let Some(color) = c.get(i) {
                     v.paint_color(*color, x, y);
+                    v.touch();
                 }
             }
         };
@@ -3035,6 +6926,7 @@ impl Session {
         match self.cmdline.parse(&input) {
             Err(e) => self.message(format!("Error: {}", e), MessageType::Error),
             Ok(cmd) => {
+                self.record_mapping(&input, &cmd);
                 self.command(cmd);
                 self.cmdline.history.add(input);
             }
@@ -3046,6 +6938,81 @@ impl Session {
         self.message_clear();
     }
 
+    fn search_hide(&mut self) {
+        self.switch_mode(self.prev_mode.unwrap_or(Mode::Normal));
+    }
+
+    /// Run the highlighted search entry. Commands which can't be run without
+    /// arguments are opened in the command line instead.
+    fn search_handle_enter(&mut self) {
+        let action = self.search.selected().map(|e| e.action.clone());
+        self.search_hide();
+
+        match action {
+            Some(search::Action::Binding(cmd)) => {
+                self.command(cmd);
+            }
+            Some(search::Action::Command(name)) => {
+                match self.cmdline.parse(&format!(":{}", name)) {
+                    Ok(cmd) => self.command(cmd),
+                    Err(_) => {
+                        self.switch_mode(Mode::Command);
+                        self.cmdline.puts(&format!("{} ", name));
+                    }
+                }
+            }
+            Some(search::Action::Line(line)) => match self.cmdline.parse(&line) {
+                Ok(cmd) => {
+                    self.command(cmd);
+                    self.cmdline.history.add(line);
+                }
+                Err(e) => self.message(format!("Error: {}", e), MessageType::Error),
+            },
+            None => {}
+        }
+    }
+
+    /// Entries listed when browsing the command history, most recent first.
+    fn history_entries(&self) -> Vec<search::Entry> {
+        let now = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        self.cmdline
+            .history
+            .iter()
+            .map(|e| {
+                search::Entry::new(
+                    e.line.clone(),
+                    e.time.map_or(String::new(), |t| history::age(t, now)),
+                    search::Action::Line(e.line.clone()),
+                )
+            })
+            .collect()
+    }
+
+    /// Entries listed in search mode: key bindings first, then all commands.
+    fn search_entries(&self) -> Vec<search::Entry> {
+        let bindings = self.key_bindings.iter().filter_map(|kb| {
+            kb.display.as_ref().map(|key| {
+                search::Entry::new(
+                    key.clone(),
+                    kb.command.to_string(),
+                    search::Action::Binding(kb.command.clone()),
+                )
+            })
+        });
+        let commands = self
+            .cmdline
+            .commands
+            .iter()
+            .filter(|(name, _, _)| *name != "#")
+            .map(|(name, help, _)| search::Entry::new(*name, *help, search::Action::Command(name)));
+
+        bindings.chain(commands).collect()
+    }
+
     fn tool(&mut self, t: Tool) {
         if std::mem::discriminant(&t) != std::mem::discriminant(&self.tool) {
             self.prev_tool = Some(self.tool.clone());
@@ -3061,6 +7028,14 @@ impl Session {
     /// Color functions
     ///////////////////////////////////////////////////////////////////////////
 
+    /// Echo the result of a color computation, optionally making it the foreground color.
+    fn color_result(&mut self, color: Rgba8, fg: bool) {
+        if fg {
+            self.pick_color(color);
+        }
+        self.message(format!("{}", color), MessageType::Echo);
+    }
+
     /// Pick the given color as foreground color.
     fn pick_color(&mut self, color: Rgba8) {
         if color.a == 0x0 {
@@ -3069,10 +7044,30 @@ impl Session {
         if color != self.fg {
             self.bg = self.fg;
             self.fg = color;
+
+            self.color_history.retain(|c| *c != color);
+            self.color_history.push_front(color);
+            self.color_history.truncate(Self::COLOR_HISTORY);
         }
         // TODO: Switch to brush.
     }
 
+    /// Cycle through the foreground color history, backwards or forwards.
+    /// Unlike picking a color, the background color is left alone.
+    fn cycle_color(&mut self, back: bool) {
+        if self.color_history.len() < 2 {
+            return;
+        }
+        if back {
+            self.color_history.rotate_left(1);
+        } else {
+            self.color_history.rotate_right(1);
+        }
+        if let Some(c) = self.color_history.front() {
+            self.fg = *c;
+        }
+    }
+
     fn sample_color(&mut self) {
         if let Some(color) = self.hover_color {
             self.pick_color(color);
@@ -3084,6 +7079,141 @@ impl Session {
 mod test {
     use super::*;
 
+    #[test]
+    fn test_ruler() {
+        let ruler = Ruler::new(ViewCoords::new(7, 2), ViewCoords::new(4, 6));
+
+        assert_eq!(ruler.dx(), 3);
+        assert_eq!(ruler.dy(), 4);
+        assert_eq!(ruler.distance(), 5.);
+    }
+
+    #[test]
+    fn test_mask_from_color() {
+        let (r, g) = (Rgba8::RED, Rgba8::GREEN);
+        let id = ViewId::default();
+        #[rustfmt::skip]
+        let pixels = [
+            r, g, g,
+            g, r, g,
+            g, g, g,
+        ];
+        let mask = Mask::from_color(id, 3, 3, &pixels, r).unwrap();
+
+        // The top-left pixel is at the top of the view.
+        assert_eq!(mask.selection, Selection::new(0, 1, 2, 3));
+        assert_eq!(mask.count(), 2);
+        assert_eq!(
+            mask.points().collect::<Vec<_>>(),
+            vec![Point2::new(1, 1), Point2::new(0, 2)]
+        );
+        assert!(mask.contains(0, 2));
+        assert!(!mask.contains(1, 2));
+        assert!(!mask.contains(2, 2));
+        assert_eq!(
+            mask.spans(),
+            vec![Rect::new(1, 1, 2, 2), Rect::new(0, 2, 1, 3)]
+        );
+
+        assert_eq!(Mask::from_color(id, 3, 3, &pixels, Rgba8::BLUE), None);
+    }
+
+    #[test]
+    fn test_mask_ops() {
+        let id = ViewId::default();
+        let bounds = Rect::origin(5, 5);
+        let dot = Mask::from_rect(id, Rect::new(2, 2, 3, 3)).unwrap();
+
+        let grown = dot.grow(1, bounds).unwrap();
+        assert_eq!(grown.selection, Selection::new(1, 1, 4, 4));
+        assert!(grown.is_full());
+        assert_eq!(grown.shrink(1), Some(dot.clone()));
+        assert_eq!(dot.shrink(1), None);
+
+        // Growing stops at the bounds.
+        assert_eq!(
+            dot.grow(4, bounds).unwrap().selection,
+            Selection::new(0, 0, 5, 5)
+        );
+
+        let inverted = grown.invert(bounds).unwrap();
+        assert_eq!(inverted.selection, Selection::new(0, 0, 5, 5));
+        assert_eq!(inverted.count(), 16);
+        assert!(!inverted.contains(2, 2));
+        assert_eq!(
+            inverted.spans()[1..4],
+            [
+                Rect::new(0, 1, 1, 2),
+                Rect::new(4, 1, 5, 2),
+                Rect::new(0, 2, 1, 3)
+            ]
+        );
+        assert_eq!(inverted.spans().len(), 8);
+        assert_eq!(inverted.invert(bounds), Some(grown));
+        assert_eq!(Mask::from_rect(id, bounds).unwrap().invert(bounds), None);
+    }
+
+    #[test]
+    fn test_mask_clip() {
+        let id = ViewId::default();
+        // A diagonal line of pixels.
+        let mask = Mask::from_fn(id, Rect::origin(4, 4), |x, y| x == y).unwrap();
+        let rect = |x1: f32, y1: f32, x2: f32, y2: f32| {
+            Shape::Rectangle(
+                Rect::new(x1, y1, x2, y2),
+                ZDepth::ZERO,
+                Rotation::ZERO,
+                Stroke::NONE,
+                Fill::Solid(Rgba8::RED.into()),
+            )
+        };
+        let pixels = |shapes: Vec<Shape>| -> Vec<(f32, f32)> {
+            shapes
+                .into_iter()
+                .filter_map(|s| match s {
+                    Shape::Rectangle(r, ..) => Some((r.x1, r.y1)),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            pixels(mask.clip(vec![rect(0., 0., 3., 2.), rect(5., 5., 6., 6.)])),
+            vec![(0., 0.), (1., 1.)]
+        );
+        // Rectangles aren't necessarily normalized.
+        assert_eq!(
+            pixels(mask.clip(vec![rect(4., 4., 2., 2.)])),
+            vec![(2., 2.), (3., 3.)]
+        );
+    }
+
+    #[test]
+    fn test_scale_dimension() {
+        assert_eq!(Session::scale_dimension(17, 8, true), 16);
+        assert_eq!(Session::scale_dimension(20, 8, true), 20);
+        assert_eq!(Session::scale_dimension(7, 8, true), 8);
+        assert_eq!(Session::scale_dimension(17, 8, false), 17);
+        assert_eq!(Session::scale_dimension(0, 8, false), 1);
+        assert_eq!(Session::scale_dimension(3, 0, true), 3);
+    }
+
+    #[test]
+    fn test_aspect_dimension() {
+        assert_eq!(Session::aspect_dimension(32, 16, 8), 16);
+        assert_eq!(Session::aspect_dimension(10, 16, 8), 5);
+        assert_eq!(Session::aspect_dimension(1, 16, 8), 1);
+        assert_eq!(Session::aspect_dimension(24, 24, 16), 16);
+    }
+
+    #[test]
+    fn test_is_animated_filter() {
+        assert!(Session::is_animated_filter("uniform float time;\n"));
+        assert!(Session::is_animated_filter("uniform highp float time ;"));
+        assert!(!Session::is_animated_filter("uniform vec2 size;\n"));
+        assert!(!Session::is_animated_filter("float time = 0.;"));
+    }
+
     #[test]
     fn test_key_bindings() {
         let mut kbs = KeyBindings::default();
@@ -3093,6 +7223,7 @@ mod test {
         let kb1 = KeyBinding {
             modes: vec![Mode::Normal],
             input: Input::Key(platform::Key::A),
+            prefix: Vec::new(),
             command: Command::Noop,
             is_toggle: false,
             display: None,
@@ -3132,6 +7263,7 @@ mod test {
         let kb = KeyBinding {
             modes: vec![Mode::Normal],
             input: Input::Key(platform::Key::Control),
+            prefix: Vec::new(),
             command: Command::Noop,
             is_toggle: false,
             display: None,
@@ -3167,4 +7299,73 @@ mod test {
             Some(kb)
         );
     }
+
+    #[test]
+    fn test_key_bindings_sequence() {
+        use platform::Key;
+
+        let single = KeyBinding {
+            modes: vec![Mode::Normal],
+            input: Input::Key(Key::F),
+            prefix: Vec::new(),
+            command: Command::FrameAdd,
+            is_toggle: false,
+            display: None,
+            modifiers: Default::default(),
+            state: InputState::Pressed,
+        };
+        let sequence = KeyBinding {
+            input: Input::Key(Key::R),
+            prefix: vec![Key::Space, Key::F],
+            command: Command::FrameResize(16, 16),
+            ..single.clone()
+        };
+
+        let mut kbs = KeyBindings::default();
+        kbs.add(single.clone());
+        kbs.add(sequence.clone());
+
+        assert_eq!(kbs.len(), 2, "sequences don't overwrite single keys");
+        assert_eq!(
+            kbs.find(
+                Input::Key(Key::F),
+                Default::default(),
+                InputState::Pressed,
+                Mode::Normal
+            ),
+            Some(single)
+        );
+        assert_eq!(
+            kbs.find(
+                Input::Key(Key::R),
+                Default::default(),
+                InputState::Pressed,
+                Mode::Normal
+            ),
+            None,
+            "sequences aren't found as single keys"
+        );
+
+        assert_eq!(
+            kbs.find_sequence(&[], Key::Space, Mode::Normal),
+            Sequence::Pending
+        );
+        assert_eq!(
+            kbs.find_sequence(&[Key::Space], Key::F, Mode::Normal),
+            Sequence::Pending
+        );
+        assert_eq!(
+            kbs.find_sequence(&[Key::Space, Key::F], Key::R, Mode::Normal),
+            Sequence::Complete(sequence)
+        );
+        assert_eq!(
+            kbs.find_sequence(&[Key::Space], Key::R, Mode::Normal),
+            Sequence::None
+        );
+        assert_eq!(kbs.find_sequence(&[], Key::F, Mode::Normal), Sequence::None);
+        assert_eq!(
+            kbs.find_sequence(&[], Key::Space, Mode::Command),
+            Sequence::None
+        );
+    }
 }
@@ -0,0 +1,858 @@
+//! Software renderer.
+//!
+//! Renders into memory on the CPU, without a graphics context. It follows the
+//! `gl` renderer pass for pass, but nothing is presented to the window: it's meant
+//! for headless replays and tests on machines without a GPU.
+use crate::cmd::Axis;
+use crate::data::{self, Assets};
+use crate::draw;
+use crate::execution::Execution;
+use crate::font::{Font, TextBatch};
+use crate::gfx::{shape2d, sprite2d, Origin, Rgba, Rgba8, ZDepth};
+use crate::gfx::{Matrix4, Rect, Repeat, Vector2, Vector3, Vector4};
+use crate::image;
+use crate::pixels;
+use crate::platform::{self, LogicalSize};
+use crate::renderer;
+use crate::session::{self, Blending, Effect, Session};
+use crate::sprite;
+use crate::util;
+use crate::view::{Resource, View, ViewId, ViewOp, ViewState};
+
+use std::collections::BTreeMap;
+use std::io;
+use std::time;
+
+pub struct Renderer {
+    pub win_size: LogicalSize,
+
+    draw_ctx: draw::Context,
+    scale_factor: f64,
+    scale: f64,
+    screen: Framebuffer,
+    blending: Blending,
+    srgb: Srgb,
+
+    staging_batch: shape2d::Batch,
+    final_batch: shape2d::Batch,
+
+    font: Texture,
+    /// The built-in font, used when no other font is set.
+    glyphs: Font,
+    cursors: Texture,
+    checker: Texture,
+    paste: Texture,
+    paste_outputs: Vec<Vec<sprite2d::Vertex>>,
+
+    view_data: BTreeMap<ViewId, ViewData>,
+}
+
+/// An RGBA texture in memory. Like a GL texture, the first row is at `v = 0`.
+struct Texture {
+    w: u32,
+    h: u32,
+    texels: Vec<Rgba8>,
+}
+
+impl Texture {
+    fn new(w: u32, h: u32) -> Self {
+        Self::from(w, h, vec![Rgba8::TRANSPARENT; (w * h) as usize])
+    }
+
+    fn from(w: u32, h: u32, texels: Vec<Rgba8>) -> Self {
+        debug_assert_eq!(texels.len(), (w * h) as usize);
+
+        Self { w, h, texels }
+    }
+
+    fn clear(&mut self, color: Rgba8) {
+        self.texels.iter_mut().for_each(|t| *t = color);
+    }
+
+    fn upload_part(&mut self, [x, y]: [u32; 2], [w, h]: [u32; 2], texels: &[Rgba8]) {
+        let (x, w) = (x as usize, w as usize);
+
+        for (row, src) in (y..y + h).zip(texels.chunks_exact(w)) {
+            let offset = row as usize * self.w as usize + x;
+            self.texels[offset..offset + w].copy_from_slice(src);
+        }
+    }
+
+    /// Sample the texture at the given coordinates, using nearest-neighbour
+    /// filtering and repeat wrapping.
+    fn sample(&self, u: f32, v: f32) -> Rgba8 {
+        let x = ((u * self.w as f32).floor() as i64).rem_euclid(self.w as i64);
+        let y = ((v * self.h as f32).floor() as i64).rem_euclid(self.h as i64);
+
+        self.texels[y as usize * self.w as usize + x as usize]
+    }
+}
+
+/// A color texture with an associated depth buffer.
+struct Framebuffer {
+    color: Texture,
+    depth: Vec<f32>,
+}
+
+impl Framebuffer {
+    fn new(w: u32, h: u32) -> Self {
+        Self {
+            color: Texture::new(w, h),
+            depth: vec![1.; (w * h) as usize],
+        }
+    }
+
+    fn clear(&mut self, color: Rgba8) {
+        self.color.clear(color);
+        self.clear_depth();
+    }
+
+    fn clear_depth(&mut self) {
+        self.depth.iter_mut().for_each(|d| *d = 1.);
+    }
+}
+
+/// Conversions between sRGB and linear color components. Textures and
+/// framebuffers store sRGB colors, while shading and blending happen in
+/// linear space, as with the sRGB formats used by the `gl` renderer.
+struct Srgb {
+    decode: [f32; 256],
+    encode: Vec<u8>,
+}
+
+impl Srgb {
+    /// Precision of the linear to sRGB conversion table.
+    const ENCODE_STEPS: usize = 4096;
+
+    fn new() -> Self {
+        let mut decode = [0.; 256];
+        for (i, d) in decode.iter_mut().enumerate() {
+            *d = Self::linearize(i as f32 / 255.);
+        }
+        let encode = (0..=Self::ENCODE_STEPS)
+            .map(|i| {
+                let c = i as f32 / Self::ENCODE_STEPS as f32;
+                let c = if c < 0.003_130_8 {
+                    c * 12.92
+                } else {
+                    1.055 * c.powf(1. / 2.4) - 0.055
+                };
+                (c * 255.).round() as u8
+            })
+            .collect();
+
+        Self { decode, encode }
+    }
+
+    fn linearize(c: f32) -> f32 {
+        if c < 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    }
+
+    /// Convert an sRGB color to a linear color.
+    fn decode(&self, c: Rgba8) -> [f32; 4] {
+        [
+            self.decode[c.r as usize],
+            self.decode[c.g as usize],
+            self.decode[c.b as usize],
+            c.a as f32 / 255.,
+        ]
+    }
+
+    /// Convert a linear color to an sRGB color.
+    fn encode(&self, [r, g, b, a]: [f32; 4]) -> Rgba8 {
+        let steps = Self::ENCODE_STEPS as f32;
+        let enc = |c: f32| self.encode[(c.clamp(0., 1.) * steps).round() as usize];
+
+        Rgba8::new(
+            enc(r),
+            enc(g),
+            enc(b),
+            (a.clamp(0., 1.) * 255.).round() as u8,
+        )
+    }
+}
+
+/// A triangle vertex in window coordinates, with depth in `z`.
+type Position = Vector3<f32>;
+
+/// Rasterizes triangles into a framebuffer, with depth testing and blending.
+struct Raster<'a> {
+    fb: &'a mut Framebuffer,
+    srgb: &'a Srgb,
+    blending: Blending,
+    /// Transforms vertex positions to normalized device coordinates.
+    transform: Matrix4<f32>,
+}
+
+impl<'a> Raster<'a> {
+    fn new(
+        fb: &'a mut Framebuffer,
+        srgb: &'a Srgb,
+        blending: Blending,
+        ortho: Matrix4<f32>,
+        transform: Matrix4<f32>,
+    ) -> Self {
+        Self {
+            fb,
+            srgb,
+            blending,
+            transform: ortho * transform,
+        }
+    }
+
+    fn sprites(&mut self, vertices: &[sprite2d::Vertex], tex: &Texture) {
+        for tri in vertices.chunks_exact(3) {
+            let ps = [
+                self.project(tri[0].position),
+                self.project(tri[1].position),
+                self.project(tri[2].position),
+            ];
+            let color = self.srgb.decode(tri[0].color);
+            let srgb = self.srgb;
+
+            self.triangle(ps, |[w0, w1, w2]| {
+                let uv = tri[0].uv * w0 + tri[1].uv * w1 + tri[2].uv * w2;
+                let opacity = tri[0].opacity * w0 + tri[1].opacity * w1 + tri[2].opacity * w2;
+                let texel = srgb.decode(tex.sample(uv.x, uv.y));
+                let mix = |t: f32, c: f32| t * (1. - color[3]) + c * color[3];
+
+                [
+                    mix(texel[0], color[0]),
+                    mix(texel[1], color[1]),
+                    mix(texel[2], color[2]),
+                    texel[3] * opacity,
+                ]
+            });
+        }
+    }
+
+    fn shapes(&mut self, vertices: &[shape2d::Vertex]) {
+        for tri in vertices.chunks_exact(3) {
+            let ps = [
+                self.project(Self::rotate(&tri[0])),
+                self.project(Self::rotate(&tri[1])),
+                self.project(Self::rotate(&tri[2])),
+            ];
+            let color = self.srgb.decode(tri[0].color);
+
+            self.triangle(ps, |_| color);
+        }
+    }
+
+    /// Rotate a shape vertex around its center.
+    fn rotate(v: &shape2d::Vertex) -> Vector3<f32> {
+        let (s, c) = v.angle.sin_cos();
+        let x = v.position.x - v.center.x;
+        let y = v.position.y - v.center.y;
+
+        Vector3::new(
+            c * x + s * y + v.center.x,
+            -s * x + c * y + v.center.y,
+            v.position.z,
+        )
+    }
+
+    /// Transform a vertex position to window coordinates.
+    fn project(&self, p: Vector3<f32>) -> Position {
+        let Vector4 { x, y, z, w } = self.transform * p.extend(1.);
+        let (fw, fh) = (self.fb.color.w as f32, self.fb.color.h as f32);
+
+        Vector3::new(
+            (x / w + 1.) / 2. * fw,
+            (y / w + 1.) / 2. * fh,
+            (z / w + 1.) / 2.,
+        )
+    }
+
+    /// Fill a triangle. The shader is called with the barycentric weights of
+    /// each covered pixel center, and returns a linear color.
+    fn triangle(&mut self, ps: [Position; 3], mut shader: impl FnMut([f32; 3]) -> [f32; 4]) {
+        let [a, b, c] = ps;
+        let area = Self::edge(a, b, c);
+
+        if area == 0. {
+            return;
+        }
+        // Make sure edges wind counter-clockwise.
+        let (b, c, flipped) = if area < 0. {
+            (c, b, true)
+        } else {
+            (b, c, false)
+        };
+        let area = area.abs();
+
+        let (w, h) = (self.fb.color.w as f32, self.fb.color.h as f32);
+        let x1 = a.x.min(b.x).min(c.x).floor().max(0.) as usize;
+        let y1 = a.y.min(b.y).min(c.y).floor().max(0.) as usize;
+        let x2 = a.x.max(b.x).max(c.x).ceil().min(w) as usize;
+        let y2 = a.y.max(b.y).max(c.y).ceil().min(h) as usize;
+
+        for y in y1..y2 {
+            for x in x1..x2 {
+                let p = Vector3::new(x as f32 + 0.5, y as f32 + 0.5, 0.);
+                let (e0, e1, e2) = (
+                    Self::edge(b, c, p),
+                    Self::edge(c, a, p),
+                    Self::edge(a, b, p),
+                );
+
+                // Pixel centers exactly on an edge are only covered by one of the
+                // triangles sharing it, so that quads aren't blended twice.
+                if !Self::covers(e0, b, c) || !Self::covers(e1, c, a) || !Self::covers(e2, a, b) {
+                    continue;
+                }
+                let (w0, w1, w2) = (e0 / area, e1 / area, e2 / area);
+                let z = a.z * w0 + b.z * w1 + c.z * w2;
+                let i = y * self.fb.color.w as usize + x;
+
+                if z > self.fb.depth[i] {
+                    continue;
+                }
+                let weights = if flipped { [w0, w2, w1] } else { [w0, w1, w2] };
+                let src = shader(weights);
+
+                self.fb.depth[i] = z;
+
+                // Transparent fragments leave the color unchanged when alpha blending.
+                if self.blending == Blending::Alpha && src[3] == 0. {
+                    continue;
+                }
+                self.fb.color.texels[i] = self.blend(src, self.fb.color.texels[i]);
+            }
+        }
+    }
+
+    fn edge(a: Position, b: Position, p: Position) -> f32 {
+        (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+    }
+
+    /// Whether a point with the given edge function value is covered by the edge,
+    /// following the top-left rule.
+    fn covers(e: f32, a: Position, b: Position) -> bool {
+        let (dx, dy) = (b.x - a.x, b.y - a.y);
+
+        e > 0. || (e == 0. && (dy < 0. || (dy == 0. && dx < 0.)))
+    }
+
+    fn blend(&self, src: [f32; 4], dst: Rgba8) -> Rgba8 {
+        match self.blending {
+            Blending::Constant => self.srgb.encode(src),
+            Blending::Alpha => {
+                let dst = self.srgb.decode(dst);
+                let a = src[3];
+
+                self.srgb.encode([
+                    src[0] * a + dst[0] * (1. - a),
+                    src[1] * a + dst[1] * (1. - a),
+                    src[2] * a + dst[2] * (1. - a),
+                    src[3] * a + dst[3] * (1. - a),
+                ])
+            }
+        }
+    }
+}
+
+struct ViewData {
+    layer: Framebuffer,
+    staging: Framebuffer,
+    /// Vertices of the view quad.
+    vertices: Vec<sprite2d::Vertex>,
+}
+
+impl ViewData {
+    fn new(w: u32, h: u32, pixels: Option<&[Rgba8]>) -> Self {
+        let batch = sprite2d::Batch::singleton(
+            w,
+            h,
+            Rect::origin(w as f32, h as f32),
+            Rect::origin(w as f32, h as f32),
+            ZDepth::default(),
+            Rgba::TRANSPARENT,
+            1.,
+            Repeat::default(),
+        );
+        let mut layer = Framebuffer::new(w, h);
+
+        if let Some(pixels) = pixels {
+            layer.color.texels.copy_from_slice(pixels);
+        }
+
+        Self {
+            layer,
+            staging: Framebuffer::new(w, h),
+            vertices: batch.vertices(),
+        }
+    }
+}
+
+impl<'a> renderer::Renderer<'a> for Renderer {
+    type Error = io::Error;
+
+    fn new(
+        _win: &mut platform::backend::Window,
+        win_size: LogicalSize,
+        scale_factor: f64,
+        assets: Assets<'a>,
+    ) -> io::Result<Self> {
+        Self::headless(win_size, scale_factor, assets)
+    }
+
+    fn init(&mut self, effects: Vec<Effect>, session: &Session) {
+        self.handle_effects(effects, session);
+    }
+
+    fn frame(
+        &mut self,
+        session: &mut Session,
+        execution: &mut Execution,
+        effects: Vec<session::Effect>,
+        avg_frametime: &time::Duration,
+    ) -> io::Result<()> {
+        if session.state != session::State::Running {
+            return Ok(());
+        }
+        self.staging_batch.clear();
+        self.final_batch.clear();
+        self.handle_effects(effects, session);
+
+        let Self {
+            draw_ctx,
+            screen,
+            blending,
+            srgb,
+            staging_batch,
+            final_batch,
+            font,
+            cursors,
+            checker,
+            paste,
+            paste_outputs,
+            view_data,
+            ..
+        } = self;
+
+        draw_ctx.clear();
+        draw_ctx.draw(session, avg_frametime, execution);
+
+        let ortho = Matrix4::ortho(screen.color.w, screen.color.h, Origin::TopLeft);
+        let identity = Matrix4::identity();
+
+        let v = session
+            .views
+            .active()
+            .expect("there must always be an active view");
+        let view_ortho = Matrix4::ortho(v.width(), v.fh, Origin::TopLeft);
+
+        if let Some(v_data) = view_data.get_mut(&v.id) {
+            // Render staged brush strokes and paste buffer to the view staging buffer.
+            // Strokes replace pixels rather than blending, so that translucent strokes
+            // don't accumulate where they overlap.
+            v_data.staging.clear(Rgba8::TRANSPARENT);
+
+            let mut raster = Raster::new(
+                &mut v_data.staging,
+                srgb,
+                Blending::Constant,
+                view_ortho,
+                identity,
+            );
+            raster.shapes(&staging_batch.vertices());
+            raster.blending = Blending::Alpha;
+
+            if !draw_ctx.paste_batch.is_empty() {
+                raster.sprites(&draw_ctx.paste_batch.vertices(), paste);
+            }
+
+            // Render final brush strokes and paste outputs to the view layer.
+            v_data.layer.clear_depth();
+
+            let mut raster = Raster::new(&mut v_data.layer, srgb, *blending, view_ortho, identity);
+            raster.shapes(&final_batch.vertices());
+            raster.blending = Blending::Alpha;
+
+            for out in paste_outputs.drain(..) {
+                raster.sprites(&out, paste);
+            }
+        }
+
+        // Render to screen framebuffer.
+        let bg = match session.settings["background"].to_rgba8() {
+            bg if bg.a == 0 => session.theme.workspace,
+            bg => bg,
+        };
+        screen.clear(bg);
+
+        let mut raster = Raster::new(screen, srgb, Blending::Alpha, ortho, identity);
+
+        if session.settings["checker"].is_set() {
+            raster.sprites(&draw_ctx.checker_batch.vertices(), checker);
+        }
+        for (id, v) in view_data.iter() {
+            if let Some(view) = session
+                .views
+                .get(*id)
+                .filter(|_| session.workspaces.contains(*id))
+            {
+                raster.transform = ortho
+                    * Matrix4::from_translation(
+                        (session.offset + view.offset).extend(*draw::VIEW_LAYER),
+                    )
+                    * Matrix4::from_nonuniform_scale(view.zoom, view.zoom, 1.0);
+
+                raster.sprites(&v.vertices, &v.layer.color);
+                raster.sprites(&v.vertices, &v.staging.color);
+            }
+        }
+        if let Some(r) = session.reference_view() {
+            if let Some(r_data) = view_data.get(&r.id) {
+                let v = session.active_view();
+                let batch = draw::draw_reference(session, v, r);
+
+                raster.transform =
+                    ortho * Matrix4::from_translation(Vector2::new(0., v.zoom).extend(0.));
+                raster.sprites(&batch.vertices(), &r_data.layer.color);
+            }
+        }
+        if let Some(c) = session.compare_view() {
+            if let Some(c_data) = view_data.get(&c.id) {
+                let v = session.active_view();
+                let batch = draw::draw_compare(session, v, c);
+
+                raster.transform =
+                    ortho * Matrix4::from_translation(Vector2::new(0., v.zoom).extend(0.));
+                raster.sprites(&batch.vertices(), &c_data.layer.color);
+            }
+        }
+        raster.transform = ortho;
+        raster.shapes(&draw_ctx.ui_batch.vertices());
+
+        if let Some(batch) = draw::draw_preview(session, session.active_view()) {
+            if let Some(v) = view_data.get(&session.views.active_id) {
+                raster.sprites(&batch.vertices(), &v.layer.color);
+            }
+        }
+
+        if session.settings["animation"].is_set() {
+            for (id, v) in view_data.iter() {
+                match session.views.get(*id) {
+                    Some(view) if view.animation.len() > 1 && session.workspaces.contains(*id) => {
+                        let batch = draw::draw_view_animation(session, view);
+
+                        raster.transform = ortho
+                            * Matrix4::from_translation(Vector2::new(0., view.zoom).extend(0.));
+                        raster.sprites(&batch.vertices(), &v.layer.color);
+                    }
+                    _ => {}
+                }
+            }
+            raster.transform = ortho;
+        }
+        raster.sprites(&draw_ctx.text_batch.vertices(), font);
+        raster.sprites(&draw_ctx.tool_batch.vertices(), cursors);
+
+        if session.mode == session::Mode::Help {
+            let mut win = shape2d::Batch::new();
+            let mut text = TextBatch::atlas(font.w, font.h);
+            draw::draw_help(session, &mut text, &mut win);
+
+            raster.shapes(&win.vertices());
+            raster.sprites(&text.vertices(), font);
+        }
+
+        // If active view is dirty, record a snapshot of it.
+        if v.is_dirty() {
+            let id = v.id;
+            let state = v.state;
+            let is_resized = v.is_resized();
+            let extent = v.extent();
+
+            if let (Some(vr), Some(v_data)) = (session.views.get_mut(id), view_data.get(&id)) {
+                let pixels = v_data.layer.color.texels.clone();
+
+                match state {
+                    ViewState::Dirty(_) if is_resized => {
+                        vr.resource.borrow_mut().record_view_resized(pixels, extent);
+                    }
+                    ViewState::Dirty(_) => {
+                        vr.resource.borrow_mut().record_view_painted(pixels);
+                    }
+                    ViewState::Okay | ViewState::Damaged(_) => {}
+                }
+            }
+        }
+
+        if session.timelapse_due() {
+            session.record_timelapse(&screen.color.texels, screen.color.w, screen.color.h);
+        }
+        if !execution.is_normal() {
+            execution
+                .record(&screen.color.texels, screen.color.w, screen.color.h)
+                .ok();
+        }
+
+        Ok(())
+    }
+
+    fn handle_scale_factor_changed(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+        self.handle_resized(self.win_size);
+    }
+}
+
+impl Renderer {
+    /// Create a renderer which isn't attached to a window.
+    pub fn headless(
+        win_size: LogicalSize,
+        scale_factor: f64,
+        assets: Assets<'_>,
+    ) -> io::Result<Self> {
+        let glyphs = Font::decode(assets.glyphs)?;
+        let (cursors_img, cursors_w, cursors_h) = image::read(data::CURSORS)?;
+        let (checker_w, checker_h) = (2, 2);
+        let (paste_w, paste_h) = (8, 8);
+
+        let font = Texture::from(glyphs.width, glyphs.height, glyphs.pixels.clone());
+        let cursors = Texture::from(cursors_w, cursors_h, Rgba8::align(&cursors_img).to_vec());
+        let checker = Texture::from(checker_w, checker_h, Rgba8::align(&draw::CHECKER).to_vec());
+        let paste = Texture::new(paste_w, paste_h);
+
+        let draw_ctx = draw::Context {
+            ui_batch: shape2d::Batch::new(),
+            text_batch: TextBatch::atlas(glyphs.width, glyphs.height),
+            overlay_batch: TextBatch::atlas(glyphs.width, glyphs.height),
+            cursor_sprite: sprite::Sprite::new(cursors_w, cursors_h),
+            tool_batch: sprite2d::Batch::new(cursors_w, cursors_h),
+            paste_batch: sprite2d::Batch::new(paste_w, paste_h),
+            checker_batch: sprite2d::Batch::new(checker_w, checker_h),
+        };
+
+        Ok(Renderer {
+            win_size,
+            draw_ctx,
+            scale_factor,
+            scale: 1.0,
+            screen: Framebuffer::new(win_size.width as u32, win_size.height as u32),
+            blending: Blending::Alpha,
+            srgb: Srgb::new(),
+            staging_batch: shape2d::Batch::new(),
+            final_batch: shape2d::Batch::new(),
+            font,
+            glyphs,
+            cursors,
+            checker,
+            paste,
+            paste_outputs: Vec::new(),
+            view_data: BTreeMap::new(),
+        })
+    }
+
+    pub fn handle_resized(&mut self, size: platform::LogicalSize) {
+        self.win_size = size;
+        self.handle_session_scale_changed(self.scale);
+    }
+
+    pub fn handle_session_scale_changed(&mut self, scale: f64) {
+        self.scale = scale;
+        self.screen = Framebuffer::new(
+            (self.win_size.width / scale) as u32,
+            (self.win_size.height / scale) as u32,
+        );
+    }
+
+    fn handle_effects(&mut self, mut effects: Vec<Effect>, session: &Session) {
+        for eff in effects.drain(..) {
+            match eff {
+                Effect::SessionResized(size) => {
+                    self.handle_resized(size);
+                }
+                Effect::SessionScaled(scale) => {
+                    self.handle_session_scale_changed(scale);
+                }
+                Effect::FontChanged(font) => {
+                    let font = font.unwrap_or_else(|| self.glyphs.clone());
+
+                    self.draw_ctx.text_batch = TextBatch::atlas(font.width, font.height);
+                    self.draw_ctx.overlay_batch = TextBatch::atlas(font.width, font.height);
+                    self.font = Texture::from(font.width, font.height, font.pixels);
+                }
+                Effect::ViewActivated(_) => {}
+                Effect::ViewAdded(id) => {
+                    if let Some(v) = session.views.get(id) {
+                        let data = v.with_pixels(|extent, pixels| {
+                            ViewData::new(extent.width(), extent.height(), Some(pixels))
+                        });
+                        self.view_data.insert(id, data);
+                    }
+                }
+                Effect::ViewRemoved(id) => {
+                    self.view_data.remove(&id);
+                }
+                Effect::ViewOps(id, ops) => {
+                    self.handle_view_ops(session.view(id), &ops);
+                }
+                Effect::ViewDamaged(id, Some(extent)) => {
+                    self.resize_view(session.view(id), extent.width(), extent.height());
+                }
+                Effect::ViewDamaged(id, None) => {
+                    self.handle_view_damaged(session.view(id));
+                }
+                Effect::ViewBlendingChanged(blending) => {
+                    self.blending = blending;
+                }
+                Effect::ViewPaintDraft(shapes) => {
+                    shapes.into_iter().for_each(|s| self.staging_batch.add(s));
+                }
+                Effect::ViewPaintFinal(shapes) => {
+                    shapes.into_iter().for_each(|s| self.final_batch.add(s));
+                }
+                Effect::ViewTouched(_) => {}
+            }
+        }
+    }
+
+    fn handle_view_ops(&mut self, v: &View<Resource>, ops: &[ViewOp]) {
+        for op in ops {
+            match op {
+                ViewOp::Resize(w, h) => {
+                    self.resize_view(v, *w, *h);
+                }
+                ViewOp::Clear(color) => {
+                    self.layer(v.id).color.clear(*color);
+                }
+                ViewOp::Blit(src, dst) => {
+                    if let Some(texels) = v.pixels_rect(&src.map(|n| n as i32)) {
+                        self.layer(v.id).color.upload_part(
+                            [dst.x1 as u32, dst.y1 as u32],
+                            [src.width() as u32, src.height() as u32],
+                            &texels,
+                        );
+                    }
+                }
+                ViewOp::Yank(src) => {
+                    if let Some(pixels) = v.pixels_rect(src) {
+                        let (w, h) = (src.width() as u32, src.height() as u32);
+
+                        self.paste = Texture::from(w, h, pixels);
+                    }
+                }
+                ViewOp::Flip(src, dir) => {
+                    if let Some(mut pixels) = v.pixels_rect(src) {
+                        let (w, h) = (src.width() as u32, src.height() as u32);
+
+                        match dir {
+                            Axis::Vertical => {
+                                let len = pixels.len();
+
+                                let (front, back) = pixels.split_at_mut(len / 2);
+                                for (front_row, back_row) in front
+                                    .chunks_exact_mut(w as usize)
+                                    .zip(back.rchunks_exact_mut(w as usize))
+                                {
+                                    front_row.swap_with_slice(back_row);
+                                }
+                            }
+                            Axis::Horizontal => {
+                                pixels
+                                    .chunks_exact_mut(w as usize)
+                                    .for_each(|row| row.reverse());
+                            }
+                        }
+                        self.paste = Texture::from(w, h, pixels);
+                    }
+                }
+                ViewOp::Rotate(src, degrees) => {
+                    if let Some(pixels) = v.pixels_rect(src) {
+                        let (w, h) = (src.width() as u32, src.height() as u32);
+
+                        // When the area spans whole frames, rotate each frame separately.
+                        let fw = v.fw;
+                        let pixels = if w > fw && w % fw == 0 && src.x1 as u32 % fw == 0 {
+                            let nframes = (w / fw) as usize;
+                            let frames =
+                                util::split_frames(&pixels, fw as usize, h as usize, nframes)
+                                    .into_iter()
+                                    .map(|f| pixels::rotsprite(&f, fw, h, *degrees))
+                                    .collect();
+
+                            util::stitch_frames(frames, fw as usize, h as usize, Rgba8::TRANSPARENT)
+                        } else {
+                            pixels::rotsprite(&pixels, w, h, *degrees)
+                        };
+                        self.paste = Texture::from(w, h, pixels);
+                    }
+                }
+                ViewOp::Paste(dst) => {
+                    let (w, h) = (dst.width() as u32, dst.height() as u32);
+
+                    // When the paste buffer was scaled, resample it to the destination size.
+                    if self.paste.w != w || self.paste.h != h {
+                        let (paste_w, paste_h) = (self.paste.w, self.paste.h);
+                        let pixels = pixels::resize(&self.paste.texels, paste_w, paste_h, w, h);
+
+                        self.paste = Texture::from(w, h, pixels);
+                    }
+                    let (paste_w, paste_h) = (self.paste.w, self.paste.h);
+                    let batch = sprite2d::Batch::singleton(
+                        paste_w,
+                        paste_h,
+                        Rect::origin(paste_w as f32, paste_h as f32),
+                        dst.map(|n| n as f32),
+                        ZDepth::default(),
+                        Rgba::TRANSPARENT,
+                        1.,
+                        Repeat::default(),
+                    );
+                    self.paste_outputs.push(batch.vertices());
+                }
+                ViewOp::SetPixel(rgba, x, y) => {
+                    self.layer(v.id)
+                        .color
+                        .upload_part([*x as u32, *y as u32], [1, 1], &[*rgba]);
+                }
+            }
+        }
+    }
+
+    fn layer(&mut self, id: ViewId) -> &mut Framebuffer {
+        &mut self
+            .view_data
+            .get_mut(&id)
+            .expect("views must have associated view data")
+            .layer
+    }
+
+    fn handle_view_damaged(&mut self, view: &View<Resource>) {
+        let (_, pixels) = view.pixels();
+        let layer = self.layer(view.id);
+
+        layer.color.texels = pixels;
+    }
+
+    fn resize_view(&mut self, view: &View<Resource>, vw: u32, vh: u32) {
+        let (ew, eh) = {
+            let extent = view.resource.borrow().extent;
+            (extent.width(), extent.height())
+        };
+
+        // Ensure not to transfer more data than can fit in the view buffer.
+        let tw = u32::min(ew, vw);
+        let th = u32::min(eh, vh);
+
+        let mut view_data = ViewData::new(vw, vh, None);
+        let trect = Rect::origin(tw as i32, th as i32);
+
+        // The rect may not be contained in the snapshot, eg. after undoing
+        // a frame removal, in which case we skip the transfer.
+        if let Some(texels) = view.pixels_rect(&trect) {
+            view_data
+                .layer
+                .color
+                .upload_part([0, vh - th], [tw, th], &texels);
+        }
+        self.view_data.insert(view.id, view_data);
+    }
+}
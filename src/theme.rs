@@ -0,0 +1,164 @@
+//! UI color themes.
+use crate::color;
+
+use crate::gfx::{Rgba, Rgba8};
+
+use std::fmt;
+use std::io::Read;
+use std::process;
+use std::sync::OnceLock;
+use std::thread;
+use std::time;
+
+/// How long to wait for the system preference to be read.
+const TIMEOUT: time::Duration = time::Duration::from_millis(500);
+
+/// A UI color theme.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Theme {
+    /// Color the workspace is cleared to, when no `background` is set.
+    pub workspace: Rgba8,
+    /// Background color of panels and overlays.
+    pub background: Rgba8,
+    /// Color of text and active UI elements.
+    pub foreground: Rgba8,
+    /// Color of secondary text and inactive UI elements.
+    pub secondary: Rgba8,
+    /// Border color of inactive views.
+    pub border: Rgba,
+    /// Border color of the view under the cursor.
+    pub hover: Rgba,
+}
+
+impl Theme {
+    pub const DARK: Theme = Theme {
+        workspace: color::TRANSPARENT,
+        background: color::BLACK,
+        foreground: color::WHITE,
+        secondary: color::GREY,
+        border: Rgba::new(0.5, 0.5, 0.5, 1.0),
+        hover: Rgba::new(0.7, 0.7, 0.7, 1.0),
+    };
+
+    pub const LIGHT: Theme = Theme {
+        workspace: Rgba8::new(0xee, 0xee, 0xee, 0xff),
+        background: Rgba8::new(0xee, 0xee, 0xee, 0xff),
+        foreground: Rgba8::new(0x11, 0x11, 0x11, 0xff),
+        secondary: color::DARK_GREY,
+        border: Rgba::new(0.6, 0.6, 0.6, 1.0),
+        hover: Rgba::new(0.4, 0.4, 0.4, 1.0),
+    };
+}
+
+/// Which theme to use.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ThemeChoice {
+    Dark,
+    Light,
+    /// Follow the system preference.
+    #[default]
+    Auto,
+}
+
+impl ThemeChoice {
+    /// The theme for this choice. With `Auto`, the system preference is queried
+    /// the first time, and remembered until rx is restarted.
+    pub fn theme(&self) -> Theme {
+        static PREFERS_LIGHT: OnceLock<bool> = OnceLock::new();
+
+        match self {
+            Self::Dark => Theme::DARK,
+            Self::Light => Theme::LIGHT,
+            Self::Auto => {
+                if *PREFERS_LIGHT.get_or_init(self::prefers_light) {
+                    Theme::LIGHT
+                } else {
+                    Theme::DARK
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for ThemeChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dark => "dark".fmt(f),
+            Self::Light => "light".fmt(f),
+            Self::Auto => "auto".fmt(f),
+        }
+    }
+}
+
+/// Run a command and return its standard output, if it succeeded within
+/// the timeout. Otherwise, the command is killed.
+fn output(cmd: &str, args: &[&str]) -> Option<String> {
+    let mut child = process::Command::new(cmd)
+        .args(args)
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::null())
+        .spawn()
+        .ok()?;
+    let start = time::Instant::now();
+
+    let status = loop {
+        if let Some(status) = child.try_wait().ok()? {
+            break status;
+        }
+        if start.elapsed() >= TIMEOUT {
+            child.kill().ok();
+            child.wait().ok();
+
+            return None;
+        }
+        thread::sleep(time::Duration::from_millis(5));
+    };
+    let mut out = String::new();
+    child.stdout.take()?.read_to_string(&mut out).ok()?;
+
+    if status.success() {
+        Some(out.to_lowercase())
+    } else {
+        None
+    }
+}
+
+/// Check whether the system prefers a light theme. The interface style is
+/// only set when dark mode is enabled, so a light theme is assumed when it
+/// can't be read.
+#[cfg(target_os = "macos")]
+fn prefers_light() -> bool {
+    self::output("defaults", &["read", "-g", "AppleInterfaceStyle"])
+        .map_or(true, |s| !s.contains("dark"))
+}
+
+/// Check whether the system prefers a light theme. When the preference
+/// can't be determined, a dark theme is assumed.
+#[cfg(target_os = "windows")]
+fn prefers_light() -> bool {
+    self::output(
+        "reg",
+        &[
+            "query",
+            r"HKCU\Software\Microsoft\Windows\CurrentVersion\Themes\Personalize",
+            "/v",
+            "AppsUseLightTheme",
+        ],
+    )
+    .map_or(false, |s| s.contains("0x1"))
+}
+
+/// Check whether the system prefers a light theme. When the preference
+/// can't be determined, a dark theme is assumed.
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn prefers_light() -> bool {
+    if let Ok(theme) = std::env::var("GTK_THEME") {
+        return !theme.to_lowercase().contains("dark");
+    }
+    self::output(
+        "gsettings",
+        &["get", "org.gnome.desktop.interface", "color-scheme"],
+    )
+    .map_or(false, |s| s.contains("prefer-light"))
+}
@@ -0,0 +1,228 @@
+//! Timelapse capture of the workspace.
+//!
+//! Unlike the event recordings made with `--record`, a timelapse captures the
+//! rendered screen itself, and encodes it as an animated GIF.
+use crate::gfx::Rgba8;
+
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+use gif::{self, SetParameter};
+
+/// A timelapse being recorded. Frames are encoded on a worker thread, since
+/// quantizing them is slow.
+pub struct Timelapse {
+    /// Path the timelapse is written to.
+    pub path: PathBuf,
+    /// Number of frames rendered since the recording started.
+    rendered: u64,
+    /// Frames to encode.
+    frames: Option<mpsc::SyncSender<Capture>>,
+    /// The worker, which returns the number of frames encoded.
+    worker: Option<thread::JoinHandle<io::Result<usize>>>,
+}
+
+/// A captured frame.
+struct Capture {
+    texels: Vec<Rgba8>,
+    width: u32,
+    height: u32,
+}
+
+impl Timelapse {
+    /// Delay between frames of the timelapse, in hundredths of a second.
+    const FRAME_DELAY: u16 = 10;
+    /// Quantization speed, from 1 (best) to 30 (fastest).
+    const ENCODING_SPEED: i32 = 30;
+    /// Number of captured frames waiting to be encoded, after which frames are dropped.
+    const QUEUE_SIZE: usize = 16;
+
+    /// Start a new timelapse, which will be written to the given path. The file
+    /// is only created once the first frame is captured.
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            if !dir.is_dir() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} is not a directory", dir.display()),
+                ));
+            }
+        }
+        let (frames, rx) = mpsc::sync_channel(Self::QUEUE_SIZE);
+        let worker = {
+            let path = path.clone();
+            thread::spawn(move || Self::encode(&path, rx))
+        };
+
+        Ok(Self {
+            path,
+            rendered: 0,
+            frames: Some(frames),
+            worker: Some(worker),
+        })
+    }
+
+    /// Advance the timelapse by one rendered frame. Returns `true` if this
+    /// frame should be captured, which is the case every `interval` frames.
+    pub fn tick(&mut self, interval: u64) -> bool {
+        let due = self.rendered % interval.max(1) == 0;
+        self.rendered += 1;
+
+        due
+    }
+
+    /// Capture a frame of the given size. Frames that don't match the size of
+    /// the first captured frame are skipped, as are frames captured while the
+    /// encoder is busy. Returns an error if encoding failed.
+    pub fn capture(&mut self, texels: &[Rgba8], width: u32, height: u32) -> io::Result<()> {
+        let capture = Capture {
+            texels: texels.to_vec(),
+            width,
+            height,
+        };
+        match self.frames.as_ref().map(|f| f.try_send(capture)) {
+            Some(Ok(())) | Some(Err(mpsc::TrySendError::Full(_))) => Ok(()),
+            // The worker stopped, which only happens on error.
+            _ => Err(self
+                .stop()
+                .err()
+                .unwrap_or_else(|| io::Error::new(io::ErrorKind::Other, "encoder stopped"))),
+        }
+    }
+
+    /// Finish the timelapse, returning the number of frames written. Returns
+    /// an error if no frames were captured, in which case nothing is written.
+    pub fn finish(mut self) -> io::Result<usize> {
+        match self.stop()? {
+            0 => Err(io::Error::new(
+                io::ErrorKind::Other,
+                "no frames were captured",
+            )),
+            n => Ok(n),
+        }
+    }
+
+    /// Wait for the worker to encode the frames captured so far, and stop it.
+    fn stop(&mut self) -> io::Result<usize> {
+        self.frames.take();
+
+        match self.worker.take().map(|w| w.join()) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(io::Error::new(io::ErrorKind::Other, "encoder panicked")),
+            None => Ok(0),
+        }
+    }
+
+    /// Encode frames as they are received. The file is created on the first
+    /// frame, since the encoder needs the frame size.
+    fn encode(path: &Path, frames: mpsc::Receiver<Capture>) -> io::Result<usize> {
+        let mut encoder = None;
+        let mut size = (0, 0);
+        let mut encoded = 0;
+
+        for Capture {
+            texels,
+            width,
+            height,
+        } in frames
+        {
+            let (w, h) = (width as u16, height as u16);
+
+            let encoder = match &mut encoder {
+                Some(encoder) => encoder,
+                None => {
+                    let f = File::create(path)?;
+                    let mut e = gif::Encoder::new(io::BufWriter::new(f), w, h, &[])?;
+                    e.set(gif::Repeat::Infinite)?;
+                    size = (w, h);
+
+                    encoder.insert(e)
+                }
+            };
+            if size != (w, h) {
+                continue;
+            }
+
+            let rgb: Vec<u8> = texels.iter().flat_map(|t| [t.r, t.g, t.b]).collect();
+            let mut frame = gif::Frame::from_rgb_speed(w, h, &rgb, Self::ENCODING_SPEED);
+            frame.delay = Self::FRAME_DELAY;
+
+            encoder.write_frame(&frame)?;
+            encoded += 1;
+        }
+        // The GIF trailer is written when the encoder is dropped.
+        Ok(encoded)
+    }
+}
+
+impl Drop for Timelapse {
+    /// Wait for captured frames to be encoded, so that the file isn't left half-written.
+    fn drop(&mut self) {
+        self.stop().ok();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_timelapse() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("timelapse.gif");
+        let (w, h) = (32, 32);
+
+        let mut timelapse = Timelapse::new(&path).unwrap();
+        let mut frames = vec![];
+
+        for i in 0..5u8 {
+            // Top half is red, bottom half is blue.
+            let mut texels = vec![Rgba8::BLUE; w * h];
+            texels[..w * h / 2].fill(Rgba8::RED);
+
+            if timelapse.tick(2) {
+                timelapse.capture(&texels, w as u32, h as u32).unwrap();
+                frames.push(i);
+            }
+        }
+        // Frames of a different size are skipped.
+        assert!(timelapse.tick(1));
+        timelapse.capture(&[Rgba8::RED], 1, 1).unwrap();
+
+        assert_eq!(frames, vec![0, 2, 4]);
+        assert_eq!(timelapse.finish().unwrap(), 3);
+
+        let mut decoder = gif::Decoder::new(File::open(&path).unwrap());
+        decoder.set(gif::ColorOutput::RGBA);
+
+        let mut reader = decoder.read_info().unwrap();
+        let mut count = 0;
+
+        while let Some(frame) = reader.read_next_frame().unwrap() {
+            assert_eq!((frame.width, frame.height), (w as u16, h as u16));
+            assert_eq!(frame.delay, Timelapse::FRAME_DELAY);
+            assert_eq!(&frame.buffer[..4], &[0xff, 0, 0, 0xff]);
+            assert_eq!(&frame.buffer[w * h * 4 - 4..], &[0, 0, 0xff, 0xff]);
+
+            count += 1;
+        }
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn test_timelapse_empty() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("timelapse.gif");
+
+        let timelapse = Timelapse::new(&path).unwrap();
+        assert!(timelapse.finish().is_err());
+        assert!(!path.exists());
+
+        assert!(Timelapse::new(tmp.path().join("missing").join("timelapse.gif")).is_err());
+    }
+}
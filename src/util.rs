@@ -39,6 +39,22 @@ pub fn stitch_frames<T: Clone>(mut frames: Vec<Vec<T>>, fw: usize, fh: usize, va
     buffer
 }
 
+/// Split a strip of frames into individual frames. The inverse of [`stitch_frames`].
+pub fn split_frames<T: Clone>(pixels: &[T], fw: usize, fh: usize, nframes: usize) -> Vec<Vec<T>> {
+    let width = fw * nframes;
+
+    (0..nframes)
+        .map(|i| {
+            let mut frame = Vec::with_capacity(fw * fh);
+            for y in 0..fh {
+                let offset = i * fw + y * width;
+                frame.extend_from_slice(&pixels[offset..offset + fw]);
+            }
+            frame
+        })
+        .collect()
+}
+
 pub fn align_u8<T>(data: &[T]) -> &[u8] {
     let (head, body, tail) = unsafe { data.align_to::<u8>() };
 
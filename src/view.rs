@@ -1,11 +1,15 @@
 pub mod path;
 pub mod resource;
+pub mod save;
 
 pub use path::{Format, Path};
-pub use resource::{Edit, EditId, Snapshot, ViewResource};
+pub use resource::{Edit, EditId, ViewResource};
+pub use save::{SaveFile, SaveJob, SaveResult, Saver};
 
-use crate::cmd::Axis;
+use crate::cmd::{Align, Axis};
+use crate::io::Manifest;
 use crate::session::{Direction, Session, SessionCoords};
+use crate::util;
 
 use crate::gfx::math::*;
 use crate::gfx::rect::Rect;
@@ -13,15 +17,27 @@ use crate::gfx::{Point, Rgba8};
 
 use nonempty::NonEmpty;
 
+use std::cell::RefCell;
 use std::collections::btree_map;
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::fmt;
 use std::io;
+use std::rc::Rc;
+use std::time;
+
+/// A view resource, shared by a view and the views mirroring it.
+pub type Resource = Rc<RefCell<ViewResource>>;
 
 /// View identifier.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Debug, Default)]
 pub struct ViewId(u16);
 
+impl From<u16> for ViewId {
+    fn from(id: u16) -> Self {
+        Self(id)
+    }
+}
+
 impl fmt::Display for ViewId {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         self.0.fmt(f)
@@ -70,6 +86,14 @@ impl ViewExtent {
         Rect::new(self.fw * n, 0, self.fw * n + self.fw, self.fh)
     }
 
+    /// Compute the index of a pixel in a buffer ordered top row first, given a point.
+    pub fn coord_to_index(&self, p: ViewCoords<u32>) -> Option<usize> {
+        self.height()
+            .checked_sub(p.y)
+            .and_then(|x| x.checked_sub(1))
+            .map(|y| (y * self.width() + p.x) as usize)
+    }
+
     /// Compute the frame index, given a point.
     /// Warning: can underflow.
     pub fn to_frame(self, p: ViewCoords<u32>) -> usize {
@@ -101,6 +125,8 @@ pub enum ViewOp {
     Yank(Rect<i32>),
     /// Flips a given area horizontally or vertically.
     Flip(Rect<i32>, Axis),
+    /// Rotates a given area counter-clockwise, by the given angle in degrees.
+    Rotate(Rect<i32>, f32),
     /// Blit the paste buffer into the given area.
     Paste(Rect<i32>),
     /// Resize the view.
@@ -134,6 +160,11 @@ pub struct View<R> {
     pub state: ViewState,
     /// Animation state of the sprite displayed by this view.
     pub animation: Animation<Rect<f32>>,
+    /// Animation frame rate, if set. Overrides the `animation/delay` setting.
+    pub fps: Option<u32>,
+    /// Frame pairs. Strokes on one frame of a pair are mirrored horizontally
+    /// onto the other.
+    pub pairs: BTreeSet<(usize, usize)>,
     /// View resource.
     pub resource: R,
 
@@ -161,6 +192,18 @@ impl<T> Animation<T> {
         self.index = (self.index + 1) % self.len();
     }
 
+    /// Advance the animation as the animation clock goes from `from` to `to`,
+    /// stepping each time the clock reaches a multiple of the delay. Animations
+    /// with the same delay thus stay in step.
+    pub fn advance(&mut self, from: time::Duration, to: time::Duration, delay: time::Duration) {
+        let delay = delay.as_millis().max(1);
+        let steps = to.as_millis() / delay - from.as_millis() / delay;
+
+        for _ in 0..steps % self.len() as u128 {
+            self.step();
+        }
+    }
+
     pub fn val(&self) -> &T {
         &self.frames[self.index % self.len()]
     }
@@ -205,6 +248,8 @@ impl<R> View<R> {
             flip_y: false,
             file_status: fs,
             animation: Animation::new(frames),
+            fps: None,
+            pairs: BTreeSet::new(),
             state: ViewState::Okay,
             saved_snapshot,
             resource,
@@ -288,7 +333,10 @@ impl<R> View<R> {
     }
 
     pub fn paint_color(&mut self, color: Rgba8, x: i32, y: i32) {
-        self.ops.push(ViewOp::SetPixel(color, x, y));
+        // Pixels outside of the view can't be painted.
+        if x >= 0 && y >= 0 && (x as u32) < self.width() && (y as u32) < self.height() {
+            self.ops.push(ViewOp::SetPixel(color, x, y));
+        }
     }
 
     pub fn yank(&mut self, area: Rect<i32>) {
@@ -299,6 +347,10 @@ impl<R> View<R> {
         self.ops.push(ViewOp::Flip(area, dir));
     }
 
+    pub fn rotate(&mut self, area: Rect<i32>, degrees: f32) {
+        self.ops.push(ViewOp::Rotate(area, degrees));
+    }
+
     pub fn paste(&mut self, area: Rect<i32>) {
         self.ops.push(ViewOp::Paste(area));
         self.touch();
@@ -416,6 +468,26 @@ impl<R> View<R> {
         self.file_status.to_string()
     }
 
+    /// Return the time remaining until the next animation step, given the
+    /// animation clock and the default delay. See [`Animation::advance`].
+    pub fn animation_remaining(
+        &self,
+        clock: time::Duration,
+        default: time::Duration,
+    ) -> time::Duration {
+        let delay = self.delay(default).as_millis().max(1);
+
+        time::Duration::from_millis((delay - clock.as_millis() % delay) as u64)
+    }
+
+    /// Return the animation delay, derived from the frame rate if set.
+    pub fn delay(&self, default: time::Duration) -> time::Duration {
+        match self.fps {
+            Some(fps) => time::Duration::from_millis(1000 / fps.max(1) as u64),
+            None => default,
+        }
+    }
+
     /// Return the view extent.
     pub fn extent(&self) -> ViewExtent {
         ViewExtent::new(self.fw, self.fh, self.animation.len())
@@ -448,6 +520,13 @@ impl<R> View<R> {
         self.saved_snapshot == Some(id)
     }
 
+    /// Mark the view as modified, if it was saved.
+    fn modified(&mut self) {
+        if let FileStatus::Saved(ref f) = self.file_status {
+            self.file_status = FileStatus::Modified(f.clone());
+        }
+    }
+
     /// Mark the view as saved at a given snapshot.
     fn saved(&mut self, id: EditId, storage: FileStorage) {
         self.file_status = FileStatus::Saved(storage);
@@ -469,40 +548,333 @@ impl<R> View<R> {
     }
 }
 
-impl View<ViewResource> {
+impl View<Resource> {
     /// Get the color at the given view coordinate.
-    pub fn color_at(&self, p: ViewCoords<u32>) -> Option<&Rgba8> {
-        let (snapshot, pixels) = self.resource.layer.current_snapshot();
-        snapshot.coord_to_index(p).and_then(|idx| pixels.get(idx))
+    pub fn color_at(&self, p: ViewCoords<u32>) -> Option<Rgba8> {
+        let resource = self.resource.borrow();
+        let (snapshot, pixels) = resource.layer.current_snapshot();
+
+        snapshot
+            .coord_to_index(p)
+            .and_then(|idx| pixels.get(idx))
+            .copied()
+    }
+
+    /// Get the current edit of the view.
+    pub fn current_edit(&self) -> EditId {
+        self.resource.borrow().current_edit()
+    }
+
+    /// Call the given function with the current view pixels, along with their
+    /// extent, without copying them. The view resource can't be modified from
+    /// within the function.
+    pub fn with_pixels<T>(&self, f: impl FnOnce(ViewExtent, &[Rgba8]) -> T) -> T {
+        let resource = self.resource.borrow();
+        let (snapshot, pixels) = resource.layer.current_snapshot();
+
+        f(snapshot.extent, pixels)
+    }
+
+    /// Get a copy of the current view pixels, along with their extent. See
+    /// [`View::with_pixels`] for reading them without a copy.
+    pub fn pixels(&self) -> (ViewExtent, Vec<Rgba8>) {
+        let resource = self.resource.borrow();
+        let (snapshot, pixels) = resource.layer.current_snapshot();
+
+        (snapshot.extent, pixels.to_vec())
+    }
+
+    /// Get a copy of the pixels of the given area, top row first, or `None`
+    /// if the area isn't within the view.
+    pub fn pixels_rect(&self, rect: &Rect<i32>) -> Option<Vec<Rgba8>> {
+        self.resource
+            .borrow()
+            .layer
+            .get_snapshot_rect(rect)
+            .map(|(_, pixels)| pixels)
     }
 
-    /// Restore a view snapshot (undo/redo an edit).
-    pub fn restore_snapshot(&mut self, dir: Direction) {
-        let result = if dir == Direction::Backward {
-            self.resource.history_prev()
+    /// Restore a view snapshot (undo/redo an edit). Returns the edit that was
+    /// undone or redone, if any.
+    pub fn restore_snapshot(&mut self, dir: Direction) -> Option<Edit> {
+        let (eid, edit) = if dir == Direction::Backward {
+            self.resource.borrow_mut().history_prev()
         } else {
-            self.resource.history_next()
-        };
+            self.resource.borrow_mut().history_next()
+        }?;
 
-        match result {
-            Some((eid, Edit::ViewResized(from, to))) => {
+        match edit {
+            Edit::ViewResized(from, to) => {
                 let extent = match dir {
                     Direction::Backward => from,
                     Direction::Forward => to,
                 };
                 self.restore_extent(eid, extent);
             }
-            Some((eid, Edit::ViewPainted)) => {
+            Edit::ViewPainted => {
                 self.restore(eid);
             }
-            Some((_, Edit::Initial)) => {}
-            None => {}
+            // Restored by the session.
+            Edit::PaletteChanged(..) | Edit::SelectionChanged(..) => {}
+            Edit::Initial => {}
+        }
+        Some(edit)
+    }
+
+    /// Replace the view pixels with the given buffer, as a single edit.
+    /// The buffer must match the current view extent.
+    pub fn paint_pixels(&mut self, pixels: Vec<Rgba8>) {
+        self.resource.borrow_mut().record_view_painted(pixels);
+        self.damaged(None);
+        self.modified();
+    }
+
+    /// Restore the view to its state from at least `ago` in the past, as a new edit.
+    /// Returns the age of the state that was restored, if any.
+    pub fn restore_checkpoint(&mut self, ago: time::Duration) -> Option<time::Duration> {
+        let (age, pixels, extent) = self.resource.borrow().checkpoint_before(ago)?;
+
+        self.replace_pixels(pixels, extent);
+
+        Some(age)
+    }
+
+    /// Restore the view to the state saved under the given name, as a new
+    /// edit. Returns whether the view changed, or `None` if there is no such state.
+    pub fn restore_label(&mut self, name: &str) -> Option<bool> {
+        let (_, pixels, extent) = self.resource.borrow().labeled(name)?;
+
+        if self.with_pixels(|e, p| (e, p) == (extent, pixels.as_slice())) {
+            return Some(false);
+        }
+        self.replace_pixels(pixels, extent);
+
+        Some(true)
+    }
+
+    /// Replace the view pixels with the given buffer, as a single edit.
+    fn replace_pixels(&mut self, pixels: Vec<Rgba8>, extent: ViewExtent) {
+        if extent == self.extent() {
+            self.paint_pixels(pixels);
+        } else {
+            self.reshape(pixels, extent);
+        }
+    }
+
+    /// Replace the view pixels with the given buffer of a different extent,
+    /// as a single edit.
+    pub fn reshape(&mut self, pixels: Vec<Rgba8>, extent: ViewExtent) {
+        self.resource
+            .borrow_mut()
+            .record_view_resized(pixels, extent);
+        self.damaged(Some(extent));
+        self.reset(extent);
+        self.modified();
+    }
+
+    /// Replace colors across all frames of the view, using the given mapping.
+    /// Returns the number of pixels changed.
+    pub fn recolor(&mut self, mapping: &BTreeMap<Rgba8, Rgba8>) -> usize {
+        let (_, pixels) = self.pixels();
+        let mut changed = 0;
+
+        let pixels: Vec<Rgba8> = pixels
+            .iter()
+            .map(|p| match mapping.get(p) {
+                Some(c) if c != p => {
+                    changed += 1;
+                    *c
+                }
+                _ => *p,
+            })
+            .collect();
+
+        if changed > 0 {
+            self.paint_pixels(pixels);
+        }
+        changed
+    }
+
+    /// Offset each frame of the view by the given amount, wrapping the pixels
+    /// that exit one edge of the frame around to the opposite edge, as a single
+    /// edit. Returns `false` if the offset leaves the view unchanged.
+    pub fn shift(&mut self, dx: i32, dy: i32) -> bool {
+        let (extent, pixels) = self.pixels();
+        let ViewExtent { fw, fh, nframes } = extent;
+
+        if dx % fw as i32 == 0 && dy % fh as i32 == 0 {
+            return false;
+        }
+        // Frame rows are ordered top first, while view coordinates grow upwards.
+        let frames = util::split_frames(&pixels, fw as usize, fh as usize, nframes)
+            .into_iter()
+            .map(|f| crate::pixels::wrap(&f, fw, fh, dx, -dy))
+            .collect();
+
+        self.paint_pixels(util::stitch_frames(
+            frames,
+            fw as usize,
+            fh as usize,
+            Rgba8::TRANSPARENT,
+        ));
+        true
+    }
+
+    /// Get the smallest frame area containing the non-transparent pixels of
+    /// every frame, or `None` if the view is empty.
+    pub fn content_bounds(&self) -> Option<Rect<i32>> {
+        let resource = self.resource.borrow();
+        let (snapshot, pixels) = resource.layer.current_snapshot();
+        let ViewExtent { fw, fh, nframes } = snapshot.extent;
+
+        util::split_frames(pixels, fw as usize, fh as usize, nframes)
+            .iter()
+            .filter_map(|f| crate::pixels::bounds(f, fw, fh, &Rgba8::TRANSPARENT))
+            .map(|r| Rect::new(r.x1, fh - r.y2, r.x2, fh - r.y1).map(|n| n as i32))
+            .reduce(|a, b| {
+                Rect::new(
+                    a.x1.min(b.x1),
+                    a.y1.min(b.y1),
+                    a.x2.max(b.x2),
+                    a.y2.max(b.y2),
+                )
+            })
+    }
+
+    /// Crop every frame to the given area, relative to the frame, as a single
+    /// edit. Parts of the area outside the frame are made transparent. Returns
+    /// `false` if the area is empty or is the whole frame.
+    pub fn crop(&mut self, area: Rect<i32>) -> bool {
+        let (extent, pixels) = self.pixels();
+        let ViewExtent { fw, fh, nframes } = extent;
+
+        if area.width() <= 0 || area.height() <= 0 || area == Rect::origin(fw as i32, fh as i32) {
+            return false;
+        }
+        let (w, h) = (area.width() as u32, area.height() as u32);
+        let frames = util::split_frames(&pixels, fw as usize, fh as usize, nframes)
+            .into_iter()
+            .map(|f| {
+                let (fw, fh) = (fw as i32, fh as i32);
+
+                // Frame rows are ordered top first, while view coordinates grow upwards.
+                (0..h as i32)
+                    .flat_map(|row| (0..w as i32).map(move |col| (col, row)))
+                    .map(|(col, row)| {
+                        let (x, y) = (area.x1 + col, fh - area.y2 + row);
+
+                        if x >= 0 && y >= 0 && x < fw && y < fh {
+                            f[(y * fw + x) as usize]
+                        } else {
+                            Rgba8::TRANSPARENT
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        self.reshape(
+            util::stitch_frames(frames, w as usize, h as usize, Rgba8::TRANSPARENT),
+            ViewExtent::new(w, h, nframes),
+        );
+        true
+    }
+
+    /// Move the content of the given frame to its center or to one of its edges,
+    /// as a single edit. The content is what's within the bounding box of the
+    /// frame's non-transparent pixels. Returns the offset the content was moved
+    /// by, or `None` if the frame is empty.
+    pub fn align(&mut self, frame: usize, align: Align) -> Option<(i32, i32)> {
+        let (extent, pixels) = self.pixels();
+        let ViewExtent { fw, fh, nframes } = extent;
+        let mut frames = util::split_frames(&pixels, fw as usize, fh as usize, nframes);
+        let content = crate::pixels::bounds(frames.get(frame)?, fw, fh, &Rgba8::TRANSPARENT)?;
+
+        // Frame rows are ordered top first, so these are offsets in rows.
+        let (x, y) = match align {
+            Align::Center => ((fw - content.width()) / 2, (fh - content.height()) / 2),
+            Align::Top => (content.x1, 0),
+            Align::Bottom => (content.x1, fh - content.height()),
+            Align::Left => (0, content.y1),
+            Align::Right => (fw - content.width(), content.y1),
+        };
+        let (dx, dy) = (x as i32 - content.x1 as i32, y as i32 - content.y1 as i32);
+
+        if (dx, dy) != (0, 0) {
+            // Only transparent pixels wrap around, since the content stays within the frame.
+            frames[frame] = crate::pixels::wrap(&frames[frame], fw, fh, dx, dy);
+
+            self.paint_pixels(util::stitch_frames(
+                frames,
+                fw as usize,
+                fh as usize,
+                Rgba8::TRANSPARENT,
+            ));
+        }
+        Some((dx, -dy))
+    }
+
+    /// Restore pixels of the given area that are currently transparent, using the given
+    /// buffer, ordered top row first. Transparent pixels of the buffer are skipped.
+    /// Returns the number of pixels restored.
+    pub fn unerase(&mut self, rect: Rect<i32>, buffer: &[Rgba8]) -> usize {
+        self.paint_area(rect, buffer, |p, c| p.a == 0 && c.a != 0)
+    }
+
+    /// Replace the pixels of the given area with the given buffer, ordered top row
+    /// first. Returns the number of pixels changed.
+    pub fn replace_area(&mut self, rect: Rect<i32>, buffer: &[Rgba8]) -> usize {
+        self.paint_area(rect, buffer, |p, c| p != c)
+    }
+
+    /// Paint the pixels of the given area with the given buffer, ordered top row first,
+    /// wherever the predicate holds for the current and new color. Returns the number
+    /// of pixels painted.
+    fn paint_area<F>(&mut self, rect: Rect<i32>, buffer: &[Rgba8], predicate: F) -> usize
+    where
+        F: Fn(&Rgba8, &Rgba8) -> bool,
+    {
+        let (extent, mut pixels) = self.pixels();
+        let bounds = extent.rect().map(|n| n as i32);
+
+        if rect.intersection(bounds) != rect {
+            return 0;
+        }
+        let (w, h) = (extent.width() as i32, extent.height() as i32);
+        let mut painted = 0;
+
+        for (i, c) in buffer.iter().enumerate() {
+            let x = rect.x1 + i as i32 % rect.width();
+            let y = rect.y2 - 1 - i as i32 / rect.width();
+            let p = &mut pixels[((h - y - 1) * w + x) as usize];
+
+            if predicate(p, c) {
+                *p = *c;
+                painted += 1;
+            }
+        }
+
+        if painted > 0 {
+            self.paint_pixels(pixels);
         }
+        painted
     }
 
+    /// Save the view to the given storage, blocking until it's written.
     pub fn save_as(&mut self, storage: &FileStorage) -> io::Result<usize> {
+        let job = self.save_job(storage)?;
+        let written = job.run()?;
+
+        self.mark_saved(job.edit_id, &job.storage);
+
+        Ok(written)
+    }
+
+    /// Prepare a save of the view to the given storage, copying the pixels
+    /// to write. See [`View::save_as`] for saving immediately.
+    pub fn save_job(&self, storage: &FileStorage) -> io::Result<SaveJob> {
         let ext = self.extent();
-        let (edit_id, written) = match &storage {
+        let rects = match storage {
             FileStorage::Single(path) => {
                 {
                     let mut path_copy = path.clone();
@@ -510,21 +882,72 @@ impl View<ViewResource> {
                     std::fs::create_dir_all(path_copy.as_path())?;
                 }
 
-                let edit_id = self.save_rect_as(ext.rect(), path)?;
-
-                (edit_id, (ext.width() * ext.height()) as usize)
+                vec![(path.clone(), ext.rect())]
             }
-            FileStorage::Range(paths) => {
-                for (i, path) in paths.iter().enumerate() {
-                    self.save_rect_as(ext.frame(i), path)?;
-                }
-
-                let edit_id = self.resource.current_edit();
+            FileStorage::Range(paths) => paths
+                .iter()
+                .enumerate()
+                .map(|(i, path)| (path.clone(), ext.frame(i)))
+                .collect(),
+        };
 
-                (edit_id, paths.len() * (ext.fw * ext.fh) as usize)
+        let mut files = Vec::with_capacity(rects.len());
+        for (path, rect) in rects {
+            // Saved views must be able to be opened again, as they were.
+            if let Some(ext) = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .filter(|e| path::EXPORT_FORMATS.contains(e))
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("`{}` files can only be exported, use `:export`", ext),
+                ));
             }
-        };
+            // Only allow overwriting of files if it's the file of the view being saved.
+            if path.exists() && self.file_storage().map_or(true, |f| !f.contains(&path)) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("\"{}\" already exists", path.display()),
+                ));
+            }
+            let pixels = self
+                .pixels_rect(&rect.map(|n| n as i32))
+                .expect("rect should be within view");
+
+            // Archives hold their extent, which is a single frame when saving frames separately.
+            let manifest = if path
+                .extension()
+                .map_or(false, |e| e == path::ARCHIVE_FORMAT)
+            {
+                Some(Manifest::new(if rect == ext.rect() {
+                    ext
+                } else {
+                    ViewExtent::new(rect.width(), rect.height(), 1)
+                }))
+            } else {
+                None
+            };
+
+            files.push(SaveFile {
+                path,
+                width: rect.width(),
+                height: rect.height(),
+                pixels,
+                manifest,
+            });
+        }
+
+        Ok(SaveJob {
+            id: self.id,
+            storage: storage.clone(),
+            edit_id: self.current_edit(),
+            files,
+        })
+    }
 
+    /// Mark the view as saved to the given storage, at the given edit.
+    pub fn mark_saved(&mut self, edit_id: EditId, storage: &FileStorage) {
         // Mark the view as saved at a specific snapshot and with the given path.
         match self.file_status {
             FileStatus::Modified(ref curr_fs) | FileStatus::New(ref curr_fs) => {
@@ -538,21 +961,10 @@ impl View<ViewResource> {
             FileStatus::Saved(_) => {}
         }
 
-        Ok(written)
-    }
-
-    /// Save part of a layer to disk.
-    fn save_rect_as(&mut self, rect: Rect<u32>, path: &std::path::Path) -> io::Result<EditId> {
-        // Only allow overwriting of files if it's the file of the view being saved.
-        if path.exists() && self.file_storage().map_or(true, |f| !f.contains(path)) {
-            return Err(io::Error::new(
-                io::ErrorKind::AlreadyExists,
-                format!("\"{}\" already exists", path.display()),
-            ));
+        // The view may have been edited since the save started.
+        if edit_id != self.current_edit() {
+            self.modified();
         }
-        let (e_id, _) = self.save(rect, path)?;
-
-        Ok(e_id)
     }
 }
 
@@ -705,6 +1117,11 @@ impl<R> ViewManager<R> {
         self.lru.front().cloned()
     }
 
+    /// Return the id of the last recently active view matching the predicate, if any.
+    pub fn recent_matching(&self, f: impl Fn(ViewId) -> bool) -> Option<ViewId> {
+        self.lru.iter().copied().find(|id| f(*id))
+    }
+
     /// Return the currently active view, if any.
     pub fn active(&self) -> Option<&View<R>> {
         self.views.get(&self.active_id)
@@ -808,33 +1225,3 @@ impl<R> ViewManager<R> {
         ViewId(id)
     }
 }
-
-impl ViewManager<ViewResource> {
-    pub fn get_snapshot_safe(&self, id: ViewId) -> Option<(&Snapshot, &[Rgba8])> {
-        self.views
-            .get(&id)
-            .map(|v| v.resource.layer.current_snapshot())
-    }
-
-    pub fn get_snapshot(&self, id: ViewId) -> (&Snapshot, &[Rgba8]) {
-        self.get_snapshot_safe(id).expect(&format!(
-            "view #{} must exist and have an associated snapshot",
-            id
-        ))
-    }
-
-    pub fn get_snapshot_rect(
-        &self,
-        id: ViewId,
-        rect: &Rect<i32>,
-    ) -> Option<(&Snapshot, Vec<Rgba8>)> {
-        self.views
-            .get(&id)
-            .map(|v| &v.resource.layer)
-            .expect(&format!(
-                "view #{} must exist and have an associated snapshot",
-                id
-            ))
-            .get_snapshot_rect(rect)
-    }
-}
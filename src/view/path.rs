@@ -5,13 +5,26 @@ use std::path;
 
 /// Rx archive format extension.
 pub const ARCHIVE_FORMAT: &str = "rxz";
+/// Raw RGBA image extension.
+pub const RAW_FORMAT: &str = "rgba";
+/// Image formats that can't hold all of a view, and are thus only exported.
+pub const EXPORT_FORMATS: &[&str] = &["ico", "bmp"];
 /// Supported image formats for reading.
-pub const SUPPORTED_READ_FORMATS: &[&str] = &["png", self::ARCHIVE_FORMAT];
+pub const SUPPORTED_READ_FORMATS: &[&str] =
+    &["png", self::RAW_FORMAT, "ora", "psd", self::ARCHIVE_FORMAT];
 
 #[derive(Debug, Copy, Clone)]
 pub enum Format {
     Png,
     Gif,
+    /// Raw RGBA pixels, without a header.
+    Rgba,
+    /// OpenRaster image, which is read flattened.
+    Ora,
+    /// Photoshop document, which is read flattened.
+    Psd,
+    /// Rx archive, see [`crate::io::Archive`].
+    Archive,
 }
 
 #[derive(Debug, Clone)]
@@ -47,6 +60,10 @@ impl<'a> TryFrom<&'a path::Path> for Path<'a, path::Path> {
         let format = match ext {
             "gif" => Format::Gif,
             "png" => Format::Png,
+            "rgba" => Format::Rgba,
+            "ora" => Format::Ora,
+            "psd" => Format::Psd,
+            self::ARCHIVE_FORMAT => Format::Archive,
             _ => {
                 return Err(io::Error::new(
                     io::ErrorKind::Other,
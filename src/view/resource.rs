@@ -2,6 +2,7 @@ use crate::gfx::color::{Rgb8, Rgba8};
 use crate::gfx::rect::Rect;
 use crate::image;
 use crate::pixels;
+use crate::session::Selection;
 use crate::util;
 use crate::view::{ViewCoords, ViewExtent};
 
@@ -9,6 +10,7 @@ use nonempty::NonEmpty;
 
 use gif::{self, SetParameter};
 
+use std::collections::{BTreeMap, VecDeque};
 use std::fmt;
 use std::fs::File;
 use std::io;
@@ -20,29 +22,124 @@ pub struct ViewResource {
     pub layer: LayerResource,
     pub history: NonEmpty<Edit>,
     pub cursor: usize,
+    /// Number of edits discarded from the start of the history.
+    trimmed: usize,
     pub extent: ViewExtent,
+    /// Snapshots taken periodically, independent of the edit history, oldest first.
+    /// Only the first one, and those following a resize, hold all of the pixels.
+    checkpoints: VecDeque<Checkpoint>,
+    /// Snapshots saved under a name by the user, to be restored on request.
+    labels: BTreeMap<String, Label>,
 }
 
 impl ViewResource {
+    /// Maximum number of checkpoints kept per view.
+    pub const MAX_CHECKPOINTS: usize = 256;
+
     pub fn new(pixels: Vec<Rgba8>, extent: ViewExtent) -> Self {
+        let checkpoint = Checkpoint {
+            time: time::Instant::now(),
+            snapshot: Snapshot::new(SnapshotId::default(), &pixels, extent),
+        };
+
         Self {
             layer: LayerResource::new(pixels, extent),
             history: NonEmpty::new(Edit::Initial),
             cursor: 0,
+            trimmed: 0,
             extent,
+            checkpoints: VecDeque::from(vec![checkpoint]),
+            labels: BTreeMap::new(),
         }
     }
 
-    pub fn save<P: AsRef<Path>>(&self, rect: Rect<u32>, path: P) -> io::Result<(EditId, usize)> {
-        let (_, pixels) = self
-            .layer
-            .get_snapshot_rect(&rect.map(|n| n as i32))
-            .expect("rect should be within view");
-        let (w, h) = (rect.width(), rect.height());
+    /// Take a checkpoint of the current view state, unless it hasn't changed
+    /// since the last checkpoint. Only the pixels that changed since the last
+    /// checkpoint are stored, unless the view was resized.
+    pub fn checkpoint(&mut self, time: time::Instant) {
+        let (current, pixels) = self.layer.current_snapshot();
+        let snapshot = match self.checkpoints.back() {
+            Some(last) if last.snapshot.extent == current.extent => {
+                let prev = self.checkpoint_pixels(self.checkpoints.len() - 1);
+
+                if prev == pixels {
+                    return;
+                }
+                Snapshot::delta(current.id, &prev, pixels, current.extent)
+            }
+            _ => Snapshot::new(current.id, pixels, current.extent),
+        };
+
+        if self.checkpoints.len() == Self::MAX_CHECKPOINTS {
+            // The checkpoint following the oldest one becomes the base of the others.
+            let pixels = self.checkpoint_pixels(1);
+            self.checkpoints.pop_front();
 
-        image::save_as(path, w, h, 1, &pixels)?;
+            if let Some(first) = self.checkpoints.front_mut() {
+                first.snapshot = Snapshot::new(first.snapshot.id, &pixels, first.snapshot.extent);
+            }
+        }
+        self.checkpoints.push_back(Checkpoint { time, snapshot });
+    }
 
-        Ok((self.cursor, (w * h) as usize))
+    /// Get the latest checkpoint which is at least as old as `ago`, or the
+    /// oldest checkpoint if there is none. Returns the checkpoint age, pixels and extent.
+    pub fn checkpoint_before(
+        &self,
+        ago: time::Duration,
+    ) -> Option<(time::Duration, Vec<Rgba8>, ViewExtent)> {
+        let index = self
+            .checkpoints
+            .iter()
+            .rposition(|c| c.time.elapsed() >= ago)
+            .unwrap_or(0);
+        let c = self.checkpoints.get(index)?;
+
+        Some((
+            c.time.elapsed(),
+            self.checkpoint_pixels(index),
+            c.snapshot.extent,
+        ))
+    }
+
+    /// Get the pixels of the checkpoint at the given index, by applying deltas
+    /// to the closest full checkpoint before it.
+    fn checkpoint_pixels(&self, index: usize) -> Vec<Rgba8> {
+        let base = self
+            .checkpoints
+            .iter()
+            .take(index + 1)
+            .rposition(|c| matches!(c.snapshot.data, SnapshotData::Full(_)))
+            .expect("the first checkpoint is always full");
+        let mut pixels = self.checkpoints[base].snapshot.pixels();
+
+        for c in self.checkpoints.range(base + 1..=index) {
+            if let SnapshotData::Delta { rect, after, .. } = &c.snapshot.data {
+                Snapshot::apply(&mut pixels, c.snapshot.width() as usize, rect, after);
+            }
+        }
+        pixels
+    }
+
+    /// Save the current view state under the given name, replacing any state
+    /// previously saved under it. Returns the edit the state was saved at.
+    pub fn label(&mut self, name: &str) -> EditId {
+        let (current, pixels) = self.layer.current_snapshot();
+        let snapshot = Snapshot::new(current.id, pixels, current.extent);
+        let edit = self.current_edit();
+
+        self.labels
+            .insert(name.to_owned(), Label { edit, snapshot });
+
+        edit
+    }
+
+    /// Get the view state saved under the given name. Returns the edit it
+    /// was saved at, along with its pixels and extent.
+    pub fn labeled(&self, name: &str) -> Option<(EditId, Vec<Rgba8>, ViewExtent)> {
+        self.labels
+            .get(name)
+            .map(|l| (l.edit, l.snapshot.pixels(), l.snapshot.extent))
     }
 
     pub fn record_view_resized(&mut self, pixels: Vec<Rgba8>, extent: ViewExtent) {
@@ -75,6 +172,43 @@ impl ViewResource {
         self.history.push(edit);
     }
 
+    /// Discard all but the last `n` edits before the current one, so that
+    /// they can no longer be undone. Returns the number of edits discarded.
+    pub fn history_trim(&mut self, n: usize) -> usize {
+        let count = self.cursor.saturating_sub(n);
+        if count == 0 {
+            return 0;
+        }
+        // The oldest edit kept becomes the initial one, so the snapshots
+        // taken by the discarded edits aren't needed anymore.
+        let snapshots = self
+            .history
+            .iter()
+            .skip(1)
+            .take(count)
+            .filter(|e| e.is_layer_edit())
+            .count();
+
+        let mut history = NonEmpty::new(Edit::Initial);
+        for edit in self.history.iter().skip(count + 1).cloned() {
+            history.push(edit);
+        }
+        self.history = history;
+        self.cursor -= count;
+        self.trimmed += count;
+        self.layer.trim(snapshots);
+
+        count
+    }
+
+    /// Discard all edits but the current one, including the ones that could
+    /// be redone. Returns the number of edits that could be undone.
+    pub fn history_clear(&mut self) -> usize {
+        self.history_truncate();
+        self.layer.truncate();
+        self.history_trim(0)
+    }
+
     pub fn history_prev(&mut self) -> Option<(usize, Edit)> {
         if self.cursor == 0 {
             return None;
@@ -89,11 +223,12 @@ impl ViewResource {
                 Edit::ViewPainted => {
                     self.layer.prev_snapshot();
                 }
-                _ => return None,
+                Edit::PaletteChanged(..) | Edit::SelectionChanged(..) => {}
+                Edit::Initial => return None,
             }
             self.cursor -= 1;
 
-            Some((self.cursor, edit))
+            Some((self.current_edit(), edit))
         } else {
             None
         }
@@ -111,16 +246,25 @@ impl ViewResource {
                 Edit::ViewPainted => {
                     self.layer.next_snapshot();
                 }
-                _ => return None,
+                Edit::PaletteChanged(..) | Edit::SelectionChanged(..) => {}
+                Edit::Initial => return None,
             }
-            Some((self.cursor, edit))
+            Some((self.current_edit(), edit))
         } else {
             None
         }
     }
 
+    /// Get the latest edit to the view pixels. Palette and selection edits
+    /// leave the pixels as they are, so they're skipped. Edit ids keep
+    /// counting from the start of the history, even when it's trimmed.
     pub fn current_edit(&self) -> EditId {
-        self.cursor
+        let cursor = (0..=self.cursor)
+            .rev()
+            .find(|i| self.history.get(*i).map_or(false, Edit::is_layer_edit))
+            .unwrap_or(0);
+
+        self.trimmed + cursor
     }
 
     pub fn save_png<P: AsRef<Path>>(&self, path: P, scale: u32) -> io::Result<usize> {
@@ -132,6 +276,65 @@ impl ViewResource {
         Ok((w * h * scale) as usize)
     }
 
+    /// Save the view as a PNG, scaled up with a smoothing filter. Each frame is
+    /// filtered separately.
+    pub fn save_png_filtered<P: AsRef<Path>>(
+        &self,
+        path: P,
+        filter: pixels::ScaleFilter,
+        scale: u32,
+    ) -> io::Result<usize> {
+        let (snapshot, pixels) = self.layer.current_snapshot();
+        let (w, h) = (snapshot.width(), snapshot.height());
+        let nframes = snapshot.extent.nframes;
+
+        let image = filter
+            .apply(pixels, w, h, nframes, scale)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        image::save_as(path, w * scale, h * scale, 1, &image)?;
+
+        Ok(image.len())
+    }
+
+    /// Save the view as an indexed PNG, using the given palette. If no bit depth
+    /// is given, the smallest one that fits the palette is used. Transparent pixels
+    /// are mapped to a transparent palette entry, which is added if necessary.
+    pub fn save_png_indexed<P: AsRef<Path>>(
+        &self,
+        path: P,
+        palette: &[Rgba8],
+        bits: Option<u8>,
+        scale: u32,
+    ) -> io::Result<usize> {
+        let (snapshot, pixels) = self.layer.current_snapshot();
+        let (w, h) = (snapshot.width(), snapshot.height());
+
+        let (palette, image) = image::index(pixels, palette)?;
+
+        let fits = |bits: u8| palette.len() <= 1 << bits;
+        let bits = match bits {
+            Some(bits) if ![1, 2, 4, 8].contains(&bits) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid bit depth {}, must be 1, 2, 4 or 8", bits),
+                ));
+            }
+            Some(bits) if !fits(bits) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} colors don't fit in a {}-bit image", palette.len(), bits),
+                ));
+            }
+            Some(bits) => bits,
+            None => [1, 2, 4, 8].into_iter().find(|b| fits(*b)).unwrap_or(8),
+        };
+
+        image::save_indexed_as(path, w, h, scale, &image, &palette, bits)?;
+
+        Ok((w * h * scale) as usize)
+    }
+
     pub fn save_svg<P: AsRef<Path>>(&self, path: P, scale: u32) -> io::Result<usize> {
         use std::io::Write;
 
@@ -263,6 +466,10 @@ pub struct LayerResource {
 }
 
 impl LayerResource {
+    /// Every so many snapshots, all the pixels are stored instead of only the changes,
+    /// to bound the work needed to reconstruct a snapshot.
+    const KEYFRAME_INTERVAL: usize = 64;
+
     fn new(pixels: Vec<Rgba8>, extent: ViewExtent) -> Self {
         Self {
             snapshots: NonEmpty::new(Snapshot::new(SnapshotId(0), &pixels, extent)),
@@ -326,43 +533,109 @@ impl LayerResource {
     }
 
     pub fn push_snapshot(&mut self, pixels: Vec<Rgba8>, extent: ViewExtent) {
-        // FIXME: If pixels match current snapshot exactly, don't add the snapshot.
-
         // If we try to add a snapshot when we're not at the
         // latest, we have to clear the list forward.
+        self.truncate();
+
+        let (current, _) = self.current_snapshot();
+        let extent_changed = current.extent != extent;
+        let id = SnapshotId(current.id.0 + 1);
+
+        self.snapshot += 1;
+
+        // Only store what changed since the previous snapshot, unless the extent
+        // changed, or it's time for a full snapshot.
+        let snapshot = if !extent_changed && self.snapshot % Self::KEYFRAME_INTERVAL != 0 {
+            Snapshot::delta(id, &self.pixels, &pixels, extent)
+        } else {
+            Snapshot::new(id, &pixels, extent)
+        };
+        self.snapshots.push(snapshot);
+        self.pixels = pixels;
+    }
+
+    /// Discard the snapshots after the current one.
+    fn truncate(&mut self) {
         if self.snapshot != self.snapshots.len() - 1 {
             self.snapshots.truncate(self.snapshot + 1);
             self.snapshot = self.snapshots.len() - 1;
         }
-        self.snapshot += 1;
-        self.snapshots
-            .push(Snapshot::new(SnapshotId(self.snapshot), &pixels, extent));
-        self.pixels = pixels;
+    }
+
+    /// Discard the first `n` snapshots, which must come before the current one.
+    fn trim(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        debug_assert!(n <= self.snapshot, "the current snapshot is kept");
+
+        // The first snapshot kept must hold all the pixels.
+        let pixels = self.reconstruct(n);
+        let mut kept = Vec::new();
+
+        while self.snapshots.len() > n {
+            kept.extend(self.snapshots.pop());
+        }
+        let first = kept.pop().expect("there is at least one snapshot kept");
+        let mut snapshots = NonEmpty::new(Snapshot::new(first.id, &pixels, first.extent));
+
+        for snapshot in kept.into_iter().rev() {
+            snapshots.push(snapshot);
+        }
+        self.snapshots = snapshots;
+        self.snapshot -= n;
     }
 
     pub fn prev_snapshot(&mut self) -> Option<&Snapshot> {
         if self.snapshot == 0 {
             return None;
         }
-        if let Some(snapshot) = self.snapshots.get(self.snapshot - 1) {
-            self.snapshot -= 1;
-            self.pixels = snapshot.pixels();
+        let current = self.snapshots.get(self.snapshot)?;
 
-            Some(snapshot)
-        } else {
-            None
+        match &current.data {
+            SnapshotData::Delta { rect, before, .. } => {
+                Snapshot::apply(&mut self.pixels, current.width() as usize, rect, before);
+            }
+            SnapshotData::Full(_) => {
+                self.pixels = self.reconstruct(self.snapshot - 1);
+            }
         }
+        self.snapshot -= 1;
+        self.snapshots.get(self.snapshot)
     }
 
     pub fn next_snapshot(&mut self) -> Option<&Snapshot> {
-        if let Some(snapshot) = self.snapshots.get(self.snapshot + 1) {
-            self.snapshot += 1;
-            self.pixels = snapshot.pixels();
+        let snapshot = self.snapshots.get(self.snapshot + 1)?;
 
-            Some(snapshot)
-        } else {
-            None
+        match &snapshot.data {
+            SnapshotData::Delta { rect, after, .. } => {
+                Snapshot::apply(&mut self.pixels, snapshot.width() as usize, rect, after);
+            }
+            SnapshotData::Full(pixels) => {
+                self.pixels = Snapshot::decompress(pixels);
+            }
         }
+        self.snapshot += 1;
+
+        Some(snapshot)
+    }
+
+    /// Get the pixels of the snapshot at the given index, by applying
+    /// deltas to the closest full snapshot before it.
+    fn reconstruct(&self, index: usize) -> Vec<Rgba8> {
+        let snapshots: Vec<&Snapshot> = self.snapshots.iter().take(index + 1).collect();
+        let base = snapshots
+            .iter()
+            .rposition(|s| matches!(s.data, SnapshotData::Full(_)))
+            .expect("the first snapshot is always full");
+        let mut pixels = snapshots[base].pixels();
+
+        for snapshot in &snapshots[base + 1..] {
+            if let SnapshotData::Delta { rect, after, .. } = &snapshot.data {
+                Snapshot::apply(&mut pixels, snapshot.width() as usize, rect, after);
+            }
+        }
+        pixels
     }
 }
 
@@ -370,9 +643,20 @@ impl LayerResource {
 pub enum Edit {
     ViewResized(ViewExtent, ViewExtent),
     ViewPainted,
+    /// The colors of the named palette changed, from and to.
+    PaletteChanged(String, Vec<Rgba8>, Vec<Rgba8>),
+    /// The selection changed, from and to.
+    SelectionChanged(Option<Selection>, Option<Selection>),
     Initial,
 }
 
+impl Edit {
+    /// Whether the edit took a layer snapshot, ie. changed the view pixels.
+    pub fn is_layer_edit(&self) -> bool {
+        !matches!(self, Self::PaletteChanged(..) | Self::SelectionChanged(..))
+    }
+}
+
 pub type EditId = usize;
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
@@ -384,13 +668,43 @@ impl fmt::Display for SnapshotId {
     }
 }
 
+/// View state at a point in time.
+#[derive(Debug)]
+struct Checkpoint {
+    time: time::Instant,
+    snapshot: Snapshot,
+}
+
+/// View state saved under a name.
+#[derive(Debug)]
+struct Label {
+    /// Edit the state was saved at.
+    edit: EditId,
+    snapshot: Snapshot,
+}
+
 #[derive(Debug)]
 pub struct Snapshot {
     pub id: SnapshotId,
     pub extent: ViewExtent,
 
     size: usize,
-    pixels: Compressed<Box<[u8]>>,
+    data: SnapshotData,
+}
+
+/// Snapshot pixel data.
+#[derive(Debug, PartialEq, Eq)]
+enum SnapshotData {
+    /// All of the view pixels.
+    Full(Compressed<Box<[u8]>>),
+    /// The pixels within `rect` before and after the edit, if the rest of
+    /// the view is unchanged since the previous snapshot. The rect is in
+    /// pixel buffer coordinates, ie. with the top row first.
+    Delta {
+        rect: Rect<usize>,
+        before: Compressed<Box<[u8]>>,
+        after: Compressed<Box<[u8]>>,
+    },
 }
 
 impl Snapshot {
@@ -408,7 +722,33 @@ impl Snapshot {
             id,
             extent,
             size,
-            pixels,
+            data: SnapshotData::Full(pixels),
+        }
+    }
+
+    /// Create a snapshot which only stores the region that changed between
+    /// `prev` and `pixels`. Both buffers must have the given extent.
+    pub fn delta(id: SnapshotId, prev: &[Rgba8], pixels: &[Rgba8], extent: ViewExtent) -> Self {
+        let size = pixels.len();
+        let width = extent.fw as usize * extent.nframes;
+
+        debug_assert!(prev.len() == size, "the pixel buffers have the same size");
+
+        let rect = Self::damage(prev, pixels, width).unwrap_or_default();
+        let before = Compressed::from(&Self::extract(prev, width, &rect))
+            .expect("compressing snapshot shouldn't result in an error");
+        let after = Compressed::from(&Self::extract(pixels, width, &rect))
+            .expect("compressing snapshot shouldn't result in an error");
+
+        Self {
+            id,
+            extent,
+            size,
+            data: SnapshotData::Delta {
+                rect,
+                before,
+                after,
+            },
         }
     }
 
@@ -429,18 +769,79 @@ impl Snapshot {
 
     ////////////////////////////////////////////////////////////////////////////
 
+    /// Get the pixels of a full snapshot.
     fn pixels(&self) -> Vec<Rgba8> {
-        let bytes = self
-            .pixels
+        match &self.data {
+            SnapshotData::Full(pixels) => Self::decompress(pixels),
+            SnapshotData::Delta { .. } => panic!("Snapshot::pixels: snapshot only holds a delta"),
+        }
+    }
+
+    fn decompress(pixels: &Compressed<Box<[u8]>>) -> Vec<Rgba8> {
+        let bytes = pixels
             .decompress()
             .expect("decompressing snapshot shouldn't result in an error");
         Rgba8::align(&bytes).into()
     }
+
+    /// Get the smallest rect containing all the pixels that differ between two buffers.
+    fn damage(a: &[Rgba8], b: &[Rgba8], width: usize) -> Option<Rect<usize>> {
+        let mut damage: Option<Rect<usize>> = None;
+
+        for (y, (ra, rb)) in a.chunks(width).zip(b.chunks(width)).enumerate() {
+            if ra == rb {
+                continue;
+            }
+            let x1 = ra.iter().zip(rb).position(|(a, b)| a != b).unwrap_or(0);
+            let x2 = width
+                - ra.iter()
+                    .rev()
+                    .zip(rb.iter().rev())
+                    .position(|(a, b)| a != b)
+                    .unwrap_or(0);
+
+            damage = Some(match damage {
+                Some(r) => Rect::new(r.x1.min(x1), r.y1, r.x2.max(x2), y + 1),
+                None => Rect::new(x1, y, x2, y + 1),
+            });
+        }
+        damage
+    }
+
+    /// Copy the pixels within `rect` out of a buffer.
+    fn extract(pixels: &[Rgba8], width: usize, rect: &Rect<usize>) -> Vec<Rgba8> {
+        let mut buffer = Vec::with_capacity(rect.area());
+
+        for y in rect.y1..rect.y2 {
+            let offset = y * width;
+            buffer.extend_from_slice(&pixels[offset + rect.x1..offset + rect.x2]);
+        }
+        buffer
+    }
+
+    /// Copy the given region pixels into a buffer, within `rect`.
+    fn apply(
+        pixels: &mut [Rgba8],
+        width: usize,
+        rect: &Rect<usize>,
+        region: &Compressed<Box<[u8]>>,
+    ) {
+        let region = Self::decompress(region);
+        let w = rect.width();
+
+        if w == 0 {
+            return;
+        }
+        for (y, row) in (rect.y1..rect.y2).zip(region.chunks(w)) {
+            let offset = y * width;
+            pixels[offset + rect.x1..offset + rect.x2].copy_from_slice(row);
+        }
+    }
 }
 
 ///////////////////////////////////////////////////////////////////////////////
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub struct Compressed<T>(T);
 
 impl Compressed<Box<[u8]>> {
@@ -455,3 +856,247 @@ impl Compressed<Box<[u8]>> {
         dec.decompress_vec(&self.0)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_damage() {
+        let a = vec![Rgba8::TRANSPARENT; 16];
+        let mut b = a.clone();
+
+        assert_eq!(Snapshot::damage(&a, &b, 4), None);
+
+        b[5] = Rgba8::RED;
+        b[10] = Rgba8::RED;
+        assert_eq!(Snapshot::damage(&a, &b, 4), Some(Rect::new(1, 1, 3, 3)));
+
+        b[15] = Rgba8::RED;
+        assert_eq!(Snapshot::damage(&a, &b, 4), Some(Rect::new(1, 1, 4, 4)));
+    }
+
+    #[test]
+    fn test_layer_snapshots() {
+        let extent = ViewExtent::new(4, 4, 1);
+        let initial = vec![Rgba8::TRANSPARENT; 16];
+        let mut layer = LayerResource::new(initial.clone(), extent);
+
+        let mut states = vec![initial];
+        for i in 0..LayerResource::KEYFRAME_INTERVAL + 2 {
+            let mut pixels = states.last().unwrap().clone();
+            pixels[i % 16] = Rgba8::new(i as u8, 0, 0, 0xff);
+            layer.push_snapshot(pixels.clone(), extent);
+            states.push(pixels);
+        }
+
+        let resized = ViewExtent::new(2, 2, 1);
+        layer.push_snapshot(vec![Rgba8::BLUE; 4], resized);
+
+        // Undo across the resize, which requires reconstructing the previous state.
+        assert_eq!(layer.prev_snapshot().unwrap().extent, extent);
+        assert_eq!(
+            layer.current_snapshot().1,
+            states.last().unwrap().as_slice()
+        );
+
+        for state in states.iter().rev().skip(1) {
+            layer.prev_snapshot().unwrap();
+            assert_eq!(layer.current_snapshot().1, state.as_slice());
+        }
+        assert!(layer.prev_snapshot().is_none());
+
+        for state in states.iter().skip(1) {
+            layer.next_snapshot().unwrap();
+            assert_eq!(layer.current_snapshot().1, state.as_slice());
+        }
+        assert_eq!(layer.next_snapshot().unwrap().extent, resized);
+        assert_eq!(layer.current_snapshot().1, vec![Rgba8::BLUE; 4].as_slice());
+        assert!(layer.next_snapshot().is_none());
+    }
+
+    #[test]
+    fn test_checkpoints() {
+        let extent = ViewExtent::new(2, 2, 1);
+        let mut view = ViewResource::new(vec![Rgba8::TRANSPARENT; 4], extent);
+        let now = time::Instant::now();
+        let ago = |secs| now - time::Duration::from_secs(secs);
+
+        // Nothing changed since the initial checkpoint.
+        view.checkpoint(ago(60));
+        assert_eq!(view.checkpoints.len(), 1);
+
+        let mut states = vec![];
+        for i in 0..ViewResource::MAX_CHECKPOINTS + 2 {
+            let mut pixels = vec![Rgba8::TRANSPARENT; 4];
+            pixels[i % 4] = Rgba8::new(i as u8, 0, 0, 0xff);
+
+            view.record_view_painted(pixels.clone());
+            view.checkpoint(ago(60));
+            states.push(pixels);
+        }
+        assert_eq!(view.checkpoints.len(), ViewResource::MAX_CHECKPOINTS);
+        assert!(matches!(
+            view.checkpoints[0].snapshot.data,
+            SnapshotData::Full(_)
+        ));
+        assert!(matches!(
+            view.checkpoints[1].snapshot.data,
+            SnapshotData::Delta { .. }
+        ));
+
+        // The oldest checkpoints were dropped, and those left are intact.
+        let kept = &states[states.len() - ViewResource::MAX_CHECKPOINTS..];
+        for (i, state) in kept.iter().enumerate() {
+            assert_eq!(&view.checkpoint_pixels(i), state);
+        }
+
+        // Asking for a time before the first checkpoint gets the oldest one.
+        let (_, pixels, _) = view
+            .checkpoint_before(time::Duration::from_secs(3600))
+            .unwrap();
+        assert_eq!(pixels, kept[0]);
+
+        let resized = ViewExtent::new(1, 1, 1);
+        view.record_view_resized(vec![Rgba8::BLUE], resized);
+        view.checkpoint(ago(30));
+        view.record_view_resized(vec![Rgba8::RED], resized);
+        view.checkpoint(now);
+
+        let (_, pixels, e) = view
+            .checkpoint_before(time::Duration::from_secs(45))
+            .unwrap();
+        assert_eq!((&pixels, e), (states.last().unwrap(), extent));
+
+        let (_, pixels, e) = view
+            .checkpoint_before(time::Duration::from_secs(15))
+            .unwrap();
+        assert_eq!((pixels, e), (vec![Rgba8::BLUE], resized));
+
+        let (_, pixels, _) = view.checkpoint_before(time::Duration::ZERO).unwrap();
+        assert_eq!(pixels, vec![Rgba8::RED]);
+    }
+
+    #[test]
+    fn test_save_png_indexed_depth() {
+        let extent = ViewExtent::new(2, 1, 1);
+        let view = ViewResource::new(vec![Rgba8::RED, Rgba8::BLUE], extent);
+        let path = std::env::temp_dir().join(format!("rx-indexed-{}.png", std::process::id()));
+        let palette = [Rgba8::RED, Rgba8::BLUE];
+
+        for bits in [0, 3, 16, 255] {
+            assert_eq!(
+                view.save_png_indexed(&path, &palette, Some(bits), 1)
+                    .unwrap_err()
+                    .kind(),
+                io::ErrorKind::InvalidInput
+            );
+        }
+        assert!(!path.exists());
+
+        assert_eq!(
+            view.save_png_indexed(&path, &palette, Some(1), 1).unwrap(),
+            2
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_history_palette() {
+        let extent = ViewExtent::new(2, 2, 1);
+        let mut view = ViewResource::new(vec![Rgba8::TRANSPARENT; 4], extent);
+        let (from, to) = (vec![Rgba8::RED], vec![]);
+
+        view.record_view_painted(vec![Rgba8::RED; 4]);
+        view.history_record(Edit::PaletteChanged(
+            String::from("default"),
+            from.clone(),
+            to.clone(),
+        ));
+        assert_eq!(view.current_edit(), 1);
+
+        // Undoing the palette edit leaves the pixels alone.
+        match view.history_prev() {
+            Some((1, Edit::PaletteChanged(_, a, b))) => assert_eq!((a, b), (from, to)),
+            other => panic!("unexpected edit {:?}", other),
+        }
+        assert_eq!(
+            view.layer.current_snapshot().1,
+            vec![Rgba8::RED; 4].as_slice()
+        );
+
+        assert!(matches!(view.history_prev(), Some((0, Edit::ViewPainted))));
+        assert!(view.history_prev().is_none());
+
+        assert!(matches!(view.history_next(), Some((1, Edit::ViewPainted))));
+        assert!(matches!(
+            view.history_next(),
+            Some((1, Edit::PaletteChanged(..)))
+        ));
+        assert!(view.history_next().is_none());
+    }
+
+    #[test]
+    fn test_history_trim() {
+        let extent = ViewExtent::new(2, 2, 1);
+        let mut view = ViewResource::new(vec![Rgba8::TRANSPARENT; 4], extent);
+        let states: Vec<Vec<Rgba8>> = (1..=LayerResource::KEYFRAME_INTERVAL as u8 + 8)
+            .map(|i| vec![Rgba8::new(i, 0, 0, 0xff); 4])
+            .collect();
+
+        for pixels in &states {
+            view.record_view_painted(pixels.clone());
+        }
+        view.history_prev().unwrap();
+
+        let edit = view.current_edit();
+        assert_eq!(view.history_trim(3), states.len() - 4);
+        assert_eq!(view.history_trim(3), 0);
+        assert_eq!(view.current_edit(), edit);
+
+        // The last three edits before the current one can still be undone.
+        for pixels in states.iter().rev().skip(2).take(3) {
+            view.history_prev().unwrap();
+            assert_eq!(view.layer.current_snapshot().1, pixels.as_slice());
+        }
+        assert!(view.history_prev().is_none());
+
+        // As can the one after it be redone, until the history is cleared.
+        for _ in 0..3 {
+            view.history_next().unwrap();
+        }
+        assert_eq!(view.history_clear(), 3);
+        assert!(view.history_next().is_none());
+        assert!(view.history_prev().is_none());
+        assert_eq!(view.current_edit(), edit);
+        assert_eq!(
+            view.layer.current_snapshot().1,
+            states[states.len() - 2].as_slice()
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        let extent = ViewExtent::new(2, 2, 1);
+        let mut view = ViewResource::new(vec![Rgba8::TRANSPARENT; 4], extent);
+
+        view.record_view_painted(vec![Rgba8::RED; 4]);
+        assert_eq!(view.label("red"), 1);
+
+        for _ in 0..LayerResource::KEYFRAME_INTERVAL {
+            view.record_view_painted(vec![Rgba8::BLUE; 4]);
+        }
+        view.record_view_resized(vec![Rgba8::GREEN; 2], ViewExtent::new(2, 1, 1));
+        view.history_clear();
+
+        // Labeled states outlive the history they were saved in.
+        assert_eq!(view.labeled("red"), Some((1, vec![Rgba8::RED; 4], extent)));
+        assert!(view.labeled("blue").is_none());
+
+        view.label("red");
+        assert_eq!(
+            view.labeled("red").map(|(_, pixels, _)| pixels),
+            Some(vec![Rgba8::GREEN; 2])
+        );
+    }
+}
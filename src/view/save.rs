@@ -0,0 +1,160 @@
+//! Saving views to disk in the background.
+use crate::gfx::Rgba8;
+use crate::image;
+use crate::io::{Archive, Manifest};
+use crate::view::{EditId, FileStorage, ViewId};
+
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+
+/// A file to write, with a copy of its pixels.
+#[derive(Debug)]
+pub struct SaveFile {
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Rgba8>,
+    /// Manifest to write the pixels with, if the file is an archive.
+    pub manifest: Option<Manifest>,
+}
+
+/// A view save, ready to be written. Since it holds copies of the pixels,
+/// the view can be edited while the save is in progress.
+#[derive(Debug)]
+pub struct SaveJob {
+    pub id: ViewId,
+    pub storage: FileStorage,
+    /// Edit at which the pixels were copied.
+    pub edit_id: EditId,
+    pub files: Vec<SaveFile>,
+}
+
+impl SaveJob {
+    /// Write the files to disk. Returns the number of pixels written.
+    pub fn run(&self) -> io::Result<usize> {
+        let mut written = 0;
+
+        for f in &self.files {
+            match &f.manifest {
+                Some(manifest) => Archive {
+                    layers: vec![f.pixels.clone()],
+                    manifest: manifest.clone(),
+                }
+                .save(&f.path)?,
+                None => image::save_as(&f.path, f.width, f.height, 1, &f.pixels)?,
+            }
+            written += f.pixels.len();
+        }
+        Ok(written)
+    }
+}
+
+/// Outcome of a save job.
+#[derive(Debug)]
+pub struct SaveResult {
+    pub id: ViewId,
+    pub storage: FileStorage,
+    pub edit_id: EditId,
+    pub result: io::Result<usize>,
+}
+
+/// Runs save jobs on a worker thread, one at a time, in the order they
+/// were submitted.
+#[derive(Debug)]
+pub struct Saver {
+    jobs: Option<mpsc::Sender<SaveJob>>,
+    results: mpsc::Receiver<SaveResult>,
+    worker: Option<thread::JoinHandle<()>>,
+    /// Number of jobs submitted, but not yet collected.
+    pending: usize,
+}
+
+impl Saver {
+    pub fn new() -> Self {
+        let (jobs, rx) = mpsc::channel::<SaveJob>();
+        let (tx, results) = mpsc::channel();
+
+        let worker = thread::spawn(move || {
+            for job in rx {
+                let result = job.run();
+                let SaveJob {
+                    id,
+                    storage,
+                    edit_id,
+                    ..
+                } = job;
+
+                if tx
+                    .send(SaveResult {
+                        id,
+                        storage,
+                        edit_id,
+                        result,
+                    })
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            jobs: Some(jobs),
+            results,
+            worker: Some(worker),
+            pending: 0,
+        }
+    }
+
+    /// Submit a job to the worker.
+    pub fn save(&mut self, job: SaveJob) -> io::Result<()> {
+        self.jobs
+            .as_ref()
+            .and_then(|jobs| jobs.send(job).ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "save worker is not running"))?;
+        self.pending += 1;
+
+        Ok(())
+    }
+
+    /// Get the results of the jobs completed since the last call, without blocking.
+    pub fn results(&mut self) -> Vec<SaveResult> {
+        let results: Vec<_> = self.results.try_iter().collect();
+        self.pending -= results.len();
+
+        results
+    }
+
+    /// Wait for all submitted jobs to complete, and get their results.
+    pub fn wait(&mut self) -> Vec<SaveResult> {
+        let mut results = Vec::with_capacity(self.pending);
+
+        while self.pending > 0 {
+            match self.results.recv() {
+                Ok(result) => results.push(result),
+                Err(_) => break,
+            }
+            self.pending -= 1;
+        }
+        results
+    }
+}
+
+impl Default for Saver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Saver {
+    /// Wait for submitted jobs to complete, so that files aren't left half-written.
+    fn drop(&mut self) {
+        self.jobs.take();
+
+        if let Some(worker) = self.worker.take() {
+            worker.join().ok();
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Workspaces, which group views.
+//!
+//! Every view belongs to exactly one workspace, and only the views of the
+//! active workspace are displayed. A workspace exists for as long as it has
+//! views, so there is nothing to clean up when the last one is closed.
+use crate::view::ViewId;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The view workspaces.
+#[derive(Debug)]
+pub struct Workspaces {
+    /// Name of the active workspace.
+    pub active: String,
+    /// Workspace of each view.
+    views: BTreeMap<ViewId, String>,
+}
+
+impl Workspaces {
+    /// Name of the workspace views start out in.
+    pub const DEFAULT: &'static str = "main";
+
+    pub fn new() -> Self {
+        Self {
+            active: Self::DEFAULT.to_owned(),
+            views: BTreeMap::new(),
+        }
+    }
+
+    /// Names of all workspaces, sorted. Includes the active workspace, even
+    /// if it has no views.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: BTreeSet<&str> = self.views.values().map(|s| s.as_str()).collect();
+        names.insert(&self.active);
+        names.into_iter().collect()
+    }
+
+    /// Check whether a workspace exists.
+    pub fn exists(&self, name: &str) -> bool {
+        self.active == name || self.views.values().any(|w| w == name)
+    }
+
+    /// Name of the workspace the given view belongs to.
+    pub fn get(&self, id: ViewId) -> Option<&str> {
+        self.views.get(&id).map(|s| s.as_str())
+    }
+
+    /// Check whether the given view belongs to the active workspace.
+    pub fn contains(&self, id: ViewId) -> bool {
+        self.get(id).map_or(true, |w| w == self.active)
+    }
+
+    /// Add a view to the active workspace.
+    pub fn add(&mut self, id: ViewId) {
+        self.views.insert(id, self.active.clone());
+    }
+
+    /// Move a view to the given workspace.
+    pub fn insert(&mut self, id: ViewId, name: &str) {
+        self.views.insert(id, name.to_owned());
+    }
+
+    /// Remove a view from its workspace.
+    pub fn remove(&mut self, id: ViewId) {
+        self.views.remove(&id);
+    }
+
+    /// The workspace after the active one, wrapping around.
+    pub fn next(&self) -> String {
+        let names = self.names();
+        let i = names.iter().position(|n| *n == self.active).unwrap_or(0);
+
+        names[(i + 1) % names.len()].to_owned()
+    }
+
+    /// The workspace before the active one, wrapping around.
+    pub fn prev(&self) -> String {
+        let names = self.names();
+        let i = names.iter().position(|n| *n == self.active).unwrap_or(0);
+
+        names[(i + names.len() - 1) % names.len()].to_owned()
+    }
+}
+
+impl Default for Workspaces {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_workspaces() {
+        let (a, b, c) = (ViewId::from(1), ViewId::from(2), ViewId::from(3));
+        let mut ws = Workspaces::new();
+
+        ws.add(a);
+        ws.add(b);
+        ws.insert(c, "icons");
+
+        assert_eq!(ws.names(), vec!["icons", "main"]);
+        assert!(ws.contains(a) && !ws.contains(c));
+        assert_eq!(ws.next(), "icons");
+        assert_eq!(ws.prev(), "icons");
+
+        ws.active = ws.next();
+        assert!(!ws.contains(a) && ws.contains(c));
+
+        ws.remove(c);
+        assert_eq!(ws.names(), vec!["icons", "main"]);
+
+        ws.active = String::from(Workspaces::DEFAULT);
+        assert_eq!(ws.names(), vec!["main"]);
+        assert!(!ws.exists("icons"));
+    }
+}
@@ -0,0 +1,271 @@
+//! ZIP archives, as used by OpenRaster (`.ora`) files.
+//!
+//! Only what image files need is supported: entries are either stored or
+//! deflated, and archives can't be split or use the ZIP64 extensions.
+//! Entries are always written stored, since images are already compressed.
+use std::convert::TryFrom;
+use std::io;
+
+/// Signature of the end of central directory record.
+const END_SIGNATURE: u32 = 0x0605_4b50;
+/// Signature of a central directory entry.
+const CENTRAL_SIGNATURE: u32 = 0x0201_4b50;
+/// Signature of a local file header.
+const LOCAL_SIGNATURE: u32 = 0x0403_4b50;
+
+/// Entry data is stored as is.
+const STORED: u16 = 0;
+/// Entry data is compressed with *deflate*.
+const DEFLATED: u16 = 8;
+/// Version of the format needed to read the archives written, ie. 2.0.
+const VERSION: u16 = 20;
+/// Modification date of the files written, in MS-DOS format: 1980-01-01.
+const DATE: u16 = (1 << 5) | 1;
+
+/// A file within an archive.
+#[derive(Debug)]
+struct Entry {
+    name: String,
+    method: u16,
+    /// Size of the data in the archive.
+    compressed: usize,
+    /// Size of the data once decompressed.
+    size: usize,
+    /// Offset of the local file header.
+    offset: usize,
+}
+
+/// Reads files out of an archive held in memory.
+#[derive(Debug)]
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    entries: Vec<Entry>,
+}
+
+impl<'a> Reader<'a> {
+    /// Read the archive's central directory.
+    pub fn new(bytes: &'a [u8]) -> io::Result<Self> {
+        // The end record is 22 bytes long, and may be followed by a comment.
+        let end = (0..=bytes.len().saturating_sub(22))
+            .rev()
+            .find(|i| self::u32_at(bytes, *i).ok() == Some(END_SIGNATURE))
+            .ok_or_else(|| self::invalid("not a zip archive"))?;
+        let count = self::u16_at(bytes, end + 10)? as usize;
+        let mut offset = self::u32_at(bytes, end + 16)? as usize;
+        let mut entries = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            if self::u32_at(bytes, offset)? != CENTRAL_SIGNATURE {
+                return Err(self::invalid("invalid central directory"));
+            }
+            let name_len = self::u16_at(bytes, offset + 28)? as usize;
+            let extra_len = self::u16_at(bytes, offset + 30)? as usize;
+            let comment_len = self::u16_at(bytes, offset + 32)? as usize;
+            let name = bytes
+                .get(offset + 46..offset + 46 + name_len)
+                .ok_or_else(self::truncated)?;
+
+            entries.push(Entry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                method: self::u16_at(bytes, offset + 10)?,
+                compressed: self::u32_at(bytes, offset + 20)? as usize,
+                size: self::u32_at(bytes, offset + 24)? as usize,
+                offset: self::u32_at(bytes, offset + 42)? as usize,
+            });
+            offset += 46 + name_len + extra_len + comment_len;
+        }
+        Ok(Self { bytes, entries })
+    }
+
+    /// Read the file with the given name, decompressing it if needed.
+    pub fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        let entry = self
+            .entries
+            .iter()
+            .find(|e| e.name == name)
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, format!("{} not found", name))
+            })?;
+        let header = entry.offset;
+
+        if self::u32_at(self.bytes, header)? != LOCAL_SIGNATURE {
+            return Err(self::invalid("invalid file header"));
+        }
+        // The local header has its own name and extra field lengths, which
+        // can differ from the central directory's.
+        let start = header
+            + 30
+            + self::u16_at(self.bytes, header + 26)? as usize
+            + self::u16_at(self.bytes, header + 28)? as usize;
+        let data = self
+            .bytes
+            .get(start..start + entry.compressed)
+            .ok_or_else(self::truncated)?;
+
+        let data = match entry.method {
+            STORED => data.to_vec(),
+            DEFLATED => miniz_oxide::inflate::decompress_to_vec(data)
+                .map_err(|_| self::invalid(&format!("{}: decompression failed", name)))?,
+            m => {
+                return Err(self::invalid(&format!(
+                    "{}: unsupported compression method {}",
+                    name, m
+                )))
+            }
+        };
+        if data.len() != entry.size {
+            return Err(self::invalid(&format!("{}: size mismatch", name)));
+        }
+        Ok(data)
+    }
+}
+
+/// Writes an archive, one file at a time.
+#[derive(Debug)]
+pub struct Writer<W> {
+    out: W,
+    /// Number of bytes written so far.
+    offset: u32,
+    /// Central directory entries of the files written.
+    central: Vec<u8>,
+    count: u16,
+}
+
+impl<W: io::Write> Writer<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            offset: 0,
+            central: Vec::new(),
+            count: 0,
+        }
+    }
+
+    /// Add a file to the archive.
+    pub fn add(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let too_large = || io::Error::new(io::ErrorKind::InvalidInput, "archive is too large");
+        let size = u32::try_from(data.len()).map_err(|_| too_large())?;
+        let mut header = Vec::with_capacity(26);
+
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes()); // Flags.
+        header.extend_from_slice(&STORED.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes()); // Time.
+        header.extend_from_slice(&DATE.to_le_bytes());
+        header.extend_from_slice(&self::crc32(data).to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes()); // Extra field length.
+
+        self.out.write_all(&LOCAL_SIGNATURE.to_le_bytes())?;
+        self.out.write_all(&header)?;
+        self.out.write_all(name.as_bytes())?;
+        self.out.write_all(data)?;
+
+        self.central
+            .extend_from_slice(&CENTRAL_SIGNATURE.to_le_bytes());
+        self.central.extend_from_slice(&VERSION.to_le_bytes()); // Version made by.
+        self.central.extend_from_slice(&header);
+        self.central.extend_from_slice(&[0; 10]); // Comment length, disk and attributes.
+        self.central.extend_from_slice(&self.offset.to_le_bytes());
+        self.central.extend_from_slice(name.as_bytes());
+
+        self.offset = self
+            .offset
+            .checked_add(30 + name.len() as u32 + size)
+            .ok_or_else(too_large)?;
+        self.count += 1;
+
+        Ok(())
+    }
+
+    /// Write the central directory, which completes the archive.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.out.write_all(&self.central)?;
+        self.out.write_all(&END_SIGNATURE.to_le_bytes())?;
+        self.out.write_all(&[0; 4])?; // Disk numbers.
+        self.out.write_all(&self.count.to_le_bytes())?;
+        self.out.write_all(&self.count.to_le_bytes())?;
+        self.out
+            .write_all(&(self.central.len() as u32).to_le_bytes())?;
+        self.out.write_all(&self.offset.to_le_bytes())?;
+        self.out.write_all(&0u16.to_le_bytes()) // Comment length.
+    }
+}
+
+/// CRC-32 checksum of the data, as stored in archives.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn u16_at(bytes: &[u8], i: usize) -> io::Result<u16> {
+    bytes
+        .get(i..i + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(self::truncated)
+}
+
+fn u32_at(bytes: &[u8], i: usize) -> io::Result<u32> {
+    bytes
+        .get(i..i + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(self::truncated)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_owned())
+}
+
+fn truncated() -> io::Error {
+    self::invalid("archive is truncated")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_archive() {
+        let mut bytes = Vec::new();
+        let mut writer = Writer::new(&mut bytes);
+
+        writer.add("mimetype", b"image/openraster").unwrap();
+        writer.add("a/b.txt", b"rx").unwrap();
+        writer.finish().unwrap();
+
+        // The first file's data follows its header.
+        assert_eq!(&bytes[38..54], b"image/openraster");
+
+        let zip = Reader::new(&bytes).unwrap();
+
+        assert_eq!(zip.read("mimetype").unwrap(), b"image/openraster");
+        assert_eq!(zip.read("a/b.txt").unwrap(), b"rx");
+        assert_eq!(
+            zip.read("c.txt").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        assert!(Reader::new(b"PK").is_err());
+        assert!(Reader::new(&bytes[..bytes.len() - 30]).is_err());
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+}
@@ -99,6 +99,11 @@ fn grid() {
     test("grid");
 }
 
+#[test]
+fn pixel_grid() {
+    test("pixel-grid");
+}
+
 #[test]
 fn source() {
     test("source");
@@ -155,6 +160,11 @@ fn run(name: &str) -> io::Result<()> {
         exec: ExecutionMode::Replay(path.clone(), DigestMode::Verify),
         glyphs,
         debug: false,
+        backend: env::var("RX_BACKEND")
+            .ok()
+            .and_then(|b| b.parse().ok())
+            .unwrap_or_default(),
+        socket: None,
     };
 
     {